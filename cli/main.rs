@@ -1,4 +1,8 @@
-use std::time::Instant;
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    time::{Duration, Instant},
+};
 
 use anyhow::Context;
 // (Full example with detailed comments in examples/17_yaml.rs)
@@ -8,13 +12,32 @@ use anyhow::Context;
 use clap::{load_yaml, App};
 use reqwest::Client;
 use sdmxblaze::{
+    archive::Archive,
     crawler::Crawler,
-    queries::metadata_query,
+    dataset::parse_sdmx_json,
+    diff::{diff, Snapshot},
+    frontier,
+    jobs::JobStore,
+    localized::LanguagePreferences,
+    metrics::{serve_metrics, Metrics},
+    minimal_structure::CatalogEntry,
+    parse::{parse_structure, Diagnostic, ParseError, ParseMode},
+    publish::{push, Target},
+    queries::{metadata_query, DataQuery},
+    report::{write_reports, ReportFormat},
     reqwest_layer::Response,
     reqwest_warc::write_warc,
+    sdmx_ml::structure_to_xml,
+    sdmx_sources::{parse_sources, sources_schema, validate_sources},
+    search::SearchIndex,
+    serve::{serve, Service},
+    store::Store,
     structure::Structure,
-    util::{filter_sources, read_sources},
+    util::{filter_sources, get_source, read_sources, read_structure_messages},
+    validate::{validate_structure, DataValidator, Issue, Severity},
 };
+use tracing::error;
+use tracing_subscriber::EnvFilter;
 use url::Url;
 
 #[tokio::main]
@@ -23,11 +46,20 @@ async fn main() -> anyhow::Result<()> {
     let yaml = load_yaml!("cli.yaml");
     let matches = App::from(yaml).get_matches();
 
+    let mut level = matches.value_of("log-level").unwrap();
+    if let Some(("crawl", sub_m)) = matches.subcommand() {
+        if sub_m.is_present("debug") && matches.occurrences_of("log-level") == 0
+        {
+            level = "debug";
+        }
+    }
+    init_logging(level, matches.value_of("log-format") == Some("json"));
+
     match matches.subcommand() {
         Some(("crawl", sub_m)) => {
-            println!("Crawling");
             let sources_file = sub_m.value_of("sources").unwrap();
-            let mut sources = read_sources(sources_file)?;
+            let all_sources = read_sources(sources_file)?;
+            let mut sources = all_sources.clone();
 
             if sub_m.is_present("SOURCES") {
                 let sourceIDs: Vec<_> = sub_m
@@ -39,18 +71,365 @@ async fn main() -> anyhow::Result<()> {
                 sources = filter_sources(sources, sourceIDs)?
             }
 
-            for source in sources {
-                // println!("{:#?}", source);
-
-                let base_url = format!("{}/", &source.url);
+            let mut cr = Crawler::default();
+            if let Some(contact) = sub_m.value_of("contact") {
+                cr = cr.with_contact(contact);
+            }
+            if let Some(db) = sub_m.value_of("db") {
+                cr = cr.with_store(Store::open(db)?);
+            }
+            if let Some(index) = sub_m.value_of("index") {
+                cr = cr.with_index(SearchIndex::open(index)?);
+            }
+            let metrics = Metrics::new();
+            cr = cr.with_metrics(metrics.clone());
+            if let Some(addr) = sub_m.value_of("metrics-addr") {
+                let server = serve_metrics(metrics.clone(), addr.parse()?);
+                tokio::spawn(async move {
+                    if let Err(e) = server.await {
+                        error!(error = %e, "Metrics endpoint failed");
+                    }
+                });
+            }
 
-                let cr = Crawler::default();
-                cr.crawl(base_url).await?;
+            let mut reports = vec![];
+            match sub_m.value_of("queue") {
+                Some(queue) => {
+                    let frontier = frontier::open(
+                        queue,
+                        sub_m.value_of("queue-name").unwrap(),
+                    )?;
+                    for source in &sources {
+                        cr.seed(frontier.as_ref(), source)?;
+                    }
+                    let lease: u64 =
+                        sub_m.value_of("lease").unwrap().parse()?;
+                    // Tasks may come from sources other workers queued
+                    reports = cr
+                        .work(
+                            frontier.as_ref(),
+                            &all_sources,
+                            Duration::from_secs(lease),
+                        )
+                        .await?;
+                }
+                None => {
+                    for source in sources {
+                        // println!("{:#?}", source);
+                        reports.push(cr.crawl(&source).await?);
+                    }
+                }
+            }
+            if let Some(path) = sub_m.value_of("report") {
+                let format = match sub_m.value_of("report-format") {
+                    Some("ndjson") => ReportFormat::Ndjson,
+                    _ => ReportFormat::Json,
+                };
+                let mut out = BufWriter::new(File::create(path)?);
+                write_reports(&mut out, &reports, format)?;
+                out.flush()?;
+            }
+            if let Some(path) = sub_m.value_of("metrics") {
+                metrics.write_to(path)?;
             }
             // sub_m.value_of("sources").ok_or("No sources file provided")
         } // clone was used
-        Some(("push", sub_m)) => {}   // push was used
-        Some(("commit", sub_m)) => {} // commit was used
+        Some(("serve", sub_m)) => {
+            let sources = read_sources(sub_m.value_of("sources").unwrap())?;
+            let metrics = Metrics::new();
+            let mut cr = Crawler::default().with_metrics(metrics.clone());
+            if let Some(contact) = sub_m.value_of("contact") {
+                cr = cr.with_contact(contact);
+            }
+            if let Some(db) = sub_m.value_of("db") {
+                cr = cr.with_store(Store::open(db)?);
+            }
+            if let Some(index) = sub_m.value_of("index") {
+                cr = cr.with_index(SearchIndex::open(index)?);
+            }
+            let jobs = JobStore::open(sub_m.value_of("jobs").unwrap())?;
+            let service = Service::new(jobs, sources, metrics);
+            serve(sub_m.value_of("addr").unwrap().parse()?, service, cr)
+                .await?;
+        }
+        Some(("search", sub_m)) => {
+            let index =
+                SearchIndex::open_read_only(sub_m.value_of("index").unwrap())?;
+            let limit = sub_m.value_of("limit").unwrap().parse()?;
+            let languages = language_preferences(sub_m.value_of("lang"))?;
+            let hits = index.search(
+                sub_m.value_of("QUERY").unwrap(),
+                limit,
+                &languages,
+            )?;
+            for hit in hits {
+                println!(
+                    "{:.3}\t{}\t{}:{}({})\t{}",
+                    hit.score,
+                    hit.source_id,
+                    hit.agency_id,
+                    hit.resource_id,
+                    hit.version,
+                    hit.name
+                );
+            }
+        }
+        Some(("validate", sub_m)) => {
+            let mode = match sub_m.is_present("strict") {
+                true => ParseMode::Strict,
+                false => ParseMode::Lenient,
+            };
+            let mut messages = vec![];
+            for path in sub_m.values_of("PATHS").unwrap() {
+                messages.extend(read_structure_messages(path, mode)?);
+            }
+            let mut errors = 0;
+            for stored in &messages {
+                let mut issues: Vec<_> = stored
+                    .diagnostics
+                    .iter()
+                    .map(|d| parse_issue(Severity::Warning, d))
+                    .collect();
+                match &stored.message {
+                    Ok(Structure {
+                        data: Some(data), ..
+                    }) => issues.extend(validate_structure(data)),
+                    Ok(_) => {}
+                    Err(e) => match e.downcast_ref::<ParseError>() {
+                        Some(e) => issues.extend(
+                            e.diagnostics
+                                .iter()
+                                .map(|d| parse_issue(Severity::Error, d)),
+                        ),
+                        None => issues.push(Issue {
+                            severity: Severity::Error,
+                            artefact: "message".to_string(),
+                            location: None,
+                            message: format!("Failed to parse: {}", e),
+                        }),
+                    },
+                }
+                for issue in issues {
+                    if issue.severity == Severity::Error {
+                        errors += 1;
+                    }
+                    match sub_m.is_present("json") {
+                        true => println!(
+                            "{}",
+                            serde_json::json!({
+                                "source": stored.label,
+                                "issue": issue
+                            })
+                        ),
+                        false => println!("{}: {}", stored.label, issue),
+                    }
+                }
+            }
+            eprintln!(
+                "Checked {} messages, found {} errors",
+                messages.len(),
+                errors
+            );
+            if errors > 0 {
+                return Err(anyhow::anyhow!("Validation failed"));
+            }
+        }
+        Some(("validate-data", sub_m)) => {
+            let path = sub_m.value_of("structures").unwrap();
+            let text = std::fs::read_to_string(path)?;
+            let structures = parse_structure(&text, None, ParseMode::Lenient)?
+                .value
+                .data
+                .with_context(|| format!("{} has no structures", path))?;
+            let validator =
+                DataValidator::find(&structures, sub_m.value_of("dataflow"))?;
+            let mut violations = 0;
+            for path in sub_m.values_of("DATA").unwrap() {
+                let text = std::fs::read_to_string(path)?;
+                let data_sets = parse_sdmx_json(&text)
+                    .with_context(|| format!("Failed to read {}", path))?;
+                for report in data_sets.iter().map(|ds| validator.validate(ds))
+                {
+                    violations += report.len();
+                    let data_set = report.data_set.iter().map(|v| (None, v));
+                    let series = report.series.iter().flat_map(|s| {
+                        s.violations.iter().map(move |v| (Some(&s.key), v))
+                    });
+                    for (key, v) in data_set.chain(series) {
+                        match (sub_m.is_present("json"), key) {
+                            (true, _) => println!(
+                                "{}",
+                                serde_json::json!({
+                                    "source": path,
+                                    "series": key,
+                                    "violation": v
+                                })
+                            ),
+                            (false, Some(key)) => {
+                                println!("{}: {}: {}", path, key, v)
+                            }
+                            (false, None) => println!("{}: {}", path, v),
+                        }
+                    }
+                }
+            }
+            eprintln!("Found {} violations", violations);
+            if violations > 0 {
+                return Err(anyhow::anyhow!("Validation failed"));
+            }
+        }
+        Some(("fetch-data", sub_m)) => {
+            let source = get_source(
+                sub_m.value_of("sources").unwrap().to_string(),
+                sub_m.value_of("SOURCE").unwrap().to_string(),
+            )?;
+            let dataflow = sub_m.value_of("DATAFLOW").unwrap();
+            let (agency_id, id) =
+                dataflow.split_once(':').with_context(|| {
+                    format!(
+                        "Expected AGENCY:ID as the dataflow, got {}",
+                        dataflow
+                    )
+                })?;
+            let mut query = DataQuery::for_source(&source, agency_id, id)
+                .with_period(sub_m.value_of("start"), sub_m.value_of("end"));
+            if let Some(key) = sub_m.value_of("key") {
+                query = query.with_key(key);
+            }
+            let data = Crawler::default().fetch_data(&source, &query).await?;
+            match sub_m.value_of("output") {
+                Some(path) => std::fs::write(path, data)?,
+                None => io::stdout().write_all(data.as_bytes())?,
+            }
+        }
+        Some(("sources", sub_m)) => match sub_m.subcommand() {
+            Some(("validate", sub_m)) => {
+                let path = sub_m.value_of("sources").unwrap();
+                let text = std::fs::read_to_string(path)?;
+                let issues = match parse_sources(&text) {
+                    Ok(sources) => validate_sources(&sources),
+                    Err(e) => vec![Issue {
+                        severity: Severity::Error,
+                        artefact: "sources".to_string(),
+                        location: None,
+                        message: format!("Failed to parse: {}", e),
+                    }],
+                };
+                let mut errors = 0;
+                for issue in &issues {
+                    if issue.severity == Severity::Error {
+                        errors += 1;
+                    }
+                    match sub_m.is_present("json") {
+                        true => println!("{}", serde_json::to_string(issue)?),
+                        false => println!("{}: {}", path, issue),
+                    }
+                }
+                eprintln!("Checked {}, found {} errors", path, errors);
+                if errors > 0 {
+                    return Err(anyhow::anyhow!("Validation failed"));
+                }
+            }
+            Some(("schema", _)) => {
+                let schema = sources_schema();
+                println!("{}", serde_json::to_string_pretty(&schema)?);
+            }
+            _ => {}
+        },
+        Some(("diff", sub_m)) => {
+            let old = read_snapshot(sub_m.value_of("OLD").unwrap())?;
+            let new = read_snapshot(sub_m.value_of("NEW").unwrap())?;
+            let changes = diff(&old, &new);
+            for change in &changes {
+                match sub_m.is_present("json") {
+                    true => println!("{}", serde_json::to_string(change)?),
+                    false => println!("{}", change),
+                }
+            }
+            eprintln!(
+                "Compared {} with {} messages, found {} changes",
+                old.len(),
+                new.len(),
+                changes.len()
+            );
+        }
+        Some(("export", sub_m)) => match sub_m.subcommand() {
+            Some(("catalog", sub_m)) => {
+                let entries = catalog(
+                    sub_m.value_of("db").unwrap(),
+                    sub_m.value_of("sources").unwrap(),
+                    sub_m.value_of("lang"),
+                )?;
+                let mut out: Box<dyn Write> = match sub_m.value_of("output") {
+                    Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+                    None => Box::new(io::stdout()),
+                };
+                for entry in entries {
+                    serde_json::to_writer(&mut out, &entry)?;
+                    writeln!(out)?;
+                }
+                out.flush()?;
+            }
+            Some(("xml", sub_m)) => {
+                let path = sub_m.value_of("PATH").unwrap();
+                let mut messages =
+                    read_structure_messages(path, ParseMode::Lenient)?;
+                if messages.len() != 1 {
+                    return Err(anyhow::anyhow!(
+                        "{} contains {} messages, expected one",
+                        path,
+                        messages.len()
+                    ));
+                }
+                let stored = messages.remove(0);
+                for d in &stored.diagnostics {
+                    eprintln!("Repaired {}: {}", stored.label, d);
+                }
+                let xml = structure_to_xml(&stored.message?)?;
+                match sub_m.value_of("output") {
+                    Some(output) => std::fs::write(output, xml)?,
+                    None => print!("{}", xml),
+                }
+            }
+            _ => {}
+        },
+        Some(("commit", sub_m)) => {
+            let entries = match sub_m.value_of("db") {
+                Some(db) => Some(catalog(
+                    db,
+                    sub_m.value_of("sources").unwrap(),
+                    sub_m.value_of("lang"),
+                )?),
+                None => None,
+            };
+            let archive = Archive::new(sub_m.value_of("archive").unwrap());
+            let manifest = archive
+                .commit(sub_m.value_of("warc").unwrap(), entries.as_deref())?;
+            println!(
+                "Committed snapshot {} with {} files",
+                manifest.id,
+                manifest.files.len()
+            );
+        }
+        Some(("push", sub_m)) => {
+            let archive = Archive::new(sub_m.value_of("archive").unwrap());
+            let id = match sub_m.value_of("SNAPSHOT") {
+                Some(id) => id.to_string(),
+                None => archive.latest()?.ok_or_else(|| {
+                    anyhow::anyhow!("No snapshots to push, run commit first")
+                })?,
+            };
+            let target = Target::parse(
+                sub_m.value_of("target").unwrap(),
+                sub_m.value_of("endpoint"),
+            )?;
+            let manifest = push(&archive, &id, &target).await?;
+            println!(
+                "Pushed snapshot {} with {} files",
+                manifest.id,
+                manifest.files.len()
+            );
+        }
         _ => {} // Either no subcommand or one not tested for...
     }
 
@@ -58,3 +437,79 @@ async fn main() -> anyhow::Result<()> {
 
     // Same as previous examples...
 }
+
+/// Reads the structure messages under a path, skipping those which fail to parse
+fn read_snapshot(path: &str) -> anyhow::Result<Snapshot> {
+    let mut snapshot = Snapshot::new();
+    for stored in read_structure_messages(path, ParseMode::Lenient)? {
+        match stored.message {
+            Ok(Structure {
+                data: Some(data), ..
+            }) => snapshot.add(stored.origin, data),
+            Ok(_) => {}
+            Err(e) => eprintln!("Skipping {}: {}", stored.label, e),
+        }
+    }
+    Ok(snapshot)
+}
+
+/// Builds the catalog of a store, with provider information from the sources file
+fn catalog(
+    db: &str,
+    sources: &str,
+    lang: Option<&str>,
+) -> anyhow::Result<Vec<CatalogEntry>> {
+    let store = Store::open(db)?;
+    let sources = read_sources(sources)?;
+    let mut entries = store.catalog(&language_preferences(lang)?)?;
+    for entry in &mut entries {
+        if let Some(source) = sources.iter().find(|s| s.id == entry.source_id) {
+            entry.set_source(source);
+        }
+    }
+    Ok(entries)
+}
+
+/// Logs to stderr, keeping stdout for the output of commands. Without
+/// RUST_LOG, events of this crate are logged up to `level` and those of
+/// dependencies only from warnings.
+fn init_logging(level: &str, json: bool) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+        let dependencies = match level {
+            "error" => "error",
+            _ => "warn",
+        };
+        EnvFilter::new(format!(
+            "{},sdmxblaze={},sdmx={}",
+            dependencies, level, level
+        ))
+    });
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr);
+    match json {
+        true => builder.json().init(),
+        false => builder.init(),
+    }
+}
+
+/// Parses the --lang option. Stored names fall back to the content languages
+/// of their message and then to its main language.
+fn language_preferences(
+    lang: Option<&str>,
+) -> anyhow::Result<LanguagePreferences> {
+    Ok(match lang {
+        Some(lang) => lang.parse()?,
+        None => LanguagePreferences::default(),
+    })
+}
+
+/// Reports a deviation from the SDMX-JSON schema as an issue of the message
+fn parse_issue(severity: Severity, d: &Diagnostic) -> Issue {
+    Issue {
+        severity,
+        artefact: "message".to_string(),
+        location: Some(d.path.clone()),
+        message: d.message.clone(),
+    }
+}
@@ -3,7 +3,8 @@ use anyhow::{anyhow, Context, Result};
 
 use reqwest::Client;
 use sdmxblaze::{
-    queries::metadata_query,
+    crawler::Crawler,
+    queries::StructureQuery,
     reqwest_warc::write_warc,
     sdmx_sources::{Accept, Source, Sources},
 };
@@ -30,10 +31,15 @@ async fn main() -> Result<()> {
         // DATA QUERIES
         let base_url = Url::parse(format!("{}/", &source.url).as_str())?;
 
-        let paths = ["dataflow", "all", "all", "latest"].to_vec();
+        match Crawler::default().detect_rest_version(source).await {
+            Ok(rest) => source.rest_version = Some(rest),
+            Err(e) => println!("{:#?}", e),
+        }
+        let query = StructureQuery::new("dataflow");
 
         // Joining with a base url replaces the last item in path (public)
-        let mut path_url = base_url.join(metadata_query(paths).as_str())?;
+        let mut path_url =
+            base_url.join(query.path(source.rest_version())?.as_str())?;
 
         let url = path_url.to_string();
 
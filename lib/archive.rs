@@ -0,0 +1,386 @@
+//! archive keeps completed crawls as immutable snapshots in a local archive
+//! directory, so they can be published (see `publish`) and compared later.
+//!
+//! Each snapshot is a directory named by a ULID, which sorts by creation time:
+//!
+//! ```text
+//! archive/01F8MECHZX3TBDSZ7XRADM79XV/
+//!     warc/           the WARC files of the crawl
+//!     index.cdx       a CDX index of the responses in the WARC files
+//!     catalog.jsonl   the dataflow catalog, if a store was given
+//!     manifest.json   the size and SHA-256 hash of every other file
+//! ```
+//!
+//! Snapshots are written to a temporary directory and renamed once complete,
+//! so a snapshot directory with a manifest is always complete.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use ulid::Ulid;
+use url::Url;
+
+use crate::{
+    minimal_structure::CatalogEntry, reqwest_warc::read_warc_file,
+    util::cdx_surt,
+};
+
+pub const MANIFEST: &str = "manifest.json";
+
+/// The files of a snapshot
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Manifest {
+    /// ID of the snapshot, which is also its directory name
+    pub id: String,
+    /// Creation time in RFC 3339 format
+    pub created: String,
+    /// The directory the WARC files were taken from
+    pub source: String,
+    pub files: Vec<ManifestFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ManifestFile {
+    /// Path relative to the snapshot directory, separated by `/`
+    pub path: String,
+    pub size: u64,
+    /// Hex encoded SHA-256 hash of the content
+    pub sha256: String,
+}
+
+/// A local directory of snapshots
+#[derive(Debug, Clone)]
+pub struct Archive {
+    root: PathBuf,
+}
+
+impl Archive {
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Archive {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn snapshot_dir(&self, id: &str) -> PathBuf {
+        self.root.join(id)
+    }
+
+    /// Copies the WARC files in `warc_dir` into a new snapshot, along with a
+    /// CDX index of them and the given catalog
+    pub fn commit<P: AsRef<Path>>(
+        &self,
+        warc_dir: P,
+        catalog: Option<&[CatalogEntry]>,
+    ) -> Result<Manifest> {
+        let warc_dir = warc_dir.as_ref();
+        let mut warcs = vec![];
+        for entry in fs::read_dir(warc_dir)
+            .with_context(|| format!("Failed to read {:?}", warc_dir))?
+        {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) == Some("warc") {
+                warcs.push(path);
+            }
+        }
+        if warcs.is_empty() {
+            return Err(anyhow!("No WARC files in {:?}", warc_dir));
+        }
+        warcs.sort();
+
+        let id = Ulid::new().to_string();
+        let partial = self.root.join(format!(".{}.partial", id));
+        fs::create_dir_all(partial.join("warc"))?;
+
+        let mut paths = vec![];
+        for warc in &warcs {
+            let name = warc
+                .file_name()
+                .and_then(|n| n.to_str())
+                .ok_or_else(|| anyhow!("Invalid WARC file name {:?}", warc))?;
+            let path = format!("warc/{}", name);
+            fs::copy(warc, partial.join(&path))?;
+            paths.push(path);
+        }
+
+        write_cdx(&partial, &paths)?;
+        paths.push("index.cdx".to_string());
+
+        if let Some(catalog) = catalog {
+            let mut out =
+                BufWriter::new(File::create(partial.join("catalog.jsonl"))?);
+            for entry in catalog {
+                serde_json::to_writer(&mut out, entry)?;
+                writeln!(out)?;
+            }
+            out.flush()?;
+            paths.push("catalog.jsonl".to_string());
+        }
+
+        let mut files = vec![];
+        for path in paths {
+            files.push(hash_file(&partial, &path)?);
+        }
+        let manifest = Manifest {
+            id: id.clone(),
+            created: Utc::now().to_rfc3339(),
+            source: warc_dir.display().to_string(),
+            files,
+        };
+        fs::write(
+            partial.join(MANIFEST),
+            serde_json::to_string_pretty(&manifest)?,
+        )?;
+
+        fs::rename(&partial, self.snapshot_dir(&id))?;
+        Ok(manifest)
+    }
+
+    /// The IDs of all complete snapshots, oldest first
+    pub fn snapshots(&self) -> Result<Vec<String>> {
+        let mut out = vec![];
+        if !self.root.exists() {
+            return Ok(out);
+        }
+        for entry in fs::read_dir(&self.root)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !name.starts_with('.') && entry.path().join(MANIFEST).exists() {
+                out.push(name);
+            }
+        }
+        out.sort();
+        Ok(out)
+    }
+
+    pub fn latest(&self) -> Result<Option<String>> {
+        Ok(self.snapshots()?.pop())
+    }
+
+    pub fn manifest(&self, id: &str) -> Result<Manifest> {
+        let path = self.snapshot_dir(id).join(MANIFEST);
+        let body = fs::read_to_string(&path).with_context(|| {
+            format!("No snapshot {} in {:?}", id, self.root)
+        })?;
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    /// Checks the size and hash of every file of a snapshot against its
+    /// manifest
+    pub fn verify(&self, id: &str) -> Result<Manifest> {
+        let manifest = self.manifest(id)?;
+        let dir = self.snapshot_dir(id);
+        for file in &manifest.files {
+            if hash_file(&dir, &file.path)? != *file {
+                return Err(anyhow!(
+                    "{} of snapshot {} does not match its manifest",
+                    file.path,
+                    id
+                ));
+            }
+        }
+        Ok(manifest)
+    }
+}
+
+fn hash_file(dir: &Path, path: &str) -> Result<ManifestFile> {
+    let mut hasher = Sha256::new();
+    let size = io::copy(&mut File::open(dir.join(path))?, &mut hasher)?;
+    Ok(ManifestFile {
+        path: path.to_string(),
+        size,
+        sha256: hex::encode(hasher.finalize()),
+    })
+}
+
+/// Writes a CDX index of the responses in the WARC files. The WARC files
+/// don't record the HTTP status, so that field is `-`. WARC files written
+/// before the requested URL was recorded have their URL key taken from the
+/// file name, and `-` as the original URL. The timestamp is taken from the
+/// file names written by `reqwest_warc::write_warc_file`.
+fn write_cdx(dir: &Path, warcs: &[String]) -> Result<()> {
+    let mut lines = vec![];
+    for path in warcs {
+        let stem = path
+            .trim_start_matches("warc/")
+            .trim_end_matches(".warc")
+            .to_string();
+        let (file_key, timestamp) = match stem.rsplit_once('-') {
+            Some((key, id)) => (key.to_string(), ulid_timestamp(id)),
+            None => (stem.clone(), None),
+        };
+        for entry in read_warc_file(dir.join(path))? {
+            if entry.warc_type != "response" {
+                continue;
+            }
+            let url =
+                entry.target_uri.as_deref().and_then(|u| Url::parse(u).ok());
+            let (url_key, original) = match &url {
+                Some(url) => (cdx_surt(url), url.as_str()),
+                None => (file_key.clone(), "-"),
+            };
+            let (headers, body) = entry.http_parts();
+            let mime = headers
+                .lines()
+                .filter_map(|l| l.split_once(':'))
+                .find(|(k, _)| k.trim().eq_ignore_ascii_case("content-type"))
+                .map(|(_, v)| v.trim().replace(' ', ""))
+                .unwrap_or_else(|| "-".to_string());
+            lines.push(format!(
+                "{} {} {} {} - sha256:{} - - {} {} {}",
+                url_key,
+                timestamp.as_deref().unwrap_or("-"),
+                original,
+                mime,
+                hex::encode(Sha256::digest(body)),
+                entry.length,
+                entry.offset,
+                path
+            ));
+        }
+    }
+    lines.sort();
+
+    let mut out = BufWriter::new(File::create(dir.join("index.cdx"))?);
+    writeln!(out, " CDX N b a m s k r M S V g")?;
+    for line in lines {
+        writeln!(out, "{}", line)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// The creation time of a ULID in the 14 digit CDX format
+fn ulid_timestamp(id: &str) -> Option<String> {
+    let ulid = Ulid::from_string(id).ok()?;
+    let time =
+        SystemTime::UNIX_EPOCH + Duration::from_millis(ulid.timestamp_ms());
+    Some(
+        DateTime::<Utc>::from(time)
+            .format("%Y%m%d%H%M%S")
+            .to_string(),
+    )
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::env;
+
+    use super::*;
+
+    /// A new directory under the temporary directory of the system
+    pub(crate) fn temp_dir() -> PathBuf {
+        let dir = env::temp_dir().join(format!("sdmxblaze-{}", Ulid::new()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn record(typ: &str, url: Option<&str>, block: &str) -> String {
+        let target = url
+            .map(|u| format!("WARC-Target-URI: {}\r\n", u))
+            .unwrap_or_default();
+        format!(
+            "WARC/1.1\r\nWARC-Type: {}\r\nWARC-Record-ID: <urn:uuid:{}>\r\n\
+             {}Content-Length: {}\r\n\r\n{}\r\n\r\n",
+            typ,
+            Ulid::new(),
+            target,
+            block.len(),
+            block
+        )
+    }
+
+    /// Writes a WARC file with a request and a JSON response for a URL, as
+    /// `write_warc` does. Without `target`, the URL is left out of the
+    /// records as in older files.
+    pub(crate) fn write_warc(dir: &Path, url: &str, target: bool) {
+        let url = Url::parse(url).unwrap();
+        let name = format!(
+            "{}-{}.warc",
+            crate::util::cdx_url_canonical(url.clone()).unwrap(),
+            Ulid::new()
+        );
+        let target = Some(url.as_str()).filter(|_| target);
+        let body = format!("{{\"url\": \"{}\"}}", url);
+        let warc = record("request", target, "accept: application/json")
+            + &record(
+                "response",
+                target,
+                &format!("content-type: application/json\r\n\r\n{}", body),
+            );
+        fs::write(dir.join(name), warc).unwrap();
+    }
+
+    #[test]
+    fn commit_indexes_responses_by_url() {
+        let warcs = temp_dir();
+        write_warc(&warcs, "https://sdw.ecb.org/service/dataflow", true);
+        write_warc(
+            &warcs,
+            "https://sdw.ecb.org/service/codelist?b=2&a=1",
+            true,
+        );
+        write_warc(&warcs, "https://sdw.ecb.org/service/old", false);
+        let archive = Archive::new(temp_dir());
+
+        let manifest = archive.commit(&warcs, None).unwrap();
+        assert_eq!(manifest.files.len(), 4);
+        archive.verify(&manifest.id).unwrap();
+        assert_eq!(archive.latest().unwrap(), Some(manifest.id.clone()));
+
+        let cdx = fs::read_to_string(
+            archive.snapshot_dir(&manifest.id).join("index.cdx"),
+        )
+        .unwrap();
+        let lines: Vec<Vec<_>> = cdx
+            .lines()
+            .skip(1)
+            .map(|l| l.split(' ').collect())
+            .collect();
+        let keys: Vec<_> = lines.iter().map(|l| (l[0], l[2], l[3])).collect();
+        assert_eq!(
+            keys,
+            vec![
+                ("org,ecb,sdw)", "-", "application/json"),
+                (
+                    "org,ecb,sdw)/service/codelist?a=1&b=2",
+                    "https://sdw.ecb.org/service/codelist?b=2&a=1",
+                    "application/json"
+                ),
+                (
+                    "org,ecb,sdw)/service/dataflow",
+                    "https://sdw.ecb.org/service/dataflow",
+                    "application/json"
+                ),
+            ]
+        );
+        // The offset and length point at the response record
+        let line = &lines[2];
+        let warc = fs::read(archive.snapshot_dir(&manifest.id).join(line[10]))
+            .unwrap();
+        let (length, offset): (usize, usize) =
+            (line[8].parse().unwrap(), line[9].parse().unwrap());
+        let record = String::from_utf8_lossy(&warc[offset..offset + length]);
+        assert!(record.starts_with("WARC/1.1\r\nWARC-Type: response"));
+        assert!(record
+            .ends_with("{\"url\": \"https://sdw.ecb.org/service/dataflow\"}"));
+    }
+
+    #[test]
+    fn verify_rejects_changed_files() {
+        let warcs = temp_dir();
+        write_warc(&warcs, "https://sdw.ecb.org/service/dataflow", true);
+        let archive = Archive::new(temp_dir());
+        let manifest = archive.commit(&warcs, None).unwrap();
+        let cdx = archive.snapshot_dir(&manifest.id).join("index.cdx");
+        fs::write(cdx, "changed").unwrap();
+        assert!(archive.verify(&manifest.id).is_err());
+    }
+}
@@ -0,0 +1,395 @@
+//! artefact defines the common interfaces of the structure types.
+//!
+//! The types in `structure` are generated from the SDMX-JSON schema, which
+//! flattens the abstract base types of the information model into every
+//! concrete type. The traits here restore that hierarchy, so code which only
+//! needs the identification of an artefact doesn't have to match on its type:
+//!
+//! - [`Identifiable`]: objects with an ID, annotations and links
+//! - [`Nameable`]: identifiable objects with a name and description
+//! - [`Maintainable`]: nameable objects maintained by an agency, with a version
+//! - [`ItemScheme`]: maintainable lists of nameable items, such as codelists
+
+use crate::{
+    localized::{LanguagePreferences, LocalizedString},
+    structure::{self, *},
+    urn::Urn,
+    version::{Version, VersionReq},
+};
+
+/// Version of maintainable artefacts which don't specify one
+pub const DEFAULT_VERSION: &str = "1.0";
+
+/// An object with an identity within its parent
+pub trait Identifiable {
+    /// The ID of the object. Some identifiable types have an optional ID in SDMX-JSON.
+    fn id(&self) -> Option<&str>;
+    fn annotations(&self) -> &[AnnotationType];
+    fn links(&self) -> &[Link];
+
+    /// The URN of the object, taken from its `self` link
+    fn urn(&self) -> Option<Urn> {
+        self.links()
+            .iter()
+            .filter(|l| l.rel == "self")
+            .find_map(|l| l.urn.as_ref()?.parse().ok())
+    }
+}
+
+/// An identifiable object with a human readable name and description
+pub trait Nameable: Identifiable {
+    /// The name in the language of the message
+    fn name(&self) -> &str;
+    fn names(&self) -> Option<&LocalizedString>;
+    /// The description in the language of the message
+    fn description(&self) -> Option<&str>;
+    fn descriptions(&self) -> Option<&LocalizedString>;
+
+    /// The name in the most preferred available language
+    fn label(&self, languages: &LanguagePreferences) -> &str {
+        languages.resolve(self.names(), self.name())
+    }
+
+    /// The description in the most preferred available language
+    fn description_label(
+        &self,
+        languages: &LanguagePreferences,
+    ) -> Option<&str> {
+        languages.resolve_opt(self.descriptions(), self.description())
+    }
+}
+
+/// A nameable artefact maintained and versioned by an agency
+pub trait Maintainable: Nameable {
+    /// The SDMX class of the artefact, e.g. `Codelist`
+    fn class(&self) -> ObjectTypeCodelistType;
+    fn agency_id(&self) -> &str;
+    /// The version as given in the message
+    fn version(&self) -> Option<&str>;
+    fn is_final(&self) -> bool;
+    fn is_external_reference(&self) -> bool;
+    fn valid_from(&self) -> Option<&str>;
+    fn valid_to(&self) -> Option<&str>;
+
+    /// The ID of the artefact. Maintainable artefacts always have an ID.
+    fn resource_id(&self) -> &str {
+        self.id().unwrap_or_default()
+    }
+
+    /// The parsed version, defaulting to `1.0` when the message has none
+    fn parsed_version(&self) -> Option<Version> {
+        self.version().unwrap_or(DEFAULT_VERSION).parse().ok()
+    }
+
+    /// The URN built from the identification of the artefact. Unlike `urn`
+    /// this does not depend on the message containing a `self` link.
+    fn maintainable_urn(&self) -> Option<Urn> {
+        Some(Urn::new(
+            self.class(),
+            self.agency_id(),
+            self.resource_id(),
+            VersionReq::Exact(self.parsed_version()?),
+        ))
+    }
+}
+
+/// A maintainable artefact which is a list of items, such as a codelist
+pub trait ItemScheme: Maintainable {
+    type Item: Nameable;
+
+    /// The top level items of the scheme
+    fn items(&self) -> &[Self::Item];
+
+    /// Whether the scheme only contains a subset of its items
+    fn is_partial(&self) -> bool;
+
+    /// Finds a top level item by ID
+    fn item(&self, id: &str) -> Option<&Self::Item> {
+        self.items().iter().find(|i| i.id() == Some(id))
+    }
+}
+
+impl Data {
+    /// All maintainable artefacts in the message
+    pub fn maintainables(&self) -> Vec<&dyn Maintainable> {
+        fn add<'a, T: Maintainable>(
+            out: &mut Vec<&'a dyn Maintainable>,
+            list: &'a Option<Vec<T>>,
+        ) {
+            out.extend(list.iter().flatten().map(|a| a as &dyn Maintainable));
+        }
+
+        let mut out = vec![];
+        add(&mut out, &self.agency_schemes);
+        add(&mut out, &self.attachment_constraints);
+        add(&mut out, &self.categorisations);
+        add(&mut out, &self.category_schemes);
+        add(&mut out, &self.codelists);
+        add(&mut out, &self.concept_schemes);
+        add(&mut out, &self.content_constraints);
+        add(&mut out, &self.data_consumer_schemes);
+        add(&mut out, &self.dataflows);
+        add(&mut out, &self.data_provider_schemes);
+        add(&mut out, &self.data_structures);
+        add(&mut out, &self.hierarchical_codelists);
+        add(&mut out, &self.metadataflows);
+        add(&mut out, &self.metadata_structures);
+        add(&mut out, &self.organisation_unit_schemes);
+        add(&mut out, &self.processes);
+        add(&mut out, &self.provision_agreements);
+        add(&mut out, &self.reporting_taxonomies);
+        add(&mut out, &self.structure_sets);
+        out
+    }
+}
+
+/// Lets the macros read both required and optional IDs
+trait OptionalStr {
+    fn optional_str(&self) -> Option<&str>;
+}
+
+impl OptionalStr for String {
+    fn optional_str(&self) -> Option<&str> {
+        Some(self)
+    }
+}
+
+impl OptionalStr for Option<String> {
+    fn optional_str(&self) -> Option<&str> {
+        self.as_deref()
+    }
+}
+
+macro_rules! identifiable {
+    ($($t:ty),* $(,)?) => {$(
+        impl Identifiable for $t {
+            fn id(&self) -> Option<&str> {
+                self.id.optional_str()
+            }
+
+            fn annotations(&self) -> &[AnnotationType] {
+                self.annotations.as_deref().unwrap_or_default()
+            }
+
+            fn links(&self) -> &[Link] {
+                self.links.as_deref().unwrap_or_default()
+            }
+        }
+    )*};
+}
+
+macro_rules! nameable {
+    ($($t:ty),* $(,)?) => {$(
+        identifiable!($t);
+
+        impl Nameable for $t {
+            fn name(&self) -> &str {
+                &self.name
+            }
+
+            fn names(&self) -> Option<&LocalizedString> {
+                self.names.as_ref()
+            }
+
+            fn description(&self) -> Option<&str> {
+                self.description.as_deref()
+            }
+
+            fn descriptions(&self) -> Option<&LocalizedString> {
+                self.descriptions.as_ref()
+            }
+        }
+    )*};
+}
+
+macro_rules! maintainable {
+    ($($t:ty => $class:ident),* $(,)?) => {$(
+        nameable!($t);
+
+        impl Maintainable for $t {
+            fn class(&self) -> ObjectTypeCodelistType {
+                ObjectTypeCodelistType::$class
+            }
+
+            fn agency_id(&self) -> &str {
+                &self.agency_id
+            }
+
+            fn version(&self) -> Option<&str> {
+                self.version.as_deref()
+            }
+
+            fn is_final(&self) -> bool {
+                self.is_final.unwrap_or(false)
+            }
+
+            fn is_external_reference(&self) -> bool {
+                self.is_external_reference.unwrap_or(false)
+            }
+
+            fn valid_from(&self) -> Option<&str> {
+                self.valid_from.as_deref()
+            }
+
+            fn valid_to(&self) -> Option<&str> {
+                self.valid_to.as_deref()
+            }
+        }
+    )*};
+}
+
+macro_rules! item_scheme {
+    ($($t:ty => $item:ty, $items:ident);* $(;)?) => {$(
+        impl ItemScheme for $t {
+            type Item = $item;
+
+            fn items(&self) -> &[$item] {
+                self.$items.as_deref().unwrap_or_default()
+            }
+
+            fn is_partial(&self) -> bool {
+                self.is_partial.unwrap_or(false)
+            }
+        }
+    )*};
+}
+
+identifiable!(
+    AttributeListTypeClass,
+    AttributeType,
+    ConstraintContentTargetType,
+    DataSetTargetType,
+    DimensionListTypeClass,
+    DimensionType,
+    GroupTypeElement,
+    HierarchicalCodeTypeElement,
+    KeyDescriptorValuesTargetType,
+    MeasureDimensionType,
+    MeasureListTypeClass,
+    MetadataAttributeType,
+    MetadataTargetTypeElement,
+    PrimaryMeasureType,
+    ReportPeriodTargetType,
+    ReportStructureTypeElement,
+    ReportingYearStartDayType,
+    TimeDimensionType,
+    TransitionTypeElement,
+    structure::Identifiable,
+);
+
+nameable!(
+    AgencyType,
+    CategorySchemeMapTypeElement,
+    CategoryTypeElement,
+    CodeTypeElement,
+    CodelistMapTypeElement,
+    ConceptSchemeMapTypeElement,
+    ConceptType,
+    DataConsumerTypeElement,
+    DataProviderTypeElement,
+    HierarchyTypeElement,
+    HybridCodelistMapTypeElement,
+    LevelTypeClass,
+    OrganisationSchemeMapTypeElement,
+    OrganisationUnitTypeElement,
+    ProcessStepTypeElement,
+    ReportingCategoryTypeElement,
+    ReportingTaxonomyMapTypeElement,
+    StructureMapTypeElement,
+);
+
+maintainable!(
+    AgencySchemeTypeElement => AgencyScheme,
+    AttachmentConstraintTypeElement => AttachmentConstraint,
+    CategorisationTypeElement => Categorisation,
+    CategorySchemeType => CategoryScheme,
+    CodelistType => Codelist,
+    ConceptSchemeType => ConceptScheme,
+    ContentConstraintTypeElement => ContentConstraint,
+    DataConsumerSchemeTypeElement => DataConsumerScheme,
+    DataProviderSchemeTypeElement => DataProviderScheme,
+    DataStructureTypeElement => DataStructure,
+    DataflowTypeElement => Dataflow,
+    HierarchicalCodelistTypeElement => HierarchicalCodelist,
+    MetadataStructureTypeElement => MetadataStructure,
+    MetadataflowTypeElement => Metadataflow,
+    OrganisationUnitSchemeTypeElement => OrganisationUnitScheme,
+    ProcessTypeElement => Process,
+    ProvisionAgreementTypeElement => ProvisionAgreement,
+    ReportingTaxonomyTypeElement => ReportingTaxonomy,
+    StructureSetTypeElement => StructureSet,
+);
+
+item_scheme!(
+    AgencySchemeTypeElement => AgencyType, agencies;
+    CategorySchemeType => CategoryTypeElement, categories;
+    CodelistType => CodeTypeElement, codes;
+    ConceptSchemeType => ConceptType, concepts;
+    DataConsumerSchemeTypeElement => DataConsumerTypeElement, data_consumers;
+    DataProviderSchemeTypeElement => DataProviderTypeElement, data_providers;
+    OrganisationUnitSchemeTypeElement => OrganisationUnitTypeElement, organisation_units;
+    ReportingTaxonomyTypeElement => ReportingCategoryTypeElement, reporting_categories;
+);
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// What an index of artefacts would keep of any maintainable
+    fn summary(
+        artefact: &impl Maintainable,
+        languages: &LanguagePreferences,
+    ) -> (ObjectTypeCodelistType, String, String, String, bool) {
+        (
+            artefact.class(),
+            artefact.resource_id().to_string(),
+            artefact.label(languages).to_string(),
+            artefact.maintainable_urn().unwrap().to_string(),
+            artefact.is_final(),
+        )
+    }
+
+    #[test]
+    fn maintainables_are_handled_generically() {
+        let codelist: CodelistType = serde_json::from_value(json!({
+            "id": "CL_FREQ", "agencyID": "SDMX", "version": "2.1",
+            "name": "Frequency", "names": { "en": "Frequency", "de": "Frequenz" },
+            "isFinal": true,
+            "codes": [{ "id": "A", "name": "Annual" }]
+        }))
+        .unwrap();
+        let dataflow: DataflowTypeElement = serde_json::from_value(json!({
+            "id": "EXR", "agencyID": "ECB", "name": "Exchange Rates",
+            "structure": "urn:sdmx:org.sdmx.infomodel.datastructure.DataStructure=ECB:ECB_EXR1(1.0)"
+        }))
+        .unwrap();
+        let languages = LanguagePreferences::new(vec!["de"]);
+
+        assert_eq!(
+            summary(&codelist, &languages),
+            (
+                ObjectTypeCodelistType::Codelist,
+                "CL_FREQ".to_string(),
+                "Frequenz".to_string(),
+                "urn:sdmx:org.sdmx.infomodel.codelist.Codelist=SDMX:CL_FREQ(2.1)"
+                    .to_string(),
+                true,
+            )
+        );
+        // without a version the dataflow defaults to 1.0
+        assert_eq!(
+            summary(&dataflow, &languages),
+            (
+                ObjectTypeCodelistType::Dataflow,
+                "EXR".to_string(),
+                "Exchange Rates".to_string(),
+                "urn:sdmx:org.sdmx.infomodel.datastructure.Dataflow=ECB:EXR(1.0)"
+                    .to_string(),
+                false,
+            )
+        );
+        assert_eq!(codelist.item("A").unwrap().name(), "Annual");
+    }
+}
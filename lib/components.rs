@@ -0,0 +1,406 @@
+//! components gives a uniform view of the components of a data structure.
+//!
+//! SDMX-JSON spreads the components of a data structure definition over
+//! separate lists with different types. [`Component`] flattens them, with the
+//! effective ID of each component, which is inherited from its concept
+//! identity when it has no explicit ID.
+
+use serde::Serialize;
+
+use crate::{
+    structure::{
+        AttributeRelationshipType, DataStructureTypeElement,
+        SimpleComponentTextFormatType, SimpleDataStructureRepresentationType,
+        UsageStatusType,
+    },
+    urn::Urn,
+};
+
+/// Reserved ID of the primary measure
+pub const OBS_VALUE: &str = "OBS_VALUE";
+/// Reserved ID of the time dimension
+pub const TIME_PERIOD: &str = "TIME_PERIOD";
+/// Reserved ID of the reporting year start day attribute
+pub const REPORTING_YEAR_START_DAY: &str = "REPORTING_YEAR_START_DAY";
+
+/// The structural role of a component
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ComponentKind {
+    Dimension,
+    MeasureDimension,
+    TimeDimension,
+    Attribute,
+    ReportingYearStartDay,
+    PrimaryMeasure,
+    /// A measure besides the primary measure, which SDMX 3.0 allows
+    Measure,
+}
+
+impl ComponentKind {
+    pub fn is_dimension(&self) -> bool {
+        matches!(
+            self,
+            ComponentKind::Dimension
+                | ComponentKind::MeasureDimension
+                | ComponentKind::TimeDimension
+        )
+    }
+}
+
+/// The level of a data message an attribute is reported at
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AttachmentLevel {
+    DataSet,
+    Group,
+    Series,
+    Observation,
+}
+
+/// A component of a data structure definition
+#[derive(Debug, Clone)]
+pub struct Component<'a> {
+    pub kind: ComponentKind,
+    /// The effective ID of the component
+    pub id: String,
+    /// Whether the ID is explicit rather than inherited from the concept identity
+    pub explicit_id: bool,
+    pub position: Option<i64>,
+    pub concept_identity: &'a str,
+    /// URN of the codelist of coded components, or of the concept scheme
+    /// enumerating a measure dimension
+    pub enumeration: Option<&'a str>,
+    pub text_format: Option<&'a SimpleComponentTextFormatType>,
+    /// Only set for attributes
+    pub assignment_status: Option<&'a UsageStatusType>,
+    /// Only set for attributes
+    pub attribute_relationship: Option<&'a AttributeRelationshipType>,
+}
+
+impl<'a> Component<'a> {
+    fn new(
+        kind: ComponentKind,
+        id: Option<&'a String>,
+        concept_identity: &'a str,
+    ) -> Self {
+        Component {
+            kind,
+            id: component_id(id, concept_identity),
+            explicit_id: id.is_some(),
+            position: None,
+            concept_identity,
+            enumeration: None,
+            text_format: None,
+            assignment_status: None,
+            attribute_relationship: None,
+        }
+    }
+
+    fn with_representation(
+        mut self,
+        repr: Option<&'a SimpleDataStructureRepresentationType>,
+    ) -> Self {
+        self.enumeration = repr.and_then(|r| r.enumeration.as_deref());
+        self.text_format = repr.and_then(|r| r.text_format.as_ref());
+        self
+    }
+
+    pub fn is_mandatory(&self) -> bool {
+        matches!(self.assignment_status, Some(UsageStatusType::Mandatory))
+    }
+
+    /// The level an attribute is reported at, as given by its attribute
+    /// relationship. Attributes which depend on the time dimension or the
+    /// primary measure are observation level, attributes which depend on
+    /// other dimensions are series level. Returns `None` for dimensions and
+    /// the primary measure.
+    pub fn attachment_level(
+        &self,
+        time_dimension: Option<&str>,
+    ) -> Option<AttachmentLevel> {
+        let rel = self.attribute_relationship?;
+        let dimensions = rel.dimensions.as_deref().unwrap_or_default();
+        let level = if rel.primary_measure.is_some()
+            || dimensions
+                .iter()
+                .any(|d| Some(d.as_str()) == time_dimension)
+        {
+            AttachmentLevel::Observation
+        } else if rel.group.is_some() {
+            AttachmentLevel::Group
+        } else if !dimensions.is_empty() {
+            AttachmentLevel::Series
+        } else {
+            AttachmentLevel::DataSet
+        };
+        Some(level)
+    }
+}
+
+/// The ID of a component, which is inherited from its concept identity when not explicit
+pub fn component_id(id: Option<&String>, concept_identity: &str) -> String {
+    match id {
+        Some(id) => id.clone(),
+        None => concept_identity
+            .parse::<Urn>()
+            .ok()
+            .and_then(|u| u.item_ids.last().cloned())
+            .unwrap_or_else(|| concept_identity.to_string()),
+    }
+}
+
+impl DataStructureTypeElement {
+    /// The dimensions in the order of the series key. Dimensions are
+    /// ordered by position where it is given and otherwise by their order in
+    /// the message, with the time dimension last.
+    pub fn dimensions(&self) -> Vec<Component<'_>> {
+        let list = match &self.data_structure_components {
+            Some(c) => &c.dimension_list,
+            None => return vec![],
+        };
+        let mut out = vec![];
+        for d in list.dimensions.iter().flatten() {
+            let mut c = Component::new(
+                ComponentKind::Dimension,
+                d.id.as_ref(),
+                &d.concept_identity,
+            )
+            .with_representation(d.local_representation.as_ref());
+            c.position = d.position;
+            out.push(c);
+        }
+        for d in list.measure_dimensions.iter().flatten() {
+            let mut c = Component::new(
+                ComponentKind::MeasureDimension,
+                d.id.as_ref(),
+                &d.concept_identity,
+            );
+            c.position = d.position;
+            c.enumeration = Some(&d.local_representation.enumeration);
+            out.push(c);
+        }
+        // Stable, so dimensions without position keep their order
+        out.sort_by_key(|c| c.position.unwrap_or(i64::MAX));
+        for d in list.time_dimensions.iter().flatten() {
+            let mut c = Component::new(
+                ComponentKind::TimeDimension,
+                d.id.as_ref(),
+                &d.concept_identity,
+            );
+            c.position = d.position;
+            out.push(c);
+        }
+        out
+    }
+
+    /// The attributes, including the reporting year start day
+    pub fn attributes(&self) -> Vec<Component<'_>> {
+        let list = match self
+            .data_structure_components
+            .as_ref()
+            .and_then(|c| c.attribute_list.as_ref())
+        {
+            Some(l) => l,
+            None => return vec![],
+        };
+        let mut out = vec![];
+        for a in list.attributes.iter().flatten() {
+            let mut c = Component::new(
+                ComponentKind::Attribute,
+                a.id.as_ref(),
+                &a.concept_identity,
+            )
+            .with_representation(a.local_representation.as_ref());
+            c.assignment_status = Some(&a.assignment_status);
+            c.attribute_relationship = Some(&a.attribute_relationship);
+            out.push(c);
+        }
+        for a in list.reporting_year_start_days.iter().flatten() {
+            let mut c = Component::new(
+                ComponentKind::ReportingYearStartDay,
+                a.id.as_ref(),
+                &a.concept_identity,
+            );
+            c.assignment_status = Some(&a.assignment_status);
+            c.attribute_relationship = Some(&a.attribute_relationship);
+            out.push(c);
+        }
+        out
+    }
+
+    pub fn primary_measure(&self) -> Option<Component<'_>> {
+        let m = &self
+            .data_structure_components
+            .as_ref()?
+            .measure_list
+            .primary_measure;
+        Some(
+            Component::new(
+                ComponentKind::PrimaryMeasure,
+                m.id.as_ref(),
+                &m.concept_identity,
+            )
+            .with_representation(m.local_representation.as_ref()),
+        )
+    }
+
+    /// The primary measure followed by any other measures
+    pub fn measures(&self) -> Vec<Component<'_>> {
+        let primary = match self.primary_measure() {
+            Some(p) => p,
+            None => return vec![],
+        };
+        let others = self
+            .data_structure_components
+            .iter()
+            .flat_map(|c| c.measure_list.measures.iter().flatten())
+            .map(|m| {
+                Component::new(
+                    ComponentKind::Measure,
+                    m.id.as_ref(),
+                    &m.concept_identity,
+                )
+                .with_representation(m.local_representation.as_ref())
+            })
+            .filter(|m| m.id != primary.id)
+            .collect::<Vec<_>>();
+        let mut out = vec![primary];
+        out.extend(others);
+        out
+    }
+
+    /// All components: the dimensions in key order, the attributes and the measures
+    pub fn components(&self) -> Vec<Component<'_>> {
+        let mut out = self.dimensions();
+        out.extend(self.attributes());
+        out.extend(self.measures());
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn concept(id: &str) -> String {
+        format!(
+            "urn:sdmx:org.sdmx.infomodel.conceptscheme.Concept=A:CS(1.0).{}",
+            id
+        )
+    }
+
+    fn dsd() -> DataStructureTypeElement {
+        serde_json::from_value(json!({
+            "id": "DSD", "agencyID": "A", "version": "1.0", "name": "DSD",
+            "dataStructureComponents": {
+                "dimensionList": {
+                    "timeDimensions": [{
+                        "conceptIdentity": concept("TIME_PERIOD"),
+                        "localRepresentation": {
+                            "textFormat": { "textType": "ObservationalTimePeriod" }
+                        }
+                    }],
+                    "dimensions": [{
+                        "id": "AREA", "position": 2,
+                        "conceptIdentity": concept("AREA")
+                    }, {
+                        "position": 1, "conceptIdentity": concept("FREQ")
+                    }, {
+                        "id": "REF", "conceptIdentity": concept("REF")
+                    }]
+                },
+                "groups": [{ "id": "SIBLING", "groupDimensions": ["AREA"] }],
+                "attributeList": {
+                    "attributes": [{
+                        "id": "UNIT", "conceptIdentity": concept("UNIT"),
+                        "assignmentStatus": "Mandatory",
+                        "attributeRelationship": { "none": {} }
+                    }, {
+                        "id": "TITLE", "conceptIdentity": concept("TITLE"),
+                        "assignmentStatus": "Conditional",
+                        "attributeRelationship": { "dimensions": ["AREA"] }
+                    }, {
+                        "id": "COMMENT", "conceptIdentity": concept("COMMENT"),
+                        "assignmentStatus": "Conditional",
+                        "attributeRelationship": {
+                            "dimensions": ["AREA"], "group": "SIBLING"
+                        }
+                    }, {
+                        "id": "NOTE", "conceptIdentity": concept("NOTE"),
+                        "assignmentStatus": "Conditional",
+                        "attributeRelationship": {
+                            "dimensions": ["AREA", "TIME_PERIOD"]
+                        }
+                    }]
+                },
+                "measureList": {
+                    "primaryMeasure": {
+                        "conceptIdentity": concept("OBS_VALUE")
+                    },
+                    "measures": [
+                        { "conceptIdentity": concept("OBS_VALUE") },
+                        { "id": "PRICE", "conceptIdentity": concept("PRICE") }
+                    ]
+                }
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn ids_are_inherited_from_the_concept_identity() {
+        let id = "ID".to_string();
+        assert_eq!(component_id(Some(&id), &concept("FREQ")), "ID");
+        assert_eq!(component_id(None, &concept("FREQ")), "FREQ");
+        assert_eq!(component_id(None, "not a urn"), "not a urn");
+    }
+
+    #[test]
+    fn dimensions_are_in_key_order() {
+        let dsd = dsd();
+        let dimensions = dsd.dimensions();
+        let ids: Vec<_> = dimensions.iter().map(|d| d.id.as_str()).collect();
+        assert_eq!(ids, vec!["FREQ", "AREA", "REF", "TIME_PERIOD"]);
+        assert!(!dimensions[0].explicit_id);
+        assert_eq!(dimensions[3].kind, ComponentKind::TimeDimension);
+        assert!(dimensions.iter().all(|d| d.kind.is_dimension()));
+    }
+
+    #[test]
+    fn attributes_are_attached_by_their_relationship() {
+        let dsd = dsd();
+        let levels: Vec<_> = dsd
+            .attributes()
+            .iter()
+            .map(|a| (a.id.clone(), a.attachment_level(Some(TIME_PERIOD))))
+            .collect();
+        assert_eq!(
+            levels,
+            vec![
+                ("UNIT".to_string(), Some(AttachmentLevel::DataSet)),
+                ("TITLE".to_string(), Some(AttachmentLevel::Series)),
+                ("COMMENT".to_string(), Some(AttachmentLevel::Group)),
+                ("NOTE".to_string(), Some(AttachmentLevel::Observation)),
+            ]
+        );
+        assert!(dsd.attributes()[0].is_mandatory());
+        assert!(!dsd.attributes()[1].is_mandatory());
+        assert_eq!(dsd.dimensions()[0].attachment_level(None), None);
+    }
+
+    #[test]
+    fn measures_start_with_the_primary_measure() {
+        let dsd = dsd();
+        let measures: Vec<_> =
+            dsd.measures().into_iter().map(|m| (m.kind, m.id)).collect();
+        assert_eq!(
+            measures,
+            vec![
+                (ComponentKind::PrimaryMeasure, "OBS_VALUE".to_string()),
+                (ComponentKind::Measure, "PRICE".to_string()),
+            ]
+        );
+        assert_eq!(dsd.components().len(), 10);
+    }
+}
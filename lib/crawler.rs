@@ -1,25 +1,63 @@
-use http::{HeaderMap, HeaderValue};
-use reqwest::Client;
+use futures::{stream, StreamExt};
+use http::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Certificate, Client, Proxy};
 
 use crate::{
+    frontier::{Frontier, Task},
+    metrics::Metrics,
     minimal_structure::Dataflow,
-    queries::metadata_query,
+    parse::{parse_structure, ParseMode, Parsed},
+    queries::{DataQuery, RestVersion, StructureQuery},
+    report::{elapsed_ms, CrawlReport, RequestReport, StageReport},
     reqwest_layer::Response,
     reqwest_warc::write_warc,
-    structure::{Data, Structure},
+    robots::RobotsTxt,
+    sdmx_sources::{seconds, Source},
+    search::SearchIndex,
+    store::{Provenance, Store},
+    structure::{Data, Meta},
+    structure_v2::SchemaVersion,
+    version::VersionReq,
 };
 use anyhow::{anyhow, Context, Result};
-use std::{any::Any, time::Instant};
+use chrono::{DateTime, Utc};
+use std::{
+    any::Any,
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 use std::{convert::TryFrom, string::ToString};
+use tracing::{debug, info, info_span, warn, Instrument};
 use url::Url;
 
+/// How long an idle worker waits before it asks the frontier again
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a robots.txt is used before it is fetched again
+const ROBOTS_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How long an unreachable robots.txt stops the crawl of its host before it
+/// is tried again
+const ROBOTS_FAILURE_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// The robots.txt of a host and when it is to be fetched again
+struct CachedRobots {
+    expires: Instant,
+    robots: Arc<RobotsTxt>,
+}
+
+/// The cached robots.txt of one host, locked while it is fetched so that
+/// concurrent requests to the host wait for one fetch
+type RobotsEntry = Arc<tokio::sync::Mutex<Option<CachedRobots>>>;
+
 async fn make_request(
+    client: &Client,
     req_url: Url,
     headers: Option<HeaderMap<HeaderValue>>,
     warc_write: bool,
 ) -> Result<Response> {
     let start = Instant::now();
-    let client = Client::new();
     let req = client
         .get(req_url.clone())
         .headers(headers.unwrap_or_default())
@@ -29,14 +67,21 @@ async fn make_request(
         .await?;
     let duration = start.elapsed();
 
-    let res = Response::parse(resp).await?;
+    let mut res = Response::parse(resp).await?;
 
     if warc_write {
         // TODO: make more performant by removing clone
-        write_warc(req, res.clone()).await?;
+        let (record_id, bytes) = write_warc(req, res.clone()).await?;
+        res.warc_record_id = Some(record_id);
+        res.warc_bytes = Some(bytes);
     }
 
-    println!("Req {:} duration {:?}, write dur", req_url, duration);
+    debug!(
+        url = %req_url,
+        status = res.status.as_u16(),
+        duration_ms = duration.as_millis() as u64,
+        "Request finished"
+    );
     Ok(res)
 }
 // pub struct Agent {}
@@ -44,11 +89,27 @@ async fn make_request(
 
 trait Stage {
     fn name(&self) -> String;
-    /// Get the URI relative to the base URL to request
+    /// Get the URI relative to the base URL to request, in the grammar of
+    /// the `rest` API version.
     /// `prior` is any prior stage data relevant to this one, serialized as JSON
-    fn get_uri(&self, prior: Vec<String>) -> Result<Vec<String>>;
+    fn get_uri(
+        &self,
+        rest: RestVersion,
+        prior: Vec<String>,
+    ) -> Result<Vec<String>>;
+
+    /// Extracts the results for the next stage from the parsed response
+    fn extract_relevant(&self, data: Data) -> Result<Vec<String>>;
 
-    fn extract_relevant(&self, res: Response) -> Result<Vec<String>>;
+    /// The results for the next stage when the response hasn't changed
+    /// since an earlier crawl, taken from what that crawl stored
+    fn extract_stored(
+        &self,
+        _store: &Store,
+        _source_id: &str,
+    ) -> Result<Vec<String>> {
+        Ok(vec![])
+    }
 }
 
 fn validate_get_body(res: &Response) -> Result<&String> {
@@ -70,26 +131,45 @@ fn validate_get_body(res: &Response) -> Result<&String> {
     Ok(bd)
 }
 
+/// Parses an SDMX-JSON 1.0 or 2.0 structure message body leniently,
+/// repairing the deviations from the schema that are common among providers.
+/// Returns the structures and the meta of the message.
+fn parse_structure_data(
+    res: &Response,
+) -> Result<(Parsed<Data>, Option<Meta>)> {
+    let bd = validate_get_body(res)?;
+    let version = res
+        .headers
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|ct| ct.to_str().ok())
+        .and_then(SchemaVersion::from_content_type);
+    let parsed = parse_structure(bd, version, ParseMode::Lenient)
+        .context(anyhow!("Failed to parse JSON from response {:}", &res.url))?;
+    let data = parsed
+        .value
+        .data
+        .ok_or_else(|| anyhow!("No data in response {:}", &res.url))?;
+    let structures = Parsed {
+        value: data,
+        version: parsed.version,
+        diagnostics: parsed.diagnostics,
+    };
+    Ok((structures, parsed.value.meta))
+}
+
 struct DataflowStage {}
 
 impl Stage for DataflowStage {
-    fn get_uri(&self, prior: Vec<String>) -> Result<Vec<String>> {
-        let paths = ["dataflow", "all", "all", "latest"].to_vec();
-        Ok(vec![metadata_query(paths)])
+    fn get_uri(
+        &self,
+        rest: RestVersion,
+        _prior: Vec<String>,
+    ) -> Result<Vec<String>> {
+        Ok(vec![StructureQuery::new("dataflow").path(rest)?])
     }
 
-    fn extract_relevant(&self, res: Response) -> Result<Vec<String>> {
-        let bd = validate_get_body(&res)?;
-        let body = bd.as_str().trim().trim_start_matches('\u{feff}');
-        println!("{}", body);
-        let s: Data = serde_json::from_str(body).context(anyhow!(
-            "Failed to parse JSON from response {:}",
-            &res.url
-        ))?;
-
-        println!("{:#?}", s);
-
-        let df = s
+    fn extract_relevant(&self, data: Data) -> Result<Vec<String>> {
+        let df = data
             // .data
             // .ok_or(anyhow!("missing data"))?
             .dataflows
@@ -97,22 +177,115 @@ impl Stage for DataflowStage {
 
         let out: Vec<_> = df
             .into_iter()
-            .map(|d| Dataflow {
-                resource_id: d.id,
-                agency_id: d.agency_id,
-                name: d.name,
-                version: d.version,
+            .map(|d| {
+                let version = d.version.as_deref().and_then(|v| {
+                    v.parse()
+                        .map_err(|e| {
+                            warn!(
+                                agency = %d.agency_id,
+                                dataflow = %d.id,
+                                version = v,
+                                error = %e,
+                                "Unparsable dataflow version, requesting \
+                                 the latest version instead"
+                            )
+                        })
+                        .ok()
+                });
+                Dataflow {
+                    resource_id: d.id,
+                    agency_id: d.agency_id,
+                    name: d.name,
+                    version,
+                    description: d.description,
+                    structure: d.structure,
+                }
             })
             .filter_map(|d| serde_json::to_string(&d).ok())
             .collect();
         return Ok(out);
     }
 
+    fn extract_stored(
+        &self,
+        store: &Store,
+        source_id: &str,
+    ) -> Result<Vec<String>> {
+        store
+            .dataflows(source_id)?
+            .iter()
+            .map(|d| Ok(serde_json::to_string(d)?))
+            .collect()
+    }
+
     fn name(&self) -> String {
         "dataflow".to_string()
     }
 }
 
+/// Requests each dataflow found by the DataflowStage along with its
+/// descendants: the data structure, codelists and concept schemes
+struct DataflowDescendantsStage {}
+
+impl Stage for DataflowDescendantsStage {
+    fn get_uri(
+        &self,
+        rest: RestVersion,
+        prior: Vec<String>,
+    ) -> Result<Vec<String>> {
+        prior
+            .iter()
+            .filter_map(|p| serde_json::from_str::<Dataflow>(p).ok())
+            .map(|d| {
+                let version = d
+                    .version
+                    .map(VersionReq::Exact)
+                    .unwrap_or(VersionReq::Latest);
+                StructureQuery::new("dataflow")
+                    .with_agency(d.agency_id)
+                    .with_id(d.resource_id)
+                    .with_version(version)
+                    .with_references("descendants")
+                    .path(rest)
+            })
+            .collect()
+    }
+
+    fn extract_relevant(&self, _data: Data) -> Result<Vec<String>> {
+        // The artefacts are persisted by the store
+        Ok(vec![])
+    }
+
+    fn name(&self) -> String {
+        "dataflow-descendants".to_string()
+    }
+}
+
+/// Requests all category schemes along with the categorisations that
+/// reference them, which are used to group dataflows by topic
+struct CategorisationStage {}
+
+impl Stage for CategorisationStage {
+    fn get_uri(
+        &self,
+        rest: RestVersion,
+        _prior: Vec<String>,
+    ) -> Result<Vec<String>> {
+        let query = StructureQuery::new("categoryscheme")
+            .with_references("categorisation");
+        Ok(vec![query.path(rest)?])
+    }
+
+    fn extract_relevant(&self, _data: Data) -> Result<Vec<String>> {
+        // The artefacts are persisted by the store
+        Ok(vec![])
+    }
+
+    fn name(&self) -> String {
+        "categorisation".to_string()
+    }
+}
+
 pub struct Crawler {
     name: String,
     version: String,
@@ -121,6 +294,28 @@ pub struct Crawler {
 
     // base_url: Option<String>,
     stages: Vec<Box<dyn Stage>>,
+    store: Option<Store>,
+    index: Option<SearchIndex>,
+    metrics: Option<Metrics>,
+    /// The robots.txt of each host, by origin
+    robots: Mutex<HashMap<String, RobotsEntry>>,
+    /// When each host may be requested next, by origin
+    next_request: Mutex<HashMap<String, Instant>>,
+    /// The HTTP client of each source, by ID
+    clients: Mutex<HashMap<String, Client>>,
+}
+
+/// Where providers can find out about the crawler, unless the operator
+/// gives their own contact
+const DEFAULT_CONTACT: &str = "https://github.com/alexkreidler/sdmx-rust";
+
+/// A user agent in the form of `Mozilla/5.0 (compatible; Googlebot/2.1;
+/// +http://www.google.com/bot.html)`
+fn user_agent(name: &str, version: &str, contact: &str) -> String {
+    format!(
+        "Mozilla/5.0 (compatible; {}/{}; +{})",
+        name, version, contact
+    )
 }
 
 impl Default for Crawler {
@@ -128,75 +323,693 @@ impl Default for Crawler {
         let name = "sdmxblaze".to_string();
         let version = "1.0".to_string();
         Crawler {
-            name: name.clone(),
-            version: version.clone(),
-            // Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)
-            // Mozilla/5.0 (compatible; {}/{})
-            user_agent: format!(
-                "Mozilla/5.0 (compatible; {}/{})",
-                name, version
-            ),
+            user_agent: user_agent(&name, &version, DEFAULT_CONTACT),
+            name,
+            version,
             warc_write: true,
             // base_url: None,
-            stages: vec![Box::new(DataflowStage {})],
+            stages: vec![
+                Box::new(DataflowStage {}),
+                Box::new(DataflowDescendantsStage {}),
+                Box::new(CategorisationStage {}),
+            ],
+            store: None,
+            index: None,
+            metrics: None,
+            robots: Mutex::new(HashMap::new()),
+            next_request: Mutex::new(HashMap::new()),
+            clients: Mutex::new(HashMap::new()),
         }
     }
 }
 
 impl Crawler {
-    pub async fn crawl(&self, endpoint: String) -> Result<()> {
+    /// Persists every parsed structure message into the given store
+    pub fn with_store(mut self, store: Store) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Adds every parsed dataflow to the given search index
+    pub fn with_index(mut self, index: SearchIndex) -> Self {
+        self.index = Some(index);
+        self
+    }
+
+    /// Counts every request in the given metrics
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Replaces the URL in the user agent, where providers can learn about
+    /// the crawl or reach its operator, with the given one
+    pub fn with_contact(mut self, contact: &str) -> Self {
+        self.user_agent = user_agent(&self.name, &self.version, contact);
+        self
+    }
+
+    /// Crawls a source through all stages. Failed requests are recorded in
+    /// the report and don't stop the crawl.
+    pub async fn crawl(&self, source: &Source) -> Result<CrawlReport> {
+        let span = info_span!("crawl", source = %source.id);
+        self.crawl_source(source, None).instrument(span).await
+    }
+
+    /// Crawls a source through all stages like `crawl`, but only fetches
+    /// the structures that changed since the given time. Every request is
+    /// conditional on `If-Modified-Since`, and a response that is `304 Not
+    /// Modified` leaves the structures of earlier crawls in the store. The
+    /// next stage then carries on from those, e.g. with the stored
+    /// dataflows. Without a store the crawl is a full one.
+    pub async fn crawl_since(
+        &self,
+        source: &Source,
+        since: DateTime<Utc>,
+    ) -> Result<CrawlReport> {
+        let span = info_span!("crawl", source = %source.id);
+        let since = match self.store {
+            Some(_) => Some(since),
+            None => {
+                warn!(
+                    source = %source.id,
+                    "No store to keep unchanged structures in, crawling all"
+                );
+                None
+            }
+        };
+        self.crawl_source(source, since).instrument(span).await
+    }
+
+    /// Requests data of a source and returns the body of the response. The
+    /// request uses the client, headers and politeness settings of the
+    /// source, and asks for the format of the query, e.g. the data format
+    /// the source prefers (see `DataQuery::for_source`), else any format.
+    pub async fn fetch_data(
+        &self,
+        source: &Source,
+        query: &DataQuery,
+    ) -> Result<String> {
+        let base_url = Url::parse(&format!("{}/", source.url))?;
+        let req_url = base_url.join(&query.path(source.rest_version())?)?;
+        self.wait_politely(source, &req_url, None).await?;
+        let accept = query.format.as_deref().unwrap_or("*/*");
+        let res = make_request(
+            &self.client(source)?,
+            req_url,
+            Some(self.headers(source, accept)?),
+            self.warc_write,
+        )
+        .await?;
+        if !res.status.is_success() {
+            return Err(anyhow!("Response status {}", res.status));
+        }
+        Ok(res.body.unwrap_or_default())
+    }
+
+    /// Detects the REST API grammar spoken by a source by requesting stubs
+    /// of all dataflows with the 3.0 grammar, then with the 2.1 grammar.
+    /// Sources that answer both are reported as 3.0. The requests use the
+    /// client, headers and politeness settings of the source.
+    pub async fn detect_rest_version(
+        &self,
+        source: &Source,
+    ) -> Result<RestVersion> {
+        let base_url = Url::parse(format!("{}/", source.url).as_str())?;
+        for rest in [RestVersion::V3_0, RestVersion::V2_1].iter() {
+            let query = StructureQuery::new("dataflow").with_detail("allstubs");
+            let req_url = base_url.join(query.path(*rest)?.as_str())?;
+            let res = match self.wait_politely(source, &req_url, None).await {
+                Ok(()) => {
+                    make_request(
+                        &self.client(source)?,
+                        req_url,
+                        Some(self.headers(source, source.structure_format())?),
+                        false,
+                    )
+                    .await
+                }
+                Err(e) => Err(e),
+            };
+            match res {
+                Ok(res) if res.status.is_success() => return Ok(*rest),
+                Ok(_) => continue,
+                Err(e) => warn!(
+                    source = %source.id,
+                    error = %format!("{:#}", e),
+                    "Failed to detect REST version"
+                ),
+            }
+        }
+        Err(anyhow!(
+            "{} does not answer SDMX REST 2.1 or 3.0 queries",
+            source.url
+        ))
+    }
+
+    async fn crawl_source(
+        &self,
+        source: &Source,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<CrawlReport> {
+        let endpoint = format!("{}/", &source.url);
         let base_url = Url::parse(
-            endpoint.as_str()
-            // self.base_url
-            //     .clone()
-            //     .ok_or_else(|| {
-            //         anyhow!(
-            //             "You must provide a base URL before starting to crawl"
-            //         )
-            //     })?
-            //     .as_str(),
+            endpoint.as_str(), // self.base_url
+                               //     .clone()
+                               //     .ok_or_else(|| {
+                               //         anyhow!(
+                               //             "You must provide a base URL before starting to crawl"
+                               //         )
+                               //     })?
+                               //     .as_str(),
         )?;
-        println!("Starting {} crawler version {}", self.name, self.version);
+        info!(
+            crawler = %self.name,
+            version = %self.version,
+            url = %source.url,
+            since = ?since.map(|s| s.to_rfc3339()),
+            "Starting crawl"
+        );
+        let start = Instant::now();
+        let mut report = CrawlReport::new(&source.id, source.rest_version());
 
         let mut prior_data = vec!["".to_string()];
         for stage in &self.stages {
-            println!("Starting stage {}", stage.name());
-
-            let urls = stage.get_uri(prior_data.clone())?;
-
-            prior_data.clear();
-            for relative_url in urls {
-                let req_url = base_url.join(relative_url.as_str())?;
-
-                let res = make_request(
-                    req_url,
-                    {
-                        let mut hm = HeaderMap::new();
-                        hm.insert(
-                            "Accept",
-                            "application/json".parse().unwrap(),
-                        );
-                        hm.insert(
-                            "User-Agent",
-                            (&self.user_agent).parse().unwrap(),
-                        );
-                        Some(hm)
-                    },
-                    self.warc_write,
-                )
+            let span = info_span!("stage", stage = %stage.name());
+            let (stage_report, out) = self
+                .run_stage(source, stage.as_ref(), &base_url, prior_data, since)
+                .instrument(span)
                 .await?;
+            prior_data = out;
+            report.stages.push(stage_report);
+        }
+        if let Some(index) = &self.index {
+            index.commit()?;
+        }
+        report.duration_ms = elapsed_ms(start);
+        info!(
+            requests = report.requests(),
+            failed = report.errors(),
+            duration_ms = report.duration_ms,
+            "Finished crawl"
+        );
+        Ok(report)
+    }
+
+    /// Requests the URLs of a stage, returning its report and the results
+    /// for the next stage
+    async fn run_stage(
+        &self,
+        source: &Source,
+        stage: &dyn Stage,
+        base_url: &Url,
+        prior: Vec<String>,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<(StageReport, Vec<String>)> {
+        info!("Starting stage");
+        let start = Instant::now();
+        let mut report = StageReport::new(&stage.name());
+        let mut out = vec![];
+
+        let mut urls = vec![];
+        for relative_url in stage.get_uri(source.rest_version(), prior)? {
+            urls.push(base_url.join(relative_url.as_str())?);
+        }
+        // Up to `concurrency` requests at once, handled in the order of the
+        // URLs
+        let mut requests = stream::iter(urls)
+            .map(|req_url| self.request(source, stage, req_url, None, since))
+            .buffered(source.concurrency.unwrap_or(1).max(1));
+        while let Some((request, mut results)) = requests.next().await {
+            out.append(&mut results);
+            report.requests.push(request);
+        }
+
+        report.results = out.len();
+        report.duration_ms = elapsed_ms(start);
+        info!(
+            requests = report.requests.len(),
+            failed =
+                report.requests.iter().filter(|r| r.error.is_some()).count(),
+            results = report.results,
+            duration_ms = report.duration_ms,
+            "Finished stage"
+        );
+        Ok((report, out))
+    }
+
+    /// Requests one URL of a stage, returning its report and the results for
+    /// the next stage, which are empty if the request failed. The crawl delay
+    /// is shared with the other workers of a frontier, if given. With
+    /// `since` the request is conditional, see `crawl_since`.
+    async fn request(
+        &self,
+        source: &Source,
+        stage: &dyn Stage,
+        req_url: Url,
+        frontier: Option<&dyn Frontier>,
+        since: Option<DateTime<Utc>>,
+    ) -> (RequestReport, Vec<String>) {
+        let mut request = RequestReport::new(req_url.as_str());
+        let span = info_span!("request", url = %req_url);
+        let results = async {
+            // The crawl delay does not count towards the duration
+            let waited = self.wait_politely(source, &req_url, frontier).await;
+            let request_start = Instant::now();
+            let result = match waited {
+                Ok(()) => {
+                    self.fetch(source, stage, req_url, since, &mut request)
+                        .await
+                }
+                Err(e) => Err(e),
+            };
+            request.duration_ms = elapsed_ms(request_start);
+            match result {
+                Ok(results) => {
+                    info!(
+                        status = request.status,
+                        duration_ms = request.duration_ms,
+                        bytes = request.bytes,
+                        "Fetched"
+                    );
+                    results
+                }
+                Err(e) => {
+                    let error = format!("{:#}", e);
+                    warn!(
+                        status = request.status,
+                        duration_ms = request.duration_ms,
+                        error = %error,
+                        "Request failed"
+                    );
+                    request.error = Some(error);
+                    vec![]
+                }
+            }
+        }
+        .instrument(span)
+        .await;
+        if let Some(metrics) = &self.metrics {
+            metrics.observe(&source.id, &request);
+        }
+        (request, results)
+    }
+
+    /// Queues the first requests of a source in a distributed crawl (see
+    /// `work`). Returns how many were new to the frontier.
+    pub fn seed(
+        &self,
+        frontier: &dyn Frontier,
+        source: &Source,
+    ) -> Result<usize> {
+        let tasks = self.next_tasks(source, 0, vec!["".to_string()])?;
+        let queued = frontier.push(&tasks)?;
+        info!(source = %source.id, queued, "Seeded frontier");
+        Ok(queued)
+    }
+
+    /// Claims and requests tasks of a distributed crawl until the frontier
+    /// is drained, queueing the URLs each response leads to. Any number of
+    /// workers can share a frontier. Returns a report per source with the
+    /// requests of this worker.
+    pub async fn work(
+        &self,
+        frontier: &dyn Frontier,
+        sources: &[Source],
+        lease: Duration,
+    ) -> Result<Vec<CrawlReport>> {
+        info!(crawler = %self.name, version = %self.version, "Starting worker");
+        let start = Instant::now();
+        let mut reports: Vec<CrawlReport> = vec![];
+        loop {
+            let claimed = match frontier.claim(lease)? {
+                Some(claimed) => claimed,
+                None if frontier.stats()?.is_drained() => break,
+                None => {
+                    // Other workers may still queue tasks or leave leases
+                    // to expire
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                    continue;
+                }
+            };
+            let task = &claimed.task;
+            let source = sources.iter().find(|s| s.id == task.source_id);
+            let stage = self.stages.iter().position(|s| s.name() == task.stage);
+            let (source, stage) = match (source, stage) {
+                (Some(source), Some(stage)) => (source, stage),
+                _ => {
+                    warn!(
+                        source = %task.source_id,
+                        stage = %task.stage,
+                        "Skipping task of an unknown source or stage"
+                    );
+                    frontier.complete(&claimed)?;
+                    continue;
+                }
+            };
+
+            let span = info_span!("crawl", source = %source.id);
+            let (request, results) = async {
+                let span = info_span!("stage", stage = %task.stage);
+                let url = Url::parse(&task.url)?;
+                let (request, results) = self
+                    .request(
+                        source,
+                        self.stages[stage].as_ref(),
+                        url,
+                        Some(frontier),
+                        None,
+                    )
+                    .instrument(span)
+                    .await;
+                let count = results.len();
+                // Queue the next URLs before completing, so the frontier is
+                // never drained while they are missing
+                let next = self.next_tasks(source, stage + 1, results)?;
+                frontier.push(&next)?;
+                if !frontier.complete(&claimed)? {
+                    warn!(
+                        url = %task.url,
+                        attempt = claimed.attempt,
+                        "Lease expired, another worker claimed the task"
+                    );
+                }
+                Ok::<_, anyhow::Error>((request, count))
+            }
+            .instrument(span)
+            .await?;
+
+            let report = stage_report(&mut reports, source, &task.stage);
+            report.duration_ms += request.duration_ms;
+            report.results += results;
+            report.requests.push(request);
+        }
+        if let Some(index) = &self.index {
+            index.commit()?;
+        }
+        for report in &mut reports {
+            report.duration_ms = elapsed_ms(start);
+        }
+        info!(
+            requests = reports.iter().map(|r| r.requests()).sum::<usize>(),
+            failed = reports.iter().map(|r| r.errors()).sum::<usize>(),
+            duration_ms = elapsed_ms(start),
+            "Frontier drained"
+        );
+        Ok(reports)
+    }
 
-                let mut out = stage.extract_relevant(res)?;
-                prior_data.append(&mut out);
+    /// The tasks of the first stage from `stage` on that has any URLs, given
+    /// the results of the stage before
+    fn next_tasks(
+        &self,
+        source: &Source,
+        mut stage: usize,
+        mut prior: Vec<String>,
+    ) -> Result<Vec<Task>> {
+        let base_url = Url::parse(&format!("{}/", &source.url))?;
+        while let Some(next) = self.stages.get(stage) {
+            let urls = next.get_uri(source.rest_version(), prior)?;
+            if !urls.is_empty() {
+                return urls
+                    .iter()
+                    .map(|url| {
+                        Ok(Task {
+                            source_id: source.id.clone(),
+                            stage: next.name(),
+                            url: base_url.join(url)?.to_string(),
+                        })
+                    })
+                    .collect();
             }
-            println!(
-                "Final output for stage {}: {:#?}",
-                stage.name(),
-                prior_data
+            stage += 1;
+            prior = vec![];
+        }
+        Ok(vec![])
+    }
+
+    /// Requests one URL of a stage, persists the structures in the response
+    /// and extracts the results for the next stage. If the request is
+    /// conditional and the response unchanged, the results are extracted
+    /// from the store instead.
+    async fn fetch(
+        &self,
+        source: &Source,
+        stage: &dyn Stage,
+        req_url: Url,
+        since: Option<DateTime<Utc>>,
+        report: &mut RequestReport,
+    ) -> Result<Vec<String>> {
+        let mut headers = self.headers(source, source.structure_format())?;
+        if let Some(since) = since {
+            let date = since.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+            headers.insert(http::header::IF_MODIFIED_SINCE, date.parse()?);
+        }
+        let res = make_request(
+            &self.client(source)?,
+            req_url,
+            Some(headers),
+            self.warc_write,
+        )
+        .await?;
+        report.status = Some(res.status.as_u16());
+        report.bytes = res.body.as_ref().map(|b| b.len() as u64);
+        report.warc_record_id = res.warc_record_id.clone();
+        report.warc_bytes = res.warc_bytes;
+        if since.is_some() && res.status == http::StatusCode::NOT_MODIFIED {
+            return match &self.store {
+                Some(store) => stage.extract_stored(store, &source.id),
+                None => Ok(vec![]),
+            };
+        }
+        if !res.status.is_success() {
+            return Err(anyhow!("Response status {}", res.status));
+        }
+
+        let (parsed, meta) = parse_structure_data(&res)?;
+        report.repairs = parsed.diagnostics.len();
+        for d in &parsed.diagnostics {
+            debug!(diagnostic = %d, "Repaired response");
+        }
+        if let Some(store) = &self.store {
+            let provenance = Provenance {
+                source_id: source.id.clone(),
+                fetched_at: Utc::now(),
+                warc_record_id: res.warc_record_id.clone(),
+                content_languages: meta
+                    .and_then(|m| m.content_languages)
+                    .unwrap_or_default(),
+            };
+            store.save(&parsed.value, &provenance)?;
+        }
+        if let Some(index) = &self.index {
+            index.add(&source.id, &parsed.value)?;
+        }
+
+        stage.extract_relevant(parsed.value)
+    }
+
+    /// Checks that the robots.txt of the host allows the URL, unless the
+    /// source says to ignore it, and waits until the host may be requested
+    /// again by this crawler or, given a frontier, by any of its workers
+    async fn wait_politely(
+        &self,
+        source: &Source,
+        url: &Url,
+        frontier: Option<&dyn Frontier>,
+    ) -> Result<()> {
+        let politeness = source.politeness.clone().unwrap_or_default();
+        let host = url.origin().ascii_serialization();
+        let robots = match politeness.robots_txt.unwrap_or(true) {
+            true => Some(self.robots_txt(source, url).await?),
+            false => None,
+        };
+        if let Some(robots) = &robots {
+            let path = match url.query() {
+                Some(query) => format!("{}?{}", url.path(), query),
+                None => url.path().to_string(),
+            };
+            if !robots.is_allowed(&self.name, &path) {
+                return Err(anyhow!(
+                    "Disallowed by the robots.txt of {}",
+                    host
+                ));
+            }
+        }
+
+        let crawl_delay = match politeness.crawl_delay {
+            Some(delay) => seconds(delay),
+            None => robots
+                .and_then(|r| r.crawl_delay(&self.name))
+                .unwrap_or_default(),
+        };
+        let rate_interval = politeness.rate_interval().unwrap_or_default();
+        let delay = crawl_delay.max(rate_interval);
+        let wait = match frontier {
+            Some(frontier) => frontier.reserve(&host, delay)?,
+            None => {
+                let now = Instant::now();
+                let mut next_request = self.next_request.lock().unwrap();
+                let at = next_request.get(&host).map_or(now, |t| now.max(*t));
+                next_request.insert(host, at + delay);
+                at - now
+            }
+        };
+        if wait > Duration::ZERO {
+            debug!(
+                wait_ms = wait.as_millis() as u64,
+                "Waiting for the crawl delay"
             );
+            tokio::time::sleep(wait).await;
         }
         Ok(())
     }
+
+    /// The robots.txt of the host of a URL, fetched and archived once a day,
+    /// or again after a few minutes if it was unreachable
+    async fn robots_txt(
+        &self,
+        source: &Source,
+        url: &Url,
+    ) -> Result<Arc<RobotsTxt>> {
+        let host = url.origin().ascii_serialization();
+        let entry =
+            self.robots.lock().unwrap().entry(host).or_default().clone();
+        let mut cached = entry.lock().await;
+        if let Some(c) = cached.as_ref() {
+            if Instant::now() < c.expires {
+                return Ok(c.robots.clone());
+            }
+        }
+
+        let robots_url = url.join("/robots.txt")?;
+        let res = make_request(
+            &self.client(source)?,
+            robots_url.clone(),
+            Some(self.headers(source, "text/plain")?),
+            self.warc_write,
+        )
+        .await;
+        // As in RFC 9309, a missing robots.txt allows everything and an
+        // unreachable one nothing
+        let (robots, ttl) = match res {
+            Ok(res) if res.status.is_success() => (
+                RobotsTxt::parse(res.body.as_deref().unwrap_or_default()),
+                ROBOTS_TTL,
+            ),
+            Ok(res) if res.status.is_client_error() => {
+                (RobotsTxt::allow_all(), ROBOTS_TTL)
+            }
+            Ok(res) => {
+                warn!(
+                    url = %robots_url,
+                    status = res.status.as_u16(),
+                    retry_s = ROBOTS_FAILURE_TTL.as_secs(),
+                    "Unreachable robots.txt, not crawling the host"
+                );
+                (RobotsTxt::disallow_all(), ROBOTS_FAILURE_TTL)
+            }
+            Err(e) => {
+                warn!(
+                    url = %robots_url,
+                    error = %format!("{:#}", e),
+                    retry_s = ROBOTS_FAILURE_TTL.as_secs(),
+                    "Unreachable robots.txt, not crawling the host"
+                );
+                (RobotsTxt::disallow_all(), ROBOTS_FAILURE_TTL)
+            }
+        };
+        debug!(
+            url = %robots_url,
+            crawl_delay = ?robots.crawl_delay(&self.name),
+            "Fetched robots.txt"
+        );
+        let robots = Arc::new(robots);
+        *cached = Some(CachedRobots {
+            expires: Instant::now() + ttl,
+            robots: robots.clone(),
+        });
+        Ok(robots)
+    }
+
+    /// The HTTP client for a source, with its timeout, proxy and TLS
+    /// settings
+    fn client(&self, source: &Source) -> Result<Client> {
+        if let Some(client) = self.clients.lock().unwrap().get(&source.id) {
+            return Ok(client.clone());
+        }
+        let mut builder = Client::builder();
+        if let Some(timeout) = source.timeout {
+            builder = builder.timeout(seconds(timeout));
+        }
+        if let Some(proxy) = &source.proxy {
+            builder = builder.proxy(Proxy::all(proxy)?);
+        }
+        if let Some(tls) = &source.tls {
+            if let Some(path) = &tls.ca_cert {
+                let pem = std::fs::read(path)
+                    .with_context(|| format!("Failed to read {}", path))?;
+                builder =
+                    builder.add_root_certificate(Certificate::from_pem(&pem)?);
+            }
+            if tls.accept_invalid_certs.unwrap_or(false) {
+                warn!(source = %source.id, "Accepting invalid certificates");
+                builder = builder.danger_accept_invalid_certs(true);
+            }
+        }
+        let client = builder.build()?;
+        self.clients
+            .lock()
+            .unwrap()
+            .insert(source.id.clone(), client.clone());
+        Ok(client)
+    }
+
+    /// The headers of a request to a source: its default headers and
+    /// credentials, the user agent and the given `Accept` header. The
+    /// default headers often hold API keys, so like the credentials they are
+    /// marked as sensitive to keep them out of the WARC files.
+    fn headers(&self, source: &Source, accept: &str) -> Result<HeaderMap> {
+        let mut hm = HeaderMap::new();
+        if let Some(headers) = &source.headers {
+            for (name, value) in &headers.other {
+                let mut value: HeaderValue = value.parse()?;
+                value.set_sensitive(true);
+                hm.insert(HeaderName::from_bytes(name.as_bytes())?, value);
+            }
+        }
+        if let Some(auth) = &source.auth {
+            let (name, value) = auth.header()?;
+            hm.insert(name, value);
+        }
+        hm.insert("Accept", accept.parse()?);
+        hm.insert("User-Agent", self.user_agent.parse()?);
+        Ok(hm)
+    }
+}
+
+/// The report of a stage of a source, added to the reports if missing
+fn stage_report<'a>(
+    reports: &'a mut Vec<CrawlReport>,
+    source: &Source,
+    stage: &str,
+) -> &'a mut StageReport {
+    let i = match reports.iter().position(|r| r.source_id == source.id) {
+        Some(i) => i,
+        None => {
+            reports.push(CrawlReport::new(&source.id, source.rest_version()));
+            reports.len() - 1
+        }
+    };
+    let stages = &mut reports[i].stages;
+    let j = match stages.iter().position(|s| s.name == stage) {
+        Some(j) => j,
+        None => {
+            stages.push(StageReport::new(stage));
+            stages.len() - 1
+        }
+    };
+    &mut stages[j]
 }
 
 #[cfg(test)]
@@ -205,4 +1018,291 @@ mod tests {
     use crate::util::get_source;
 
     use super::*;
+
+    use std::{
+        convert::Infallible,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use hyper::{
+        service::{make_service_fn, service_fn},
+        Body, Server,
+    };
+    use serde_json::json;
+    use warc::WarcWriter;
+
+    use crate::reqwest_warc::crate_warc_request;
+
+    /// Serves a robots.txt with the given status, slowly enough for requests
+    /// to overlap, and counts how often it was requested
+    fn robots_server(status: u16) -> (Source, Arc<AtomicUsize>) {
+        let count = Arc::new(AtomicUsize::new(0));
+        let counter = count.clone();
+        let make = make_service_fn(move |_| {
+            let counter = counter.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |_| {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    async move {
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        let res = hyper::Response::builder()
+                            .status(status)
+                            .body(Body::from("User-agent: *\nDisallow: /x\n"))
+                            .unwrap();
+                        Ok::<_, Infallible>(res)
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make);
+        let url = format!("http://{}/", server.local_addr());
+        tokio::spawn(server);
+        let source = serde_json::from_value(
+            json!({ "id": "TEST", "name": "Test", "url": url }),
+        )
+        .unwrap();
+        (source, count)
+    }
+
+    /// Serves dataflow stubs in the 2.1 grammar only, and only to requests
+    /// with the API key header of the source
+    fn rest_2_1_server(api_key: &str) -> Source {
+        let make = make_service_fn(move |_| async move {
+            Ok::<_, Infallible>(service_fn(move |req: hyper::Request<Body>| {
+                let key = req.headers().get("X-Api-Key");
+                let authorized = matches!(key, Some(k) if k == "secret");
+                let v3 = req.uri().path().starts_with("/structure/");
+                let status = match authorized && !v3 {
+                    true => 200,
+                    false => 404,
+                };
+                let res = hyper::Response::builder()
+                    .status(status)
+                    .body(Body::from("{}"))
+                    .unwrap();
+                async move { Ok::<_, Infallible>(res) }
+            }))
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make);
+        let url = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+        serde_json::from_value(json!({
+            "id": "TEST", "name": "Test", "url": url,
+            "headers": { "X-Api-Key": api_key }
+        }))
+        .unwrap()
+    }
+
+    fn crawler() -> Crawler {
+        Crawler {
+            warc_write: false,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn user_agent_has_a_contact_url() {
+        assert_eq!(
+            Crawler::default().user_agent,
+            "Mozilla/5.0 (compatible; sdmxblaze/1.0; \
+             +https://github.com/alexkreidler/sdmx-rust)"
+        );
+        let cr = Crawler::default().with_contact("mailto:ops@example.org");
+        assert!(cr.user_agent.ends_with("; +mailto:ops@example.org)"));
+    }
+
+    #[tokio::test]
+    async fn concurrent_requests_fetch_robots_txt_once() {
+        let (source, count) = robots_server(200);
+        let cr = crawler();
+        let url = Url::parse(&source.url).unwrap().join("/x/y").unwrap();
+        let all = futures::future::join_all(
+            (0..5).map(|_| cr.robots_txt(&source, &url)),
+        )
+        .await;
+        for robots in all {
+            assert!(!robots.unwrap().is_allowed("sdmxblaze", "/x/y"));
+        }
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn unreachable_robots_txt_is_retried_soon() {
+        let (source, count) = robots_server(503);
+        let cr = crawler();
+        let url = Url::parse(&source.url).unwrap();
+        let robots = cr.robots_txt(&source, &url).await.unwrap();
+        assert!(!robots.is_allowed("sdmxblaze", "/"));
+
+        let host = url.origin().ascii_serialization();
+        let entry = cr.robots.lock().unwrap()[&host].clone();
+        {
+            let mut cached = entry.lock().await;
+            let cached = cached.as_mut().unwrap();
+            assert!(cached.expires <= Instant::now() + ROBOTS_FAILURE_TTL);
+            // as if the failure had expired
+            cached.expires = Instant::now();
+        }
+        cr.robots_txt(&source, &url).await.unwrap();
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn rest_version_is_detected_with_the_source_headers() {
+        let cr = crawler();
+        let source = rest_2_1_server("secret");
+        let rest = cr.detect_rest_version(&source).await.unwrap();
+        assert_eq!(rest, RestVersion::V2_1);
+
+        let source = rest_2_1_server("wrong");
+        assert!(cr.detect_rest_version(&source).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn source_headers_are_left_out_of_the_warc_files() {
+        let cr = crawler();
+        let source: Source = serde_json::from_value(json!({
+            "id": "TEST", "name": "Test", "url": "http://127.0.0.1:1",
+            "headers": { "X-Api-Key": "secret-key" }
+        }))
+        .unwrap();
+        let headers = cr.headers(&source, "application/json").unwrap();
+        let req = Client::new()
+            .get(&source.url)
+            .headers(headers)
+            .build()
+            .unwrap();
+        let record = crate_warc_request(req, "id".to_string()).await.unwrap();
+        let mut warc = vec![];
+        WarcWriter::new(&mut warc).write(&record).unwrap();
+        let warc = String::from_utf8_lossy(&warc);
+        assert!(warc.contains("x-api-key: [redacted]"));
+        assert!(!warc.contains("secret-key"));
+    }
+
+    /// The path and `If-Modified-Since` header of each request to a server
+    type Recorded = Arc<Mutex<Vec<(String, String)>>>;
+
+    /// Answers every request with `304 Not Modified` and records it
+    fn not_modified_server() -> (Source, Recorded) {
+        let requests = Arc::new(Mutex::new(vec![]));
+        let recorded = requests.clone();
+        let make = make_service_fn(move |_| {
+            let recorded = recorded.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(
+                    move |req: hyper::Request<Body>| {
+                        let since = req
+                            .headers()
+                            .get("If-Modified-Since")
+                            .map(|v| v.to_str().unwrap().to_string());
+                        recorded.lock().unwrap().push((
+                            req.uri().to_string(),
+                            since.unwrap_or_default(),
+                        ));
+                        let res = hyper::Response::builder()
+                            .status(304)
+                            .body(Body::empty())
+                            .unwrap();
+                        async move { Ok::<_, Infallible>(res) }
+                    },
+                ))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make);
+        let url = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+        let source = serde_json::from_value(json!({
+            "id": "TEST", "name": "Test", "url": url,
+            "politeness": { "robots_txt": false }
+        }))
+        .unwrap();
+        (source, requests)
+    }
+
+    #[tokio::test]
+    async fn unchanged_dataflows_are_taken_from_the_store() {
+        let (source, requests) = not_modified_server();
+        let store = Store::open_in_memory().unwrap();
+        let data: Data = serde_json::from_value(json!({
+            "dataflows": [{
+                "id": "EXR", "agencyID": "ECB", "version": "1.0",
+                "name": "Exchange Rates"
+            }]
+        }))
+        .unwrap();
+        let provenance = Provenance {
+            source_id: "TEST".to_string(),
+            fetched_at: Utc::now(),
+            warc_record_id: None,
+            content_languages: vec![],
+        };
+        store.save(&data, &provenance).unwrap();
+        let cr = crawler().with_store(store);
+
+        let since = "2021-03-04T05:06:07Z".parse().unwrap();
+        let report = cr.crawl_since(&source, since).await.unwrap();
+        assert_eq!(report.errors(), 0);
+        assert_eq!(report.stages[0].results, 1);
+        let requests = requests.lock().unwrap();
+        let paths: Vec<_> = requests.iter().map(|r| r.0.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "/dataflow/all/all/latest",
+                "/dataflow/ECB/EXR/1.0?references=descendants",
+                "/categoryscheme/all/all/latest?references=categorisation",
+            ]
+        );
+        for (_, since) in requests.iter() {
+            assert_eq!(since, "Thu, 04 Mar 2021 05:06:07 GMT");
+        }
+    }
+
+    #[tokio::test]
+    async fn data_is_requested_in_the_format_of_the_source() {
+        // answers with the path and the Accept header of the request
+        let make = make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(|req: hyper::Request<Body>| {
+                let accept = req.headers()["Accept"].to_str().unwrap();
+                let body = format!("{} {}", req.uri(), accept);
+                async move {
+                    Ok::<_, Infallible>(hyper::Response::new(Body::from(body)))
+                }
+            }))
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make);
+        let url = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+        let source: Source = serde_json::from_value(json!({
+            "id": "TEST", "name": "Test", "url": url,
+            "politeness": { "robots_txt": false },
+            "formats": { "data": "application/vnd.sdmx.data+csv;version=1.0.0" }
+        }))
+        .unwrap();
+
+        let query =
+            DataQuery::for_source(&source, "ECB", "EXR").with_key("D.USD");
+        let body = crawler().fetch_data(&source, &query).await.unwrap();
+        assert_eq!(
+            body,
+            "/data/ECB,EXR,latest/D.USD application/vnd.sdmx.data+csv;version=1.0.0"
+        );
+    }
+
+    #[tokio::test]
+    async fn requests_are_limited_to_the_rate_of_the_source() {
+        let cr = crawler();
+        let source: Source = serde_json::from_value(json!({
+            "id": "TEST", "name": "Test", "url": "http://127.0.0.1:1",
+            "politeness": { "robots_txt": false, "requests_per_second": 20 }
+        }))
+        .unwrap();
+        let url = Url::parse(&source.url).unwrap();
+        let start = Instant::now();
+        for _ in 0..3 {
+            cr.wait_politely(&source, &url, None).await.unwrap();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
 }
@@ -0,0 +1,503 @@
+//! dataset holds statistical data in a format independent form, as produced
+//! by parsing SDMX data messages, so it can be checked against its data
+//! structure definition (see `validate::DataValidator`).
+//!
+//! Values are kept as strings, as they appear in the message, so that text
+//! format facets like decimals and patterns can be checked.
+
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::components::TIME_PERIOD;
+
+/// The value of one dimension in a key
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct KeyValue {
+    /// ID of the dimension
+    pub id: String,
+    pub value: String,
+}
+
+/// A data set: attributes attached at each level and the series
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct DataSet {
+    /// Attributes attached to the whole data set
+    #[serde(default)]
+    pub attributes: BTreeMap<String, String>,
+    #[serde(default)]
+    pub groups: Vec<Group>,
+    #[serde(default)]
+    pub series: Vec<Series>,
+}
+
+/// Attribute values attached to a group of series
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Group {
+    /// ID of the group in the data structure
+    pub id: String,
+    /// Values of the dimensions of the group
+    pub key: Vec<KeyValue>,
+    #[serde(default)]
+    pub attributes: BTreeMap<String, String>,
+}
+
+/// A time series, identified by the values of all dimensions except time
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Series {
+    /// The series key in the order given by the message
+    pub key: Vec<KeyValue>,
+    #[serde(default)]
+    pub attributes: BTreeMap<String, String>,
+    #[serde(default)]
+    pub observations: Vec<Observation>,
+}
+
+impl Series {
+    /// The series key in the usual dot separated form, e.g. `D.USD.EUR.SP00.A`
+    pub fn key_string(&self) -> String {
+        let values: Vec<_> =
+            self.key.iter().map(|k| k.value.as_str()).collect();
+        values.join(".")
+    }
+
+    /// The value of a dimension in the series key
+    pub fn key_value(&self, dimension: &str) -> Option<&str> {
+        self.key
+            .iter()
+            .find(|k| k.id == dimension)
+            .map(|k| k.value.as_str())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Observation {
+    /// Value of the time dimension
+    pub time_period: String,
+    /// Value of the primary measure, which is missing for e.g. embargoed observations
+    pub value: Option<String>,
+    #[serde(default)]
+    pub attributes: BTreeMap<String, String>,
+}
+
+/// A data message in SDMX-JSON 1.0. Older messages have the structure and
+/// data sets at the top level instead of under `data`.
+#[derive(Deserialize)]
+struct JsonMessage {
+    data: Option<JsonData>,
+    #[serde(flatten)]
+    top: JsonData,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct JsonData {
+    structure: Option<JsonStructure>,
+    #[serde(default)]
+    data_sets: Vec<JsonDataSet>,
+}
+
+#[derive(Deserialize, Default)]
+struct JsonStructure {
+    #[serde(default)]
+    dimensions: JsonLevels,
+    #[serde(default)]
+    attributes: JsonLevels,
+}
+
+/// The components at each level of a message, with the values the indices
+/// in the data sets refer to
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct JsonLevels {
+    #[serde(default)]
+    data_set: Vec<JsonComponent>,
+    #[serde(default)]
+    series: Vec<JsonComponent>,
+    #[serde(default)]
+    observation: Vec<JsonComponent>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonComponent {
+    id: String,
+    key_position: Option<usize>,
+    role: Option<String>,
+    #[serde(default)]
+    values: Vec<JsonValue>,
+}
+
+/// A value of a component. Uncoded attribute values may only have a name.
+#[derive(Deserialize)]
+struct JsonValue {
+    id: Option<String>,
+    name: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct JsonDataSet {
+    #[serde(default)]
+    attributes: Vec<Option<usize>>,
+    #[serde(default)]
+    series: HashMap<String, JsonSeries>,
+    /// Observations by their full key, when all dimensions are at the
+    /// observation level
+    #[serde(default)]
+    observations: HashMap<String, Vec<serde_json::Value>>,
+}
+
+#[derive(Deserialize)]
+struct JsonSeries {
+    #[serde(default)]
+    attributes: Vec<Option<usize>>,
+    #[serde(default)]
+    observations: HashMap<String, Vec<serde_json::Value>>,
+}
+
+impl JsonComponent {
+    fn is_time(&self) -> bool {
+        self.id == TIME_PERIOD || self.role.as_deref() == Some("time")
+    }
+
+    fn value(&self, index: usize) -> Result<String> {
+        let value = self.values.get(index).ok_or_else(|| {
+            anyhow!(
+                "{} has {} values, but value {} is referenced",
+                self.id,
+                self.values.len(),
+                index
+            )
+        })?;
+        match (&value.id, &value.name) {
+            (Some(id), _) => Ok(id.clone()),
+            (None, Some(serde_json::Value::String(name))) => Ok(name.clone()),
+            _ => Err(anyhow!("value {} of {} has no ID", index, self.id)),
+        }
+    }
+}
+
+/// Parses a key of the form `0:1:0` into value indices
+fn parse_indices(key: &str) -> Result<Vec<usize>> {
+    if key.is_empty() {
+        return Ok(vec![]);
+    }
+    key.split(':')
+        .map(|i| i.parse().map_err(|_| anyhow!("Invalid key {}", key)))
+        .collect()
+}
+
+/// Entries ordered by their key indices, as the order of JSON objects is
+/// lost when they are read
+fn sorted<T>(entries: &HashMap<String, T>) -> Result<Vec<(Vec<usize>, &T)>> {
+    let mut out = entries
+        .iter()
+        .map(|(k, v)| Ok((parse_indices(k)?, v)))
+        .collect::<Result<Vec<_>>>()?;
+    out.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(out)
+}
+
+fn key_values<'a>(
+    components: impl Iterator<Item = &'a JsonComponent>,
+    indices: &[usize],
+) -> Result<Vec<(Option<usize>, KeyValue)>> {
+    let components: Vec<_> = components.collect();
+    if components.len() != indices.len() {
+        return Err(anyhow!(
+            "The key has {} values but there are {} dimensions",
+            indices.len(),
+            components.len()
+        ));
+    }
+    components
+        .into_iter()
+        .zip(indices)
+        .map(|(c, i)| {
+            let value = c.value(*i)?;
+            Ok((
+                c.key_position,
+                KeyValue {
+                    id: c.id.clone(),
+                    value,
+                },
+            ))
+        })
+        .collect()
+}
+
+fn attribute_values(
+    components: &[JsonComponent],
+    indices: &[Option<usize>],
+) -> Result<BTreeMap<String, String>> {
+    let mut out = BTreeMap::new();
+    for (c, i) in components.iter().zip(indices) {
+        if let Some(i) = i {
+            out.insert(c.id.clone(), c.value(*i)?);
+        }
+    }
+    Ok(out)
+}
+
+/// An observation is its value followed by the indices of its attributes
+fn observation(
+    time_period: String,
+    values: &[serde_json::Value],
+    attributes: &[JsonComponent],
+) -> Result<Observation> {
+    let value = match values.first() {
+        None | Some(serde_json::Value::Null) => None,
+        Some(serde_json::Value::String(s)) => Some(s.clone()),
+        Some(v) => Some(v.to_string()),
+    };
+    let indices = values
+        .iter()
+        .skip(1)
+        .map(|v| match v {
+            serde_json::Value::Null => Ok(None),
+            v => v
+                .as_u64()
+                .map(|i| Some(i as usize))
+                .ok_or_else(|| anyhow!("Invalid attribute value index {}", v)),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Observation {
+        time_period,
+        value,
+        attributes: attribute_values(attributes, &indices)?,
+    })
+}
+
+/// Parses the data sets of an SDMX-JSON 1.0 data message. Data sets may
+/// be organised in series with the time dimension at the observation
+/// level, or have all dimensions at the observation level, in which case
+/// the observations are grouped into series by the other dimensions.
+///
+/// Series keys are ordered by the key positions of the dimensions, if the
+/// message gives them. Groups are not read.
+pub fn parse_sdmx_json(text: &str) -> Result<Vec<DataSet>> {
+    let message: JsonMessage = serde_json::from_str(text)?;
+    let data = message.data.unwrap_or(message.top);
+    let structure = data
+        .structure
+        .ok_or_else(|| anyhow!("The message has no structure"))?;
+    let dimensions = &structure.dimensions;
+    let attributes = &structure.attributes;
+
+    // Dimensions with a single value for the whole data set
+    let fixed = key_values(
+        dimensions.data_set.iter(),
+        &vec![0; dimensions.data_set.len()],
+    )?;
+    let time = dimensions.observation.iter().position(|d| d.is_time());
+    let key = |mut values: Vec<(Option<usize>, KeyValue)>| {
+        values.extend(fixed.iter().cloned());
+        values.sort_by_key(|(position, _)| position.unwrap_or(usize::MAX));
+        values.into_iter().map(|(_, k)| k).collect::<Vec<_>>()
+    };
+
+    let mut out = vec![];
+    for ds in &data.data_sets {
+        let mut series = vec![];
+        if !ds.series.is_empty()
+            && (time.is_none() || dimensions.observation.len() != 1)
+        {
+            return Err(anyhow!(
+                "Only series with the time dimension at the observation level can be read"
+            ));
+        }
+        for (indices, s) in sorted(&ds.series)? {
+            let mut observations = vec![];
+            for (i, values) in sorted(&s.observations)? {
+                let (_, period) =
+                    key_values(dimensions.observation.iter(), &i)?.remove(0);
+                observations.push(observation(
+                    period.value,
+                    values,
+                    &attributes.observation,
+                )?);
+            }
+            series.push(Series {
+                key: key(key_values(dimensions.series.iter(), &indices)?),
+                attributes: attribute_values(
+                    &attributes.series,
+                    &s.attributes,
+                )?,
+                observations,
+            });
+        }
+
+        // Flat observations are grouped into series in order of their keys
+        let mut flat: Vec<Series> = vec![];
+        let mut positions = HashMap::new();
+        for (indices, values) in sorted(&ds.observations)? {
+            let mut values_by_dimension =
+                key_values(dimensions.observation.iter(), &indices)?;
+            let period = match time {
+                Some(t) => values_by_dimension.remove(t).1.value,
+                None => String::new(),
+            };
+            let series_key = key(values_by_dimension);
+            let obs = observation(period, values, &attributes.observation)?;
+            let s = Series {
+                key: series_key,
+                ..Default::default()
+            };
+            let position =
+                *positions.entry(s.key_string()).or_insert_with(|| {
+                    flat.push(s);
+                    flat.len() - 1
+                });
+            flat[position].observations.push(obs);
+        }
+        series.extend(flat);
+
+        out.push(DataSet {
+            attributes: attribute_values(&attributes.data_set, &ds.attributes)?,
+            groups: vec![],
+            series,
+        });
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn key(series: &Series) -> Vec<(&str, &str)> {
+        series
+            .key
+            .iter()
+            .map(|k| (k.id.as_str(), k.value.as_str()))
+            .collect()
+    }
+
+    fn structure() -> serde_json::Value {
+        json!({
+            "dimensions": {
+                "dataSet": [{
+                    "id": "FREQ", "keyPosition": 0,
+                    "values": [{ "id": "A" }]
+                }],
+                "series": [{
+                    "id": "AREA", "keyPosition": 1,
+                    "values": [{ "id": "DE" }, { "id": "FR" }]
+                }],
+                "observation": [{
+                    "id": "TIME_PERIOD", "role": "time",
+                    "values": [{ "id": "2020" }, { "id": "2021" }]
+                }]
+            },
+            "attributes": {
+                "dataSet": [{ "id": "UNIT_MULT", "values": [{ "id": "0" }] }],
+                "series": [{ "id": "TITLE", "values": [{ "name": "Prices" }] }],
+                "observation": [{
+                    "id": "OBS_STATUS",
+                    "values": [{ "id": "A" }, { "id": "E" }]
+                }]
+            }
+        })
+    }
+
+    #[test]
+    fn series_messages_are_read() {
+        let message = json!({
+            "data": {
+                "structure": structure(),
+                "dataSets": [{
+                    "attributes": [0],
+                    "series": {
+                        "1": {
+                            "attributes": [null],
+                            "observations": { "0": [2.5, 1] }
+                        },
+                        "0": {
+                            "attributes": [0],
+                            "observations": {
+                                "1": [null, null],
+                                "0": ["1.50", 0]
+                            }
+                        }
+                    }
+                }]
+            }
+        });
+        let data_sets = parse_sdmx_json(&message.to_string()).unwrap();
+        assert_eq!(data_sets.len(), 1);
+        let ds = &data_sets[0];
+        assert_eq!(ds.attributes.get("UNIT_MULT").unwrap(), "0");
+        assert_eq!(ds.series.len(), 2);
+
+        let de = &ds.series[0];
+        assert_eq!(key(de), vec![("FREQ", "A"), ("AREA", "DE")]);
+        assert_eq!(de.attributes.get("TITLE").unwrap(), "Prices");
+        let periods: Vec<_> =
+            de.observations.iter().map(|o| &o.time_period).collect();
+        assert_eq!(periods, vec!["2020", "2021"]);
+        assert_eq!(de.observations[0].value.as_deref(), Some("1.50"));
+        assert_eq!(
+            de.observations[0].attributes.get("OBS_STATUS").unwrap(),
+            "A"
+        );
+        assert_eq!(de.observations[1].value, None);
+        assert!(de.observations[1].attributes.is_empty());
+
+        let fr = &ds.series[1];
+        assert_eq!(fr.key_string(), "A.FR");
+        assert!(fr.attributes.is_empty());
+        assert_eq!(fr.observations[0].value.as_deref(), Some("2.5"));
+        assert_eq!(
+            fr.observations[0].attributes.get("OBS_STATUS").unwrap(),
+            "E"
+        );
+    }
+
+    #[test]
+    fn flat_messages_are_grouped_into_series() {
+        let mut structure = structure();
+        let dimensions = &mut structure["dimensions"];
+        let area = dimensions["series"][0].take();
+        dimensions["series"] = json!([]);
+        dimensions["observation"]
+            .as_array_mut()
+            .unwrap()
+            .insert(0, area);
+        let message = json!({
+            "structure": structure,
+            "dataSets": [{
+                "observations": {
+                    "1:0": [3],
+                    "0:1": [2],
+                    "0:0": [1]
+                }
+            }]
+        });
+        let data_sets = parse_sdmx_json(&message.to_string()).unwrap();
+        let series = &data_sets[0].series;
+        let keys: Vec<_> = series.iter().map(|s| s.key_string()).collect();
+        assert_eq!(keys, vec!["A.DE", "A.FR"]);
+        let values: Vec<_> = series[0]
+            .observations
+            .iter()
+            .map(|o| (o.time_period.as_str(), o.value.as_deref()))
+            .collect();
+        assert_eq!(values, vec![("2020", Some("1")), ("2021", Some("2"))]);
+    }
+
+    #[test]
+    fn invalid_indices_are_rejected() {
+        let message = json!({
+            "data": {
+                "structure": structure(),
+                "dataSets": [{
+                    "series": { "2": { "observations": { "0": [1] } } }
+                }]
+            }
+        });
+        assert!(parse_sdmx_json(&message.to_string()).is_err());
+    }
+}
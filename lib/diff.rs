@@ -0,0 +1,554 @@
+//! diff compares two snapshots of crawled structures, e.g. the WARC files of
+//! two crawl runs, to find the changes providers make without notice.
+//!
+//! Artefacts are matched by source, class, agency and ID. The source of a
+//! message is the origin of the URL it was fetched from, so artefacts with
+//! the same ID published by two providers are compared separately. When a
+//! snapshot contains several versions of an artefact only the latest is
+//! compared, so a new version is reported as a version change along with
+//! what changed in it.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use serde::Serialize;
+
+use crate::{
+    artefact::{Identifiable, ItemScheme, Maintainable, Nameable},
+    structure::{
+        Data, DataStructureTypeElement, DataflowTypeElement,
+        ObjectTypeCodelistType,
+    },
+    urn::class_name,
+};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    /// The name of a dataflow changed
+    Renamed,
+    VersionChanged,
+    /// The data structure of a dataflow changed
+    StructureChanged,
+    /// The name of any other artefact or of an item changed
+    LabelChanged,
+    ItemAdded,
+    ItemRemoved,
+    DimensionAdded,
+    DimensionRemoved,
+    DimensionOrderChanged,
+}
+
+/// A difference between two snapshots
+#[derive(Serialize, Debug, Clone)]
+pub struct Change {
+    pub kind: ChangeKind,
+    /// The origin of the source the artefact was fetched from, if known
+    pub source: Option<String>,
+    /// The artefact that changed, e.g. `Codelist ECB:CL_FREQ`
+    pub artefact: String,
+    /// The item or dimension within the artefact, if any
+    pub item: Option<String>,
+    /// Language of a changed name. Missing when the messages only give the
+    /// name in their main language.
+    pub language: Option<String>,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn or_none(s: &Option<String>) -> &str {
+            s.as_deref().unwrap_or("(none)")
+        }
+
+        if let Some(source) = &self.source {
+            write!(f, "{} ", source)?;
+        }
+        write!(f, "{}", self.artefact)?;
+        if let Some(item) = &self.item {
+            write!(f, " {}", item)?;
+        }
+        match self.kind {
+            ChangeKind::Added
+            | ChangeKind::ItemAdded
+            | ChangeKind::DimensionAdded => write!(f, ": added"),
+            ChangeKind::Removed
+            | ChangeKind::ItemRemoved
+            | ChangeKind::DimensionRemoved => write!(f, ": removed"),
+            ChangeKind::Renamed | ChangeKind::LabelChanged => {
+                write!(f, ": name")?;
+                if let Some(language) = &self.language {
+                    write!(f, " ({})", language)?;
+                }
+                write!(
+                    f,
+                    " changed from {:?} to {:?}",
+                    or_none(&self.old),
+                    or_none(&self.new)
+                )
+            }
+            ChangeKind::VersionChanged => write!(
+                f,
+                ": version changed from {} to {}",
+                or_none(&self.old),
+                or_none(&self.new)
+            ),
+            ChangeKind::StructureChanged => write!(
+                f,
+                ": structure changed from {} to {}",
+                or_none(&self.old),
+                or_none(&self.new)
+            ),
+            ChangeKind::DimensionOrderChanged => write!(
+                f,
+                ": dimension order changed from {} to {}",
+                or_none(&self.old),
+                or_none(&self.new)
+            ),
+        }
+    }
+}
+
+/// The structures of one crawl, from any number of messages
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    /// The messages with the origin of their source, if known
+    messages: Vec<(Option<String>, Data)>,
+}
+
+/// Source, agency and ID of an artefact
+type Key = (Option<String>, String, String);
+
+impl Snapshot {
+    pub fn new() -> Self {
+        Snapshot::default()
+    }
+
+    /// Adds a message fetched from `source`, e.g. the origin of the URL of
+    /// its WARC record
+    pub fn add(&mut self, source: Option<String>, data: Data) {
+        self.messages.push((source, data));
+    }
+
+    /// Number of messages in the snapshot
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// The latest version of each artefact in the lists selected by `list`,
+    /// by source, agency and ID
+    fn latest<'a, T, F>(&'a self, list: F) -> BTreeMap<Key, &'a T>
+    where
+        T: Maintainable,
+        F: Fn(&'a Data) -> Option<&'a Vec<T>>,
+    {
+        let mut out: BTreeMap<Key, &T> = BTreeMap::new();
+        for (source, data) in &self.messages {
+            for a in list(data).into_iter().flatten() {
+                let key = (
+                    source.clone(),
+                    a.agency_id().to_string(),
+                    a.resource_id().to_string(),
+                );
+                let newer = match out.get(&key) {
+                    Some(existing) => {
+                        a.parsed_version() > existing.parsed_version()
+                    }
+                    None => true,
+                };
+                if newer {
+                    out.insert(key, a);
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Compares the latest version of the dataflows, codelists, concept schemes,
+/// category schemes and data structures of two snapshots
+pub fn diff(old: &Snapshot, new: &Snapshot) -> Vec<Change> {
+    let mut out = vec![];
+    compare(
+        &mut out,
+        old.latest(|d| d.dataflows.as_ref()),
+        new.latest(|d| d.dataflows.as_ref()),
+        compare_structure,
+    );
+    compare(
+        &mut out,
+        old.latest(|d| d.codelists.as_ref()),
+        new.latest(|d| d.codelists.as_ref()),
+        compare_items,
+    );
+    compare(
+        &mut out,
+        old.latest(|d| d.concept_schemes.as_ref()),
+        new.latest(|d| d.concept_schemes.as_ref()),
+        compare_items,
+    );
+    compare(
+        &mut out,
+        old.latest(|d| d.category_schemes.as_ref()),
+        new.latest(|d| d.category_schemes.as_ref()),
+        compare_items,
+    );
+    compare(
+        &mut out,
+        old.latest(|d| d.data_structures.as_ref()),
+        new.latest(|d| d.data_structures.as_ref()),
+        compare_dimensions,
+    );
+    out
+}
+
+/// Collects the changes of one artefact
+struct Changes<'a> {
+    source: Option<String>,
+    artefact: String,
+    out: &'a mut Vec<Change>,
+}
+
+impl Changes<'_> {
+    fn push(
+        &mut self,
+        kind: ChangeKind,
+        item: Option<&str>,
+        old: Option<String>,
+        new: Option<String>,
+    ) {
+        self.out.push(Change {
+            kind,
+            source: self.source.clone(),
+            artefact: self.artefact.clone(),
+            item: item.map(|i| i.to_string()),
+            language: None,
+            old,
+            new,
+        });
+    }
+
+    /// Reports the languages in which the name of an artefact or item changed
+    fn labels<N: Nameable + ?Sized>(
+        &mut self,
+        kind: ChangeKind,
+        item: Option<&str>,
+        old: &N,
+        new: &N,
+    ) {
+        let old = labels(old);
+        let new = labels(new);
+        let languages: BTreeSet<_> = old.keys().chain(new.keys()).collect();
+        for language in languages {
+            let (o, n) = (old.get(language), new.get(language));
+            if o != n {
+                self.out.push(Change {
+                    kind,
+                    source: self.source.clone(),
+                    artefact: self.artefact.clone(),
+                    item: item.map(|i| i.to_string()),
+                    language: language.clone(),
+                    old: o.cloned(),
+                    new: n.cloned(),
+                });
+            }
+        }
+    }
+}
+
+/// The names of an object by language, or the name in the main language of
+/// the message
+fn labels<N: Nameable + ?Sized>(n: &N) -> BTreeMap<Option<String>, String> {
+    match n.names().filter(|names| !names.is_empty()) {
+        Some(names) => names
+            .iter()
+            .map(|(l, t)| (Some(l.to_string()), t.to_string()))
+            .collect(),
+        None => vec![(None, n.name().to_string())].into_iter().collect(),
+    }
+}
+
+/// Identifies an artefact across versions, e.g. `Codelist ECB:CL_FREQ`
+fn artefact_label(m: &dyn Maintainable) -> String {
+    format!(
+        "{} {}:{}",
+        class_name(m.class()),
+        m.agency_id(),
+        m.resource_id()
+    )
+}
+
+fn compare<T, F>(
+    out: &mut Vec<Change>,
+    old: BTreeMap<Key, &T>,
+    new: BTreeMap<Key, &T>,
+    mut details: F,
+) where
+    T: Maintainable,
+    F: FnMut(&mut Changes, &T, &T),
+{
+    for (key, o) in &old {
+        if !new.contains_key(key) {
+            let mut changes = Changes {
+                source: key.0.clone(),
+                artefact: artefact_label(*o),
+                out,
+            };
+            changes.push(ChangeKind::Removed, None, None, None);
+        }
+    }
+    for (key, n) in &new {
+        let mut changes = Changes {
+            source: key.0.clone(),
+            artefact: artefact_label(*n),
+            out,
+        };
+        let o = match old.get(key) {
+            Some(o) => o,
+            None => {
+                changes.push(ChangeKind::Added, None, None, None);
+                continue;
+            }
+        };
+        if o.parsed_version() != n.parsed_version() {
+            changes.push(
+                ChangeKind::VersionChanged,
+                None,
+                o.version().map(|v| v.to_string()),
+                n.version().map(|v| v.to_string()),
+            );
+        }
+        let kind = match n.class() {
+            ObjectTypeCodelistType::Dataflow => ChangeKind::Renamed,
+            _ => ChangeKind::LabelChanged,
+        };
+        changes.labels(kind, None, *o, *n);
+        details(&mut changes, o, n);
+    }
+}
+
+/// Reports a dataflow whose data structure changed, e.g. to another DSD or
+/// to another version of its DSD
+fn compare_structure(
+    changes: &mut Changes,
+    old: &DataflowTypeElement,
+    new: &DataflowTypeElement,
+) {
+    if old.structure != new.structure {
+        changes.push(
+            ChangeKind::StructureChanged,
+            None,
+            old.structure.clone(),
+            new.structure.clone(),
+        );
+    }
+}
+
+/// Reports added, removed and renamed items. Removals aren't reported when
+/// either scheme is partial, as the items may just have been left out.
+fn compare_items<S: ItemScheme>(changes: &mut Changes, old: &S, new: &S) {
+    let items = |s: &S| -> BTreeMap<String, usize> {
+        s.items()
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, i)| Some((i.id()?.to_string(), idx)))
+            .collect()
+    };
+    let (old_items, new_items) = (items(old), items(new));
+
+    if !old.is_partial() && !new.is_partial() {
+        for id in old_items.keys().filter(|id| !new_items.contains_key(*id)) {
+            changes.push(ChangeKind::ItemRemoved, Some(id), None, None);
+        }
+    }
+    for (id, idx) in &new_items {
+        match old_items.get(id) {
+            Some(old_idx) => changes.labels(
+                ChangeKind::LabelChanged,
+                Some(id),
+                &old.items()[*old_idx],
+                &new.items()[*idx],
+            ),
+            None => changes.push(ChangeKind::ItemAdded, Some(id), None, None),
+        }
+    }
+}
+
+/// Reports added and removed dimensions and changes to the order of the
+/// dimensions in the series key
+fn compare_dimensions(
+    changes: &mut Changes,
+    old: &DataStructureTypeElement,
+    new: &DataStructureTypeElement,
+) {
+    let ids = |dsd: &DataStructureTypeElement| -> Vec<String> {
+        dsd.dimensions().into_iter().map(|c| c.id).collect()
+    };
+    let (old_ids, new_ids) = (ids(old), ids(new));
+
+    for id in old_ids.iter().filter(|id| !new_ids.contains(id)) {
+        changes.push(ChangeKind::DimensionRemoved, Some(id), None, None);
+    }
+    for id in new_ids.iter().filter(|id| !old_ids.contains(id)) {
+        changes.push(ChangeKind::DimensionAdded, Some(id), None, None);
+    }
+
+    let common = |ids: &[String], other: &[String]| -> Vec<String> {
+        ids.iter()
+            .filter(|id| other.contains(id))
+            .cloned()
+            .collect()
+    };
+    if common(&old_ids, &new_ids) != common(&new_ids, &old_ids) {
+        changes.push(
+            ChangeKind::DimensionOrderChanged,
+            None,
+            Some(old_ids.join(".")),
+            Some(new_ids.join(".")),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    const ECB: &str = "https://sdw-wsrest.ecb.europa.eu";
+    const ESTAT: &str = "https://ec.europa.eu";
+
+    fn snapshot(messages: Vec<(&str, Value)>) -> Snapshot {
+        let mut snapshot = Snapshot::new();
+        for (source, data) in messages {
+            let data = serde_json::from_value(data).unwrap();
+            snapshot.add(Some(source.to_string()), data);
+        }
+        snapshot
+    }
+
+    fn codelist(version: &str, codes: Value) -> Value {
+        json!({
+            "codelists": [{
+                "id": "CL_FREQ", "agencyID": "SDMX", "version": version,
+                "name": "Frequency", "codes": codes
+            }]
+        })
+    }
+
+    fn dataflow(structure: &str) -> Value {
+        let dsd = "urn:sdmx:org.sdmx.infomodel.datastructure.DataStructure";
+        json!({
+            "dataflows": [{
+                "id": "EXR", "agencyID": "ECB", "version": "1.0",
+                "name": "Exchange rates",
+                "structure": format!("{}={}", dsd, structure)
+            }]
+        })
+    }
+
+    fn kinds(changes: &[Change]) -> Vec<(ChangeKind, Option<&str>)> {
+        changes
+            .iter()
+            .map(|c| (c.kind, c.item.as_deref()))
+            .collect()
+    }
+
+    #[test]
+    fn artefacts_are_compared_per_source() {
+        let annual = json!([{ "id": "A", "name": "Annual" }]);
+        let both = json!([
+            { "id": "A", "name": "Annual" },
+            { "id": "M", "name": "Monthly" }
+        ]);
+        // Each source has its own variant of the same codelist
+        let old = snapshot(vec![
+            (ECB, codelist("2.0", annual.clone())),
+            (ESTAT, codelist("2.1", both.clone())),
+        ]);
+        let new = snapshot(vec![
+            (ESTAT, codelist("2.1", both)),
+            (ECB, codelist("2.0", annual.clone())),
+        ]);
+        assert!(diff(&old, &new).is_empty());
+
+        let new = snapshot(vec![(ECB, codelist("2.0", annual))]);
+        let changes = diff(&old, &new);
+        assert_eq!(kinds(&changes), vec![(ChangeKind::Removed, None)]);
+        assert_eq!(changes[0].source.as_deref(), Some(ESTAT));
+        assert_eq!(
+            changes[0].to_string(),
+            "https://ec.europa.eu Codelist SDMX:CL_FREQ: removed"
+        );
+    }
+
+    #[test]
+    fn dataflows_moved_to_another_structure_are_reported() {
+        let old = snapshot(vec![(ECB, dataflow("ECB:ECB_EXR1(1.0)"))]);
+        let new = snapshot(vec![(ECB, dataflow("ECB:ECB_EXR2(1.0)"))]);
+        let changes = diff(&old, &new);
+        assert_eq!(kinds(&changes), vec![(ChangeKind::StructureChanged, None)]);
+        assert!(changes[0]
+            .old
+            .as_deref()
+            .unwrap()
+            .ends_with("ECB_EXR1(1.0)"));
+        assert!(changes[0]
+            .new
+            .as_deref()
+            .unwrap()
+            .ends_with("ECB_EXR2(1.0)"));
+
+        let same = snapshot(vec![(ECB, dataflow("ECB:ECB_EXR1(1.0)"))]);
+        assert!(diff(&old, &same).is_empty());
+    }
+
+    #[test]
+    fn codes_and_versions_are_compared() {
+        let old = snapshot(vec![(
+            ECB,
+            codelist(
+                "1.0",
+                json!([
+                    { "id": "A", "name": "Annual" },
+                    { "id": "Q", "name": "Quarterly" }
+                ]),
+            ),
+        )]);
+        let new = snapshot(vec![
+            (
+                ECB,
+                codelist(
+                    "1.1",
+                    json!([
+                        { "id": "A", "name": "Yearly" },
+                        { "id": "M", "name": "Monthly" }
+                    ]),
+                ),
+            ),
+            // older versions are not compared
+            (ECB, codelist("0.9", json!([]))),
+        ]);
+        let changes = diff(&old, &new);
+        assert_eq!(
+            kinds(&changes),
+            vec![
+                (ChangeKind::VersionChanged, None),
+                (ChangeKind::ItemRemoved, Some("Q")),
+                (ChangeKind::LabelChanged, Some("A")),
+                (ChangeKind::ItemAdded, Some("M")),
+            ]
+        );
+        assert_eq!(changes[2].old.as_deref(), Some("Annual"));
+        assert_eq!(changes[2].new.as_deref(), Some("Yearly"));
+    }
+}
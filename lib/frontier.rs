@@ -0,0 +1,598 @@
+//! frontier holds the URLs a distributed crawl has yet to request, shared by
+//! any number of worker processes (see `Crawler::work`).
+//!
+//! Workers claim one task at a time with a lease. A worker that requested a
+//! URL queues the URLs the response leads to and then completes the task. If
+//! the worker dies instead, the lease expires and another worker claims the
+//! task again, up to `MAX_ATTEMPTS` times. A URL is only ever queued once per
+//! queue, so every crawl needs a queue name of its own.
+//!
+//! The queue also keeps when each host may be requested next, so the workers
+//! together wait the crawl delay of a host between requests, rather than
+//! each of them. This relies on the clocks of the workers agreeing.
+//!
+//! Queues are kept in a SQLite database, for workers on one machine, or in
+//! Redis or a compatible server, for workers on several machines:
+//!
+//! ```text
+//! frontier.sqlite           a SQLite database, created if needed
+//! redis://127.0.0.1:6379/0  a Redis database
+//! ```
+
+use std::{path::Path, sync::Mutex, time::Duration};
+
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+/// How often a task is claimed before it is given up
+pub const MAX_ATTEMPTS: u32 = 3;
+
+/// A URL to request in a stage of the crawl of a source
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Task {
+    pub source_id: String,
+    /// Name of the crawler stage the URL belongs to
+    pub stage: String,
+    pub url: String,
+}
+
+impl Task {
+    /// Identifies the task within a queue
+    fn key(&self) -> String {
+        format!("{} {}", self.source_id, self.url)
+    }
+}
+
+/// A task claimed by a worker
+#[derive(Debug, Clone)]
+pub struct Lease {
+    pub task: Task,
+    /// Proves the lease when completing the task
+    token: String,
+    /// How often the task was claimed, including this time
+    pub attempt: u32,
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrontierStats {
+    /// Tasks waiting to be claimed
+    pub pending: usize,
+    /// Tasks claimed by a worker, including those whose lease expired
+    pub leased: usize,
+    pub done: usize,
+    /// Tasks given up after `MAX_ATTEMPTS` expired leases
+    pub failed: usize,
+}
+
+impl FrontierStats {
+    /// Whether there is nothing left to claim, now or later
+    pub fn is_drained(&self) -> bool {
+        self.pending == 0 && self.leased == 0
+    }
+}
+
+/// A queue of tasks shared by the workers of a crawl
+pub trait Frontier: Send + Sync {
+    /// Queues the tasks that were never queued before, returning how many
+    fn push(&self, tasks: &[Task]) -> Result<usize>;
+
+    /// Claims the next pending task, or one whose lease expired, for the
+    /// given time
+    fn claim(&self, lease: Duration) -> Result<Option<Lease>>;
+
+    /// Marks a claimed task as done. Returns false if the lease expired and
+    /// another worker claimed the task since.
+    fn complete(&self, lease: &Lease) -> Result<bool>;
+
+    fn stats(&self) -> Result<FrontierStats>;
+
+    /// Reserves the next request to a host, at least `delay` after the one
+    /// any worker reserved before. Returns how long to wait for it.
+    fn reserve(&self, host: &str, delay: Duration) -> Result<Duration>;
+}
+
+/// Opens the queue with the given name at a SQLite path or `redis://` URL
+pub fn open(location: &str, name: &str) -> Result<Box<dyn Frontier>> {
+    match location.starts_with("redis://") {
+        true => Ok(Box::new(RedisFrontier::open(location, name)?)),
+        false => Ok(Box::new(SqliteFrontier::open(location, name)?)),
+    }
+}
+
+fn now_ms() -> i64 {
+    Utc::now().timestamp_millis()
+}
+
+fn lease_until(lease: Duration) -> i64 {
+    now_ms() + lease.as_millis() as i64
+}
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS frontier (
+    id INTEGER PRIMARY KEY,
+    queue TEXT NOT NULL,
+    key TEXT NOT NULL,
+    source_id TEXT NOT NULL,
+    stage TEXT NOT NULL,
+    url TEXT NOT NULL,
+    state TEXT NOT NULL DEFAULT 'pending',
+    token TEXT,
+    lease_until INTEGER,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    UNIQUE (queue, key)
+);
+CREATE INDEX IF NOT EXISTS frontier_state ON frontier(queue, state, id);
+CREATE TABLE IF NOT EXISTS frontier_hosts (
+    queue TEXT NOT NULL,
+    host TEXT NOT NULL,
+    next_request INTEGER NOT NULL,
+    PRIMARY KEY (queue, host)
+);
+";
+
+/// A queue in a SQLite database, which several processes can share
+pub struct SqliteFrontier {
+    conn: Mutex<Connection>,
+    name: String,
+}
+
+impl SqliteFrontier {
+    /// Opens (creating if needed) the database at the given path
+    pub fn open<P: AsRef<Path>>(path: P, name: &str) -> Result<Self> {
+        SqliteFrontier::init(Connection::open(path)?, name)
+    }
+
+    /// Opens a temporary database that only lives in memory
+    pub fn open_in_memory(name: &str) -> Result<Self> {
+        SqliteFrontier::init(Connection::open_in_memory()?, name)
+    }
+
+    fn init(conn: Connection, name: &str) -> Result<Self> {
+        // Other workers hold the database locked while claiming
+        conn.busy_timeout(Duration::from_secs(30))?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(SqliteFrontier {
+            conn: Mutex::new(conn),
+            name: name.to_string(),
+        })
+    }
+}
+
+impl Frontier for SqliteFrontier {
+    fn push(&self, tasks: &[Task]) -> Result<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let mut added = 0;
+        for task in tasks {
+            added += tx.execute(
+                "INSERT OR IGNORE INTO frontier
+                 (queue, key, source_id, stage, url) VALUES (?, ?, ?, ?, ?)",
+                params![
+                    self.name,
+                    task.key(),
+                    task.source_id,
+                    task.stage,
+                    task.url
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(added)
+    }
+
+    fn claim(&self, lease: Duration) -> Result<Option<Lease>> {
+        let now = now_ms();
+        let mut conn = self.conn.lock().unwrap();
+        // Takes the write lock right away, so no other worker claims the
+        // same task in between
+        let tx =
+            conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        tx.execute(
+            "UPDATE frontier SET state = 'failed', token = NULL
+             WHERE queue = ? AND state = 'leased' AND lease_until <= ?
+               AND attempts >= ?",
+            params![self.name, now, MAX_ATTEMPTS],
+        )?;
+        let next = tx
+            .query_row(
+                "SELECT id, source_id, stage, url, attempts FROM frontier
+                 WHERE queue = ? AND (state = 'pending'
+                    OR (state = 'leased' AND lease_until <= ?))
+                 ORDER BY id LIMIT 1",
+                params![self.name, now],
+                |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        Task {
+                            source_id: row.get(1)?,
+                            stage: row.get(2)?,
+                            url: row.get(3)?,
+                        },
+                        row.get::<_, u32>(4)?,
+                    ))
+                },
+            )
+            .optional()?;
+        let (id, task, attempts) = match next {
+            Some(next) => next,
+            None => {
+                // Keep the tasks given up above
+                tx.commit()?;
+                return Ok(None);
+            }
+        };
+        let token = Ulid::new().to_string();
+        tx.execute(
+            "UPDATE frontier SET state = 'leased', token = ?, lease_until = ?,
+                attempts = attempts + 1
+             WHERE id = ?",
+            params![token, lease_until(lease), id],
+        )?;
+        tx.commit()?;
+        Ok(Some(Lease {
+            task,
+            token,
+            attempt: attempts + 1,
+        }))
+    }
+
+    fn complete(&self, lease: &Lease) -> Result<bool> {
+        let changed = self.conn.lock().unwrap().execute(
+            "UPDATE frontier SET state = 'done', token = NULL
+             WHERE queue = ? AND key = ? AND state = 'leased' AND token = ?",
+            params![self.name, lease.task.key(), lease.token],
+        )?;
+        Ok(changed == 1)
+    }
+
+    fn stats(&self) -> Result<FrontierStats> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT state, count(*) FROM frontier WHERE queue = ?
+             GROUP BY state",
+        )?;
+        let mut rows = stmt.query(params![self.name])?;
+        let mut stats = FrontierStats::default();
+        while let Some(row) = rows.next()? {
+            let count = row.get::<_, i64>(1)? as usize;
+            match row.get::<_, String>(0)?.as_str() {
+                "pending" => stats.pending = count,
+                "leased" => stats.leased = count,
+                "done" => stats.done = count,
+                "failed" => stats.failed = count,
+                s => return Err(anyhow!("Unknown task state {}", s)),
+            }
+        }
+        Ok(stats)
+    }
+
+    fn reserve(&self, host: &str, delay: Duration) -> Result<Duration> {
+        let now = now_ms();
+        let mut conn = self.conn.lock().unwrap();
+        let tx =
+            conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let next: Option<i64> = tx
+            .query_row(
+                "SELECT next_request FROM frontier_hosts
+                 WHERE queue = ? AND host = ?",
+                params![self.name, host],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let at = next.map_or(now, |next| next.max(now));
+        tx.execute(
+            "INSERT INTO frontier_hosts (queue, host, next_request)
+             VALUES (?, ?, ?)
+             ON CONFLICT (queue, host)
+             DO UPDATE SET next_request = excluded.next_request",
+            params![self.name, host, at + delay.as_millis() as i64],
+        )?;
+        tx.commit()?;
+        Ok(Duration::from_millis((at - now) as u64))
+    }
+}
+
+/// Queues the tasks whose key is new. KEYS: seen, pending, tasks. ARGV: key
+/// and task JSON of each task.
+const PUSH: &str = r"
+local added = 0
+for i = 1, #ARGV, 2 do
+    if redis.call('SADD', KEYS[1], ARGV[i]) == 1 then
+        redis.call('HSET', KEYS[3], ARGV[i], ARGV[i + 1])
+        redis.call('RPUSH', KEYS[2], ARGV[i])
+        added = added + 1
+    end
+end
+return added
+";
+
+/// Requeues or gives up the tasks whose lease expired, then leases the next
+/// pending task. KEYS: pending, tasks, attempts, leases, tokens, failed.
+/// ARGV: now, lease until, token, maximum attempts.
+const CLAIM: &str = r"
+for _, key in ipairs(redis.call('ZRANGEBYSCORE', KEYS[4], '-inf', ARGV[1])) do
+    redis.call('ZREM', KEYS[4], key)
+    redis.call('HDEL', KEYS[5], key)
+    local attempts = tonumber(redis.call('HGET', KEYS[3], key) or '0')
+    if attempts >= tonumber(ARGV[4]) then
+        redis.call('INCR', KEYS[6])
+    else
+        redis.call('RPUSH', KEYS[1], key)
+    end
+end
+local key = redis.call('LPOP', KEYS[1])
+if not key then
+    return false
+end
+local attempts = redis.call('HINCRBY', KEYS[3], key, 1)
+redis.call('ZADD', KEYS[4], ARGV[2], key)
+redis.call('HSET', KEYS[5], key, ARGV[3])
+return {redis.call('HGET', KEYS[2], key), attempts}
+";
+
+/// Ends a lease if the token still holds it. KEYS: leases, tokens, done.
+/// ARGV: key, token.
+const COMPLETE: &str = r"
+if redis.call('HGET', KEYS[2], ARGV[1]) ~= ARGV[2] then
+    return 0
+end
+redis.call('HDEL', KEYS[2], ARGV[1])
+redis.call('ZREM', KEYS[1], ARGV[1])
+redis.call('INCR', KEYS[3])
+return 1
+";
+
+/// Reserves the next request to a host. KEYS: hosts. ARGV: host, now, delay.
+const RESERVE: &str = r"
+local now = tonumber(ARGV[2])
+local next = tonumber(redis.call('HGET', KEYS[1], ARGV[1]) or '0')
+local at = math.max(now, next)
+redis.call('HSET', KEYS[1], ARGV[1], at + tonumber(ARGV[3]))
+return at - now
+";
+
+/// A queue in Redis, kept in keys starting with the queue name. Claims run
+/// as Lua scripts, so any server that runs those works.
+pub struct RedisFrontier {
+    conn: Mutex<redis::Connection>,
+    name: String,
+    push: redis::Script,
+    claim: redis::Script,
+    complete: redis::Script,
+    reserve: redis::Script,
+}
+
+impl RedisFrontier {
+    pub fn open(url: &str, name: &str) -> Result<Self> {
+        let conn = redis::Client::open(url)?.get_connection()?;
+        Ok(RedisFrontier {
+            conn: Mutex::new(conn),
+            name: name.to_string(),
+            push: redis::Script::new(PUSH),
+            claim: redis::Script::new(CLAIM),
+            complete: redis::Script::new(COMPLETE),
+            reserve: redis::Script::new(RESERVE),
+        })
+    }
+
+    fn key(&self, suffix: &str) -> String {
+        format!("{}:{}", self.name, suffix)
+    }
+}
+
+impl Frontier for RedisFrontier {
+    fn push(&self, tasks: &[Task]) -> Result<usize> {
+        if tasks.is_empty() {
+            return Ok(0);
+        }
+        let mut invocation = self.push.prepare_invoke();
+        invocation
+            .key(self.key("seen"))
+            .key(self.key("pending"))
+            .key(self.key("tasks"));
+        for task in tasks {
+            invocation.arg(task.key()).arg(serde_json::to_string(task)?);
+        }
+        let mut conn = self.conn.lock().unwrap();
+        Ok(invocation.invoke(&mut *conn)?)
+    }
+
+    fn claim(&self, lease: Duration) -> Result<Option<Lease>> {
+        let token = Ulid::new().to_string();
+        let mut conn = self.conn.lock().unwrap();
+        let claimed: Option<(String, u32)> = self
+            .claim
+            .key(self.key("pending"))
+            .key(self.key("tasks"))
+            .key(self.key("attempts"))
+            .key(self.key("leases"))
+            .key(self.key("tokens"))
+            .key(self.key("failed"))
+            .arg(now_ms())
+            .arg(lease_until(lease))
+            .arg(&token)
+            .arg(MAX_ATTEMPTS)
+            .invoke(&mut *conn)?;
+        match claimed {
+            Some((task, attempt)) => Ok(Some(Lease {
+                task: serde_json::from_str(&task)?,
+                token,
+                attempt,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    fn complete(&self, lease: &Lease) -> Result<bool> {
+        let mut conn = self.conn.lock().unwrap();
+        let completed: i64 = self
+            .complete
+            .key(self.key("leases"))
+            .key(self.key("tokens"))
+            .key(self.key("done"))
+            .arg(lease.task.key())
+            .arg(&lease.token)
+            .invoke(&mut *conn)?;
+        Ok(completed == 1)
+    }
+
+    fn stats(&self) -> Result<FrontierStats> {
+        let mut conn = self.conn.lock().unwrap();
+        let (pending, leased, done, failed): (
+            usize,
+            usize,
+            Option<usize>,
+            Option<usize>,
+        ) = redis::pipe()
+            .atomic()
+            .llen(self.key("pending"))
+            .zcard(self.key("leases"))
+            .get(self.key("done"))
+            .get(self.key("failed"))
+            .query(&mut *conn)?;
+        Ok(FrontierStats {
+            pending,
+            leased,
+            done: done.unwrap_or(0),
+            failed: failed.unwrap_or(0),
+        })
+    }
+
+    fn reserve(&self, host: &str, delay: Duration) -> Result<Duration> {
+        let mut conn = self.conn.lock().unwrap();
+        let wait: i64 = self
+            .reserve
+            .key(self.key("hosts"))
+            .arg(host)
+            .arg(now_ms())
+            .arg(delay.as_millis() as i64)
+            .invoke(&mut *conn)?;
+        Ok(Duration::from_millis(wait.max(0) as u64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    /// A fresh queue in memory and, if `REDIS_URL` is set, one in Redis
+    fn frontiers() -> Vec<Box<dyn Frontier>> {
+        let name = format!("test-{}", Ulid::new());
+        let mut out: Vec<Box<dyn Frontier>> =
+            vec![Box::new(SqliteFrontier::open_in_memory(&name).unwrap())];
+        if let Ok(url) = env::var("REDIS_URL") {
+            out.push(Box::new(RedisFrontier::open(&url, &name).unwrap()));
+        }
+        out
+    }
+
+    fn task(url: &str) -> Task {
+        Task {
+            source_id: "ECB".to_string(),
+            stage: "dataflow".to_string(),
+            url: url.to_string(),
+        }
+    }
+
+    const LONG: Duration = Duration::from_secs(60);
+    /// A lease which has expired by the next claim
+    const EXPIRED: Duration = Duration::from_secs(0);
+
+    #[test]
+    fn push_queues_tasks_once() {
+        for f in frontiers() {
+            assert_eq!(f.push(&[task("a"), task("b")]).unwrap(), 2);
+            assert_eq!(f.push(&[task("a"), task("c")]).unwrap(), 1);
+            assert_eq!(f.push(&[]).unwrap(), 0);
+            assert_eq!(f.stats().unwrap().pending, 3);
+        }
+    }
+
+    #[test]
+    fn claim_leases_tasks_in_order() {
+        for f in frontiers() {
+            f.push(&[task("a"), task("b")]).unwrap();
+            let a = f.claim(LONG).unwrap().unwrap();
+            let b = f.claim(LONG).unwrap().unwrap();
+            assert_eq!((a.task, a.attempt), (task("a"), 1));
+            assert_eq!((b.task.url.as_str(), b.attempt), ("b", 1));
+            assert!(f.claim(LONG).unwrap().is_none());
+            let stats = f.stats().unwrap();
+            assert_eq!((stats.pending, stats.leased), (0, 2));
+            assert!(!stats.is_drained());
+        }
+    }
+
+    #[test]
+    fn expired_leases_are_claimed_again() {
+        for f in frontiers() {
+            f.push(&[task("a")]).unwrap();
+            let first = f.claim(EXPIRED).unwrap().unwrap();
+            let second = f.claim(LONG).unwrap().unwrap();
+            assert_eq!(second.task, first.task);
+            assert_eq!(second.attempt, 2);
+            assert!(f.claim(LONG).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn tasks_fail_after_max_attempts() {
+        for f in frontiers() {
+            f.push(&[task("a")]).unwrap();
+            for attempt in 1..=MAX_ATTEMPTS {
+                let lease = f.claim(EXPIRED).unwrap().unwrap();
+                assert_eq!(lease.attempt, attempt);
+            }
+            assert!(f.claim(LONG).unwrap().is_none());
+            let stats = f.stats().unwrap();
+            assert_eq!(stats.failed, 1);
+            assert!(stats.is_drained());
+        }
+    }
+
+    #[test]
+    fn complete_needs_the_current_lease() {
+        for f in frontiers() {
+            f.push(&[task("a")]).unwrap();
+            let stale = f.claim(EXPIRED).unwrap().unwrap();
+            let current = f.claim(LONG).unwrap().unwrap();
+            assert!(!f.complete(&stale).unwrap());
+            assert!(f.complete(&current).unwrap());
+            assert!(!f.complete(&current).unwrap());
+            let stats = f.stats().unwrap();
+            assert_eq!(stats.done, 1);
+            assert!(stats.is_drained());
+        }
+    }
+
+    #[test]
+    fn is_drained_once_nothing_is_pending_or_leased() {
+        for f in frontiers() {
+            assert!(f.stats().unwrap().is_drained());
+            f.push(&[task("a")]).unwrap();
+            assert!(!f.stats().unwrap().is_drained());
+            let lease = f.claim(LONG).unwrap().unwrap();
+            assert!(!f.stats().unwrap().is_drained());
+            f.complete(&lease).unwrap();
+            assert!(f.stats().unwrap().is_drained());
+        }
+    }
+
+    #[test]
+    fn reserve_spaces_requests_to_a_host() {
+        let delay = Duration::from_secs(10);
+        for f in frontiers() {
+            assert_eq!(f.reserve("https://a", delay).unwrap(), Duration::ZERO);
+            let wait = f.reserve("https://a", delay).unwrap();
+            assert!(wait > Duration::from_secs(9) && wait <= delay);
+            let wait = f.reserve("https://a", delay).unwrap();
+            assert!(wait > Duration::from_secs(19));
+            assert_eq!(f.reserve("https://b", delay).unwrap(), Duration::ZERO);
+        }
+    }
+}
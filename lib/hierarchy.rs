@@ -0,0 +1,586 @@
+//! hierarchy navigates the code hierarchies of codelists and hierarchical codelists.
+//!
+//! A codelist arranges its codes through the `parent` of each code, while a
+//! hierarchy of a hierarchical codelist nests references to codes from other
+//! codelists, so the same code may appear more than once. Both are turned into
+//! a [`CodeTree`], whose nodes are addressed by [`NodeId`], e.g. to roll up
+//! the countries of a REF_AREA codelist into their regions:
+//!
+//! ```ignore
+//! let tree = codelist.tree()?;
+//! let de = tree.get("DE").unwrap();
+//! let region = tree.ancestor_at_depth(de, 0);
+//! ```
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    artefact::{Identifiable, Maintainable, Nameable},
+    localized::LanguagePreferences,
+    structure::{
+        CodeTypeElement, CodelistType, HierarchicalCodeTypeElement,
+        HierarchicalCodelistTypeElement, HierarchyTypeElement, LevelTypeClass,
+    },
+    urn::Urn,
+};
+
+/// Handle of a node within a [`CodeTree`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(usize);
+
+/// A code at one position of a hierarchy
+#[derive(Debug, Clone)]
+pub struct CodeNode<'a> {
+    /// ID of the node, which is the code ID in a codelist and the
+    /// hierarchical code ID in a hierarchy
+    pub id: &'a str,
+    /// ID of the code the node stands for
+    pub code_id: String,
+    /// URN of the code, when a hierarchy references it by URN
+    pub code_urn: Option<Urn>,
+    /// The code itself, when it is known, see `CodeTree::resolve_codes`
+    pub code: Option<&'a CodeTypeElement>,
+    /// Level URN given by a hierarchical code which skips levels
+    level: Option<&'a str>,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    depth: usize,
+}
+
+/// A forest of codes, built from a codelist or from one hierarchy of a
+/// hierarchical codelist
+#[derive(Debug, Clone)]
+pub struct CodeTree<'a> {
+    nodes: Vec<CodeNode<'a>>,
+    roots: Vec<NodeId>,
+    /// Formal levels of a hierarchy, from the top down
+    levels: Vec<&'a LevelTypeClass>,
+}
+
+impl<'a> CodeTree<'a> {
+    /// Builds the tree of a codelist from the `parent` of each code. Codes
+    /// whose parent is not in the codelist, as in partial codelists, become
+    /// roots. Duplicate code IDs and cycles of parents are errors.
+    pub fn from_codelist(codelist: &'a CodelistType) -> Result<Self> {
+        let mut tree = CodeTree {
+            nodes: vec![],
+            roots: vec![],
+            levels: vec![],
+        };
+        let mut index = HashMap::new();
+        for code in codelist.codes.iter().flatten() {
+            let id = match &code.id {
+                Some(id) => id.as_str(),
+                None => continue,
+            };
+            if index.insert(id, NodeId(tree.nodes.len())).is_some() {
+                return Err(anyhow!(
+                    "Duplicate code {} in codelist {}",
+                    id,
+                    codelist.id
+                ));
+            }
+            tree.nodes.push(CodeNode {
+                id,
+                code_id: id.to_string(),
+                code_urn: None,
+                code: Some(code),
+                level: None,
+                parent: None,
+                children: vec![],
+                depth: 0,
+            });
+        }
+
+        for idx in 0..tree.nodes.len() {
+            let node = NodeId(idx);
+            let parent = tree.nodes[idx]
+                .code
+                .and_then(|c| c.parent.as_deref())
+                .and_then(|p| index.get(p).copied());
+            match parent {
+                Some(parent) => {
+                    tree.nodes[idx].parent = Some(parent);
+                    tree.nodes[parent.0].children.push(node);
+                }
+                None => tree.roots.push(node),
+            }
+        }
+
+        // Every node on a cycle has a parent, so none of them is reachable from the roots
+        tree.set_depths();
+        if let Some(node) = tree.nodes.iter().find(|n| n.depth == usize::MAX) {
+            return Err(anyhow!(
+                "Cycle of parent codes in codelist {} at code {}",
+                codelist.id,
+                node.id
+            ));
+        }
+        Ok(tree)
+    }
+
+    /// Builds the tree of one hierarchy of a hierarchical codelist. A code
+    /// which is nested below itself is an error.
+    pub fn from_hierarchy(hierarchy: &'a HierarchyTypeElement) -> Result<Self> {
+        let mut levels = vec![];
+        let mut level = hierarchy.level.as_ref().as_ref();
+        while let Some(l) = level {
+            levels.push(l);
+            level = l.level.as_ref().as_ref();
+        }
+
+        let mut tree = CodeTree {
+            nodes: vec![],
+            roots: vec![],
+            levels,
+        };
+        for code in &hierarchy.hierarchical_codes {
+            let root = tree.add_hierarchical_code(code, None, 0)?;
+            tree.roots.push(root);
+        }
+        Ok(tree)
+    }
+
+    fn add_hierarchical_code(
+        &mut self,
+        code: &'a HierarchicalCodeTypeElement,
+        parent: Option<NodeId>,
+        depth: usize,
+    ) -> Result<NodeId> {
+        let code_urn = code.code.as_ref().and_then(|c| c.parse::<Urn>().ok());
+        let code_id = code
+            .code_id
+            .clone()
+            .or_else(|| code_urn.as_ref().and_then(|u| u.item_id()))
+            .ok_or_else(|| {
+                anyhow!("Hierarchical code {} references no code", code.id)
+            })?;
+
+        let node = NodeId(self.nodes.len());
+        if let Some(parent) = parent {
+            let repeated = std::iter::once(parent)
+                .chain(self.ancestors(parent))
+                .find(|a| self.nodes[a.0].code_id == code_id);
+            if let Some(ancestor) = repeated {
+                return Err(anyhow!(
+                    "Code {} is nested below itself at hierarchical code {} \
+                     (first at {})",
+                    code_id,
+                    code.id,
+                    self.nodes[ancestor.0].id
+                ));
+            }
+            self.nodes[parent.0].children.push(node);
+        }
+        self.nodes.push(CodeNode {
+            id: &code.id,
+            code_id,
+            code_urn,
+            code: None,
+            level: code.level.as_deref(),
+            parent,
+            children: vec![],
+            depth,
+        });
+        for child in code.hierarchical_codes.iter().flatten() {
+            self.add_hierarchical_code(child, Some(node), depth + 1)?;
+        }
+        Ok(node)
+    }
+
+    /// Sets the depth of every node reachable from the roots, leaving
+    /// `usize::MAX` for the others
+    fn set_depths(&mut self) {
+        for node in &mut self.nodes {
+            node.depth = usize::MAX;
+        }
+        let mut stack: Vec<_> = self.roots.iter().map(|r| (*r, 0)).collect();
+        while let Some((node, depth)) = stack.pop() {
+            self.nodes[node.0].depth = depth;
+            stack.extend(
+                self.nodes[node.0].children.iter().map(|c| (*c, depth + 1)),
+            );
+        }
+    }
+
+    /// Links the nodes of a hierarchy to the codes of the given codelists.
+    /// Nodes referencing their code by URN are matched on the codelist
+    /// agency, ID and version; nodes referencing it through a codelist alias
+    /// are matched on the code ID in any of the codelists.
+    pub fn resolve_codes<I>(&mut self, codelists: I)
+    where
+        I: IntoIterator<Item = &'a CodelistType>,
+    {
+        let codelists: Vec<_> = codelists.into_iter().collect();
+        for node in &mut self.nodes {
+            if node.code.is_some() {
+                continue;
+            }
+            let matching = codelists.iter().filter(|cl| match &node.code_urn {
+                Some(urn) => {
+                    cl.agency_id == urn.agency_id
+                        && cl.id == urn.id
+                        && cl
+                            .parsed_version()
+                            .is_some_and(|v| urn.version.matches(&v))
+                }
+                None => true,
+            });
+            node.code = matching
+                .flat_map(|cl| cl.codes.iter().flatten())
+                .find(|c| c.id.as_deref() == Some(node.code_id.as_str()));
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn node(&self, node: NodeId) -> &CodeNode<'a> {
+        &self.nodes[node.0]
+    }
+
+    /// All nodes in depth-first order
+    pub fn walk(&self) -> Vec<NodeId> {
+        let mut out = vec![];
+        for root in &self.roots {
+            out.push(*root);
+            out.extend(self.descendants(*root));
+        }
+        out
+    }
+
+    /// The first node standing for a code. In a codelist this is the only one.
+    pub fn get(&self, code_id: &str) -> Option<NodeId> {
+        self.find(code_id).next()
+    }
+
+    /// All nodes standing for a code, as codes may appear several times in a hierarchy
+    pub fn find<'b>(
+        &'b self,
+        code_id: &'b str,
+    ) -> impl Iterator<Item = NodeId> + 'b {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter(move |(_, n)| n.code_id == code_id)
+            .map(|(idx, _)| NodeId(idx))
+    }
+
+    /// The nodes without a parent
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    /// The nodes without children
+    pub fn leaves(&self) -> Vec<NodeId> {
+        self.walk()
+            .into_iter()
+            .filter(|n| self.nodes[n.0].children.is_empty())
+            .collect()
+    }
+
+    pub fn parent(&self, node: NodeId) -> Option<NodeId> {
+        self.nodes[node.0].parent
+    }
+
+    pub fn children(&self, node: NodeId) -> &[NodeId] {
+        &self.nodes[node.0].children
+    }
+
+    /// The ancestors of a node, from its parent up to the root
+    pub fn ancestors(&self, node: NodeId) -> Vec<NodeId> {
+        let mut out = vec![];
+        let mut current = self.parent(node);
+        while let Some(parent) = current {
+            out.push(parent);
+            current = self.parent(parent);
+        }
+        out
+    }
+
+    /// All nodes below a node, in depth-first order
+    pub fn descendants(&self, node: NodeId) -> Vec<NodeId> {
+        let mut out = vec![];
+        let mut stack: Vec<_> = self.children(node).iter().rev().collect();
+        while let Some(child) = stack.pop() {
+            out.push(*child);
+            stack.extend(self.children(*child).iter().rev());
+        }
+        out
+    }
+
+    /// The number of ancestors of a node, i.e. 0 for roots
+    pub fn depth(&self, node: NodeId) -> usize {
+        self.nodes[node.0].depth
+    }
+
+    /// The ancestor of a node (or the node itself) at the given depth, e.g.
+    /// the region of a country when regions are the roots
+    pub fn ancestor_at_depth(
+        &self,
+        node: NodeId,
+        depth: usize,
+    ) -> Option<NodeId> {
+        let own = self.depth(node);
+        if depth > own {
+            return None;
+        }
+        let mut current = node;
+        for _ in depth..own {
+            current = self.parent(current)?;
+        }
+        Some(current)
+    }
+
+    /// The formal levels of a hierarchy from the top down. Codelists have no levels.
+    pub fn levels(&self) -> &[&'a LevelTypeClass] {
+        &self.levels
+    }
+
+    /// The formal level of a node, which is given explicitly when a
+    /// hierarchical code skips levels and otherwise follows its depth
+    pub fn level(&self, node: NodeId) -> Option<&'a LevelTypeClass> {
+        let n = &self.nodes[node.0];
+        match n.level {
+            Some(level) => {
+                let level_id = level
+                    .parse::<Urn>()
+                    .ok()
+                    .and_then(|u| u.item_ids.last().cloned())
+                    .unwrap_or_else(|| level.to_string());
+                self.levels
+                    .iter()
+                    .find(|l| l.id() == Some(level_id.as_str()))
+                    .copied()
+            }
+            None => self.levels.get(n.depth).copied(),
+        }
+    }
+
+    /// The name of the level of a node in the most preferred available language
+    pub fn level_name(
+        &self,
+        node: NodeId,
+        languages: &LanguagePreferences,
+    ) -> Option<&'a str> {
+        self.level(node).map(|l| l.label(languages))
+    }
+
+    /// The name of the code of a node in the most preferred available
+    /// language, or the code ID when the code is not known
+    pub fn label(&self, node: NodeId, languages: &LanguagePreferences) -> &str {
+        let n = &self.nodes[node.0];
+        match n.code {
+            Some(code) => code.label(languages),
+            None => &n.code_id,
+        }
+    }
+}
+
+impl CodelistType {
+    /// The hierarchy of the codes, see `CodeTree::from_codelist`
+    pub fn tree(&self) -> Result<CodeTree<'_>> {
+        CodeTree::from_codelist(self)
+    }
+}
+
+impl HierarchicalCodelistTypeElement {
+    /// The trees of all hierarchies, keyed by hierarchy ID
+    pub fn trees(&self) -> Result<Vec<(&str, CodeTree<'_>)>> {
+        self.hierarchies
+            .iter()
+            .flatten()
+            .map(|h| {
+                Ok((
+                    h.id.as_deref().unwrap_or_default(),
+                    CodeTree::from_hierarchy(h)?,
+                ))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn codelist(codes: serde_json::Value) -> CodelistType {
+        serde_json::from_value(json!({
+            "id": "CL_AREA", "agencyID": "A", "version": "1.0",
+            "name": "Areas", "codes": codes
+        }))
+        .unwrap()
+    }
+
+    fn ids(tree: &CodeTree, nodes: &[NodeId]) -> Vec<String> {
+        nodes.iter().map(|n| tree.node(*n).id.to_string()).collect()
+    }
+
+    #[test]
+    fn codelists_are_arranged_by_parent() {
+        let cl = codelist(json!([
+            { "id": "EU", "name": "European Union" },
+            { "id": "DE", "name": "Germany", "parent": "EU" },
+            { "id": "BY", "name": "Bavaria", "parent": "DE" },
+            { "id": "FR", "name": "France", "parent": "EU" },
+            { "id": "US", "name": "United States" },
+            // partial codelists may lack the parent
+            { "id": "XK", "name": "Kosovo", "parent": "EUROPE" }
+        ]));
+        let tree = cl.tree().unwrap();
+        let node = |id: &str| tree.get(id).unwrap();
+
+        assert_eq!(tree.len(), 6);
+        assert_eq!(ids(&tree, tree.roots()), vec!["EU", "US", "XK"]);
+        assert_eq!(ids(&tree, tree.children(node("EU"))), vec!["DE", "FR"]);
+        assert_eq!(tree.parent(node("BY")), Some(node("DE")));
+        assert_eq!(ids(&tree, &tree.ancestors(node("BY"))), vec!["DE", "EU"]);
+        assert!(tree.ancestors(node("EU")).is_empty());
+        assert_eq!(
+            ids(&tree, &tree.descendants(node("EU"))),
+            vec!["DE", "BY", "FR"]
+        );
+        assert_eq!(
+            ids(&tree, &tree.walk()),
+            vec!["EU", "DE", "BY", "FR", "US", "XK"]
+        );
+        assert_eq!(ids(&tree, &tree.leaves()), vec!["BY", "FR", "US", "XK"]);
+        assert_eq!(tree.depth(node("EU")), 0);
+        assert_eq!(tree.depth(node("BY")), 2);
+        assert_eq!(tree.ancestor_at_depth(node("BY"), 0), Some(node("EU")));
+        assert_eq!(tree.ancestor_at_depth(node("BY"), 2), Some(node("BY")));
+        assert_eq!(tree.ancestor_at_depth(node("DE"), 2), None);
+        // codelists have no levels
+        assert!(tree.levels().is_empty());
+        let languages = LanguagePreferences::default();
+        assert_eq!(tree.level_name(node("DE"), &languages), None);
+        assert_eq!(tree.label(node("DE"), &languages), "Germany");
+    }
+
+    #[test]
+    fn cycles_and_duplicates_are_rejected() {
+        let cl = codelist(json!([
+            { "id": "EU", "name": "European Union" },
+            { "id": "A", "name": "A", "parent": "B" },
+            { "id": "B", "name": "B", "parent": "C" },
+            { "id": "C", "name": "C", "parent": "A" }
+        ]));
+        let err = CodeTree::from_codelist(&cl).unwrap_err();
+        assert!(err.to_string().contains("Cycle"), "{}", err);
+
+        let cl = codelist(json!([{ "id": "A", "name": "A", "parent": "A" }]));
+        assert!(CodeTree::from_codelist(&cl).is_err());
+
+        let cl = codelist(json!([
+            { "id": "A", "name": "A" },
+            { "id": "A", "name": "A again" }
+        ]));
+        let err = CodeTree::from_codelist(&cl).unwrap_err();
+        assert!(err.to_string().contains("Duplicate"), "{}", err);
+    }
+
+    fn hierarchy(codes: serde_json::Value) -> HierarchyTypeElement {
+        serde_json::from_value(json!({
+            "id": "H", "name": "Regions",
+            "level": {
+                "id": "REGION", "name": "Region",
+                "names": { "en": "Region", "de": "Region" },
+                "level": {
+                    "id": "COUNTRY", "name": "Country",
+                    "names": { "en": "Country", "de": "Land" },
+                    "level": { "id": "STATE", "name": "State" }
+                }
+            },
+            "hierarchicalCodes": codes
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn hierarchies_have_levels() {
+        let area = "urn:sdmx:org.sdmx.infomodel.codelist.Code=A:CL_AREA(1.0)";
+        let level =
+            "urn:sdmx:org.sdmx.infomodel.codelist.Level=A:HCL(1.0).H.STATE";
+        let h = hierarchy(json!([
+            {
+                "id": "1", "code": format!("{}.EU", area),
+                "hierarchicalCodes": [
+                    {
+                        "id": "2", "codeID": "DE",
+                        "hierarchicalCodes": [{ "id": "3", "codeID": "BY" }]
+                    },
+                    // skips the country level
+                    { "id": "4", "codeID": "DC", "level": level }
+                ]
+            },
+            {
+                "id": "5", "codeID": "NATO",
+                "hierarchicalCodes": [{ "id": "6", "codeID": "DE" }]
+            }
+        ]));
+        let cl = codelist(json!([
+            { "id": "EU", "name": "European Union" },
+            { "id": "DE", "name": "Germany", "names": { "de": "Deutschland" } }
+        ]));
+        let mut tree = CodeTree::from_hierarchy(&h).unwrap();
+        let node = |id: &str| {
+            NodeId(tree.nodes.iter().position(|n| n.id == id).unwrap())
+        };
+
+        assert_eq!(ids(&tree, tree.roots()), vec!["1", "5"]);
+        assert_eq!(tree.node(node("1")).code_id, "EU");
+        assert_eq!(ids(&tree, tree.children(node("1"))), vec!["2", "4"]);
+        assert_eq!(ids(&tree, &tree.ancestors(node("3"))), vec!["2", "1"]);
+        assert_eq!(
+            ids(&tree, &tree.descendants(node("1"))),
+            vec!["2", "3", "4"]
+        );
+        assert_eq!(tree.depth(node("3")), 2);
+        // codes may appear more than once
+        assert_eq!(
+            ids(&tree, &tree.find("DE").collect::<Vec<_>>()),
+            vec!["2", "6"]
+        );
+        assert_eq!(tree.get("DE"), Some(node("2")));
+
+        let levels: Vec<_> = tree.levels().iter().map(|l| l.id()).collect();
+        assert_eq!(
+            levels,
+            vec![Some("REGION"), Some("COUNTRY"), Some("STATE")]
+        );
+        let de = LanguagePreferences::new(vec!["de"]);
+        assert_eq!(tree.level_name(node("1"), &de), Some("Region"));
+        assert_eq!(tree.level_name(node("2"), &de), Some("Land"));
+        assert_eq!(tree.level_name(node("3"), &de), Some("State"));
+        assert_eq!(tree.level_name(node("4"), &de), Some("State"));
+
+        let (eu, germany, bavaria) = (node("1"), node("2"), node("3"));
+        assert_eq!(tree.label(germany, &de), "DE");
+        tree.resolve_codes(vec![&cl]);
+        assert_eq!(tree.label(eu, &de), "European Union");
+        assert_eq!(tree.label(germany, &de), "Deutschland");
+        assert_eq!(tree.label(bavaria, &de), "BY");
+    }
+
+    #[test]
+    fn codes_nested_below_themselves_are_rejected() {
+        let h = hierarchy(json!([{
+            "id": "1", "codeID": "EU",
+            "hierarchicalCodes": [{
+                "id": "2", "codeID": "DE",
+                "hierarchicalCodes": [{ "id": "3", "codeID": "EU" }]
+            }]
+        }]));
+        let err = CodeTree::from_hierarchy(&h).unwrap_err();
+        assert!(err.to_string().contains("nested below itself"), "{}", err);
+    }
+}
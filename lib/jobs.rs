@@ -0,0 +1,636 @@
+//! jobs persists the crawl jobs of the crawl service (see `serve`) in a
+//! SQLite database, so queued and finished jobs survive restarts.
+//!
+//! A job crawls a list of sources in order. The report of each source is
+//! saved as soon as it is crawled, so the progress of a running job can be
+//! followed and its partial report fetched. Reports are kept in a table of
+//! their own along with their request counts, so listing jobs and finding
+//! the last crawl of a source don't read the reports of every job.
+
+use std::{path::Path, sync::Mutex};
+
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use crate::report::CrawlReport;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS jobs (
+    id TEXT PRIMARY KEY,
+    sources TEXT NOT NULL,
+    status TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    started_at TEXT,
+    finished_at TEXT,
+    current_source TEXT,
+    error TEXT
+);
+
+CREATE TABLE IF NOT EXISTS job_reports (
+    id INTEGER PRIMARY KEY,
+    job_id TEXT NOT NULL REFERENCES jobs(id),
+    source_id TEXT NOT NULL,
+    started_at TEXT NOT NULL,
+    requests INTEGER NOT NULL,
+    errors INTEGER NOT NULL,
+    report TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS job_reports_job ON job_reports(job_id, id);
+CREATE INDEX IF NOT EXISTS job_reports_source
+    ON job_reports(source_id, errors, started_at);
+
+CREATE TABLE IF NOT EXISTS schedule_runs (
+    id INTEGER PRIMARY KEY,
+    source_id TEXT NOT NULL,
+    due_at TEXT NOT NULL,
+    triggered_at TEXT NOT NULL,
+    job_id TEXT REFERENCES jobs(id),
+    skipped TEXT
+);
+CREATE INDEX IF NOT EXISTS schedule_runs_source
+    ON schedule_runs(source_id, id);
+";
+
+/// The columns of a job, with the progress summed up from its reports
+const COLUMNS: &str = "id, sources, status, created_at, started_at, \
+    finished_at, current_source, error, \
+    (SELECT COUNT(*) FROM job_reports r WHERE r.job_id = jobs.id), \
+    (SELECT COALESCE(SUM(requests), 0) FROM job_reports r \
+     WHERE r.job_id = jobs.id), \
+    (SELECT COALESCE(SUM(errors), 0) FROM job_reports r \
+     WHERE r.job_id = jobs.id)";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Completed => "completed",
+            JobStatus::Failed => "failed",
+            JobStatus::Cancelled => "cancelled",
+        }
+    }
+
+    fn parse(s: &str) -> Result<Self> {
+        Ok(match s {
+            "queued" => JobStatus::Queued,
+            "running" => JobStatus::Running,
+            "completed" => JobStatus::Completed,
+            "failed" => JobStatus::Failed,
+            "cancelled" => JobStatus::Cancelled,
+            _ => return Err(anyhow!("Unknown job status {}", s)),
+        })
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, JobStatus::Queued | JobStatus::Running)
+    }
+}
+
+/// A crawl of one or more sources
+#[derive(Serialize, Debug, Clone)]
+pub struct Job {
+    pub id: String,
+    /// IDs of the sources to crawl, in order
+    pub sources: Vec<String>,
+    pub status: JobStatus,
+    /// Times in RFC 3339 format
+    pub created_at: String,
+    pub started_at: Option<String>,
+    pub finished_at: Option<String>,
+    pub progress: Progress,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Progress {
+    /// Number of sources crawled
+    pub done: usize,
+    pub total: usize,
+    /// The source being crawled
+    pub current: Option<String>,
+    /// Requests of the crawled sources
+    pub requests: usize,
+    /// Requests of the crawled sources that failed
+    pub failed: usize,
+}
+
+/// A time a scheduled source was due, and the job queued for it
+#[derive(Serialize, Debug, Clone)]
+pub struct ScheduleRun {
+    pub source_id: String,
+    /// Times in RFC 3339 format
+    pub due_at: String,
+    pub triggered_at: String,
+    pub job_id: Option<String>,
+    /// Why no job was queued
+    pub skipped: Option<String>,
+}
+
+/// A SQLite database of crawl jobs
+pub struct JobStore {
+    conn: Mutex<Connection>,
+}
+
+impl JobStore {
+    /// Opens (creating if needed) the database at the given path
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        JobStore::init(Connection::open(path)?)
+    }
+
+    /// Opens a temporary database that only lives in memory
+    pub fn open_in_memory() -> Result<Self> {
+        JobStore::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(JobStore {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, Connection>> {
+        self.conn
+            .lock()
+            .map_err(|_| anyhow!("Job store connection lock was poisoned"))
+    }
+
+    /// Queues a crawl of the given sources
+    pub fn enqueue(&self, sources: Vec<String>) -> Result<Job> {
+        let id = Ulid::new().to_string();
+        self.lock()?.execute(
+            "INSERT INTO jobs (id, sources, status, created_at) \
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                id,
+                serde_json::to_string(&sources)?,
+                JobStatus::Queued.as_str(),
+                Utc::now().to_rfc3339(),
+            ],
+        )?;
+        self.get(&id)?
+            .ok_or_else(|| anyhow!("Job {} was not saved", id))
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<Job>> {
+        let conn = self.lock()?;
+        let row = conn
+            .query_row(
+                &format!("SELECT {} FROM jobs WHERE id = ?1", COLUMNS),
+                params![id],
+                read_row,
+            )
+            .optional()?;
+        row.map(job).transpose()
+    }
+
+    /// All jobs, newest first. Jobs are ordered by insertion rather than by
+    /// ID, as ULIDs made in the same millisecond are in random order.
+    pub fn list(&self) -> Result<Vec<Job>> {
+        let conn = self.lock()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM jobs ORDER BY rowid DESC",
+            COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![], read_row)?;
+        let mut out = vec![];
+        for row in rows {
+            out.push(job(row?)?);
+        }
+        Ok(out)
+    }
+
+    /// The reports of the sources a job has crawled so far
+    pub fn reports(&self, id: &str) -> Result<Option<Vec<CrawlReport>>> {
+        let conn = self.lock()?;
+        let exists: Option<i64> = conn
+            .query_row("SELECT 1 FROM jobs WHERE id = ?1", params![id], |r| {
+                r.get(0)
+            })
+            .optional()?;
+        if exists.is_none() {
+            return Ok(None);
+        }
+        let mut stmt = conn.prepare(
+            "SELECT report FROM job_reports WHERE job_id = ?1 ORDER BY id",
+        )?;
+        let rows = stmt.query_map(params![id], |r| r.get::<_, String>(0))?;
+        let mut out = vec![];
+        for report in rows {
+            out.push(serde_json::from_str(&report?)?);
+        }
+        Ok(Some(out))
+    }
+
+    /// When the newest crawl of a source without failed requests started,
+    /// in RFC 3339 format. Start times are all in UTC, so they compare as
+    /// strings.
+    pub fn last_clean_crawl(&self, source_id: &str) -> Result<Option<String>> {
+        Ok(self.lock()?.query_row(
+            "SELECT MAX(started_at) FROM job_reports \
+             WHERE source_id = ?1 AND errors = 0",
+            params![source_id],
+            |r| r.get(0),
+        )?)
+    }
+
+    /// The oldest queued job
+    pub fn next_queued(&self) -> Result<Option<Job>> {
+        let conn = self.lock()?;
+        let row = conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM jobs WHERE status = ?1 \
+                     ORDER BY rowid LIMIT 1",
+                    COLUMNS
+                ),
+                params![JobStatus::Queued.as_str()],
+                read_row,
+            )
+            .optional()?;
+        row.map(job).transpose()
+    }
+
+    /// Marks a queued job as running. Returns false if the job is no longer
+    /// queued, e.g. because it was cancelled.
+    pub fn start(&self, id: &str) -> Result<bool> {
+        let changed = self.lock()?.execute(
+            "UPDATE jobs SET status = ?2, started_at = ?3 \
+             WHERE id = ?1 AND status = ?4",
+            params![
+                id,
+                JobStatus::Running.as_str(),
+                Utc::now().to_rfc3339(),
+                JobStatus::Queued.as_str(),
+            ],
+        )?;
+        Ok(changed > 0)
+    }
+
+    pub fn set_current(&self, id: &str, source_id: &str) -> Result<()> {
+        self.lock()?.execute(
+            "UPDATE jobs SET current_source = ?2 WHERE id = ?1",
+            params![id, source_id],
+        )?;
+        Ok(())
+    }
+
+    /// Appends the report of a crawled source to a job
+    pub fn add_report(&self, id: &str, report: &CrawlReport) -> Result<()> {
+        let mut conn = self.lock()?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO job_reports \
+             (job_id, source_id, started_at, requests, errors, report) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                id,
+                report.source_id,
+                report.started_at,
+                report.requests() as i64,
+                report.errors() as i64,
+                serde_json::to_string(report)?,
+            ],
+        )?;
+        tx.execute(
+            "UPDATE jobs SET current_source = NULL WHERE id = ?1",
+            params![id],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Records the outcome of a running job. Jobs that were cancelled while
+    /// running keep their status.
+    pub fn finish(
+        &self,
+        id: &str,
+        status: JobStatus,
+        error: Option<&str>,
+    ) -> Result<()> {
+        self.lock()?.execute(
+            "UPDATE jobs SET status = ?2, error = ?3, finished_at = ?4, \
+             current_source = NULL \
+             WHERE id = ?1 AND status = ?5",
+            params![
+                id,
+                status.as_str(),
+                error,
+                Utc::now().to_rfc3339(),
+                JobStatus::Running.as_str(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Cancels a queued or running job. Returns false if the job had
+    /// already finished.
+    pub fn cancel(&self, id: &str) -> Result<bool> {
+        let changed = self.lock()?.execute(
+            "UPDATE jobs SET status = ?2, finished_at = ?3, \
+             current_source = NULL \
+             WHERE id = ?1 AND status IN (?4, ?5)",
+            params![
+                id,
+                JobStatus::Cancelled.as_str(),
+                Utc::now().to_rfc3339(),
+                JobStatus::Queued.as_str(),
+                JobStatus::Running.as_str(),
+            ],
+        )?;
+        Ok(changed > 0)
+    }
+
+    /// Whether a queued or running job includes the source
+    pub fn is_active(&self, source_id: &str) -> Result<bool> {
+        let conn = self.lock()?;
+        let mut stmt =
+            conn.prepare("SELECT sources FROM jobs WHERE status IN (?1, ?2)")?;
+        let rows = stmt.query_map(
+            params![JobStatus::Queued.as_str(), JobStatus::Running.as_str()],
+            |r| r.get::<_, String>(0),
+        )?;
+        for sources in rows {
+            let sources: Vec<String> = serde_json::from_str(&sources?)?;
+            if sources.iter().any(|s| s == source_id) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    pub fn add_schedule_run(&self, run: &ScheduleRun) -> Result<()> {
+        self.lock()?.execute(
+            "INSERT INTO schedule_runs \
+             (source_id, due_at, triggered_at, job_id, skipped) \
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                run.source_id,
+                run.due_at,
+                run.triggered_at,
+                run.job_id,
+                run.skipped,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Whether the job was queued by the schedule of its source
+    pub fn is_scheduled(&self, job_id: &str) -> Result<bool> {
+        Ok(self.lock()?.query_row(
+            "SELECT EXISTS (SELECT 1 FROM schedule_runs WHERE job_id = ?1)",
+            params![job_id],
+            |r| r.get(0),
+        )?)
+    }
+
+    /// The latest runs of a scheduled source, newest first
+    pub fn schedule_runs(
+        &self,
+        source_id: &str,
+        limit: usize,
+    ) -> Result<Vec<ScheduleRun>> {
+        let conn = self.lock()?;
+        let mut stmt = conn.prepare(
+            "SELECT source_id, due_at, triggered_at, job_id, skipped \
+             FROM schedule_runs WHERE source_id = ?1 \
+             ORDER BY id DESC LIMIT ?2",
+        )?;
+        let rows = stmt.query_map(params![source_id, limit as i64], |r| {
+            Ok(ScheduleRun {
+                source_id: r.get(0)?,
+                due_at: r.get(1)?,
+                triggered_at: r.get(2)?,
+                job_id: r.get(3)?,
+                skipped: r.get(4)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Queues the jobs that were running when the service stopped again, so
+    /// they are crawled from the start. Returns the number of jobs.
+    pub fn requeue_interrupted(&self) -> Result<usize> {
+        let mut conn = self.lock()?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            "DELETE FROM job_reports WHERE job_id IN \
+             (SELECT id FROM jobs WHERE status = ?1)",
+            params![JobStatus::Running.as_str()],
+        )?;
+        let requeued = tx.execute(
+            "UPDATE jobs SET status = ?1, started_at = NULL, \
+             current_source = NULL \
+             WHERE status = ?2",
+            params![JobStatus::Queued.as_str(), JobStatus::Running.as_str()],
+        )?;
+        tx.commit()?;
+        Ok(requeued)
+    }
+}
+
+type JobRow = (
+    String,
+    String,
+    String,
+    String,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    i64,
+    i64,
+    i64,
+);
+
+fn read_row(r: &Row) -> rusqlite::Result<JobRow> {
+    Ok((
+        r.get(0)?,
+        r.get(1)?,
+        r.get(2)?,
+        r.get(3)?,
+        r.get(4)?,
+        r.get(5)?,
+        r.get(6)?,
+        r.get(7)?,
+        r.get(8)?,
+        r.get(9)?,
+        r.get(10)?,
+    ))
+}
+
+fn job(row: JobRow) -> Result<Job> {
+    let (
+        id,
+        sources,
+        status,
+        created_at,
+        started_at,
+        finished_at,
+        current,
+        error,
+        done,
+        requests,
+        failed,
+    ) = row;
+    let sources: Vec<String> = serde_json::from_str(&sources)?;
+    Ok(Job {
+        id,
+        progress: Progress {
+            done: done as usize,
+            total: sources.len(),
+            current,
+            requests: requests as usize,
+            failed: failed as usize,
+        },
+        sources,
+        status: JobStatus::parse(&status)?,
+        created_at,
+        started_at,
+        finished_at,
+        error,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        queries::RestVersion,
+        report::{RequestReport, StageReport},
+    };
+
+    fn sources(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn status(jobs: &JobStore, id: &str) -> JobStatus {
+        jobs.get(id).unwrap().unwrap().status
+    }
+
+    #[test]
+    fn jobs_run_in_queue_order() {
+        let jobs = JobStore::open_in_memory().unwrap();
+        let first = jobs.enqueue(sources(&["A"])).unwrap();
+        let second = jobs.enqueue(sources(&["B", "C"])).unwrap();
+        assert_eq!(first.status, JobStatus::Queued);
+        assert_eq!(second.progress.total, 2);
+
+        assert_eq!(jobs.next_queued().unwrap().unwrap().id, first.id);
+        assert!(jobs.start(&first.id).unwrap());
+        assert!(!jobs.start(&first.id).unwrap());
+        assert_eq!(status(&jobs, &first.id), JobStatus::Running);
+        assert_eq!(jobs.next_queued().unwrap().unwrap().id, second.id);
+
+        jobs.finish(&first.id, JobStatus::Completed, None).unwrap();
+        let finished = jobs.get(&first.id).unwrap().unwrap();
+        assert_eq!(finished.status, JobStatus::Completed);
+        assert!(finished.finished_at.is_some());
+        // newest first
+        let listed: Vec<_> =
+            jobs.list().unwrap().into_iter().map(|j| j.id).collect();
+        assert_eq!(listed, vec![second.id, first.id]);
+    }
+
+    #[test]
+    fn cancelled_jobs_stay_cancelled() {
+        let jobs = JobStore::open_in_memory().unwrap();
+        let queued = jobs.enqueue(sources(&["A"])).unwrap();
+        assert!(jobs.cancel(&queued.id).unwrap());
+        assert!(!jobs.start(&queued.id).unwrap());
+        assert!(jobs.next_queued().unwrap().is_none());
+
+        let running = jobs.enqueue(sources(&["A"])).unwrap();
+        jobs.start(&running.id).unwrap();
+        assert!(jobs.cancel(&running.id).unwrap());
+        jobs.finish(&running.id, JobStatus::Failed, Some("cancelled"))
+            .unwrap();
+        let job = jobs.get(&running.id).unwrap().unwrap();
+        assert_eq!(job.status, JobStatus::Cancelled);
+        assert_eq!(job.error, None);
+        assert!(!jobs.cancel(&running.id).unwrap());
+    }
+
+    #[test]
+    fn reports_track_progress() {
+        let jobs = JobStore::open_in_memory().unwrap();
+        let job = jobs.enqueue(sources(&["A", "B"])).unwrap();
+        jobs.start(&job.id).unwrap();
+        jobs.set_current(&job.id, "A").unwrap();
+        let progress = jobs.get(&job.id).unwrap().unwrap().progress;
+        assert_eq!(progress.current.as_deref(), Some("A"));
+
+        let mut report = CrawlReport::new("A", RestVersion::V2_1);
+        let mut stage = StageReport::new("dataflows");
+        stage.requests.push(RequestReport::new("http://a/1"));
+        let mut failed = RequestReport::new("http://a/2");
+        failed.error = Some("timeout".into());
+        stage.requests.push(failed);
+        report.stages.push(stage);
+        jobs.add_report(&job.id, &report).unwrap();
+
+        let progress = jobs.get(&job.id).unwrap().unwrap().progress;
+        assert_eq!((progress.done, progress.total), (1, 2));
+        assert_eq!((progress.requests, progress.failed), (2, 1));
+        assert_eq!(progress.current, None);
+        let reports = jobs.reports(&job.id).unwrap().unwrap();
+        assert_eq!(reports[0].source_id, "A");
+        assert!(jobs.reports("missing").unwrap().is_none());
+        // the crawl had a failed request
+        assert_eq!(jobs.last_clean_crawl("A").unwrap(), None);
+    }
+
+    #[test]
+    fn interrupted_jobs_are_queued_again() {
+        let jobs = JobStore::open_in_memory().unwrap();
+        let job = jobs.enqueue(sources(&["A"])).unwrap();
+        jobs.start(&job.id).unwrap();
+        jobs.add_report(&job.id, &CrawlReport::new("A", RestVersion::V2_1))
+            .unwrap();
+        let done = jobs.enqueue(sources(&["B"])).unwrap();
+        jobs.start(&done.id).unwrap();
+        jobs.finish(&done.id, JobStatus::Completed, None).unwrap();
+
+        assert!(jobs.is_active("A").unwrap());
+        assert!(!jobs.is_active("B").unwrap());
+        assert_eq!(jobs.requeue_interrupted().unwrap(), 1);
+        let job = jobs.get(&job.id).unwrap().unwrap();
+        assert_eq!(job.status, JobStatus::Queued);
+        assert_eq!((job.started_at, job.progress.done), (None, 0));
+        assert_eq!(status(&jobs, &done.id), JobStatus::Completed);
+    }
+
+    #[test]
+    fn schedule_runs_are_listed_newest_first() {
+        let jobs = JobStore::open_in_memory().unwrap();
+        for due_at in &["2021-01-01T00:00:00Z", "2021-01-02T00:00:00Z"] {
+            jobs.add_schedule_run(&ScheduleRun {
+                source_id: "A".into(),
+                due_at: due_at.to_string(),
+                triggered_at: due_at.to_string(),
+                job_id: None,
+                skipped: Some("busy".into()),
+            })
+            .unwrap();
+        }
+        let runs = jobs.schedule_runs("A", 1).unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].due_at, "2021-01-02T00:00:00Z");
+        assert_eq!(jobs.schedule_runs("A", 10).unwrap().len(), 2);
+        assert!(jobs.schedule_runs("B", 10).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,298 @@
+//! localized handles text which is given in several languages, like the
+//! `names` and `descriptions` of artefacts.
+//!
+//! Languages are identified by IETF BCP 47 tags such as `en`, `fr-CH` or
+//! `zh-Hant`. Tags are compared case-insensitively, and a preference for
+//! `en-GB` is satisfied by `en` (and the other way round) when no exact
+//! match exists.
+
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// Text in one or more languages, keyed by language tag
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct LocalizedString(BTreeMap<String, String>);
+
+impl LocalizedString {
+    pub fn new() -> Self {
+        LocalizedString::default()
+    }
+
+    pub fn insert<L: ToString, S: ToString>(&mut self, language: L, text: S) {
+        self.0.insert(language.to_string(), text.to_string());
+    }
+
+    /// The text in exactly the given language, ignoring case
+    pub fn get(&self, language: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(l, _)| l.eq_ignore_ascii_case(language))
+            .map(|(_, t)| t.as_str())
+    }
+
+    /// The text in the language that best matches the given one. This first
+    /// tries the tag itself, then the tag with its subtags removed one by one
+    /// (`de-CH-1996`, `de-CH`, `de`), and finally any more specific tag
+    /// (`de-AT` for `de`).
+    pub fn lookup(&self, language: &str) -> Option<&str> {
+        let mut tag = language;
+        loop {
+            if let Some(text) = self.get(tag) {
+                return Some(text);
+            }
+            match tag.rsplit_once('-') {
+                Some((prefix, _)) => tag = prefix,
+                None => break,
+            }
+        }
+        self.0
+            .iter()
+            .find(|(l, _)| is_subtag_of(l, language))
+            .map(|(_, t)| t.as_str())
+    }
+
+    /// The language tags the text is available in
+    pub fn languages(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(|l| l.as_str())
+    }
+
+    /// The text in all languages
+    pub fn values(&self) -> impl Iterator<Item = &str> {
+        self.0.values().map(|t| t.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(l, t)| (l.as_str(), t.as_str()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<'de> Deserialize<'de> for LocalizedString {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        // SDMX-JSON allows any value in the map, but only strings are meaningful
+        let map: BTreeMap<String, Option<serde_json::Value>> =
+            BTreeMap::deserialize(deserializer)?;
+        Ok(LocalizedString(
+            map.into_iter()
+                .filter_map(|(l, v)| match v {
+                    Some(serde_json::Value::String(s)) => Some((l, s)),
+                    _ => None,
+                })
+                .collect(),
+        ))
+    }
+}
+
+/// Whether `tag` is a more specific form of `language`, e.g. `en-GB` of `en`
+fn is_subtag_of(tag: &str, language: &str) -> bool {
+    tag.len() > language.len()
+        && tag.as_bytes()[language.len()] == b'-'
+        && tag[..language.len()].eq_ignore_ascii_case(language)
+}
+
+/// An ordered list of preferred languages used to pick one label from a
+/// localized text
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LanguagePreferences {
+    languages: Vec<String>,
+}
+
+impl LanguagePreferences {
+    pub fn new<I, S>(languages: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: ToString,
+    {
+        LanguagePreferences {
+            languages: languages.into_iter().map(|l| l.to_string()).collect(),
+        }
+    }
+
+    /// Appends the content languages of a message (`contentLanguages` in
+    /// its meta) as fallbacks, in the order given by the message
+    pub fn with_content_languages(mut self, content: &[String]) -> Self {
+        for language in content {
+            if !self
+                .languages
+                .iter()
+                .any(|l| l.eq_ignore_ascii_case(language))
+            {
+                self.languages.push(language.clone());
+            }
+        }
+        self
+    }
+
+    pub fn languages(&self) -> &[String] {
+        &self.languages
+    }
+
+    /// Picks the label for the most preferred available language. Without a
+    /// match this falls back to `default`, which is the single-language
+    /// field of the message (e.g. `name`), and then to any language.
+    pub fn resolve<'a>(
+        &self,
+        localized: Option<&'a LocalizedString>,
+        default: &'a str,
+    ) -> &'a str {
+        self.resolve_opt(localized, Some(default))
+            .unwrap_or(default)
+    }
+
+    /// Like `resolve`, for optional fields like `description`
+    pub fn resolve_opt<'a>(
+        &self,
+        localized: Option<&'a LocalizedString>,
+        default: Option<&'a str>,
+    ) -> Option<&'a str> {
+        let preferred = localized.and_then(|text| {
+            self.languages.iter().find_map(|l| text.lookup(l))
+        });
+        preferred
+            .or_else(|| default.filter(|d| !d.is_empty()))
+            .or_else(|| localized.and_then(|text| text.values().next()))
+    }
+}
+
+impl fmt::Display for LanguagePreferences {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.languages.join(","))
+    }
+}
+
+impl FromStr for LanguagePreferences {
+    type Err = anyhow::Error;
+
+    /// Parses a comma separated list in the format of the HTTP
+    /// Accept-Language header, e.g. `fr-CH, fr;q=0.9, en;q=0.8`
+    fn from_str(s: &str) -> Result<Self> {
+        let mut weighted = vec![];
+        for item in s.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+            let (language, q) = match item.split_once(';') {
+                Some((language, param)) => {
+                    let q = param
+                        .trim()
+                        .strip_prefix("q=")
+                        .and_then(|q| q.parse::<f32>().ok())
+                        .ok_or_else(|| {
+                            anyhow!("Invalid language weight in {}", item)
+                        })?;
+                    (language.trim(), q)
+                }
+                None => (item, 1.0),
+            };
+            // a weight of 0 marks a language as not acceptable
+            if language == "*" || q <= 0.0 {
+                continue;
+            }
+            if !is_language_tag(language) {
+                return Err(anyhow!("Invalid language tag {}", language));
+            }
+            weighted.push((language, q));
+        }
+        // Stable, so languages with equal weights keep their order
+        weighted.sort_by(|a, b| b.1.total_cmp(&a.1));
+        Ok(LanguagePreferences::new(
+            weighted.into_iter().map(|(l, _)| l),
+        ))
+    }
+}
+
+/// A loose check of the BCP 47 syntax: alphanumeric subtags of 1 to 8
+/// characters separated by hyphens, starting with a 2 to 8 letter language
+fn is_language_tag(tag: &str) -> bool {
+    let mut subtags = tag.split('-');
+    let language = subtags.next().unwrap_or_default();
+    (2..=8).contains(&language.len())
+        && language.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags.all(|s| {
+            (1..=8).contains(&s.len())
+                && s.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(pairs: &[(&str, &str)]) -> LocalizedString {
+        let mut text = LocalizedString::new();
+        for (language, t) in pairs {
+            text.insert(language, t);
+        }
+        text
+    }
+
+    fn strings(s: &[&str]) -> Vec<String> {
+        s.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn lookup_falls_back_to_related_tags() {
+        let names = text(&[("de", "Land"), ("fr-CH", "Pays")]);
+        assert_eq!(names.lookup("DE"), Some("Land"));
+        assert_eq!(names.lookup("de-CH-1996"), Some("Land"));
+        assert_eq!(names.lookup("fr"), Some("Pays"));
+        assert_eq!(names.lookup("en"), None);
+    }
+
+    #[test]
+    fn accept_language_is_ordered_by_weight() {
+        let prefs: LanguagePreferences =
+            "en;q=0.5, fr-CH, *, de;q=0.9, it;q=0.5".parse().unwrap();
+        assert_eq!(prefs.languages(), strings(&["fr-CH", "de", "en", "it"]));
+        assert!("en;q=x".parse::<LanguagePreferences>().is_err());
+        assert!("e1".parse::<LanguagePreferences>().is_err());
+    }
+
+    #[test]
+    fn content_languages_follow_the_preferences() {
+        let prefs = LanguagePreferences::new(vec!["de", "EN"])
+            .with_content_languages(&strings(&["fr", "en", "it"]));
+        assert_eq!(prefs.languages(), strings(&["de", "EN", "fr", "it"]));
+    }
+
+    #[test]
+    fn labels_are_negotiated_in_order() {
+        let names = text(&[("en", "Country"), ("fr", "Pays"), ("it", "Paese")]);
+        let content = strings(&["it", "en"]);
+        let resolve = |prefs: LanguagePreferences, default| {
+            prefs.resolve(Some(&names), default).to_string()
+        };
+
+        // the preferences first, even over the name in the message language
+        let prefs = LanguagePreferences::new(vec!["es", "fr"]);
+        assert_eq!(
+            resolve(prefs.with_content_languages(&content), "Country"),
+            "Pays"
+        );
+        // then the content languages of the message
+        let prefs = LanguagePreferences::new(vec!["es"]);
+        assert_eq!(resolve(prefs.clone(), "Country"), "Country");
+        assert_eq!(
+            resolve(prefs.with_content_languages(&content), "Country"),
+            "Paese"
+        );
+        // languages with a weight of 0 are not asked for
+        let prefs: LanguagePreferences = "fr;q=0, es".parse().unwrap();
+        assert_eq!(prefs, LanguagePreferences::new(vec!["es"]));
+        assert_eq!(resolve(prefs, "Country"), "Country");
+        // then the name, and then any language
+        assert_eq!(
+            resolve(LanguagePreferences::default(), "Country"),
+            "Country"
+        );
+        assert_eq!(resolve(LanguagePreferences::default(), ""), "Country");
+        assert_eq!(
+            LanguagePreferences::default().resolve_opt(None, None),
+            None
+        );
+    }
+}
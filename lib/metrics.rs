@@ -0,0 +1,328 @@
+//! metrics counts the requests of crawls and exposes them in the Prometheus
+//! text format, either over HTTP at `/metrics` or as a file written when a
+//! crawl ends.
+
+use std::{
+    collections::BTreeMap,
+    convert::Infallible,
+    fmt::Write,
+    fs,
+    net::SocketAddr,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use tracing::info;
+
+use crate::report::RequestReport;
+
+/// Content type of the Prometheus text format
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Upper bounds of the request duration buckets, in seconds
+const DURATION_BUCKETS: &[f64] =
+    &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+/// Upper bounds of the response size buckets, in bytes
+const SIZE_BUCKETS: &[f64] = &[1e3, 1e4, 1e5, 1e6, 1e7, 1e8];
+
+/// The metrics of all crawls of a process. Clones share the same counters.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    inner: Arc<Mutex<Registry>>,
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+    /// Requests by source and status
+    requests: BTreeMap<(String, String), u64>,
+    /// Requests that failed by source, including those with a successful
+    /// status whose body couldn't be used
+    failures: BTreeMap<String, u64>,
+    durations: BTreeMap<String, Histogram>,
+    sizes: BTreeMap<String, Histogram>,
+    warc_bytes: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Clone)]
+struct Histogram {
+    bounds: &'static [f64],
+    /// Observations per bucket, not cumulative
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Histogram {
+            bounds,
+            counts: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        if let Some(idx) = self.bounds.iter().position(|b| value <= *b) {
+            self.counts[idx] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics::default()
+    }
+
+    /// Counts a request of a crawl of the source
+    pub fn observe(&self, source_id: &str, request: &RequestReport) {
+        let mut r = self.inner.lock().unwrap();
+        let source = source_id.to_string();
+        let status = request
+            .status
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| "none".to_string());
+        *r.requests.entry((source.clone(), status)).or_default() += 1;
+        if request.error.is_some() {
+            *r.failures.entry(source.clone()).or_default() += 1;
+        }
+        r.durations
+            .entry(source.clone())
+            .or_insert_with(|| Histogram::new(DURATION_BUCKETS))
+            .observe(request.duration_ms as f64 / 1000.0);
+        if let Some(bytes) = request.bytes {
+            r.sizes
+                .entry(source.clone())
+                .or_insert_with(|| Histogram::new(SIZE_BUCKETS))
+                .observe(bytes as f64);
+        }
+        if let Some(bytes) = request.warc_bytes {
+            *r.warc_bytes.entry(source).or_default() += bytes;
+        }
+    }
+
+    /// The metrics in the Prometheus text format
+    pub fn render(&self) -> String {
+        let r = self.inner.lock().unwrap();
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "sdmx_requests_total",
+            "counter",
+            "Requests by source and HTTP status, none if there was no response",
+        );
+        for ((source, status), n) in &r.requests {
+            let labels = [("source", source.as_str()), ("status", status)];
+            sample(&mut out, "sdmx_requests_total", &labels, *n as f64);
+        }
+
+        header(
+            &mut out,
+            "sdmx_request_failures_total",
+            "counter",
+            "Requests whose response could not be used",
+        );
+        for (source, n) in &r.failures {
+            let labels = [("source", source.as_str())];
+            sample(&mut out, "sdmx_request_failures_total", &labels, *n as f64);
+        }
+
+        histograms(
+            &mut out,
+            "sdmx_request_duration_seconds",
+            "Duration of requests, including reading the body",
+            &r.durations,
+        );
+        histograms(
+            &mut out,
+            "sdmx_response_size_bytes",
+            "Size of response bodies",
+            &r.sizes,
+        );
+
+        header(
+            &mut out,
+            "sdmx_warc_bytes_written_total",
+            "counter",
+            "Bytes written to WARC files",
+        );
+        for (source, n) in &r.warc_bytes {
+            let labels = [("source", source.as_str())];
+            sample(
+                &mut out,
+                "sdmx_warc_bytes_written_total",
+                &labels,
+                *n as f64,
+            );
+        }
+        out
+    }
+
+    /// Writes the metrics to a file in the Prometheus text format, e.g. for
+    /// the textfile collector of the node exporter
+    pub fn write_to<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, self.render())?;
+        Ok(())
+    }
+
+    /// Answers `GET /metrics`
+    pub fn respond(&self, req: &Request<Body>) -> Response<Body> {
+        if req.method() != Method::GET || req.uri().path() != "/metrics" {
+            return Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::empty())
+                .unwrap();
+        }
+        Response::builder()
+            .header(hyper::header::CONTENT_TYPE, CONTENT_TYPE)
+            .body(Body::from(self.render()))
+            .unwrap()
+    }
+}
+
+/// Serves the metrics at `/metrics` until the future is dropped
+pub async fn serve_metrics(metrics: Metrics, addr: SocketAddr) -> Result<()> {
+    let make_service = make_service_fn(move |_| {
+        let metrics = metrics.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let res = metrics.respond(&req);
+                async move { Ok::<_, Infallible>(res) }
+            }))
+        }
+    });
+    let server = Server::try_bind(&addr)?.serve(make_service);
+    info!(addr = %server.local_addr(), "Serving metrics");
+    server.await?;
+    Ok(())
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+}
+
+fn sample(out: &mut String, name: &str, labels: &[(&str, &str)], value: f64) {
+    let labels: Vec<_> = labels
+        .iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, escape(v)))
+        .collect();
+    writeln!(out, "{}{{{}}} {}", name, labels.join(","), value).unwrap();
+}
+
+fn histograms(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    histograms: &BTreeMap<String, Histogram>,
+) {
+    header(out, name, "histogram", help);
+    let bucket = format!("{}_bucket", name);
+    for (source, h) in histograms {
+        let mut cumulative = 0;
+        for (bound, count) in h.bounds.iter().zip(&h.counts) {
+            cumulative += count;
+            let le = bound.to_string();
+            let labels = [("source", source.as_str()), ("le", &le)];
+            sample(out, &bucket, &labels, cumulative as f64);
+        }
+        let labels = [("source", source.as_str()), ("le", "+Inf")];
+        sample(out, &bucket, &labels, h.count as f64);
+        let labels = [("source", source.as_str())];
+        sample(out, &format!("{}_sum", name), &labels, h.sum);
+        sample(out, &format!("{}_count", name), &labels, h.count as f64);
+    }
+}
+
+/// Escapes a label value
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(
+        status: Option<u16>,
+        duration_ms: u64,
+        bytes: Option<u64>,
+        error: Option<&str>,
+    ) -> RequestReport {
+        RequestReport {
+            url: "https://example.org/rest/dataflow".to_string(),
+            status,
+            duration_ms,
+            bytes,
+            warc_record_id: None,
+            warc_bytes: bytes.map(|b| b + 500),
+            repairs: 0,
+            error: error.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn requests_are_rendered_in_the_text_format() {
+        let metrics = Metrics::new();
+        metrics.observe("ECB", &request(Some(200), 80, Some(2000), None));
+        metrics.observe("ECB", &request(Some(200), 300, Some(50), None));
+        metrics.observe("ECB", &request(None, 70_000, None, Some("timeout")));
+        let out = metrics.render();
+        let lines: Vec<_> = out.lines().collect();
+
+        for line in &[
+            "# TYPE sdmx_requests_total counter",
+            "sdmx_requests_total{source=\"ECB\",status=\"200\"} 2",
+            "sdmx_requests_total{source=\"ECB\",status=\"none\"} 1",
+            "sdmx_request_failures_total{source=\"ECB\"} 1",
+            "# TYPE sdmx_request_duration_seconds histogram",
+            "sdmx_request_duration_seconds_bucket{source=\"ECB\",le=\"0.05\"} 0",
+            "sdmx_request_duration_seconds_bucket{source=\"ECB\",le=\"0.1\"} 1",
+            "sdmx_request_duration_seconds_bucket{source=\"ECB\",le=\"0.5\"} 2",
+            "sdmx_request_duration_seconds_bucket{source=\"ECB\",le=\"60\"} 2",
+            "sdmx_request_duration_seconds_bucket{source=\"ECB\",le=\"+Inf\"} 3",
+            "sdmx_request_duration_seconds_sum{source=\"ECB\"} 70.38",
+            "sdmx_request_duration_seconds_count{source=\"ECB\"} 3",
+            "sdmx_response_size_bytes_bucket{source=\"ECB\",le=\"1000\"} 1",
+            "sdmx_response_size_bytes_bucket{source=\"ECB\",le=\"10000\"} 2",
+            "sdmx_response_size_bytes_bucket{source=\"ECB\",le=\"+Inf\"} 2",
+            "sdmx_response_size_bytes_sum{source=\"ECB\"} 2050",
+            "sdmx_response_size_bytes_count{source=\"ECB\"} 2",
+            "sdmx_warc_bytes_written_total{source=\"ECB\"} 3050",
+        ] {
+            assert!(lines.contains(line), "missing {} in\n{}", line, out);
+        }
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        let metrics = Metrics::new();
+        metrics.observe("a\"b\\c\nd", &request(Some(404), 10, None, None));
+        assert!(metrics.render().contains(
+            "sdmx_requests_total{source=\"a\\\"b\\\\c\\nd\",status=\"404\"} 1"
+        ));
+    }
+
+    #[test]
+    fn only_get_metrics_is_served() {
+        let metrics = Metrics::new();
+        let get = |uri| Request::get(uri).body(Body::empty()).unwrap();
+        let res = metrics.respond(&get("/metrics"));
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[hyper::header::CONTENT_TYPE], CONTENT_TYPE);
+        let res = metrics.respond(&get("/"));
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+}
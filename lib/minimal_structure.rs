@@ -1,9 +1,49 @@
 use serde::{Deserialize, Serialize};
 
+use crate::{sdmx_sources::Source, version::Version};
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Dataflow {
     pub resource_id: String,
     pub agency_id: String,
     pub name: String,
-    pub version: Option<String>,
+    pub version: Option<Version>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Urn reference to the data structure definition of the dataflow
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub structure: Option<String>,
+}
+
+/// A flat, denormalized description of a dataflow and everything needed to
+/// find it, for bulk import into search engines like TypeSense
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CatalogEntry {
+    /// Unique ID of the entry across all sources, e.g. `ECB:ECB:EXR(1.0)`
+    pub id: String,
+    pub source_id: String,
+    pub source_name: Option<String>,
+    pub source_url: Option<String>,
+    #[serde(flatten)]
+    pub dataflow: Dataflow,
+    pub structure_name: Option<String>,
+    /// Dimension IDs in key order
+    pub dimensions: Vec<String>,
+    pub attributes: Vec<String>,
+    /// Names of the codelists used by the dimensions and attributes
+    pub codelists: Vec<String>,
+    /// Names of all codes in those codelists
+    pub codes: Vec<String>,
+    /// Names of the categories the dataflow is categorised under
+    pub categories: Vec<String>,
+    /// When the dataflow was fetched, in RFC 3339 format
+    pub fetched_at: String,
+}
+
+impl CatalogEntry {
+    /// Fills in the provider information from the source the entry was crawled from
+    pub fn set_source(&mut self, source: &Source) {
+        self.source_name = Some(source.name.clone());
+        self.source_url = Some(source.url.clone());
+    }
 }
@@ -1,8 +1,31 @@
+pub mod archive;
+pub mod artefact;
+pub mod components;
 pub mod crawler;
+pub mod dataset;
+pub mod diff;
+pub mod frontier;
+pub mod hierarchy;
+pub mod jobs;
+pub mod localized;
+pub mod metrics;
 pub mod minimal_structure;
+pub mod parse;
+pub mod publish;
 pub mod queries;
+pub mod report;
 pub mod reqwest_layer;
 pub mod reqwest_warc;
+pub mod robots;
+pub mod schedule;
+pub mod sdmx_ml;
 pub mod sdmx_sources;
+pub mod search;
+pub mod serve;
+pub mod store;
 pub mod structure;
+pub mod structure_v2;
+pub mod urn;
 pub mod util;
+pub mod validate;
+pub mod version;
@@ -0,0 +1,721 @@
+//! parse reads SDMX-JSON structure messages in one of two modes.
+//!
+//! [`ParseMode::Strict`] is meant for conformance testing: any unknown field
+//! or value that does not fit the schema fails the message, with the JSON
+//! path of every deviation.
+//!
+//! [`ParseMode::Lenient`] tolerates the quirks of providers which do not
+//! follow the schema closely. It repairs the message before deserializing it
+//! and records each repair as a [`Diagnostic`]:
+//!
+//! - numbers and booleans given as strings, and the other way round
+//! - single values where an array is expected, and arrays where a single
+//!   value is expected
+//! - missing required fields, which get a default (a `name` defaults to one
+//!   of the `names` or to the `id`)
+//! - the contents of `data` given at the top level of the message
+//!
+//! Values which cannot be repaired are removed, dropping the enclosing
+//! artefact when the value is required.
+//!
+//! Both modes read SDMX-JSON 1.0 and 2.0 messages, converting the latter
+//! with `structure_v2`.
+
+use std::{
+    collections::{HashMap, HashSet},
+    error, fmt, mem,
+};
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::{
+    structure::Structure,
+    structure_v2::{convert_data, SchemaVersion},
+};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ParseMode {
+    Strict,
+    #[default]
+    Lenient,
+}
+
+/// The kind of deviation from the schema
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticKind {
+    /// A field the schema does not define, which is ignored
+    UnknownField,
+    /// A value of the wrong type, which was converted in lenient mode
+    TypeMismatch,
+    /// A required field, which was given a default in lenient mode
+    MissingField,
+    /// A value or artefact which was removed because it could not be repaired
+    Removed,
+    /// The message has no `data` envelope around its artefacts
+    MissingEnvelope,
+}
+
+/// A deviation from the schema, located by its JSON path, e.g.
+/// `$.data.dataflows[3].version`
+#[derive(Serialize, Debug, Clone)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// The deviations which failed a message in strict mode
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} deviations from the schema", self.diagnostics.len())?;
+        for d in &self.diagnostics {
+            write!(f, "\n  {}", d)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for ParseError {}
+
+/// A parsed message with the deviations found in it
+#[derive(Debug, Clone)]
+pub struct Parsed<T> {
+    pub value: T,
+    /// The schema version of the message
+    pub version: SchemaVersion,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Repairs are retried at most this often for one path before the
+/// enclosing artefact is dropped
+const MAX_ATTEMPTS: usize = 4;
+
+/// Parses a structure message. Strict mode fails with a [`ParseError`] if
+/// there are any deviations; lenient mode only fails on invalid JSON or a
+/// message which cannot be repaired at all.
+///
+/// The schema version is detected from the message unless given, e.g. from
+/// the content type of the response. SDMX-JSON 2.0 messages are converted
+/// to the SDMX-JSON 1.0 model, see `structure_v2`.
+pub fn parse_structure(
+    body: &str,
+    version: Option<SchemaVersion>,
+    mode: ParseMode,
+) -> Result<Parsed<Structure>> {
+    let body = body.trim().trim_start_matches('\u{feff}');
+    let value: Value = serde_json::from_str(body)?;
+    parse_structure_value(value, version, mode)
+}
+
+pub fn parse_structure_value(
+    mut value: Value,
+    version: Option<SchemaVersion>,
+    mode: ParseMode,
+) -> Result<Parsed<Structure>> {
+    let mut diagnostics = vec![];
+    if mode == ParseMode::Lenient {
+        wrap_envelope(&mut value, &mut diagnostics);
+    }
+    let version = version.unwrap_or_else(|| SchemaVersion::detect(&value));
+    if version == SchemaVersion::V2 {
+        if let Some(data) = value.get_mut("data") {
+            convert_data(data);
+        }
+    }
+
+    if mode == ParseMode::Strict {
+        let (result, unknown) = deserialize(&value);
+        diagnostics.extend(unknown);
+        match result {
+            Ok(structure) if diagnostics.is_empty() => {
+                return Ok(Parsed {
+                    value: structure,
+                    version,
+                    diagnostics,
+                })
+            }
+            Ok(_) => {}
+            Err((path, message)) => diagnostics.push(mismatch(&path, &message)),
+        }
+        return Err(ParseError { diagnostics }.into());
+    }
+
+    // Paths of fields inserted as null, whose type is only known from the
+    // error the null causes
+    let mut inserted: HashSet<String> = HashSet::new();
+    let mut attempts: HashMap<String, usize> = HashMap::new();
+    loop {
+        let (result, unknown) = deserialize(&value);
+        let (path, message) = match result {
+            Ok(structure) => {
+                diagnostics.extend(unknown);
+                return Ok(Parsed {
+                    value: structure,
+                    version,
+                    diagnostics,
+                });
+            }
+            Err(e) => e,
+        };
+        let count = attempts.entry(json_path(&path)).or_insert(0);
+        *count += 1;
+        if *count > MAX_ATTEMPTS {
+            remove_artefact(&mut value, &path, &message, &mut diagnostics)?;
+            continue;
+        }
+        // A deviation is usually repeated in every item of a list, so each
+        // repair is applied to all of them at once rather than
+        // deserializing the message again for every item
+        let similar = similar(&value, &path);
+        if let Some(field) = message
+            .strip_prefix("missing field `")
+            .and_then(|m| m.strip_suffix('`'))
+        {
+            if matches!(get(&value, &path), Some(Value::Object(_))) {
+                for at in similar {
+                    let object = get_mut(&mut value, &at)
+                        .and_then(|v| v.as_object_mut());
+                    let object = match object {
+                        Some(o) if !o.contains_key(field) => o,
+                        _ => continue,
+                    };
+                    let default = match field {
+                        "name" => default_name(object),
+                        _ => Value::Null,
+                    };
+                    object.insert(field.to_string(), default);
+                    let mut field_path = at;
+                    field_path.push(Segment::Key(field.to_string()));
+                    diagnostics.push(Diagnostic {
+                        kind: DiagnosticKind::MissingField,
+                        path: json_path(&field_path),
+                        message: format!("{} is required but missing", field),
+                    });
+                    inserted.insert(json_path(&field_path));
+                }
+                continue;
+            }
+        } else if let Some(expected) = message
+            .strip_prefix("invalid type: ")
+            .and_then(|m| m.rsplit_once(", expected "))
+            .map(|(_, expected)| expected)
+        {
+            let node = get(&value, &path);
+            if node.and_then(|n| coerce(n, expected)).is_some() {
+                // Backwards, so removing an item does not move the ones
+                // still to be repaired
+                let mut repairs = vec![];
+                for at in similar.into_iter().rev() {
+                    let node = match get_mut(&mut value, &at) {
+                        Some(node) => node,
+                        None => continue,
+                    };
+                    match coerce(node, expected) {
+                        Some(repaired) => {
+                            *node = repaired;
+                            // Defaults of inserted fields are already
+                            // reported as missing
+                            if !inserted.contains(&json_path(&at)) {
+                                repairs.push(mismatch(&at, &message));
+                            }
+                        }
+                        None => {
+                            remove(&mut value, &at, &message, &mut repairs)?
+                        }
+                    }
+                }
+                diagnostics.extend(repairs.into_iter().rev());
+                continue;
+            }
+        }
+        remove(&mut value, &path, &message, &mut diagnostics)?;
+    }
+}
+
+/// A segment of a path into a JSON value
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+type DeserializeResult = (
+    std::result::Result<Structure, (Vec<Segment>, String)>,
+    Vec<Diagnostic>,
+);
+
+/// Deserializes a message, returning the path and message of the first
+/// error and the unknown fields
+fn deserialize(value: &Value) -> DeserializeResult {
+    let mut unknown = vec![];
+    let mut callback = |path: serde_ignored::Path| {
+        let path = ignored_path(&path);
+        unknown.push(Diagnostic {
+            kind: DiagnosticKind::UnknownField,
+            path: json_path(&path),
+            message: "is not defined by the schema".to_string(),
+        });
+    };
+    let de = serde_ignored::Deserializer::new(value, &mut callback);
+    let result = serde_path_to_error::deserialize(de).map_err(|e| {
+        let path = e
+            .path()
+            .iter()
+            .filter_map(|s| match s {
+                serde_path_to_error::Segment::Seq { index } => {
+                    Some(Segment::Index(*index))
+                }
+                serde_path_to_error::Segment::Map { key } => {
+                    Some(Segment::Key(key.clone()))
+                }
+                _ => None,
+            })
+            .collect();
+        (path, e.into_inner().to_string())
+    });
+    (result, unknown)
+}
+
+fn ignored_path(path: &serde_ignored::Path) -> Vec<Segment> {
+    use serde_ignored::Path;
+    match path {
+        Path::Root => vec![],
+        Path::Seq { parent, index } => {
+            let mut out = ignored_path(parent);
+            out.push(Segment::Index(*index));
+            out
+        }
+        Path::Map { parent, key } => {
+            let mut out = ignored_path(parent);
+            out.push(Segment::Key(key.clone()));
+            out
+        }
+        Path::Some { parent }
+        | Path::NewtypeStruct { parent }
+        | Path::NewtypeVariant { parent } => ignored_path(parent),
+    }
+}
+
+/// Formats a path like `$.data.dataflows[0]['odd key']`
+fn json_path(path: &[Segment]) -> String {
+    let mut out = "$".to_string();
+    for segment in path {
+        match segment {
+            Segment::Index(i) => out += &format!("[{}]", i),
+            Segment::Key(k)
+                if !k.is_empty()
+                    && k.chars().all(|c| c.is_alphanumeric() || c == '_') =>
+            {
+                out += &format!(".{}", k)
+            }
+            Segment::Key(k) => {
+                out += &format!("['{}']", k.replace('\'', "\\'"))
+            }
+        }
+    }
+    out
+}
+
+fn get<'a>(value: &'a Value, path: &[Segment]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |v, segment| match segment {
+        Segment::Key(k) => v.get(k.as_str()),
+        Segment::Index(i) => v.get(*i),
+    })
+}
+
+fn get_mut<'a>(
+    value: &'a mut Value,
+    path: &[Segment],
+) -> Option<&'a mut Value> {
+    path.iter().try_fold(value, |v, segment| match segment {
+        Segment::Key(k) => v.get_mut(k.as_str()),
+        Segment::Index(i) => v.get_mut(*i),
+    })
+}
+
+/// The paths of the values at the same place as the one at `path` in any
+/// item of the lists along it, e.g. the annotations of every code for
+/// `$.data.codelists[0].codes[3].annotations`, which are of the same JSON
+/// type. In document order, including `path` itself.
+fn similar(value: &Value, path: &[Segment]) -> Vec<Vec<Segment>> {
+    fn visit(
+        value: &Value,
+        rest: &[Segment],
+        kind: mem::Discriminant<Value>,
+        at: &mut Vec<Segment>,
+        out: &mut Vec<Vec<Segment>>,
+    ) {
+        match rest.split_first() {
+            None if mem::discriminant(value) == kind => out.push(at.clone()),
+            None => {}
+            Some((Segment::Key(k), rest)) => {
+                if let Some(v) = value.get(k.as_str()) {
+                    at.push(Segment::Key(k.clone()));
+                    visit(v, rest, kind, at, out);
+                    at.pop();
+                }
+            }
+            Some((Segment::Index(_), rest)) => {
+                for (i, v) in value.as_array().into_iter().flatten().enumerate()
+                {
+                    at.push(Segment::Index(i));
+                    visit(v, rest, kind, at, out);
+                    at.pop();
+                }
+            }
+        }
+    }
+    let mut out = vec![];
+    if let Some(node) = get(value, path) {
+        visit(value, path, mem::discriminant(node), &mut vec![], &mut out);
+    }
+    out
+}
+
+fn mismatch(path: &[Segment], message: &str) -> Diagnostic {
+    let kind = match message.starts_with("missing field") {
+        true => DiagnosticKind::MissingField,
+        false => DiagnosticKind::TypeMismatch,
+    };
+    Diagnostic {
+        kind,
+        path: json_path(path),
+        message: message.to_string(),
+    }
+}
+
+/// Some providers return the contents of `data` at the top level
+fn wrap_envelope(value: &mut Value, diagnostics: &mut Vec<Diagnostic>) {
+    let object = match value.as_object() {
+        Some(o) => o,
+        None => return,
+    };
+    let envelope = ["data", "meta", "errors"];
+    if object.is_empty()
+        || object.keys().any(|k| envelope.contains(&k.as_str()))
+    {
+        return;
+    }
+    let data = value.take();
+    *value = serde_json::json!({ "data": data });
+    diagnostics.push(Diagnostic {
+        kind: DiagnosticKind::MissingEnvelope,
+        path: "$".to_string(),
+        message: "the artefacts are not inside data".to_string(),
+    });
+}
+
+/// The name of an artefact without one: its name in any language, or its ID
+fn default_name(object: &Map<String, Value>) -> Value {
+    let localized = object
+        .get("names")
+        .and_then(|n| n.as_object())
+        .and_then(|n| n.values().find_map(|v| v.as_str()));
+    let name = localized
+        .or_else(|| object.get("id").and_then(|id| id.as_str()))
+        .unwrap_or_default();
+    Value::String(name.to_string())
+}
+
+/// Converts a value to the type serde expected, as described in its error
+/// message, e.g. `a sequence`, `f64` or `a string`
+fn coerce(value: &Value, expected: &str) -> Option<Value> {
+    if expected == "a sequence" {
+        return match value {
+            Value::Null => Some(Value::Array(vec![])),
+            v => Some(Value::Array(vec![v.clone()])),
+        };
+    }
+    if let Value::Array(items) = value {
+        return Some(items.first().cloned().unwrap_or(Value::Null));
+    }
+    let integer = [
+        "i8", "i16", "i32", "i64", "u8", "u16", "u32", "u64", "usize", "isize",
+    ]
+    .contains(&expected);
+    match value {
+        _ if integer || ["f32", "f64"].contains(&expected) => {
+            let number = match value {
+                Value::Null => 0.0,
+                Value::String(s) => s.trim().parse::<f64>().ok()?,
+                Value::Bool(b) => *b as u8 as f64,
+                _ => return None,
+            };
+            match integer {
+                true if number.fract() == 0.0 => Some((number as i64).into()),
+                true => None,
+                false => {
+                    serde_json::Number::from_f64(number).map(Value::Number)
+                }
+            }
+        }
+        Value::Null if expected == "a boolean" => Some(Value::Bool(false)),
+        Value::String(s) if expected == "a boolean" => {
+            match s.trim().to_ascii_lowercase().as_str() {
+                "true" | "1" => Some(Value::Bool(true)),
+                "false" | "0" => Some(Value::Bool(false)),
+                _ => None,
+            }
+        }
+        Value::Number(n) if expected == "a boolean" => {
+            Some(Value::Bool(n.as_f64() != Some(0.0)))
+        }
+        Value::Null if expected == "a string" => {
+            Some(Value::String(String::new()))
+        }
+        Value::Number(n) if expected == "a string" => {
+            Some(Value::String(n.to_string()))
+        }
+        Value::Bool(b) if expected == "a string" => {
+            Some(Value::String(b.to_string()))
+        }
+        Value::Null
+            if expected == "a map" || expected.starts_with("struct ") =>
+        {
+            Some(Value::Object(Map::new()))
+        }
+        _ => None,
+    }
+}
+
+/// Removes a value which cannot be repaired. Optional fields are dropped;
+/// if the field is required, the next attempt finds it missing and gives it
+/// a default.
+fn remove(
+    value: &mut Value,
+    path: &[Segment],
+    message: &str,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<()> {
+    let (last, parent) = match path.split_last() {
+        Some(split) => split,
+        None => return Err(anyhow!("Cannot repair the message: {}", message)),
+    };
+    let removed = match (get_mut(value, parent), last) {
+        (Some(Value::Object(o)), Segment::Key(k)) => {
+            o.remove(k.as_str()).is_some()
+        }
+        _ => false,
+    };
+    if !removed {
+        return remove_artefact(value, path, message, diagnostics);
+    }
+    diagnostics.push(Diagnostic {
+        kind: DiagnosticKind::Removed,
+        path: json_path(path),
+        message: format!("removed: {}", message),
+    });
+    Ok(())
+}
+
+/// Removes the innermost array element containing the path, which is the
+/// artefact or item the error is in
+fn remove_artefact(
+    value: &mut Value,
+    path: &[Segment],
+    message: &str,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<()> {
+    let (position, index) = path
+        .iter()
+        .enumerate()
+        .rev()
+        .find_map(|(p, s)| match s {
+            Segment::Index(i) => Some((p, *i)),
+            Segment::Key(_) => None,
+        })
+        .ok_or_else(|| anyhow!("Cannot repair the message: {}", message))?;
+    match get_mut(value, &path[..position]) {
+        Some(Value::Array(items)) if index < items.len() => {
+            items.remove(index);
+        }
+        _ => return Err(anyhow!("Cannot repair the message: {}", message)),
+    }
+    diagnostics.push(Diagnostic {
+        kind: DiagnosticKind::Removed,
+        path: json_path(&path[..=position]),
+        message: format!("removed: {} at {}", message, json_path(path)),
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::structure::Data;
+
+    fn lenient(value: Value) -> (Data, Vec<Diagnostic>) {
+        let parsed = parse_structure_value(value, None, ParseMode::Lenient)
+            .expect("repairable message");
+        (parsed.value.data.expect("data"), parsed.diagnostics)
+    }
+
+    fn dataflow(fields: Value) -> Value {
+        let mut df = json!({ "id": "DF", "agencyID": "A", "name": "Flow" });
+        for (k, v) in fields.as_object().unwrap() {
+            df[k] = v.clone();
+        }
+        json!({ "data": { "dataflows": [df] } })
+    }
+
+    fn kinds(diagnostics: &[Diagnostic]) -> Vec<DiagnosticKind> {
+        diagnostics.iter().map(|d| d.kind).collect()
+    }
+
+    #[test]
+    fn strings_become_numbers_and_booleans() {
+        let (data, diagnostics) = lenient(json!({ "data": {
+            "dataflows": [{
+                "id": "DF", "agencyID": "A", "name": "Flow", "isFinal": "true"
+            }],
+            "conceptSchemes": [{
+                "id": "CS", "agencyID": "A", "name": "Concepts",
+                "concepts": [{
+                    "id": "C", "name": "C",
+                    "coreRepresentation": { "textFormat": { "decimals": "2" } }
+                }]
+            }]
+        }}));
+        assert_eq!(data.dataflows.unwrap()[0].is_final, Some(true));
+        let concept =
+            &data.concept_schemes.unwrap()[0].concepts.clone().unwrap()[0];
+        let format = concept.core_representation.as_ref().unwrap();
+        assert_eq!(format.text_format.as_ref().unwrap().decimals, Some(2));
+        assert_eq!(
+            kinds(&diagnostics),
+            vec![DiagnosticKind::TypeMismatch, DiagnosticKind::TypeMismatch]
+        );
+    }
+
+    #[test]
+    fn numbers_and_booleans_become_strings() {
+        let (data, diagnostics) =
+            lenient(dataflow(json!({ "version": 1, "description": true })));
+        let df = &data.dataflows.unwrap()[0];
+        assert_eq!(df.version.as_deref(), Some("1"));
+        assert_eq!(df.description.as_deref(), Some("true"));
+        let paths: Vec<_> = diagnostics.iter().map(|d| &d.path).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "$.data.dataflows[0].description",
+                "$.data.dataflows[0].version"
+            ]
+        );
+    }
+
+    #[test]
+    fn single_values_and_arrays_are_swapped() {
+        let (data, diagnostics) = lenient(dataflow(json!({
+            "annotations": { "id": "NOTE" },
+            "version": ["2.0", "1.0"]
+        })));
+        let df = &data.dataflows.unwrap()[0];
+        let annotations = df.annotations.as_ref().unwrap();
+        assert_eq!(annotations.len(), 1);
+        assert_eq!(annotations[0].id.as_deref(), Some("NOTE"));
+        assert_eq!(df.version.as_deref(), Some("2.0"));
+        assert_eq!(diagnostics.len(), 2);
+    }
+
+    #[test]
+    fn missing_fields_get_defaults() {
+        let (data, diagnostics) = lenient(json!({ "data": { "dataflows": [
+            { "id": "DF1", "agencyID": "A", "names": { "fr": "Flux" } },
+            { "id": "DF2", "agencyID": "A" },
+            { "id": "DF3", "name": "Flow" }
+        ]}}));
+        let dataflows = data.dataflows.unwrap();
+        assert_eq!(dataflows[0].name, "Flux");
+        assert_eq!(dataflows[1].name, "DF2");
+        assert_eq!(dataflows[2].agency_id, "");
+        // The null inserted for agencyID is not reported again as a mismatch
+        assert_eq!(kinds(&diagnostics), vec![DiagnosticKind::MissingField; 3]);
+        assert_eq!(diagnostics[2].path, "$.data.dataflows[2].agencyID");
+    }
+
+    #[test]
+    fn artefacts_at_the_top_level_are_wrapped() {
+        let (data, diagnostics) = lenient(json!({ "dataflows": [
+            { "id": "DF", "agencyID": "A", "name": "Flow" }
+        ]}));
+        assert_eq!(data.dataflows.unwrap().len(), 1);
+        assert_eq!(kinds(&diagnostics), vec![DiagnosticKind::MissingEnvelope]);
+    }
+
+    #[test]
+    fn unrepairable_values_are_removed() {
+        let (data, diagnostics) = lenient(json!({ "data": { "dataflows": [
+            { "id": "DF1", "agencyID": "A", "name": "Flow", "isFinal": "maybe" },
+            "DF2",
+            { "id": "DF3", "agencyID": "A", "name": "Flow" }
+        ]}}));
+        let dataflows = data.dataflows.unwrap();
+        let ids: Vec<_> = dataflows.iter().map(|d| d.id.as_str()).collect();
+        assert_eq!(ids, vec!["DF1", "DF3"]);
+        assert_eq!(dataflows[0].is_final, None);
+        assert_eq!(kinds(&diagnostics), vec![DiagnosticKind::Removed; 2]);
+        assert_eq!(diagnostics[0].path, "$.data.dataflows[0].isFinal");
+        assert_eq!(diagnostics[1].path, "$.data.dataflows[1]");
+    }
+
+    #[test]
+    fn strict_mode_reports_every_deviation() {
+        let value = dataflow(json!({ "isFinal": "true", "colour": "red" }));
+        let e = parse_structure_value(value, None, ParseMode::Strict)
+            .unwrap_err()
+            .downcast::<ParseError>()
+            .unwrap();
+        let paths: Vec<_> = e.diagnostics.iter().map(|d| &d.path).collect();
+        assert_eq!(
+            paths,
+            vec!["$.data.dataflows[0].colour", "$.data.dataflows[0].isFinal"]
+        );
+    }
+
+    #[test]
+    fn repeated_deviations_are_repaired_in_one_pass() {
+        let codes: Vec<_> = (0..8000)
+            .map(|i| {
+                json!({
+                    "id": format!("C{}", i),
+                    "annotations": { "id": "A", "text": i }
+                })
+            })
+            .collect();
+        let value = json!({ "data": { "codelists": [{
+            "id": "CL", "agencyID": "A", "name": "Codes", "codes": codes
+        }]}});
+        let start = Instant::now();
+        let (data, diagnostics) = lenient(value);
+        let codes = data.codelists.unwrap()[0].codes.clone().unwrap();
+        assert_eq!(codes.len(), 8000);
+        assert_eq!(codes[7999].name, "C7999");
+        let text = &codes[7999].annotations.as_ref().unwrap()[0].text;
+        assert_eq!(text.as_deref(), Some("7999"));
+        // A missing name, the annotations object and its text per code
+        assert_eq!(diagnostics.len(), 3 * 8000);
+        let first = &diagnostics[0].path;
+        assert_eq!(first, "$.data.codelists[0].codes[0].annotations");
+        assert!(start.elapsed().as_secs() < 30, "{:?}", start.elapsed());
+    }
+}
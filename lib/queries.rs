@@ -1,6 +1,17 @@
-// use anyhow::{anyhow, Result};
-// use serde::{Deserialize, Serialize};
-use std::string::ToString;
+//! queries builds SDMX REST query paths, relative to the endpoint base URL.
+//!
+//! SDMX 2.1 and 3.0 use different URL grammars, e.g. the 2.1 query
+//! `dataflow/ECB/EXR/latest` is `structure/dataflow/ECB/EXR/~` in 3.0 and
+//! the 2.1 data query `data/ECB,EXR,1.0/D.USD` is
+//! `data/dataflow/ECB/EXR/1.0/D.USD` in 3.0. [`StructureQuery`] and
+//! [`DataQuery`] can generate both, selected by [`RestVersion`].
+
+use anyhow::{anyhow, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{fmt, string::ToString};
+
+use crate::{sdmx_sources::Source, version::VersionReq};
 
 /// Should follow this precise order: resource, agencyID, resourceID, version, itemID
 /// The function does not currently check the order or the correctness of the values provided,
@@ -11,3 +22,516 @@ pub fn metadata_query<T: ToString>(data: Vec<T>) -> String {
         .collect::<Vec<String>>()
         .join("/")
 }
+
+/// The version of the SDMX REST API spoken by an endpoint
+#[derive(
+    Serialize,
+    Deserialize,
+    JsonSchema,
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+)]
+pub enum RestVersion {
+    #[default]
+    #[serde(rename = "2.1")]
+    V2_1,
+    #[serde(rename = "3.0")]
+    V3_0,
+}
+
+impl fmt::Display for RestVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RestVersion::V2_1 => write!(f, "2.1"),
+            RestVersion::V3_0 => write!(f, "3.0"),
+        }
+    }
+}
+
+impl RestVersion {
+    /// Formats an agency or resource ID, where `None` is the wildcard
+    fn id(&self, id: &Option<String>) -> String {
+        match (id, self) {
+            (Some(id), _) => id.clone(),
+            (None, RestVersion::V2_1) => "all".to_string(),
+            (None, RestVersion::V3_0) => "*".to_string(),
+        }
+    }
+
+    fn version(&self, version: &VersionReq) -> Result<String> {
+        match (version, self) {
+            (VersionReq::Latest, RestVersion::V2_1) => Ok("latest".to_string()),
+            (VersionReq::Latest, RestVersion::V3_0) => Ok("~".to_string()),
+            (VersionReq::All, RestVersion::V2_1) => Ok("all".to_string()),
+            (VersionReq::All, RestVersion::V3_0) => Ok("*".to_string()),
+            (VersionReq::LatestStable, RestVersion::V2_1)
+            | (VersionReq::Wildcard { .. }, RestVersion::V2_1) => Err(anyhow!(
+                "Version {} is not supported by SDMX REST 2.1",
+                version
+            )),
+            _ => Ok(version.to_string()),
+        }
+    }
+
+    /// The name of a structure resource, which was renamed for some
+    /// artefacts in 3.0
+    fn resource<'a>(&self, resource: &'a str) -> &'a str {
+        match (resource, self) {
+            ("contentconstraint", RestVersion::V3_0) => "dataconstraint",
+            ("hierarchicalcodelist", RestVersion::V3_0) => "hierarchy",
+            ("dataconstraint", RestVersion::V2_1) => "contentconstraint",
+            ("hierarchy", RestVersion::V2_1) => "hierarchicalcodelist",
+            (resource, _) => resource,
+        }
+    }
+}
+
+/// Appends query parameters to a path, skipping those without a value
+fn with_params(path: String, params: Vec<(String, Option<String>)>) -> String {
+    let params: Vec<_> = params
+        .into_iter()
+        .filter_map(|(k, v)| v.map(|v| format!("{}={}", k, v)))
+        .collect();
+    match params.is_empty() {
+        true => path,
+        false => format!("{}?{}", path, params.join("&")),
+    }
+}
+
+/// A query for structural metadata, e.g. all dataflows or a codelist
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructureQuery {
+    /// The 2.1 resource name, e.g. `dataflow` or `codelist`
+    pub resource: String,
+    /// Agency ID, all agencies if missing
+    pub agency_id: Option<String>,
+    /// Resource ID, all resources if missing
+    pub resource_id: Option<String>,
+    pub version: VersionReq,
+    pub item_id: Option<String>,
+    /// Which referencing or referenced artefacts to return as well, e.g.
+    /// `descendants`
+    pub references: Option<String>,
+    /// Amount of information to return, e.g. `allstubs`
+    pub detail: Option<String>,
+}
+
+impl StructureQuery {
+    /// A query for the latest version of all artefacts of a resource
+    pub fn new<S: ToString>(resource: S) -> Self {
+        StructureQuery {
+            resource: resource.to_string(),
+            agency_id: None,
+            resource_id: None,
+            version: VersionReq::Latest,
+            item_id: None,
+            references: None,
+            detail: None,
+        }
+    }
+
+    pub fn with_agency<S: ToString>(mut self, agency_id: S) -> Self {
+        self.agency_id = Some(agency_id.to_string());
+        self
+    }
+
+    pub fn with_id<S: ToString>(mut self, resource_id: S) -> Self {
+        self.resource_id = Some(resource_id.to_string());
+        self
+    }
+
+    pub fn with_version(mut self, version: VersionReq) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn with_item<S: ToString>(mut self, item_id: S) -> Self {
+        self.item_id = Some(item_id.to_string());
+        self
+    }
+
+    pub fn with_references<S: ToString>(mut self, references: S) -> Self {
+        self.references = Some(references.to_string());
+        self
+    }
+
+    pub fn with_detail<S: ToString>(mut self, detail: S) -> Self {
+        self.detail = Some(detail.to_string());
+        self
+    }
+
+    /// The query path in the grammar of the given REST API version
+    pub fn path(&self, rest: RestVersion) -> Result<String> {
+        let mut paths = vec![rest.resource(&self.resource).to_string()];
+        if rest == RestVersion::V3_0 {
+            paths.insert(0, "structure".to_string());
+        }
+        paths.push(rest.id(&self.agency_id));
+        paths.push(rest.id(&self.resource_id));
+        paths.push(rest.version(&self.version)?);
+        if let Some(item_id) = &self.item_id {
+            paths.push(item_id.clone());
+        }
+
+        Ok(with_params(
+            metadata_query(paths),
+            vec![
+                ("references".to_string(), self.references.clone()),
+                ("detail".to_string(), self.detail.clone()),
+            ],
+        ))
+    }
+}
+
+/// A query for data, or for the data availability, of a dataflow
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataQuery {
+    /// The kind of structure the data is requested for, `dataflow` unless
+    /// the data of a data structure or provision agreement is requested.
+    /// Only 3.0 supports other contexts than dataflows.
+    pub context: String,
+    pub agency_id: Option<String>,
+    pub resource_id: String,
+    pub version: VersionReq,
+    /// The dot separated series key, e.g. `D.USD+JPY.EUR..A`, all series if
+    /// missing
+    pub key: Option<String>,
+    /// Filters on component values, the `c[DIM]=` parameters of 3.0
+    pub filters: Vec<(String, String)>,
+    pub start_period: Option<String>,
+    pub end_period: Option<String>,
+    /// The `Accept` header to send with the query, the default format of
+    /// the endpoint if missing
+    pub format: Option<String>,
+}
+
+impl DataQuery {
+    /// A query for all data of the latest version of a dataflow
+    pub fn new<S: ToString>(agency_id: S, resource_id: S) -> Self {
+        DataQuery {
+            context: "dataflow".to_string(),
+            agency_id: Some(agency_id.to_string()),
+            resource_id: resource_id.to_string(),
+            version: VersionReq::Latest,
+            key: None,
+            filters: vec![],
+            start_period: None,
+            end_period: None,
+            format: None,
+        }
+    }
+
+    /// A query for all data of the latest version of a dataflow of a source,
+    /// in the data format the source prefers
+    pub fn for_source<S: ToString>(
+        source: &Source,
+        agency_id: S,
+        resource_id: S,
+    ) -> Self {
+        let query = DataQuery::new(agency_id, resource_id);
+        match source.data_format() {
+            Some(format) => query.with_format(format),
+            None => query,
+        }
+    }
+
+    pub fn with_context<S: ToString>(mut self, context: S) -> Self {
+        self.context = context.to_string();
+        self
+    }
+
+    pub fn with_version(mut self, version: VersionReq) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn with_key<S: ToString>(mut self, key: S) -> Self {
+        self.key = Some(key.to_string());
+        self
+    }
+
+    /// Restricts a component to the given values, e.g. `c[FREQ]=A,M`.
+    /// Operators like `ge:` may be used as allowed by the 3.0 grammar.
+    pub fn with_filter<S: ToString>(mut self, component: S, value: S) -> Self {
+        self.filters
+            .push((component.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn with_format<S: ToString>(mut self, format: S) -> Self {
+        self.format = Some(format.to_string());
+        self
+    }
+
+    pub fn with_period<S: ToString>(
+        mut self,
+        start: Option<S>,
+        end: Option<S>,
+    ) -> Self {
+        self.start_period = start.map(|s| s.to_string());
+        self.end_period = end.map(|s| s.to_string());
+        self
+    }
+
+    /// The query path for the data in the grammar of the given REST API version
+    pub fn path(&self, rest: RestVersion) -> Result<String> {
+        let path = self.context_path("data", rest)?;
+        Ok(with_params(path, self.params(rest)))
+    }
+
+    /// The query path for the availability of data, i.e. the values that
+    /// have data for each dimension
+    pub fn availability_path(&self, rest: RestVersion) -> Result<String> {
+        let resource = match rest {
+            RestVersion::V2_1 => "availableconstraint",
+            RestVersion::V3_0 => "availability",
+        };
+        let path = self.context_path(resource, rest)?;
+        Ok(with_params(path, self.params(rest)))
+    }
+
+    fn context_path(
+        &self,
+        resource: &str,
+        rest: RestVersion,
+    ) -> Result<String> {
+        let key = self.key.clone().unwrap_or_else(|| match rest {
+            RestVersion::V2_1 => "all".to_string(),
+            RestVersion::V3_0 => "*".to_string(),
+        });
+        let version = rest.version(&self.version)?;
+        let paths = match rest {
+            RestVersion::V2_1 => {
+                if !self.filters.is_empty() {
+                    return Err(anyhow!(
+                        "Component filters require SDMX REST 3.0"
+                    ));
+                }
+                if self.context != "dataflow" {
+                    return Err(anyhow!(
+                        "Data queries by {} require SDMX REST 3.0",
+                        self.context
+                    ));
+                }
+                let flow = [
+                    rest.id(&self.agency_id),
+                    self.resource_id.clone(),
+                    version,
+                ];
+                vec![resource.to_string(), flow.join(","), key]
+            }
+            RestVersion::V3_0 => vec![
+                resource.to_string(),
+                self.context.clone(),
+                rest.id(&self.agency_id),
+                self.resource_id.clone(),
+                version,
+                key,
+            ],
+        };
+        Ok(metadata_query(paths))
+    }
+
+    fn params(&self, rest: RestVersion) -> Vec<(String, Option<String>)> {
+        match rest {
+            RestVersion::V2_1 => vec![
+                ("startPeriod".to_string(), self.start_period.clone()),
+                ("endPeriod".to_string(), self.end_period.clone()),
+            ],
+            RestVersion::V3_0 => {
+                let period: Vec<_> = vec![
+                    self.start_period.as_ref().map(|s| format!("ge:{}", s)),
+                    self.end_period.as_ref().map(|s| format!("le:{}", s)),
+                ]
+                .into_iter()
+                .flatten()
+                .collect();
+                let mut params: Vec<_> = self
+                    .filters
+                    .iter()
+                    .map(|(c, v)| (format!("c[{}]", c), Some(v.clone())))
+                    .collect();
+                if !period.is_empty() {
+                    params.push((
+                        "c[TIME_PERIOD]".to_string(),
+                        Some(period.join("+")),
+                    ));
+                }
+                params
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    use RestVersion::{V2_1, V3_0};
+
+    #[test]
+    fn structure_queries_follow_the_grammar_of_each_version() {
+        let v1: VersionReq = "1.0".parse().unwrap();
+        let cases = vec![
+            (
+                StructureQuery::new("dataflow"),
+                "dataflow/all/all/latest",
+                "structure/dataflow/*/*/~",
+            ),
+            (
+                StructureQuery::new("codelist")
+                    .with_agency("ECB")
+                    .with_id("CL_FREQ")
+                    .with_version(v1)
+                    .with_item("A"),
+                "codelist/ECB/CL_FREQ/1.0/A",
+                "structure/codelist/ECB/CL_FREQ/1.0/A",
+            ),
+            (
+                StructureQuery::new("dataflow")
+                    .with_agency("ECB")
+                    .with_version(VersionReq::All)
+                    .with_references("descendants")
+                    .with_detail("allstubs"),
+                "dataflow/ECB/all/all?references=descendants&detail=allstubs",
+                "structure/dataflow/ECB/*/*\
+                 ?references=descendants&detail=allstubs",
+            ),
+            (
+                StructureQuery::new("contentconstraint"),
+                "contentconstraint/all/all/latest",
+                "structure/dataconstraint/*/*/~",
+            ),
+            (
+                StructureQuery::new("dataconstraint"),
+                "contentconstraint/all/all/latest",
+                "structure/dataconstraint/*/*/~",
+            ),
+            (
+                StructureQuery::new("hierarchicalcodelist"),
+                "hierarchicalcodelist/all/all/latest",
+                "structure/hierarchy/*/*/~",
+            ),
+            (
+                StructureQuery::new("hierarchy"),
+                "hierarchicalcodelist/all/all/latest",
+                "structure/hierarchy/*/*/~",
+            ),
+        ];
+        for (query, v2_1, v3_0) in cases {
+            assert_eq!(query.path(V2_1).unwrap(), v2_1);
+            assert_eq!(query.path(V3_0).unwrap(), v3_0);
+        }
+
+        // 3.0 wildcards have no 2.1 equivalent
+        for version in &["+", "1.2+.0"] {
+            let query = StructureQuery::new("codelist")
+                .with_version(version.parse().unwrap());
+            assert!(query.path(V2_1).is_err(), "{}", version);
+        }
+        let query = StructureQuery::new("codelist")
+            .with_version("1.2+.0".parse().unwrap());
+        assert_eq!(query.path(V3_0).unwrap(), "structure/codelist/*/*/1.2+.0");
+    }
+
+    #[test]
+    fn data_queries_follow_the_grammar_of_each_version() {
+        let v1: VersionReq = "1.0".parse().unwrap();
+        let exr = || DataQuery::new("ECB", "EXR");
+        let cases = vec![
+            (
+                exr(),
+                Some("data/ECB,EXR,latest/all"),
+                "data/dataflow/ECB/EXR/~/*",
+            ),
+            (
+                exr().with_version(v1).with_key("D.USD+JPY.EUR..A"),
+                Some("data/ECB,EXR,1.0/D.USD+JPY.EUR..A"),
+                "data/dataflow/ECB/EXR/1.0/D.USD+JPY.EUR..A",
+            ),
+            (
+                exr().with_period(Some("2020-01"), Some("2020-12")),
+                Some("data/ECB,EXR,latest/all?startPeriod=2020-01&endPeriod=2020-12"),
+                "data/dataflow/ECB/EXR/~/*?c[TIME_PERIOD]=ge:2020-01+le:2020-12",
+            ),
+            (
+                exr().with_period(Some("2020"), None),
+                Some("data/ECB,EXR,latest/all?startPeriod=2020"),
+                "data/dataflow/ECB/EXR/~/*?c[TIME_PERIOD]=ge:2020",
+            ),
+            (
+                exr()
+                    .with_filter("FREQ", "A,M")
+                    .with_filter("OBS_VALUE", "ge:1")
+                    .with_period(None, Some("2021")),
+                None,
+                "data/dataflow/ECB/EXR/~/*\
+                 ?c[FREQ]=A,M&c[OBS_VALUE]=ge:1&c[TIME_PERIOD]=le:2021",
+            ),
+            (
+                exr().with_context("datastructure"),
+                None,
+                "data/datastructure/ECB/EXR/~/*",
+            ),
+        ];
+        for (query, v2_1, v3_0) in cases {
+            match v2_1 {
+                Some(path) => assert_eq!(query.path(V2_1).unwrap(), path),
+                None => assert!(query.path(V2_1).is_err(), "{:?}", query),
+            }
+            assert_eq!(query.path(V3_0).unwrap(), v3_0);
+        }
+    }
+
+    #[test]
+    fn availability_queries_are_renamed_in_3_0() {
+        let query = DataQuery::new("ECB", "EXR").with_key("D..EUR");
+        assert_eq!(
+            query.availability_path(V2_1).unwrap(),
+            "availableconstraint/ECB,EXR,latest/D..EUR"
+        );
+        assert_eq!(
+            query.availability_path(V3_0).unwrap(),
+            "availability/dataflow/ECB/EXR/~/D..EUR"
+        );
+        let query = query.with_filter("FREQ", "D");
+        assert!(query.availability_path(V2_1).is_err());
+        assert_eq!(
+            query.availability_path(V3_0).unwrap(),
+            "availability/dataflow/ECB/EXR/~/D..EUR?c[FREQ]=D"
+        );
+    }
+
+    #[test]
+    fn data_queries_use_the_data_format_of_the_source() {
+        let source = |formats: serde_json::Value| -> Source {
+            serde_json::from_value(json!({
+                "id": "TEST", "name": "Test", "url": "http://localhost",
+                "headers": { "Accept": "application/xml" },
+                "formats": formats
+            }))
+            .unwrap()
+        };
+        let csv = "application/vnd.sdmx.data+csv;version=1.0.0";
+        let query = DataQuery::for_source(
+            &source(json!({ "data": csv })),
+            "ECB",
+            "EXR",
+        );
+        assert_eq!(query.format.as_deref(), Some(csv));
+        // the Accept header of the source is the fallback
+        let query = DataQuery::for_source(&source(json!({})), "ECB", "EXR");
+        assert_eq!(query.format.as_deref(), Some("application/xml"));
+        assert_eq!(
+            query.path(RestVersion::V2_1).unwrap(),
+            "data/ECB,EXR,latest/all"
+        );
+    }
+}
@@ -0,0 +1,134 @@
+//! report describes the outcome of a crawl, so downstream tools can consume
+//! crawl results without scraping the log.
+
+use std::{io::Write, time::Instant};
+
+use anyhow::Result;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::queries::RestVersion;
+
+/// The outcome of crawling one source
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CrawlReport {
+    pub source_id: String,
+    pub rest_version: RestVersion,
+    /// Start time in RFC 3339 format
+    pub started_at: String,
+    pub duration_ms: u64,
+    pub stages: Vec<StageReport>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StageReport {
+    pub name: String,
+    pub duration_ms: u64,
+    pub requests: Vec<RequestReport>,
+    /// Number of results handed to the next stage
+    pub results: usize,
+}
+
+/// The outcome of one request. Requests that fail don't stop the crawl,
+/// their error is recorded instead.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RequestReport {
+    pub url: String,
+    /// HTTP status, missing when no response was received
+    pub status: Option<u16>,
+    pub duration_ms: u64,
+    /// Size of the response body
+    pub bytes: Option<u64>,
+    pub warc_record_id: Option<String>,
+    /// Size of the WARC file written for the request
+    pub warc_bytes: Option<u64>,
+    /// Number of deviations from the schema repaired while parsing
+    pub repairs: usize,
+    pub error: Option<String>,
+}
+
+impl CrawlReport {
+    pub fn new(source_id: &str, rest_version: RestVersion) -> Self {
+        CrawlReport {
+            source_id: source_id.to_string(),
+            rest_version,
+            started_at: Utc::now().to_rfc3339(),
+            duration_ms: 0,
+            stages: vec![],
+        }
+    }
+
+    /// Number of requests that failed in any stage
+    pub fn errors(&self) -> usize {
+        self.stages
+            .iter()
+            .flat_map(|s| &s.requests)
+            .filter(|r| r.error.is_some())
+            .count()
+    }
+
+    pub fn requests(&self) -> usize {
+        self.stages.iter().map(|s| s.requests.len()).sum()
+    }
+}
+
+impl StageReport {
+    pub fn new(name: &str) -> Self {
+        StageReport {
+            name: name.to_string(),
+            duration_ms: 0,
+            requests: vec![],
+            results: 0,
+        }
+    }
+}
+
+impl RequestReport {
+    pub fn new(url: &str) -> Self {
+        RequestReport {
+            url: url.to_string(),
+            status: None,
+            duration_ms: 0,
+            bytes: None,
+            warc_record_id: None,
+            warc_bytes: None,
+            repairs: 0,
+            error: None,
+        }
+    }
+}
+
+/// Milliseconds since `start`
+pub(crate) fn elapsed_ms(start: Instant) -> u64 {
+    start.elapsed().as_millis() as u64
+}
+
+/// How reports are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// A JSON array of all reports
+    Json,
+    /// One JSON report per line
+    Ndjson,
+}
+
+/// Writes the reports of a crawl run in the given format
+pub fn write_reports<W: Write>(
+    out: &mut W,
+    reports: &[CrawlReport],
+    format: ReportFormat,
+) -> Result<()> {
+    match format {
+        ReportFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, reports)?;
+            writeln!(out)?;
+        }
+        ReportFormat::Ndjson => {
+            for report in reports {
+                serde_json::to_writer(&mut *out, report)?;
+                writeln!(out)?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -10,6 +10,10 @@ pub struct Response {
     pub url: url::Url,
     pub body: Option<String>,
     pub version: http::Version,
+    /// Record ID of the response in the WARC archive, if it was written
+    pub warc_record_id: Option<String>,
+    /// Size of the WARC file written for the request and response
+    pub warc_bytes: Option<u64>,
 }
 
 impl From<reqwest::Response> for Response {
@@ -22,6 +26,8 @@ impl From<reqwest::Response> for Response {
             body: None,
             //r.text().await?,
             version: r.version(),
+            warc_record_id: None,
+            warc_bytes: None,
         }
     }
 }
@@ -35,6 +41,8 @@ impl Response {
             url: r.url().clone(),
             version: r.version(),
             body: Some(r.text().await?),
+            warc_record_id: None,
+            warc_bytes: None,
         })
     }
 }
@@ -1,10 +1,15 @@
 //! reqwest_warc handles serializing reqwest's Request and Response types to WARC files using the warc library
 
-use http::HeaderMap;
-use std::{fs::OpenOptions, path};
+use http::{HeaderMap, HeaderValue};
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Read},
+    path,
+};
+use tracing::{debug, warn};
 use ulid::Ulid;
 use util::cdx_url_canonical;
-use warc::{Record, RecordType, WarcWriter};
+use warc::{Record, RecordType, WarcHeader, WarcWriter};
 
 use crate::{reqwest_layer::Response, util};
 use anyhow::{anyhow, Result};
@@ -22,7 +27,10 @@ pub fn serialize_headers(headers: &HeaderMap) -> String {
 const empty: [u8; 0] = [];
 // TODO: rather than a tuple with just the record and URL, maybe preserve other metadata
 // Maybe return own request
-pub async fn crate_warc_request(req: reqwest::Request) -> Result<Record> {
+pub async fn crate_warc_request(
+    req: reqwest::Request,
+    id: String,
+) -> Result<Record> {
     let bd = match req.body() {
         Some(b) => b
             .as_bytes()
@@ -30,14 +38,29 @@ pub async fn crate_warc_request(req: reqwest::Request) -> Result<Record> {
         None => &empty,
     };
 
-    let headers = req.headers().clone();
+    let mut headers = req.headers().clone();
+    // Keep credentials out of the archive
+    for value in headers.values_mut() {
+        if value.is_sensitive() {
+            *value = HeaderValue::from_static("[redacted]");
+        }
+    }
 
-    Ok(create_warc(RecordType::Request, &headers, bd)?)
+    let url = req.url().clone();
+    Ok(create_warc(id, RecordType::Request, &url, &headers, bd)?)
 }
 
-pub async fn crate_warc_response(res: Response) -> Result<Record> {
+/// The record of a response to a request for `url`, which may differ from
+/// the URL of the response after redirects
+pub async fn crate_warc_response(
+    res: Response,
+    url: &url::Url,
+    id: String,
+) -> Result<Record> {
     Ok(create_warc(
+        id,
         RecordType::Response,
+        url,
         &res.headers,
         res.body
             .or(Some("".to_string()))
@@ -48,7 +71,9 @@ pub async fn crate_warc_response(res: Response) -> Result<Record> {
 }
 
 fn create_warc(
+    id: String,
     typ: RecordType,
+    url: &url::Url,
     headers: &HeaderMap,
     body: &[u8],
 ) -> Result<Record> {
@@ -60,19 +85,19 @@ fn create_warc(
     // https://stackoverflow.com/questions/40792801/best-way-to-concatenate-vectors-in-rust
     let warc_body = [before, body.into()].concat();
 
-    // Record ID
-    let id = Ulid::new().to_string();
-
     let mut record = Record::default();
     record.set_warc_version("1.1");
     record.set_warc_id(id);
     record.set_warc_type(typ);
+    record.set_header(WarcHeader::TargetURI, url.as_str())?;
     record.replace_body(warc_body);
 
     Ok(record)
 }
 
-pub fn write_warc_file(url: url::Url, records: Vec<Record>) -> Result<()> {
+/// Writes the records to a new file in `./warc-out`, returning the number of
+/// bytes written
+pub fn write_warc_file(url: url::Url, records: Vec<Record>) -> Result<u64> {
     // WARC File ID
     let id = Ulid::new().to_string();
 
@@ -85,34 +110,141 @@ pub fn write_warc_file(url: url::Url, records: Vec<Record>) -> Result<()> {
         "warc".to_string(),
     ]
     .join(".");
+    let file_path = path::Path::new(dir).join(fname);
     let file = OpenOptions::new()
         .write(true)
         .create_new(true)
-        .open(path::Path::new(dir).join(fname))?;
+        .open(&file_path)?;
     let mut writer = WarcWriter::new(file);
 
+    let count = records.len();
+    let mut bytes = 0;
     for record in records {
-        writer.write(&record)?;
+        bytes += writer.write(&record)? as u64;
     }
-    Ok(())
+    debug!(
+        file = %file_path.display(),
+        records = count,
+        bytes,
+        "Wrote WARC file"
+    );
+    Ok(bytes)
 }
 
+/// Writes the request and response to a new WARC file, returning the record ID of the response
+/// and the number of bytes written
 pub async fn write_warc<R: Into<Response>>(
     req: reqwest::Request,
     response: R,
-) -> Result<()> {
+) -> Result<(String, u64)> {
     let res = response.into();
     if req.url() != &res.url && res.url.scheme() != "https" {
-        println!(
-            "URLs on request and response are not equal, second not https. req: {:?}, res: {:?}",
-            req.url().to_string(),
-            res.url
+        warn!(
+            request_url = %req.url(),
+            response_url = %res.url,
+            "URLs on request and response are not equal, second not https"
         );
     }
     let req_url = req.url().clone();
+    let response_id = Ulid::new().to_string();
     let records = vec![
-        crate_warc_request(req).await?,
-        crate_warc_response(res).await?,
+        crate_warc_request(req, Ulid::new().to_string()).await?,
+        crate_warc_response(res, &req_url, response_id.clone()).await?,
     ];
-    write_warc_file(req_url, records)
+    let bytes = write_warc_file(req_url, records)?;
+    Ok((response_id, bytes))
+}
+
+/// A record read back from a WARC file
+#[derive(Debug, Clone)]
+pub struct WarcEntry {
+    pub id: String,
+    /// The WARC-Type header, e.g. `response`
+    pub warc_type: String,
+    /// The WARC-Target-URI header, the URL that was requested
+    pub target_uri: Option<String>,
+    /// The record block. For requests and responses written by `write_warc`
+    /// these are the HTTP headers followed by the HTTP body.
+    pub block: Vec<u8>,
+    /// Byte offset of the record in the file
+    pub offset: u64,
+    /// Length of the record in the file, from the version line to the end
+    /// of the block
+    pub length: u64,
+}
+
+impl WarcEntry {
+    /// Splits the block of a request or response into the HTTP headers and body
+    pub fn http_parts(&self) -> (String, &[u8]) {
+        match self.block.windows(4).position(|w| w == b"\r\n\r\n") {
+            Some(idx) => (
+                String::from_utf8_lossy(&self.block[..idx]).to_string(),
+                &self.block[idx + 4..],
+            ),
+            None => (String::new(), &self.block[..]),
+        }
+    }
+}
+
+/// Reads all records of an uncompressed WARC file
+pub fn read_warc_file<P: AsRef<path::Path>>(path: P) -> Result<Vec<WarcEntry>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut out = vec![];
+    let mut line = String::new();
+    let mut position = 0;
+    loop {
+        // Records are separated by blank lines
+        let offset = loop {
+            line.clear();
+            let read = reader.read_line(&mut line)?;
+            if read == 0 {
+                return Ok(out);
+            }
+            position += read as u64;
+            if !line.trim().is_empty() {
+                break position - read as u64;
+            }
+        };
+        if !line.starts_with("WARC/") {
+            return Err(anyhow!("Invalid WARC record start {:?}", line.trim()));
+        }
+
+        let mut headers = vec![];
+        loop {
+            line.clear();
+            let read = reader.read_line(&mut line)?;
+            if read == 0 {
+                return Err(anyhow!("Truncated WARC record header"));
+            }
+            position += read as u64;
+            let l = line.trim_end();
+            if l.is_empty() {
+                break;
+            }
+            if let Some((k, v)) = l.split_once(':') {
+                headers.push((k.trim().to_string(), v.trim().to_string()));
+            }
+        }
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.clone())
+        };
+
+        let len: usize = header("Content-Length")
+            .ok_or_else(|| anyhow!("WARC record without Content-Length"))?
+            .parse()?;
+        let mut block = vec![0; len];
+        reader.read_exact(&mut block)?;
+        position += len as u64;
+        out.push(WarcEntry {
+            id: header("WARC-Record-ID").unwrap_or_default(),
+            warc_type: header("WARC-Type").unwrap_or_default(),
+            target_uri: header("WARC-Target-URI"),
+            block,
+            offset,
+            length: position - offset,
+        });
+    }
 }
@@ -0,0 +1,265 @@
+//! robots parses robots.txt files as specified in RFC 9309, along with the
+//! non-standard `Crawl-delay` most providers use to ask for a slower crawl.
+//!
+//! Rules are chosen by the crawler's product token (e.g. `sdmxblaze`),
+//! falling back to the `*` group. The longest matching path wins and `Allow`
+//! wins ties, so with
+//!
+//! ```text
+//! User-agent: *
+//! Disallow: /rest/data/
+//! Allow: /rest/data/public
+//! Crawl-delay: 2
+//! ```
+//!
+//! `/rest/data/public/EXR` may be requested but `/rest/data/private` may
+//! not, and requests are made at least 2 seconds apart.
+
+use std::time::Duration;
+
+use crate::sdmx_sources::seconds;
+
+/// The rules of a robots.txt file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RobotsTxt {
+    groups: Vec<Group>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Group {
+    /// Lowercase product tokens, or `*`
+    agents: Vec<String>,
+    rules: Vec<Rule>,
+    /// Seconds between requests
+    crawl_delay: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Rule {
+    allow: bool,
+    /// A path that may contain `*` wildcards and end in `$`
+    pattern: String,
+}
+
+impl RobotsTxt {
+    /// A robots.txt that allows everything, as if it did not exist
+    pub fn allow_all() -> Self {
+        RobotsTxt::default()
+    }
+
+    /// A robots.txt that allows nothing, assumed when it is unreachable
+    pub fn disallow_all() -> Self {
+        RobotsTxt {
+            groups: vec![Group {
+                agents: vec!["*".to_string()],
+                rules: vec![Rule {
+                    allow: false,
+                    pattern: "/".to_string(),
+                }],
+                crawl_delay: None,
+            }],
+        }
+    }
+
+    /// Parses a robots.txt file, skipping the lines it does not understand
+    pub fn parse(s: &str) -> Self {
+        let mut groups: Vec<Group> = vec![];
+        // Whether the last line was a user-agent, which continues a group
+        let mut in_agents = false;
+        for line in s.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => {
+                    (key.trim().to_ascii_lowercase(), value.trim())
+                }
+                None => continue,
+            };
+            if key == "user-agent" {
+                if !in_agents {
+                    groups.push(Group::default());
+                    in_agents = true;
+                }
+                let agent = value.to_ascii_lowercase();
+                groups.last_mut().unwrap().agents.push(agent);
+                continue;
+            }
+            in_agents = false;
+            // Rules before the first user-agent belong to no group
+            let group = match groups.last_mut() {
+                Some(group) => group,
+                None => continue,
+            };
+            match key.as_str() {
+                "allow" | "disallow" if !value.is_empty() => {
+                    group.rules.push(Rule {
+                        allow: key == "allow",
+                        pattern: value.to_string(),
+                    })
+                }
+                "crawl-delay" => group.crawl_delay = value.parse().ok(),
+                _ => {}
+            }
+        }
+        RobotsTxt { groups }
+    }
+
+    /// The groups for a product token, merged if several name it
+    fn groups(&self, agent: &str) -> Vec<&Group> {
+        let agent = agent.to_ascii_lowercase();
+        let named: Vec<_> = self
+            .groups
+            .iter()
+            .filter(|g| g.agents.contains(&agent))
+            .collect();
+        match named.is_empty() {
+            true => self
+                .groups
+                .iter()
+                .filter(|g| g.agents.iter().any(|a| a == "*"))
+                .collect(),
+            false => named,
+        }
+    }
+
+    /// Whether the crawler with the product token may request a path,
+    /// including its query
+    pub fn is_allowed(&self, agent: &str, path: &str) -> bool {
+        if path == "/robots.txt" {
+            return true;
+        }
+        self.groups(agent)
+            .iter()
+            .flat_map(|g| g.rules.iter())
+            .filter(|r| matches(&r.pattern, path))
+            // Longest pattern first, then allow before disallow
+            .max_by_key(|r| (r.pattern.len(), r.allow))
+            .map(|r| r.allow)
+            .unwrap_or(true)
+    }
+
+    /// The time the crawler with the product token should leave between
+    /// requests, at most `MAX_SECONDS`
+    pub fn crawl_delay(&self, agent: &str) -> Option<Duration> {
+        self.groups(agent)
+            .iter()
+            .filter_map(|g| g.crawl_delay)
+            .filter(|d| d.is_finite() && *d >= 0.0)
+            .reduce(f64::max)
+            .map(seconds)
+    }
+}
+
+/// Whether a path starts with a pattern, where `*` matches any characters
+/// and a trailing `$` the end of the path
+fn matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let mut rest = match path.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let parts: Vec<_> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        let last = i == parts.len() - 1;
+        // The last part of an anchored pattern has to end the path
+        let found = match last && anchored {
+            true => rest.len().checked_sub(part.len()).filter(|start| {
+                rest.is_char_boundary(*start) && rest[*start..] == **part
+            }),
+            false => rest.find(part),
+        };
+        match found {
+            Some(start) => rest = &rest[start + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sdmx_sources::MAX_SECONDS;
+
+    #[test]
+    fn consecutive_user_agents_share_a_group() {
+        let robots = RobotsTxt::parse(
+            "Disallow: /before\n\
+             User-agent: SDMXBlaze\n\
+             User-agent: other # comment\n\
+             Disallow: /private\n\
+             \n\
+             User-agent: *\n\
+             Disallow: /\n",
+        );
+        assert_eq!(robots.groups.len(), 2);
+        assert_eq!(robots.groups[0].agents, vec!["sdmxblaze", "other"]);
+        // rules before the first user-agent are ignored
+        assert_eq!(
+            robots.groups[0].rules,
+            vec![Rule {
+                allow: false,
+                pattern: "/private".to_string()
+            }]
+        );
+        assert!(robots.is_allowed("sdmxblaze", "/before"));
+        assert!(robots.is_allowed("Other", "/public"));
+        assert!(!robots.is_allowed("sdmxblaze", "/private/x"));
+        // agents without a group of their own fall back to `*`
+        assert!(!robots.is_allowed("googlebot", "/public"));
+        assert!(robots.is_allowed("googlebot", "/robots.txt"));
+    }
+
+    #[test]
+    fn patterns_match_wildcards_and_the_end_of_the_path() {
+        assert!(matches("/rest", "/rest/data"));
+        assert!(!matches("/rest", "/res"));
+        assert!(matches("/*/data", "/rest/data/EXR"));
+        assert!(matches("/*.json$", "/rest/dataflow.json"));
+        assert!(!matches("/*.json$", "/rest/dataflow.json?x=1"));
+        assert!(matches("/rest$", "/rest"));
+        assert!(!matches("/rest$", "/rest/"));
+        assert!(matches("/a*b*c$", "/abxbc"));
+        assert!(!matches("/a*b*c$", "/abcd"));
+        assert!(matches("*", "/anything"));
+    }
+
+    #[test]
+    fn the_longest_rule_wins_and_allow_wins_ties() {
+        let robots = RobotsTxt::parse(
+            "User-agent: *\n\
+             Disallow: /rest/data/\n\
+             Allow: /rest/data/public\n\
+             Disallow: /tie\n\
+             Allow: /tie\n\
+             Crawl-delay: 2\n\
+             \n\
+             User-agent: sdmxblaze\n\
+             Disallow: /x\n\
+             Crawl-delay: 1\n\
+             \n\
+             User-agent: sdmxblaze\n\
+             Crawl-delay: 5\n",
+        );
+        assert!(robots.is_allowed("bot", "/rest/data/public/EXR"));
+        assert!(!robots.is_allowed("bot", "/rest/data/private"));
+        assert!(robots.is_allowed("bot", "/rest/dataflow"));
+        assert!(robots.is_allowed("bot", "/tie"));
+        assert_eq!(robots.crawl_delay("bot"), Some(Duration::from_secs(2)));
+        // groups naming the agent are merged, with the longest delay
+        assert_eq!(
+            robots.crawl_delay("sdmxblaze"),
+            Some(Duration::from_secs(5))
+        );
+        assert!(!robots.is_allowed("sdmxblaze", "/x"));
+        assert!(robots.is_allowed("sdmxblaze", "/rest/data/private"));
+        assert_eq!(RobotsTxt::allow_all().crawl_delay("bot"), None);
+        let slow = RobotsTxt::parse("User-agent: *\nCrawl-delay: 1e30\n");
+        assert_eq!(slow.crawl_delay("bot"), Some(MAX_SECONDS));
+        assert!(!RobotsTxt::disallow_all().is_allowed("bot", "/"));
+    }
+}
@@ -0,0 +1,455 @@
+//! schedule describes when the crawl service (see `serve`) crawls a source
+//! on its own. Sources give their schedule as a string, either a cron
+//! expression or an interval:
+//!
+//! ```text
+//! 0 3 * * *       every day at 03:00 UTC
+//! 30 */6 * * 1-5  every six hours at half past, Monday to Friday
+//! @daily          the aliases @hourly, @daily, @weekly and @monthly
+//! every 6h        six hours after the previous crawl, also s, m and d
+//! ```
+//!
+//! Intervals count from the time the previous scheduled crawl was queued, or
+//! skipped because a crawl of the source was still running, rather than
+//! from the time it was due, so crawls are not queued back to back after the
+//! service was stopped for a while.
+//!
+//! Cron expressions have the five fields minute, hour, day of month, month
+//! and day of week (0 or 7 is Sunday), each `*`, a number, a range `1-5`, a
+//! step `*/15` or `1-30/2`, or a list of those. They are evaluated in UTC.
+//! As in cron, a day matches if either the day of month or the day of week
+//! matches when both are restricted.
+
+use std::{fmt, str::FromStr, time::Duration};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, Duration as Span, Timelike, Utc};
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{InstanceType, Metadata, Schema, SchemaObject},
+    JsonSchema,
+};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// When a source is crawled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Schedule {
+    Cron(Cron),
+    /// A fixed time after the previous crawl was queued
+    Every(Duration),
+}
+
+impl Schedule {
+    /// The first time a source is due when it has never been crawled on
+    /// schedule: right away for intervals, the next match for cron
+    /// expressions
+    pub fn first(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Schedule::Cron(cron) => cron.next_after(now),
+            Schedule::Every(_) => Some(now),
+        }
+    }
+
+    /// The next time a source is due after `previous`, which is when the
+    /// previous crawl was queued for intervals. `None` if a cron expression
+    /// never matches, e.g. `0 0 31 2 *`, or an interval ends after the
+    /// latest representable time.
+    pub fn next_after(&self, previous: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Schedule::Cron(cron) => cron.next_after(previous),
+            Schedule::Every(interval) => {
+                previous.checked_add_signed(Span::from_std(*interval).ok()?)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Schedule::Cron(cron) => write!(f, "{}", cron.expression),
+            Schedule::Every(interval) => {
+                write!(f, "every {}", format_interval(*interval))
+            }
+        }
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        match s.strip_prefix("every ") {
+            Some(interval) => Ok(Schedule::Every(parse_interval(interval)?)),
+            None => Ok(Schedule::Cron(s.parse()?)),
+        }
+    }
+}
+
+impl Serialize for Schedule {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Schedule {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+impl JsonSchema for Schedule {
+    fn schema_name() -> String {
+        "Schedule".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            metadata: Some(Box::new(Metadata {
+                description: Some(
+                    "A cron expression like `0 3 * * *` or an interval like \
+                     `every 6h`"
+                        .to_string(),
+                ),
+                examples: vec!["0 3 * * *".into(), "every 6h".into()],
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+/// A cron expression, with the allowed values of each field as bit sets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cron {
+    expression: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether the day of month and day of week fields are `*`
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Cron {
+    fn matches_day(&self, t: DateTime<Utc>) -> bool {
+        let day = has(self.days, t.day());
+        let weekday = has(self.weekdays, t.weekday().num_days_from_sunday());
+        match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+
+    /// The first minute after `t` that matches
+    pub fn next_after(&self, t: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut t = t.with_second(0)?.with_nanosecond(0)? + Span::minutes(1);
+        // Any expression that matches at all does so within 4 years
+        let end = t + Span::days(4 * 366);
+        while t < end {
+            if !has(self.months, t.month()) {
+                let (year, month) = match t.month() {
+                    12 => (t.year() + 1, 1),
+                    m => (t.year(), m + 1),
+                };
+                t = t
+                    .with_day(1)?
+                    .with_hour(0)?
+                    .with_minute(0)?
+                    .with_month(month)?
+                    .with_year(year)?;
+            } else if !self.matches_day(t) {
+                t = t.with_hour(0)?.with_minute(0)? + Span::days(1);
+            } else if !has(self.hours, t.hour()) {
+                t = t.with_minute(0)? + Span::hours(1);
+            } else if !has(self.minutes, t.minute()) {
+                t += Span::minutes(1);
+            } else {
+                return Some(t);
+            }
+        }
+        None
+    }
+}
+
+impl FromStr for Cron {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let expression = s.trim().to_string();
+        let expanded = match expression.as_str() {
+            "@hourly" => "0 * * * *",
+            "@daily" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            e => e,
+        };
+        let fields: Vec<_> = expanded.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(anyhow!(
+                "Cron expression {:?} should have 5 fields: minute hour \
+                 day-of-month month day-of-week",
+                expression
+            ));
+        }
+        let field = |idx: usize, min: u32, max: u32| {
+            parse_field(fields[idx], min, max).map_err(|e| {
+                anyhow!("Invalid cron expression {:?}: {}", expression, e)
+            })
+        };
+        let mut weekdays = field(4, 0, 7)?;
+        // 7 is another name for Sunday
+        if has(weekdays, 7) {
+            weekdays |= 1;
+        }
+        Ok(Cron {
+            minutes: field(0, 0, 59)?,
+            hours: field(1, 0, 23)?,
+            days: field(2, 1, 31)?,
+            months: field(3, 1, 12)?,
+            weekdays,
+            any_day: fields[2] == "*",
+            any_weekday: fields[4] == "*",
+            expression,
+        })
+    }
+}
+
+fn has(set: u64, value: u32) -> bool {
+    set & (1 << value) != 0
+}
+
+/// Parses a field of a cron expression into the set of allowed values
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64> {
+    let number = |s: &str| -> Result<u32> {
+        let n: u32 = s.parse().map_err(|_| anyhow!("{:?} is no number", s))?;
+        if n < min || n > max {
+            return Err(anyhow!("{} is not within {}-{}", n, min, max));
+        }
+        Ok(n)
+    };
+
+    let mut set = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, Some(step)),
+            None => (part, None),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (number(start)?, number(end)?),
+            // `5/15` means from 5 to the maximum
+            None if step.is_some() => (number(range)?, max),
+            None => {
+                let n = number(range)?;
+                (n, n)
+            }
+        };
+        let step = match step {
+            Some(s) => s
+                .parse::<u32>()
+                .ok()
+                .filter(|s| *s > 0)
+                .ok_or_else(|| anyhow!("Invalid step {:?}", s))?,
+            None => 1,
+        };
+        if start > end {
+            return Err(anyhow!("Empty range {:?}", range));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+    Ok(set)
+}
+
+/// Parses intervals like `90s`, `30m`, `6h`, `1d` or `1h30m`
+fn parse_interval(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    let mut seconds: u64 = 0;
+    let mut number = String::new();
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return Err(anyhow!("Invalid interval {:?}", s)),
+        };
+        seconds = number
+            .parse::<u64>()
+            .ok()
+            .and_then(|n| n.checked_mul(unit))
+            .and_then(|n| seconds.checked_add(n))
+            .ok_or_else(|| anyhow!("Invalid interval {:?}", s))?;
+        number.clear();
+    }
+    if !number.is_empty() || seconds == 0 {
+        return Err(anyhow!(
+            "Invalid interval {:?}, use e.g. 30m, 6h or 1d",
+            s
+        ));
+    }
+    let interval = Duration::from_secs(seconds);
+    // Intervals are added to times, which have a smaller range
+    if Span::from_std(interval).is_err() {
+        return Err(anyhow!("Invalid interval {:?}, too long", s));
+    }
+    Ok(interval)
+}
+
+fn format_interval(interval: Duration) -> String {
+    let mut seconds = interval.as_secs();
+    let mut out = String::new();
+    for (unit, size) in [("d", 86400), ("h", 3600), ("m", 60), ("s", 1)].iter()
+    {
+        if seconds >= *size {
+            out += &format!("{}{}", seconds / size, unit);
+            seconds %= size;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        time.parse().unwrap()
+    }
+
+    fn next(expression: &str, after: &str) -> Option<DateTime<Utc>> {
+        let cron: Cron = expression.parse().unwrap();
+        cron.next_after(at(after))
+    }
+
+    #[test]
+    fn days_match_the_day_of_month_or_the_day_of_week() {
+        // 2021-01-01 is a Friday
+        let friday_or_13th = "0 0 13 * 5";
+        assert_eq!(
+            next(friday_or_13th, "2021-01-01T00:00:00Z"),
+            Some(at("2021-01-08T00:00:00Z"))
+        );
+        assert_eq!(
+            next(friday_or_13th, "2021-01-08T00:00:00Z"),
+            Some(at("2021-01-13T00:00:00Z"))
+        );
+        // with one of them `*` only the other restricts the day
+        assert_eq!(
+            next("0 0 13 * *", "2021-01-01T00:00:00Z"),
+            Some(at("2021-01-13T00:00:00Z"))
+        );
+        assert_eq!(
+            next("0 0 * * 5", "2021-01-01T00:00:00Z"),
+            Some(at("2021-01-08T00:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn steps_ranges_and_lists() {
+        assert_eq!(
+            next("*/15 * * * *", "2021-01-01T10:07:30Z"),
+            Some(at("2021-01-01T10:15:00Z"))
+        );
+        assert_eq!(
+            next("30 */6 * * *", "2021-01-01T07:00:00Z"),
+            Some(at("2021-01-01T12:30:00Z"))
+        );
+        let cron: Cron = "1-30/10,5/20 * * * *".parse().unwrap();
+        let minutes: Vec<_> =
+            (0..60).filter(|m| has(cron.minutes, *m)).collect();
+        assert_eq!(minutes, vec![1, 5, 11, 21, 25, 45]);
+        assert_eq!(
+            next("0 9 * * 1-5", "2021-01-01T10:00:00Z"),
+            Some(at("2021-01-04T09:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn seven_is_sunday() {
+        let sunday: Cron = "0 0 * * 7".parse().unwrap();
+        let zero: Cron = "0 0 * * 0".parse().unwrap();
+        assert!(has(sunday.weekdays, 0));
+        assert_eq!(
+            sunday.next_after(at("2021-01-01T00:00:00Z")),
+            zero.next_after(at("2021-01-01T00:00:00Z"))
+        );
+        assert_eq!(
+            next("0 0 * * 6-7", "2021-01-02T12:00:00Z"),
+            Some(at("2021-01-03T00:00:00Z"))
+        );
+        assert_eq!(
+            next("@weekly", "2021-01-01T00:00:00Z"),
+            Some(at("2021-01-03T00:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn impossible_dates_never_match() {
+        assert_eq!(next("0 0 31 2 *", "2021-01-01T00:00:00Z"), None);
+        assert_eq!(next("0 0 31 4,6,9,11 *", "2021-01-01T00:00:00Z"), None);
+        // leap days are found years ahead
+        assert_eq!(
+            next("0 0 29 2 *", "2021-03-01T00:00:00Z"),
+            Some(at("2024-02-29T00:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn invalid_expressions_are_rejected() {
+        for expression in &[
+            "* * * *",
+            "60 * * * *",
+            "0 24 * * *",
+            "0 0 0 * *",
+            "0 0 * 13 *",
+            "0 0 * * 8",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "a * * * *",
+            "@yearly",
+        ] {
+            assert!(expression.parse::<Cron>().is_err(), "{}", expression);
+        }
+    }
+
+    #[test]
+    fn intervals_count_from_the_previous_crawl() {
+        let every: Schedule = "every 1h30m".parse().unwrap();
+        assert_eq!(every, Schedule::Every(Duration::from_secs(5400)));
+        assert_eq!(every.to_string(), "every 1h30m");
+        let now = at("2021-01-01T12:00:00Z");
+        assert_eq!(every.first(now), Some(now));
+        assert_eq!(every.next_after(now), Some(at("2021-01-01T13:30:00Z")));
+        for invalid in &[
+            "every 6",
+            "every 0m",
+            "every 1w",
+            "every h",
+            "every 99999999999999d",
+            "every 999999999999999999999d",
+        ] {
+            assert!(invalid.parse::<Schedule>().is_err(), "{}", invalid);
+        }
+        // due after the latest representable time
+        let long: Schedule = "every 200000000d".parse().unwrap();
+        assert_eq!(long.next_after(now), None);
+    }
+}
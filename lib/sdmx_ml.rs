@@ -0,0 +1,1169 @@
+//! sdmx_ml writes structure messages as SDMX-ML 2.1 XML, for tools which
+//! don't read SDMX-JSON such as validation engines and registry imports.
+//!
+//! The SDMX-JSON 1.0 structure model maps closely to SDMX-ML 2.1, so the
+//! writer follows the structure types field by field. References are written
+//! as URNs. Artefacts without an SDMX-ML mapping here (metadata structures,
+//! metadataflows, structure sets, reporting taxonomies, processes and
+//! attachment constraints) are rejected rather than silently dropped.
+
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    artefact::{
+        Identifiable, ItemScheme, Maintainable, Nameable, DEFAULT_VERSION,
+    },
+    components::component_id,
+    localized::LocalizedString,
+    structure::*,
+    urn::class_name,
+};
+
+const MESSAGE_NS: &str =
+    "http://www.sdmx.org/resources/sdmxml/schemas/v2_1/message";
+const STRUCTURE_NS: &str =
+    "http://www.sdmx.org/resources/sdmxml/schemas/v2_1/structure";
+const COMMON_NS: &str =
+    "http://www.sdmx.org/resources/sdmxml/schemas/v2_1/common";
+
+/// Language of names which are only given in the language of the message
+const DEFAULT_LANGUAGE: &str = "en";
+
+type Attributes<'a> = [(&'a str, String)];
+
+/// Writes a structure message as an SDMX-ML 2.1 `Structure` message. The
+/// header is taken from the `meta` object where present.
+pub fn structure_to_xml(structure: &Structure) -> Result<String> {
+    let data = structure
+        .data
+        .as_ref()
+        .ok_or_else(|| anyhow!("The message has no structures"))?;
+    write_message(data, structure.meta.as_ref())
+}
+
+/// Writes structures as an SDMX-ML 2.1 `Structure` message with a generated
+/// header
+pub fn data_to_xml(data: &Data) -> Result<String> {
+    write_message(data, None)
+}
+
+fn write_message(data: &Data, meta: Option<&Meta>) -> Result<String> {
+    let unsupported: Vec<_> = data
+        .maintainables()
+        .into_iter()
+        .filter(|m| {
+            matches!(
+                m.class(),
+                ObjectTypeCodelistType::AttachmentConstraint
+                    | ObjectTypeCodelistType::Metadataflow
+                    | ObjectTypeCodelistType::MetadataStructure
+                    | ObjectTypeCodelistType::Process
+                    | ObjectTypeCodelistType::ReportingTaxonomy
+                    | ObjectTypeCodelistType::StructureSet
+            )
+        })
+        .map(|m| {
+            format!(
+                "{} {}:{}",
+                class_name(m.class()),
+                m.agency_id(),
+                m.resource_id()
+            )
+        })
+        .collect();
+    if !unsupported.is_empty() {
+        return Err(anyhow!(
+            "SDMX-ML export does not support {}",
+            unsupported.join(", ")
+        ));
+    }
+
+    let language = meta
+        .and_then(|m| m.content_languages.as_ref())
+        .and_then(|l| l.first())
+        .map(|l| l.as_str())
+        .unwrap_or(DEFAULT_LANGUAGE);
+    let mut w = XmlWriter::new(language);
+    w.out
+        .push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    w.open(
+        "mes:Structure",
+        &[
+            ("xmlns:mes", MESSAGE_NS.to_string()),
+            ("xmlns:str", STRUCTURE_NS.to_string()),
+            ("xmlns:com", COMMON_NS.to_string()),
+        ],
+    );
+    header(&mut w, meta);
+    w.open("mes:Structures", &[]);
+    structures(&mut w, data)?;
+    w.close("mes:Structures");
+    w.close("mes:Structure");
+    Ok(w.out)
+}
+
+/// Writes indented XML elements into a string
+struct XmlWriter<'a> {
+    out: String,
+    depth: usize,
+    language: &'a str,
+}
+
+impl<'a> XmlWriter<'a> {
+    fn new(language: &'a str) -> Self {
+        XmlWriter {
+            out: String::new(),
+            depth: 0,
+            language,
+        }
+    }
+
+    fn start_tag(&mut self, name: &str, attributes: &Attributes) {
+        self.out.push_str(&"  ".repeat(self.depth));
+        self.out.push('<');
+        self.out.push_str(name);
+        for (key, value) in attributes {
+            self.out
+                .push_str(&format!(" {}=\"{}\"", key, escape(value)));
+        }
+    }
+
+    fn open(&mut self, name: &str, attributes: &Attributes) {
+        self.start_tag(name, attributes);
+        self.out.push_str(">\n");
+        self.depth += 1;
+    }
+
+    fn close(&mut self, name: &str) {
+        self.depth -= 1;
+        self.out.push_str(&"  ".repeat(self.depth));
+        self.out.push_str(&format!("</{}>\n", name));
+    }
+
+    fn empty(&mut self, name: &str, attributes: &Attributes) {
+        self.start_tag(name, attributes);
+        self.out.push_str("/>\n");
+    }
+
+    fn text(&mut self, name: &str, attributes: &Attributes, text: &str) {
+        self.start_tag(name, attributes);
+        self.out
+            .push_str(&format!(">{}</{}>\n", escape(text), name));
+    }
+
+    /// Writes an element for each language of a localized text, or for the
+    /// text in the language of the message
+    fn localized(
+        &mut self,
+        name: &str,
+        texts: Option<&LocalizedString>,
+        text: Option<&str>,
+    ) {
+        match texts.filter(|t| !t.is_empty()) {
+            Some(texts) => {
+                for (language, text) in texts.iter() {
+                    self.text(name, &[("xml:lang", language.to_string())], text)
+                }
+            }
+            None => {
+                if let Some(text) = text {
+                    let language = self.language.to_string();
+                    self.text(name, &[("xml:lang", language)], text)
+                }
+            }
+        }
+    }
+
+    /// Writes a reference to an artefact by URN
+    fn reference(&mut self, name: &str, urn: &str) {
+        self.open(name, &[]);
+        self.text("URN", &[], urn);
+        self.close(name);
+    }
+
+    /// Writes a reference to a component or item within the same artefact
+    fn local_reference(&mut self, name: &str, id: &str) {
+        self.open(name, &[]);
+        self.empty("Ref", &[("id", id.to_string())]);
+        self.close(name);
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// The non-null fields of a text format as XML attributes. The SDMX-JSON
+/// text format fields have the same names as the SDMX-ML attributes.
+fn format_attributes<T: Serialize>(
+    format: &T,
+) -> Result<Vec<(String, String)>> {
+    let value = serde_json::to_value(format)?;
+    let fields = match value {
+        Value::Object(fields) => fields,
+        _ => return Err(anyhow!("Text format is not an object")),
+    };
+    Ok(fields
+        .into_iter()
+        .filter(|(_, v)| !v.is_null())
+        .map(|(k, v)| match v {
+            Value::String(s) => (k, s),
+            v => (k, v.to_string()),
+        })
+        .collect())
+}
+
+fn format_element<T: Serialize>(
+    w: &mut XmlWriter,
+    name: &str,
+    format: &T,
+) -> Result<()> {
+    let attributes = format_attributes(format)?;
+    let attributes: Vec<_> = attributes
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.clone()))
+        .collect();
+    w.empty(name, &attributes);
+    Ok(())
+}
+
+/// Writes any of the representation types: an enumeration, with an
+/// optional enumeration format, or else a text format
+fn representation<T: Serialize>(
+    w: &mut XmlWriter,
+    name: &str,
+    repr: &T,
+) -> Result<()> {
+    let value = serde_json::to_value(repr)?;
+    let enumeration = value.get("enumeration").and_then(|e| e.as_str());
+    let enumeration_format =
+        value.get("enumerationFormat").filter(|f| !f.is_null());
+    let text_format = value.get("textFormat").filter(|f| !f.is_null());
+    if enumeration.is_none() && text_format.is_none() {
+        return Ok(());
+    }
+
+    w.open(name, &[]);
+    match enumeration {
+        Some(enumeration) => {
+            w.reference("str:Enumeration", enumeration);
+            if let Some(format) = enumeration_format {
+                format_element(w, "str:EnumerationFormat", format)?;
+            }
+        }
+        None => {
+            if let Some(format) = text_format {
+                format_element(w, "str:TextFormat", format)?;
+            }
+        }
+    }
+    w.close(name);
+    Ok(())
+}
+
+fn header(w: &mut XmlWriter, meta: Option<&Meta>) {
+    w.open("mes:Header", &[]);
+    match meta {
+        Some(meta) => {
+            w.text("mes:ID", &[], &meta.id);
+            let test = meta.test.unwrap_or(false).to_string();
+            w.text("mes:Test", &[], &test);
+            w.text("mes:Prepared", &[], &meta.prepared);
+            party(w, "mes:Sender", &meta.sender);
+            for receiver in meta.receivers.iter().flatten() {
+                party(w, "mes:Receiver", receiver);
+            }
+            w.localized("com:Name", meta.names.as_ref(), meta.name.as_deref());
+        }
+        None => {
+            let id = format!("IREF{}", Utc::now().timestamp());
+            w.text("mes:ID", &[], &id);
+            w.text("mes:Test", &[], "false");
+            w.text("mes:Prepared", &[], &Utc::now().to_rfc3339());
+            w.empty("mes:Sender", &[("id", "not_supplied".to_string())]);
+        }
+    }
+    w.close("mes:Header");
+}
+
+fn party(w: &mut XmlWriter, name: &str, party: &Party) {
+    let attributes = [("id", party.id.clone())];
+    if party.name.is_none() && party.names.is_none() {
+        w.empty(name, &attributes);
+        return;
+    }
+    w.open(name, &attributes);
+    w.localized("com:Name", party.names.as_ref(), party.name.as_deref());
+    w.close(name);
+}
+
+fn annotations(w: &mut XmlWriter, annotations: &[AnnotationType]) {
+    if annotations.is_empty() {
+        return;
+    }
+    w.open("com:Annotations", &[]);
+    for a in annotations {
+        let attributes: Vec<_> =
+            a.id.iter().map(|id| ("id", id.clone())).collect();
+        w.open("com:Annotation", &attributes);
+        if let Some(title) = &a.title {
+            w.text("com:AnnotationTitle", &[], title);
+        }
+        if let Some(kind) = &a.annotation_type_type {
+            w.text("com:AnnotationType", &[], kind);
+        }
+        if let Some(url) = annotation_url(a) {
+            w.text("com:AnnotationURL", &[], url);
+        }
+        w.localized("com:AnnotationText", a.texts.as_ref(), a.text.as_deref());
+        w.close("com:Annotation");
+    }
+    w.close("com:Annotations");
+}
+
+/// The single URL an SDMX-ML annotation allows: the `self` link, or else the
+/// first link with an address
+fn annotation_url(a: &AnnotationType) -> Option<&str> {
+    let links = a.links.as_deref().unwrap_or(&[]);
+    links
+        .iter()
+        .filter(|l| l.rel == "self")
+        .chain(links.iter())
+        .find_map(|l| l.href.as_deref())
+}
+
+/// The `id` and `urn` attributes of an identifiable object
+fn identifiable_attributes<T: Identifiable + ?Sized>(
+    i: &T,
+) -> Vec<(&'static str, String)> {
+    let mut out = vec![];
+    if let Some(id) = i.id() {
+        out.push(("id", id.to_string()));
+    }
+    if let Some(urn) = i.urn() {
+        out.push(("urn", urn.to_string()));
+    }
+    out
+}
+
+/// Opens the element of a nameable object and writes its annotations, names
+/// and descriptions
+fn open_nameable<T: Nameable + ?Sized>(
+    w: &mut XmlWriter,
+    name: &str,
+    attributes: &Attributes,
+    n: &T,
+) {
+    w.open(name, attributes);
+    annotations(w, n.annotations());
+    w.localized("com:Name", n.names(), Some(n.name()));
+    w.localized("com:Description", n.descriptions(), n.description());
+}
+
+fn open_maintainable<T: Maintainable + ?Sized>(
+    w: &mut XmlWriter,
+    name: &str,
+    m: &T,
+    extra: &Attributes,
+) {
+    let mut attributes = vec![("id", m.resource_id().to_string())];
+    if let Some(urn) = m.maintainable_urn() {
+        attributes.push(("urn", urn.to_string()));
+    }
+    attributes.push(("agencyID", m.agency_id().to_string()));
+    // SDMX-ML requires a version
+    let version = m.version().unwrap_or(DEFAULT_VERSION);
+    attributes.push(("version", version.to_string()));
+    if m.is_external_reference() {
+        attributes.push(("isExternalReference", "true".to_string()));
+    }
+    attributes.push(("isFinal", m.is_final().to_string()));
+    if let Some(valid_from) = m.valid_from() {
+        attributes.push(("validFrom", valid_from.to_string()));
+    }
+    if let Some(valid_to) = m.valid_to() {
+        attributes.push(("validTo", valid_to.to_string()));
+    }
+    attributes.extend(extra.iter().cloned());
+    open_nameable(w, name, &attributes, m);
+}
+
+fn open_item_scheme<T: ItemScheme>(w: &mut XmlWriter, name: &str, scheme: &T) {
+    let extra = [("isPartial", scheme.is_partial().to_string())];
+    open_maintainable(w, name, scheme, &extra);
+}
+
+/// Writes a simple item, e.g. a code, calling `content` for the elements
+/// after the names
+fn item<T, F>(w: &mut XmlWriter, name: &str, item: &T, content: F) -> Result<()>
+where
+    T: Nameable,
+    F: FnOnce(&mut XmlWriter) -> Result<()>,
+{
+    open_nameable(w, name, &identifiable_attributes(item), item);
+    content(w)?;
+    w.close(name);
+    Ok(())
+}
+
+fn structures(w: &mut XmlWriter, data: &Data) -> Result<()> {
+    if data.agency_schemes.is_some()
+        || data.data_consumer_schemes.is_some()
+        || data.data_provider_schemes.is_some()
+        || data.organisation_unit_schemes.is_some()
+    {
+        w.open("str:OrganisationSchemes", &[]);
+        for scheme in data.agency_schemes.iter().flatten() {
+            open_item_scheme(w, "str:AgencyScheme", scheme);
+            for a in scheme.items() {
+                item(w, "str:Agency", a, |_| Ok(()))?;
+            }
+            w.close("str:AgencyScheme");
+        }
+        for scheme in data.data_consumer_schemes.iter().flatten() {
+            open_item_scheme(w, "str:DataConsumerScheme", scheme);
+            for c in scheme.items() {
+                item(w, "str:DataConsumer", c, |_| Ok(()))?;
+            }
+            w.close("str:DataConsumerScheme");
+        }
+        for scheme in data.data_provider_schemes.iter().flatten() {
+            open_item_scheme(w, "str:DataProviderScheme", scheme);
+            for p in scheme.items() {
+                item(w, "str:DataProvider", p, |_| Ok(()))?;
+            }
+            w.close("str:DataProviderScheme");
+        }
+        for scheme in data.organisation_unit_schemes.iter().flatten() {
+            open_item_scheme(w, "str:OrganisationUnitScheme", scheme);
+            for u in scheme.items() {
+                item(w, "str:OrganisationUnit", u, |w| {
+                    parent(w, u.parent.as_deref());
+                    Ok(())
+                })?;
+            }
+            w.close("str:OrganisationUnitScheme");
+        }
+        w.close("str:OrganisationSchemes");
+    }
+
+    if let Some(dataflows) = &data.dataflows {
+        w.open("str:Dataflows", &[]);
+        for df in dataflows {
+            open_maintainable(w, "str:Dataflow", df, &[]);
+            if let Some(structure) = &df.structure {
+                w.reference("str:Structure", structure);
+            }
+            w.close("str:Dataflow");
+        }
+        w.close("str:Dataflows");
+    }
+
+    if let Some(schemes) = &data.category_schemes {
+        w.open("str:CategorySchemes", &[]);
+        for scheme in schemes {
+            open_item_scheme(w, "str:CategoryScheme", scheme);
+            for c in scheme.items() {
+                category(w, c)?;
+            }
+            w.close("str:CategoryScheme");
+        }
+        w.close("str:CategorySchemes");
+    }
+
+    if let Some(categorisations) = &data.categorisations {
+        w.open("str:Categorisations", &[]);
+        for c in categorisations {
+            open_maintainable(w, "str:Categorisation", c, &[]);
+            if let Some(source) = &c.source {
+                w.reference("str:Source", source);
+            }
+            if let Some(target) = &c.target {
+                w.reference("str:Target", target);
+            }
+            w.close("str:Categorisation");
+        }
+        w.close("str:Categorisations");
+    }
+
+    if let Some(codelists) = &data.codelists {
+        w.open("str:Codelists", &[]);
+        for codelist in codelists {
+            open_item_scheme(w, "str:Codelist", codelist);
+            for code in codelist.items() {
+                item(w, "str:Code", code, |w| {
+                    parent(w, code.parent.as_deref());
+                    Ok(())
+                })?;
+            }
+            w.close("str:Codelist");
+        }
+        w.close("str:Codelists");
+    }
+
+    if let Some(hcls) = &data.hierarchical_codelists {
+        w.open("str:HierarchicalCodelists", &[]);
+        for hcl in hcls {
+            hierarchical_codelist(w, hcl)?;
+        }
+        w.close("str:HierarchicalCodelists");
+    }
+
+    if let Some(schemes) = &data.concept_schemes {
+        w.open("str:Concepts", &[]);
+        for scheme in schemes {
+            open_item_scheme(w, "str:ConceptScheme", scheme);
+            for concept in scheme.items() {
+                item(w, "str:Concept", concept, |w| {
+                    parent(w, concept.parent.as_deref());
+                    if let Some(repr) = &concept.core_representation {
+                        representation(w, "str:CoreRepresentation", repr)?;
+                    }
+                    Ok(())
+                })?;
+            }
+            w.close("str:ConceptScheme");
+        }
+        w.close("str:Concepts");
+    }
+
+    if let Some(dsds) = &data.data_structures {
+        w.open("str:DataStructures", &[]);
+        for dsd in dsds {
+            data_structure(w, dsd)?;
+        }
+        w.close("str:DataStructures");
+    }
+
+    if let Some(constraints) = &data.content_constraints {
+        w.open("str:Constraints", &[]);
+        for c in constraints {
+            content_constraint(w, c)?;
+        }
+        w.close("str:Constraints");
+    }
+
+    if let Some(agreements) = &data.provision_agreements {
+        w.open("str:ProvisionAgreements", &[]);
+        for pa in agreements {
+            open_maintainable(w, "str:ProvisionAgreement", pa, &[]);
+            w.reference("str:StructureUsage", &pa.structure_usage);
+            w.reference("str:DataProvider", &pa.data_provider);
+            w.close("str:ProvisionAgreement");
+        }
+        w.close("str:ProvisionAgreements");
+    }
+    Ok(())
+}
+
+fn parent(w: &mut XmlWriter, parent: Option<&str>) {
+    if let Some(parent) = parent {
+        w.local_reference("str:Parent", parent);
+    }
+}
+
+fn category(w: &mut XmlWriter, category: &CategoryTypeElement) -> Result<()> {
+    item(w, "str:Category", category, |w| {
+        for c in category.categories.iter().flatten() {
+            self::category(w, c)?;
+        }
+        Ok(())
+    })
+}
+
+fn hierarchical_codelist(
+    w: &mut XmlWriter,
+    hcl: &HierarchicalCodelistTypeElement,
+) -> Result<()> {
+    open_maintainable(w, "str:HierarchicalCodelist", hcl, &[]);
+    for h in hcl.hierarchies.iter().flatten() {
+        let mut attributes = identifiable_attributes(h);
+        if let Some(leveled) = h.leveled {
+            attributes.push(("leveled", leveled.to_string()));
+        }
+        open_nameable(w, "str:Hierarchy", &attributes, h);
+        for code in &h.hierarchical_codes {
+            hierarchical_code(w, code);
+        }
+        if let Some(level) = h.level.as_ref() {
+            self::level(w, level)?;
+        }
+        w.close("str:Hierarchy");
+    }
+    w.close("str:HierarchicalCodelist");
+    Ok(())
+}
+
+fn hierarchical_code(w: &mut XmlWriter, code: &HierarchicalCodeTypeElement) {
+    let mut attributes = identifiable_attributes(code);
+    if let Some(version) = &code.version {
+        attributes.push(("version", version.clone()));
+    }
+    if let Some(valid_from) = &code.valid_from {
+        attributes.push(("validFrom", valid_from.clone()));
+    }
+    if let Some(valid_to) = &code.valid_to {
+        attributes.push(("validTo", valid_to.clone()));
+    }
+    w.open("str:HierarchicalCode", &attributes);
+    annotations(w, code.annotations());
+    match (&code.code, &code.codelist_alias_ref, &code.code_id) {
+        (Some(urn), _, _) => w.reference("str:Code", urn),
+        (None, Some(alias), Some(id)) => {
+            w.text("str:CodelistAliasRef", &[], alias);
+            w.local_reference("str:CodeID", id);
+        }
+        _ => {}
+    }
+    for child in code.hierarchical_codes.iter().flatten() {
+        hierarchical_code(w, child);
+    }
+    if let Some(level) = &code.level {
+        w.local_reference("str:Level", level);
+    }
+    w.close("str:HierarchicalCode");
+}
+
+fn level(w: &mut XmlWriter, level: &LevelTypeClass) -> Result<()> {
+    item(w, "str:Level", level, |w| {
+        if let Some(format) = &level.coding_format {
+            format_element(w, "str:CodingFormat", format)?;
+        }
+        if let Some(child) = level.level.as_ref() {
+            self::level(w, child)?;
+        }
+        Ok(())
+    })
+}
+
+/// The ID attribute of a component, inherited from its concept identity if
+/// not explicit
+fn component_attributes<T: Identifiable>(
+    c: &T,
+    concept_identity: &str,
+) -> Vec<(&'static str, String)> {
+    let id = c.id().map(|id| id.to_string());
+    let mut attributes =
+        vec![("id", component_id(id.as_ref(), concept_identity))];
+    if let Some(urn) = c.urn() {
+        attributes.push(("urn", urn.to_string()));
+    }
+    attributes
+}
+
+fn concept_roles(w: &mut XmlWriter, roles: &Option<Vec<String>>) {
+    for role in roles.iter().flatten() {
+        w.reference("str:ConceptRole", role);
+    }
+}
+
+fn attribute_relationship(w: &mut XmlWriter, rel: &AttributeRelationshipType) {
+    w.open("str:AttributeRelationship", &[]);
+    let dimensions = rel.dimensions.as_deref().unwrap_or_default();
+    if let Some(group) = &rel.group {
+        w.local_reference("str:Group", group);
+    } else if let Some(measure) = &rel.primary_measure {
+        w.local_reference("str:PrimaryMeasure", measure);
+    } else if !dimensions.is_empty() {
+        for d in dimensions {
+            w.local_reference("str:Dimension", d);
+        }
+        for g in rel.attachment_groups.iter().flatten() {
+            w.local_reference("str:AttachmentGroup", g);
+        }
+    } else {
+        w.empty("str:None", &[]);
+    }
+    w.close("str:AttributeRelationship");
+}
+
+fn data_structure(
+    w: &mut XmlWriter,
+    dsd: &DataStructureTypeElement,
+) -> Result<()> {
+    open_maintainable(w, "str:DataStructure", dsd, &[]);
+    let components = match &dsd.data_structure_components {
+        Some(c) => c,
+        None => {
+            w.close("str:DataStructure");
+            return Ok(());
+        }
+    };
+    w.open("str:DataStructureComponents", &[]);
+
+    let list = &components.dimension_list;
+    let id = list
+        .id
+        .clone()
+        .unwrap_or_else(|| "DimensionDescriptor".into());
+    w.open("str:DimensionList", &[("id", id)]);
+    annotations(w, list.annotations());
+    for d in list.dimensions.iter().flatten() {
+        let mut attributes = component_attributes(d, &d.concept_identity);
+        if let Some(position) = d.position {
+            attributes.push(("position", position.to_string()));
+        }
+        w.open("str:Dimension", &attributes);
+        annotations(w, d.annotations());
+        w.reference("str:ConceptIdentity", &d.concept_identity);
+        if let Some(repr) = &d.local_representation {
+            representation(w, "str:LocalRepresentation", repr)?;
+        }
+        concept_roles(w, &d.concept_roles);
+        w.close("str:Dimension");
+    }
+    for d in list.measure_dimensions.iter().flatten() {
+        let mut attributes = component_attributes(d, &d.concept_identity);
+        if let Some(position) = d.position {
+            attributes.push(("position", position.to_string()));
+        }
+        w.open("str:MeasureDimension", &attributes);
+        annotations(w, d.annotations());
+        w.reference("str:ConceptIdentity", &d.concept_identity);
+        representation(w, "str:LocalRepresentation", &d.local_representation)?;
+        concept_roles(w, &d.concept_roles);
+        w.close("str:MeasureDimension");
+    }
+    for d in list.time_dimensions.iter().flatten() {
+        let mut attributes = component_attributes(d, &d.concept_identity);
+        if let Some(position) = d.position {
+            attributes.push(("position", position.to_string()));
+        }
+        w.open("str:TimeDimension", &attributes);
+        annotations(w, d.annotations());
+        w.reference("str:ConceptIdentity", &d.concept_identity);
+        representation(w, "str:LocalRepresentation", &d.local_representation)?;
+        w.close("str:TimeDimension");
+    }
+    w.close("str:DimensionList");
+
+    for g in components.groups.iter().flatten() {
+        w.open("str:Group", &identifiable_attributes(g));
+        annotations(w, g.annotations());
+        for d in g.group_dimensions.iter().flatten() {
+            w.open("str:GroupDimension", &[]);
+            w.local_reference("str:DimensionReference", d);
+            w.close("str:GroupDimension");
+        }
+        if let Some(constraint) = &g.attachment_constraint {
+            w.reference("str:AttachmentConstraint", constraint);
+        }
+        w.close("str:Group");
+    }
+
+    if let Some(list) = &components.attribute_list {
+        let id = list
+            .id
+            .clone()
+            .unwrap_or_else(|| "AttributeDescriptor".into());
+        w.open("str:AttributeList", &[("id", id)]);
+        annotations(w, list.annotations());
+        for a in list.attributes.iter().flatten() {
+            let mut attributes = component_attributes(a, &a.concept_identity);
+            attributes.push(("assignmentStatus", usage(&a.assignment_status)));
+            w.open("str:Attribute", &attributes);
+            annotations(w, a.annotations());
+            w.reference("str:ConceptIdentity", &a.concept_identity);
+            if let Some(repr) = &a.local_representation {
+                representation(w, "str:LocalRepresentation", repr)?;
+            }
+            concept_roles(w, &a.concept_roles);
+            attribute_relationship(w, &a.attribute_relationship);
+            w.close("str:Attribute");
+        }
+        for a in list.reporting_year_start_days.iter().flatten() {
+            let mut attributes = component_attributes(a, &a.concept_identity);
+            attributes.push(("assignmentStatus", usage(&a.assignment_status)));
+            w.open("str:ReportingYearStartDay", &attributes);
+            annotations(w, a.annotations());
+            w.reference("str:ConceptIdentity", &a.concept_identity);
+            representation(
+                w,
+                "str:LocalRepresentation",
+                &a.local_representation,
+            )?;
+            attribute_relationship(w, &a.attribute_relationship);
+            w.close("str:ReportingYearStartDay");
+        }
+        w.close("str:AttributeList");
+    }
+
+    let list = &components.measure_list;
+    let measure = &list.primary_measure;
+    let measure_id =
+        component_id(measure.id.as_ref(), &measure.concept_identity);
+    if let Some(other) = list.measures.iter().flatten().find(|m| {
+        component_id(m.id.as_ref(), &m.concept_identity) != measure_id
+    }) {
+        return Err(anyhow!(
+            "SDMX-ML 2.1 only supports one measure, {} has {} as well",
+            dsd.id,
+            component_id(other.id.as_ref(), &other.concept_identity)
+        ));
+    }
+    let id = list
+        .id
+        .clone()
+        .unwrap_or_else(|| "MeasureDescriptor".into());
+    w.open("str:MeasureList", &[("id", id)]);
+    annotations(w, list.annotations());
+    w.open(
+        "str:PrimaryMeasure",
+        &component_attributes(measure, &measure.concept_identity),
+    );
+    annotations(w, measure.annotations());
+    w.reference("str:ConceptIdentity", &measure.concept_identity);
+    if let Some(repr) = &measure.local_representation {
+        representation(w, "str:LocalRepresentation", repr)?;
+    }
+    w.close("str:PrimaryMeasure");
+    w.close("str:MeasureList");
+
+    w.close("str:DataStructureComponents");
+    w.close("str:DataStructure");
+    Ok(())
+}
+
+fn usage(status: &UsageStatusType) -> String {
+    match status {
+        UsageStatusType::Conditional => "Conditional",
+        UsageStatusType::Mandatory => "Mandatory",
+    }
+    .to_string()
+}
+
+fn content_constraint(
+    w: &mut XmlWriter,
+    c: &ContentConstraintTypeElement,
+) -> Result<()> {
+    let kind = match c.type_type {
+        Some(ContentConstraintTypeCodeType::Actual) => "Actual",
+        _ => "Allowed",
+    };
+    open_maintainable(
+        w,
+        "str:ContentConstraint",
+        c,
+        &[("type", kind.to_string())],
+    );
+
+    if let Some(attachment) = &c.constraint_attachment {
+        w.open("str:ConstraintAttachment", &[]);
+        if let Some(provider) = &attachment.data_provider {
+            w.reference("str:DataProvider", provider);
+        }
+        for urn in attachment.data_structures.iter().flatten() {
+            w.reference("str:DataStructure", urn);
+        }
+        for urn in attachment.dataflows.iter().flatten() {
+            w.reference("str:Dataflow", urn);
+        }
+        for urn in attachment.provision_agreements.iter().flatten() {
+            w.reference("str:ProvisionAgreement", urn);
+        }
+        if let Some(url) = &attachment.simple_data_source {
+            w.text("str:SimpleDataSource", &[], url);
+        }
+        w.close("str:ConstraintAttachment");
+    }
+
+    for set in c.data_key_sets.iter().flatten() {
+        w.open(
+            "str:DataKeySet",
+            &[("isIncluded", set.is_included.to_string())],
+        );
+        for key in &set.keys {
+            w.open("str:Key", &[]);
+            for kv in &key.key_values {
+                w.open("com:KeyValue", &[("id", kv.id.clone())]);
+                w.text("com:Value", &[], &kv.value);
+                w.close("com:KeyValue");
+            }
+            w.close("str:Key");
+        }
+        w.close("str:DataKeySet");
+    }
+
+    for region in c.cube_regions.iter().flatten() {
+        let include = region.is_included.unwrap_or(true).to_string();
+        w.open("str:CubeRegion", &[("include", include)]);
+        for kv in region.key_values.iter().flatten() {
+            component_values(
+                w,
+                "com:KeyValue",
+                &kv.id,
+                kv.values.as_deref(),
+                kv.cascade_values.as_deref(),
+                kv.time_range.as_ref(),
+            );
+        }
+        for a in region.attributes.iter().flatten() {
+            component_values(
+                w,
+                "com:Attribute",
+                &a.id,
+                a.values.as_deref(),
+                a.cascade_values.as_deref(),
+                a.time_range.as_ref(),
+            );
+        }
+        w.close("str:CubeRegion");
+    }
+
+    if let Some(calendar) = &c.release_calendar {
+        w.open("str:ReleaseCalendar", &[]);
+        w.text("str:Periodicity", &[], &calendar.periodicity);
+        w.text("str:Offset", &[], &calendar.offset);
+        w.text("str:Tolerance", &[], &calendar.tolerance);
+        w.close("str:ReleaseCalendar");
+    }
+    if let Some(period) = &c.reference_period {
+        w.empty(
+            "str:ReferencePeriod",
+            &[
+                ("startTime", period.start_time.clone()),
+                ("endTime", period.end_time.clone()),
+            ],
+        );
+    }
+    w.close("str:ContentConstraint");
+    Ok(())
+}
+
+fn component_values(
+    w: &mut XmlWriter,
+    name: &str,
+    id: &str,
+    values: Option<&[String]>,
+    cascade_values: Option<&[String]>,
+    time_range: Option<&TimeRangeValueType>,
+) {
+    w.open(name, &[("id", id.to_string())]);
+    for v in values.unwrap_or_default() {
+        match cascade_values.unwrap_or_default().contains(v) {
+            true => w.text("com:Value", &[("cascadeValues", "true".into())], v),
+            false => w.text("com:Value", &[], v),
+        }
+    }
+    if let Some(range) = time_range {
+        w.open("com:TimeRange", &[]);
+        let periods = [
+            ("com:BeforePeriod", &range.before_period),
+            ("com:AfterPeriod", &range.after_period),
+            ("com:StartPeriod", &range.start_period),
+            ("com:EndPeriod", &range.end_period),
+        ];
+        for (name, period) in periods.iter() {
+            if let Some(TimePeriodRangeType {
+                is_inclusive,
+                period: Some(period),
+            }) = period
+            {
+                let inclusive = is_inclusive.unwrap_or(true).to_string();
+                w.text(name, &[("isInclusive", inclusive)], period);
+            }
+        }
+        w.close("com:TimeRange");
+    }
+    w.close(name);
+}
+
+#[cfg(test)]
+mod tests {
+    use roxmltree::{Document, Node};
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    /// The order of the message elements in `StructuresType` of the SDMX-ML
+    /// 2.1 schema
+    const STRUCTURES_ORDER: &[&str] = &[
+        "OrganisationSchemes",
+        "Dataflows",
+        "Metadataflows",
+        "CategorySchemes",
+        "Categorisations",
+        "Codelists",
+        "HierarchicalCodelists",
+        "Concepts",
+        "MetadataStructures",
+        "DataStructures",
+        "StructureSets",
+        "ReportingTaxonomies",
+        "Processes",
+        "Constraints",
+        "ProvisionAgreements",
+    ];
+
+    fn message(data: Value) -> Structure {
+        serde_json::from_value(json!({
+            "meta": {
+                "id": "IREF1",
+                "prepared": "2021-01-01T00:00:00Z",
+                "sender": { "id": "A & B" },
+                "name": "Test <message>"
+            },
+            "data": data
+        }))
+        .expect("structure message")
+    }
+
+    fn annotated_codelist() -> Value {
+        json!({
+            "id": "CL_AREA", "agencyID": "A", "version": "1.0",
+            "name": "Areas \"quoted\" & 'apostrophes'",
+            "description": "<b>not markup</b>",
+            "annotations": [{
+                "title": "Links",
+                "links": [
+                    { "rel": "external", "href": "http://example.org/1" },
+                    { "rel": "self", "href": "http://example.org/self?a=1&b=2" }
+                ]
+            }],
+            "codes": [
+                { "id": "W", "name": "World" },
+                { "id": "FR", "name": "France", "parent": "W" }
+            ]
+        })
+    }
+
+    fn children<'a, 'i>(node: Node<'a, 'i>) -> Vec<Node<'a, 'i>> {
+        node.children().filter(|n| n.is_element()).collect()
+    }
+
+    fn names(node: Node) -> Vec<String> {
+        children(node)
+            .into_iter()
+            .map(|n| n.tag_name().name().to_string())
+            .collect()
+    }
+
+    fn child<'a, 'i>(node: Node<'a, 'i>, name: &str) -> Node<'a, 'i> {
+        children(node)
+            .into_iter()
+            .find(|n| n.tag_name().name() == name)
+            .unwrap_or_else(|| panic!("no {} element", name))
+    }
+
+    #[test]
+    fn output_is_well_formed_and_namespaced() {
+        let xml = structure_to_xml(&message(json!({
+            "codelists": [annotated_codelist()]
+        })))
+        .unwrap();
+        let doc = Document::parse(&xml).expect("well-formed XML");
+        let root = doc.root_element();
+        assert_eq!(root.tag_name().name(), "Structure");
+        assert_eq!(root.tag_name().namespace(), Some(MESSAGE_NS));
+        assert_eq!(names(root), vec!["Header", "Structures"]);
+        let codelist =
+            child(child(child(root, "Structures"), "Codelists"), "Codelist");
+        assert_eq!(codelist.tag_name().namespace(), Some(STRUCTURE_NS));
+        let name = child(codelist, "Name");
+        assert_eq!(name.tag_name().namespace(), Some(COMMON_NS));
+        assert_eq!(
+            name.attribute(("http://www.w3.org/XML/1998/namespace", "lang")),
+            Some("en")
+        );
+    }
+
+    #[test]
+    fn special_characters_are_escaped() {
+        let xml = structure_to_xml(&message(json!({
+            "codelists": [annotated_codelist()]
+        })))
+        .unwrap();
+        assert!(xml.contains("A &amp; B"));
+        assert!(xml.contains("&lt;b&gt;not markup&lt;/b&gt;"));
+        let doc = Document::parse(&xml).unwrap();
+        let root = doc.root_element();
+        let header = child(root, "Header");
+        assert_eq!(child(header, "Sender").attribute("id"), Some("A & B"));
+        assert_eq!(child(header, "Name").text(), Some("Test <message>"));
+        let codelist =
+            child(child(child(root, "Structures"), "Codelists"), "Codelist");
+        assert_eq!(
+            child(codelist, "Name").text(),
+            Some("Areas \"quoted\" & 'apostrophes'")
+        );
+        assert_eq!(
+            child(codelist, "Description").text(),
+            Some("<b>not markup</b>")
+        );
+    }
+
+    #[test]
+    fn elements_follow_the_schema_order() {
+        let xml = structure_to_xml(&message(json!({
+            "codelists": [annotated_codelist()],
+            "dataflows": [{
+                "id": "DF", "agencyID": "A", "version": "1.0", "name": "Flow",
+                "structure": "urn:sdmx:org.sdmx.infomodel.datastructure.DataStructure=A:DSD(1.0)"
+            }],
+            "conceptSchemes": [{
+                "id": "CS", "agencyID": "A", "version": "1.0",
+                "name": "Concepts",
+                "concepts": [{ "id": "AREA", "name": "Area" }]
+            }]
+        })))
+        .unwrap();
+        let doc = Document::parse(&xml).unwrap();
+        let root = doc.root_element();
+
+        let header = names(child(root, "Header"));
+        assert_eq!(header, vec!["ID", "Test", "Prepared", "Sender", "Name"]);
+
+        let structures = names(child(root, "Structures"));
+        let positions: Vec<_> = structures
+            .iter()
+            .map(|s| STRUCTURES_ORDER.iter().position(|o| o == s).unwrap())
+            .collect();
+        let mut sorted = positions.clone();
+        sorted.sort_unstable();
+        assert_eq!(positions, sorted, "{:?}", structures);
+
+        let structures = child(root, "Structures");
+        let codelist = child(child(structures, "Codelists"), "Codelist");
+        assert_eq!(
+            names(codelist),
+            vec!["Annotations", "Name", "Description", "Code", "Code"]
+        );
+        let code = children(codelist)[4];
+        assert_eq!(names(code), vec!["Name", "Parent"]);
+        let dataflow = child(child(structures, "Dataflows"), "Dataflow");
+        assert_eq!(names(dataflow), vec!["Name", "Structure"]);
+    }
+
+    #[test]
+    fn annotations_have_one_url() {
+        let xml = structure_to_xml(&message(json!({
+            "codelists": [annotated_codelist()]
+        })))
+        .unwrap();
+        let doc = Document::parse(&xml).unwrap();
+        let annotation = doc
+            .descendants()
+            .find(|n| n.tag_name().name() == "Annotation")
+            .unwrap();
+        assert_eq!(names(annotation), vec!["AnnotationTitle", "AnnotationURL"]);
+        assert_eq!(
+            child(annotation, "AnnotationURL").text(),
+            Some("http://example.org/self?a=1&b=2")
+        );
+
+        let mut codelist = annotated_codelist();
+        codelist["annotations"][0]["links"][1]["rel"] = json!("external");
+        let xml =
+            structure_to_xml(&message(json!({ "codelists": [codelist] })))
+                .unwrap();
+        assert_eq!(xml.matches("<com:AnnotationURL>").count(), 1);
+        assert!(xml.contains("<com:AnnotationURL>http://example.org/1<"));
+    }
+}
@@ -1,9 +1,27 @@
+use anyhow::{anyhow, Context, Result};
+use http::{header, HeaderName, HeaderValue};
+use mime::Mime;
+use schemars::{schema::RootSchema, schema_for, JsonSchema};
 use serde::{Deserialize, Serialize};
-use std::{fmt::Debug, time::Duration};
+use std::{
+    collections::{BTreeMap, HashSet},
+    env,
+    fmt::Debug,
+    path::Path,
+    time::Duration,
+};
+use url::Url;
+
+use crate::{
+    queries::RestVersion,
+    schedule::Schedule,
+    validate::{Issue, Severity},
+};
 
 pub type Sources = Vec<Source>;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Source {
     pub id: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -14,8 +32,42 @@ pub struct Source {
     pub supports: Option<Supports>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub documentation: Option<String>,
+    /// Headers sent with every request, except `Accept` which is used for
+    /// data queries. Their values are kept out of the WARC files.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub headers: Option<Headers>,
+    /// Credentials sent with every request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth: Option<Auth>,
+    /// The media types to request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub formats: Option<Formats>,
+    /// Seconds after which a request is given up, no limit if missing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<f64>,
+    /// How many requests of a stage are made at once, 1 if missing. Use
+    /// `politeness.requests_per_second` to limit the rate.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub concurrency: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls: Option<Tls>,
+    /// The proxy to send requests through, e.g. `http://proxy:3128`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+
+    /// The REST API grammar spoken by the endpoint, 2.1 if missing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rest_version: Option<RestVersion>,
+
+    /// When `sdmx serve` crawls the source on its own, e.g. `0 3 * * *` or
+    /// `every 6h`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<Schedule>,
+
+    /// Overrides how the robots.txt of the source is followed, and limits
+    /// the request rate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub politeness: Option<Politeness>,
 
     /// Accept headers for structure queries (e.g. dataflows, datastructure)
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -33,7 +85,65 @@ pub struct Source {
     pub elapsed: Vec<Duration>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+impl Source {
+    /// The REST API grammar to generate queries for
+    pub fn rest_version(&self) -> RestVersion {
+        self.rest_version.unwrap_or_default()
+    }
+
+    /// The `Accept` header for structure queries
+    pub fn structure_format(&self) -> &str {
+        self.formats
+            .as_ref()
+            .and_then(|f| f.structure.as_deref())
+            .unwrap_or("application/json")
+    }
+
+    /// The `Accept` header for data queries, if the source prefers one
+    pub fn data_format(&self) -> Option<&str> {
+        let data = self.formats.as_ref().and_then(|f| f.data.as_deref());
+        data.or_else(|| self.headers.as_ref()?.accept.as_deref())
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Politeness {
+    /// Whether to obey the robots.txt of the source, true if missing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub robots_txt: Option<bool>,
+    /// Seconds between requests, instead of the `Crawl-delay` of the
+    /// robots.txt
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crawl_delay: Option<f64>,
+    /// The most requests per second sent to the host of the source, shared
+    /// by concurrent requests. Applies together with the crawl delay.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requests_per_second: Option<f64>,
+}
+
+impl Politeness {
+    /// The time between requests to the host required by the rate limit
+    pub fn rate_interval(&self) -> Option<Duration> {
+        let rate = self.requests_per_second.filter(|r| *r > 0.0)?;
+        Some(seconds(1.0 / rate))
+    }
+}
+
+/// The longest delay or timeout honoured, whether from a source or from a
+/// robots.txt
+pub const MAX_SECONDS: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A number of seconds from a source or a robots.txt as a duration, cut to
+/// between zero and `MAX_SECONDS`. Unlike `Duration::from_secs_f64` it
+/// doesn't panic on values too large for a duration.
+pub fn seconds(secs: f64) -> Duration {
+    Duration::try_from_secs_f64(secs.max(0.0))
+        .map_or(MAX_SECONDS, |d| d.min(MAX_SECONDS))
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Accept {
     /// Accept headers with 200 status
     pub supported_accept_headers: Vec<String>,
@@ -42,13 +152,98 @@ pub struct Accept {
     pub denied_accept_headers: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct Headers {
     #[serde(rename = "Accept")]
-    pub accept: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accept: Option<String>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, String>,
+}
+
+/// How to authenticate with a source. Secrets are read from environment
+/// variables, so they stay out of the sources file.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Auth {
+    /// A key sent in a header, e.g. `Ocp-Apim-Subscription-Key`
+    ApiKey {
+        header: String,
+        key_env: String,
+    },
+    Basic {
+        username: String,
+        password_env: String,
+    },
+    Bearer {
+        token_env: String,
+    },
+}
+
+impl Auth {
+    /// The header to send with every request, marked as sensitive so it is
+    /// left out of the WARC files
+    pub fn header(&self) -> Result<(HeaderName, HeaderValue)> {
+        let secret = |var: &str| {
+            env::var(var).with_context(|| {
+                format!("Missing environment variable {} for auth", var)
+            })
+        };
+        let (name, value) = match self {
+            Auth::ApiKey { header, key_env } => {
+                (header.parse()?, secret(key_env)?)
+            }
+            Auth::Basic {
+                username,
+                password_env,
+            } => {
+                let credentials =
+                    format!("{}:{}", username, secret(password_env)?);
+                let value = format!("Basic {}", base64::encode(credentials));
+                (header::AUTHORIZATION, value)
+            }
+            Auth::Bearer { token_env } => (
+                header::AUTHORIZATION,
+                format!("Bearer {}", secret(token_env)?),
+            ),
+        };
+        let mut value = HeaderValue::from_str(&value).map_err(|_| {
+            anyhow!("Invalid characters in the {} header", name)
+        })?;
+        value.set_sensitive(true);
+        Ok((name, value))
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Formats {
+    /// The `Accept` header for structure queries, `application/json` if
+    /// missing. The crawler only parses SDMX-JSON.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub structure: Option<String>,
+    /// The `Accept` header for data queries, e.g. of `sdmx fetch-data`,
+    /// `headers.Accept` if missing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+}
+
+/// Settings for endpoints with broken certificate chains
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Tls {
+    /// A PEM file with a certificate to trust in addition to the system's,
+    /// such as a missing intermediate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ca_cert: Option<String>,
+    /// Accept any certificate, which makes the connection insecure
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accept_invalid_certs: Option<bool>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Supports {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub preview: Option<bool>,
@@ -68,3 +263,377 @@ pub struct Supports {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub datastructure: Option<bool>,
 }
+
+/// Parses a sources file. Unknown fields are rejected, and errors name the
+/// field they were found in, e.g. `3.supports: unknown field ...`.
+pub fn parse_sources(s: &str) -> Result<Sources> {
+    let de = &mut serde_json::Deserializer::from_str(s);
+    serde_path_to_error::deserialize(de).map_err(|e| anyhow!("{}", e))
+}
+
+/// The JSON schema of a sources file, for editors and other tools
+pub fn sources_schema() -> RootSchema {
+    let mut schema = schema_for!(Sources);
+    let metadata = schema.schema.metadata();
+    metadata.title = Some("Sources".to_string());
+    metadata.description =
+        Some("The SDMX endpoints crawled by sdmxblaze".to_string());
+    schema
+}
+
+/// Checks what the types of a sources file cannot: unique IDs, URLs and
+/// media types which parse, and that secrets and certificates exist
+pub fn validate_sources(sources: &[Source]) -> Vec<Issue> {
+    let mut out = vec![];
+    let mut ids = HashSet::new();
+    for source in sources {
+        let mut issues = SourceIssues {
+            artefact: format!("Source {}", source.id),
+            out: &mut out,
+        };
+        if source.id.trim().is_empty() {
+            issues.error("id", "The ID is empty");
+        } else if !ids.insert(source.id.as_str()) {
+            issues.error("id", "The ID is used by an earlier source");
+        }
+        check_source(source, &mut issues);
+    }
+    out
+}
+
+/// Collects the issues of one source
+struct SourceIssues<'a> {
+    artefact: String,
+    out: &'a mut Vec<Issue>,
+}
+
+impl SourceIssues<'_> {
+    fn push<M: ToString>(&mut self, severity: Severity, field: &str, msg: M) {
+        self.out.push(Issue {
+            severity,
+            artefact: self.artefact.clone(),
+            location: Some(field.to_string()),
+            message: msg.to_string(),
+        });
+    }
+
+    fn error<M: ToString>(&mut self, field: &str, message: M) {
+        self.push(Severity::Error, field, message)
+    }
+
+    fn warning<M: ToString>(&mut self, field: &str, message: M) {
+        self.push(Severity::Warning, field, message)
+    }
+}
+
+fn check_source(source: &Source, issues: &mut SourceIssues) {
+    match Url::parse(&source.url) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+        Ok(url) => issues.error(
+            "url",
+            format!(
+                "Unsupported scheme {}, expected http or https",
+                url.scheme()
+            ),
+        ),
+        Err(e) => issues.error("url", format!("Invalid URL: {}", e)),
+    }
+    if let Some(proxy) = &source.proxy {
+        if let Err(e) = Url::parse(proxy) {
+            issues.error("proxy", format!("Invalid URL: {}", e));
+        }
+    }
+    if let Some(headers) = &source.headers {
+        if let Some(accept) = &headers.accept {
+            check_accept(accept, "headers.Accept", issues);
+        }
+        for (name, value) in &headers.other {
+            let field = format!("headers.{}", name);
+            if HeaderName::from_bytes(name.as_bytes()).is_err() {
+                issues.error(&field, "Invalid header name");
+            }
+            if HeaderValue::from_str(value).is_err() {
+                issues.error(&field, "Invalid characters in the header value");
+            }
+        }
+    }
+    if let Some(formats) = &source.formats {
+        if let Some(structure) = &formats.structure {
+            check_accept(structure, "formats.structure", issues);
+            if !structure.contains("json") {
+                issues.warning(
+                    "formats.structure",
+                    "The crawler only parses SDMX-JSON structure messages",
+                );
+            }
+        }
+        if let Some(data) = &formats.data {
+            check_accept(data, "formats.data", issues);
+        }
+    }
+    for (field, accept) in &[
+        ("structural_accept", &source.structural_accept),
+        ("data_accept", &source.data_accept),
+    ] {
+        let accept = match accept {
+            Some(accept) => accept,
+            None => continue,
+        };
+        let lists = [
+            ("supported_accept_headers", &accept.supported_accept_headers),
+            ("denied_accept_headers", &accept.denied_accept_headers),
+        ];
+        for (list, headers) in &lists {
+            for (i, header) in headers.iter().enumerate() {
+                let field = format!("{}.{}[{}]", field, list, i);
+                check_accept(header, &field, issues);
+            }
+        }
+    }
+    if let Some(auth) = &source.auth {
+        let (field, var) = match auth {
+            Auth::ApiKey { header, key_env } => {
+                if HeaderName::from_bytes(header.as_bytes()).is_err() {
+                    issues.error("auth.header", "Invalid header name");
+                }
+                ("auth.key_env", key_env)
+            }
+            Auth::Basic { password_env, .. } => {
+                ("auth.password_env", password_env)
+            }
+            Auth::Bearer { token_env } => ("auth.token_env", token_env),
+        };
+        if env::var_os(var).is_none() {
+            issues.warning(
+                field,
+                format!("The environment variable {} is not set", var),
+            );
+        }
+    }
+    if let Some(timeout) = source.timeout {
+        if !(timeout.is_finite() && timeout > 0.0) {
+            issues.error("timeout", "Expected a positive number of seconds");
+        }
+    }
+    if source.concurrency == Some(0) {
+        issues.error("concurrency", "Expected at least 1");
+    }
+    let politeness = source.politeness.clone().unwrap_or_default();
+    if let Some(delay) = politeness.crawl_delay {
+        if !(delay.is_finite() && delay >= 0.0) {
+            issues.error(
+                "politeness.crawl_delay",
+                "Expected a number of seconds of at least 0",
+            );
+        }
+    }
+    if let Some(rate) = politeness.requests_per_second {
+        if !(rate.is_finite() && rate > 0.0) {
+            issues.error(
+                "politeness.requests_per_second",
+                "Expected a positive number",
+            );
+        }
+    }
+    let ca_cert = source.tls.as_ref().and_then(|t| t.ca_cert.as_ref());
+    if let Some(path) = ca_cert {
+        if !Path::new(path).is_file() {
+            issues.warning("tls.ca_cert", format!("No file at {}", path));
+        }
+    }
+}
+
+/// Checks that an `Accept` header is a list of media types, which may have
+/// parameters such as `version=2.1` or `q=0.9`
+fn check_accept(accept: &str, field: &str, issues: &mut SourceIssues) {
+    for media_type in accept.split(',').map(str::trim) {
+        match media_type.parse::<Mime>() {
+            Ok(m) if !m.subtype().as_str().is_empty() => {}
+            Ok(_) => issues.error(
+                field,
+                format!("Invalid media type {:?}: no subtype", media_type),
+            ),
+            Err(e) => issues.error(
+                field,
+                format!("Invalid media type {:?}: {}", media_type, e),
+            ),
+        }
+    }
+    if HeaderValue::from_str(accept).is_err() {
+        issues.error(field, "Invalid characters in the header value");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn sources(value: serde_json::Value) -> Sources {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn source(fields: serde_json::Value) -> serde_json::Value {
+        let mut source =
+            json!({ "id": "ECB", "name": "ECB", "url": "https://sdw.ecb.org" });
+        for (k, v) in fields.as_object().unwrap() {
+            source[k] = v.clone();
+        }
+        source
+    }
+
+    /// The severity, source, field and message of each issue
+    fn issues(sources: &[Source]) -> Vec<(Severity, String, String, String)> {
+        validate_sources(sources)
+            .into_iter()
+            .map(|i| {
+                (
+                    i.severity,
+                    i.artefact,
+                    i.location.unwrap_or_default(),
+                    i.message,
+                )
+            })
+            .collect()
+    }
+
+    fn error(
+        source: &str,
+        field: &str,
+        message: &str,
+    ) -> (Severity, String, String, String) {
+        (
+            Severity::Error,
+            format!("Source {}", source),
+            field.to_string(),
+            message.to_string(),
+        )
+    }
+
+    #[test]
+    fn ids_must_be_unique() {
+        let list = sources(json!([
+            source(json!({})),
+            source(json!({ "id": "OECD" })),
+            source(json!({})),
+            source(json!({ "id": " " })),
+        ]));
+        assert_eq!(
+            issues(&list),
+            vec![
+                error("ECB", "id", "The ID is used by an earlier source"),
+                error(" ", "id", "The ID is empty"),
+            ]
+        );
+    }
+
+    #[test]
+    fn urls_must_be_http() {
+        let list = sources(json!([
+            source(json!({ "url": "ftp://sdw.ecb.org" })),
+            source(json!({ "id": "B", "url": "sdw.ecb.org" })),
+            source(json!({ "id": "C", "proxy": "http://proxy:3128" })),
+        ]));
+        assert_eq!(
+            issues(&list),
+            vec![
+                error(
+                    "ECB",
+                    "url",
+                    "Unsupported scheme ftp, expected http or https"
+                ),
+                error("B", "url", "Invalid URL: relative URL without a base"),
+            ]
+        );
+    }
+
+    #[test]
+    fn accept_headers_must_be_media_types() {
+        let list = sources(json!([source(json!({
+            "headers": {
+                "Accept": "application/vnd.sdmx.data+json;version=1.0.0, application/json;q=0.9"
+            },
+            "formats": {
+                "structure": "application/xml",
+                "data": "csv"
+            },
+            "structural_accept": {
+                "supported_accept_headers": ["application/json"],
+                "denied_accept_headers": ["text/"]
+            }
+        }))]));
+        let found = issues(&list);
+        let fields: Vec<_> = found
+            .iter()
+            .map(|(severity, _, field, _)| (*severity, field.as_str()))
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                (Severity::Warning, "formats.structure"),
+                (Severity::Error, "formats.data"),
+                (
+                    Severity::Error,
+                    "structural_accept.denied_accept_headers[0]"
+                ),
+            ]
+        );
+        assert!(found[1].3.starts_with("Invalid media type \"csv\""));
+    }
+
+    #[test]
+    fn secrets_must_be_in_the_environment() {
+        let var = "SDMX_SOURCES_TEST_MISSING_KEY";
+        std::env::remove_var(var);
+        let list = sources(json!([source(json!({
+            "auth": { "type": "api_key", "header": "X-Api-Key", "key_env": var }
+        }))]));
+        assert_eq!(
+            issues(&list),
+            vec![(
+                Severity::Warning,
+                "Source ECB".to_string(),
+                "auth.key_env".to_string(),
+                format!("The environment variable {} is not set", var),
+            )]
+        );
+        let err = list[0].auth.as_ref().unwrap().header().unwrap_err();
+        assert!(err.to_string().contains(var), "{}", err);
+    }
+
+    #[test]
+    fn seconds_are_cut_to_a_day() {
+        assert_eq!(seconds(1.5), Duration::from_millis(1500));
+        assert_eq!(seconds(-1.0), Duration::ZERO);
+        assert_eq!(seconds(1e30), MAX_SECONDS);
+        assert_eq!(seconds(f64::INFINITY), MAX_SECONDS);
+        let politeness = Politeness {
+            requests_per_second: Some(1e-300),
+            ..Default::default()
+        };
+        assert_eq!(politeness.rate_interval(), Some(MAX_SECONDS));
+    }
+
+    #[test]
+    fn the_checked_in_schema_is_up_to_date() {
+        let checked_in: serde_json::Value =
+            serde_json::from_str(include_str!("../sources.schema.json"))
+                .unwrap();
+        assert_eq!(
+            checked_in,
+            serde_json::to_value(sources_schema()).unwrap(),
+            "regenerate it with `sdmx sources schema > sources.schema.json`"
+        );
+    }
+
+    #[test]
+    fn the_checked_in_sources_are_valid() {
+        let list = parse_sources(include_str!("../sources.json")).unwrap();
+        let errors: Vec<_> = validate_sources(&list)
+            .into_iter()
+            .filter(|i| i.severity == Severity::Error)
+            .collect();
+        assert!(errors.is_empty(), "{:?}", errors);
+    }
+}
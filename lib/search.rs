@@ -0,0 +1,546 @@
+//! search maintains a local full-text index of dataflows across all crawled sources.
+//!
+//! Each dataflow is indexed as one document which also contains the labels of
+//! the concepts and codes used by its data structure, so a query like
+//! `unemployment youth` matches dataflows whose dimensions have an age code
+//! for young people even if the dataflow name does not mention it.
+
+use std::{
+    fs,
+    path::Path,
+    sync::{Mutex, MutexGuard},
+};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use tantivy::{
+    collector::TopDocs,
+    directory::MmapDirectory,
+    doc,
+    query::QueryParser,
+    schema::{Field, Schema, Value, STORED, STRING, TEXT},
+    Index, IndexReader, IndexWriter, ReloadPolicy, TantivyDocument, Term,
+};
+
+use crate::{
+    artefact::Maintainable,
+    localized::{LanguagePreferences, LocalizedString},
+    structure::{
+        AnnotationType, Data, DataStructureTypeElement, DataflowTypeElement,
+    },
+    urn::Urn,
+};
+
+/// Memory budget of the index writer
+const WRITER_HEAP_SIZE: usize = 50_000_000;
+
+/// The fields of a dataflow document
+#[derive(Clone, Copy)]
+struct Fields {
+    key: Field,
+    source_id: Field,
+    agency_id: Field,
+    resource_id: Field,
+    version: Field,
+    name: Field,
+    description: Field,
+    names: Field,
+    descriptions: Field,
+    labels: Field,
+    concepts: Field,
+    codes: Field,
+    annotations: Field,
+}
+
+impl Fields {
+    fn schema() -> (Schema, Fields) {
+        let mut sb = Schema::builder();
+        let fields = Fields {
+            key: sb.add_text_field("key", STRING | STORED),
+            source_id: sb.add_text_field("source_id", STRING | STORED),
+            agency_id: sb.add_text_field("agency_id", STRING | STORED),
+            resource_id: sb.add_text_field("resource_id", STRING | STORED),
+            version: sb.add_text_field("version", STRING | STORED),
+            name: sb.add_text_field("name", TEXT | STORED),
+            description: sb.add_text_field("description", TEXT | STORED),
+            names: sb.add_text_field("names", STORED),
+            descriptions: sb.add_text_field("descriptions", STORED),
+            labels: sb.add_text_field("labels", TEXT),
+            concepts: sb.add_text_field("concepts", TEXT),
+            codes: sb.add_text_field("codes", TEXT),
+            annotations: sb.add_text_field("annotations", TEXT),
+        };
+        (sb.build(), fields)
+    }
+}
+
+/// A dataflow matching a search query
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SearchHit {
+    pub score: f32,
+    pub source_id: String,
+    pub agency_id: String,
+    pub resource_id: String,
+    pub version: String,
+    pub name: String,
+    pub description: Option<String>,
+}
+
+/// A full-text index of dataflows stored in a local directory
+pub struct SearchIndex {
+    index: Index,
+    reader: IndexReader,
+    /// Only one writer may hold the lock on an index directory, so indexes
+    /// opened for searching have none
+    writer: Option<Mutex<IndexWriter>>,
+    fields: Fields,
+}
+
+impl SearchIndex {
+    /// Opens (creating if needed) the index in the given directory for
+    /// indexing and searching. This takes the index writer lock, so only one
+    /// process can open an index this way at a time.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        fs::create_dir_all(&dir)?;
+        let (schema, fields) = Fields::schema();
+        let index = Index::open_or_create(MmapDirectory::open(dir)?, schema)?;
+        let writer = index.writer(WRITER_HEAP_SIZE)?;
+        Self::new(index, Some(writer), fields)
+    }
+
+    /// Opens an existing index for searching only, without taking the writer
+    /// lock, so it can be searched while a crawl or server is indexing
+    pub fn open_read_only<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref();
+        let index = Index::open_in_dir(dir).map_err(|e| {
+            anyhow!("Failed to open search index {}: {}", dir.display(), e)
+        })?;
+        let (_, fields) = Fields::schema();
+        Self::new(index, None, fields)
+    }
+
+    fn new(
+        index: Index,
+        writer: Option<IndexWriter>,
+        fields: Fields,
+    ) -> Result<Self> {
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+        Ok(SearchIndex {
+            index,
+            reader,
+            writer: writer.map(Mutex::new),
+            fields,
+        })
+    }
+
+    /// Adds every dataflow in a structure message to the index, replacing
+    /// previously indexed versions of the same document. Concepts and codes
+    /// are taken from the data structures, concept schemes and codelists
+    /// included in the same message. Changes are visible after `commit`.
+    pub fn add(&self, source_id: &str, data: &Data) -> Result<()> {
+        let writer = self.lock_writer()?;
+        let f = self.fields;
+        for df in data.dataflows.iter().flatten() {
+            let version = df.version.clone().unwrap_or_default();
+            let key = format!(
+                "{}:{}:{}({})",
+                source_id, df.agency_id, df.id, version
+            );
+
+            let mut labels = localized(df.names.as_ref());
+            labels.extend(localized(df.descriptions.as_ref()));
+            let mut document = doc!(
+                f.key => key.clone(),
+                f.source_id => source_id,
+                f.agency_id => df.agency_id.clone(),
+                f.resource_id => df.id.clone(),
+                f.version => version,
+                f.name => df.name.clone(),
+                f.labels => labels.join("\n"),
+                f.annotations => annotation_text(&df.annotations).join("\n"),
+            );
+            if let Some(description) = &df.description {
+                document.add_text(f.description, description);
+            }
+            if let Some(names) = &df.names {
+                document.add_text(f.names, serde_json::to_string(names)?);
+            }
+            if let Some(descriptions) = &df.descriptions {
+                document.add_text(
+                    f.descriptions,
+                    serde_json::to_string(descriptions)?,
+                );
+            }
+            if let Some(dsd) = find_structure(data, df) {
+                document
+                    .add_text(f.concepts, concept_labels(data, dsd).join("\n"));
+                document.add_text(f.codes, code_labels(data, dsd).join("\n"));
+            }
+
+            writer.delete_term(Term::from_field_text(f.key, &key));
+            writer.add_document(document)?;
+        }
+        Ok(())
+    }
+
+    /// Persists all added documents and makes them searchable
+    pub fn commit(&self) -> Result<()> {
+        self.lock_writer()?.commit()?;
+        self.reader.reload()?;
+        Ok(())
+    }
+
+    /// Ranks the indexed dataflows against a query. The query supports the
+    /// tantivy query syntax, e.g. `+unemployment youth` or `name:rates`.
+    /// Names and descriptions of the hits are given in the most preferred
+    /// available language.
+    pub fn search(
+        &self,
+        query: &str,
+        limit: usize,
+        languages: &LanguagePreferences,
+    ) -> Result<Vec<SearchHit>> {
+        let f = self.fields;
+        let mut parser = QueryParser::for_index(
+            &self.index,
+            vec![
+                f.name,
+                f.description,
+                f.labels,
+                f.concepts,
+                f.codes,
+                f.annotations,
+            ],
+        );
+        parser.set_field_boost(f.name, 3.0);
+        parser.set_field_boost(f.labels, 2.0);
+        parser.set_field_boost(f.description, 1.5);
+        let query = parser.parse_query(query)?;
+
+        let searcher = self.reader.searcher();
+        let top = searcher.search(&query, &TopDocs::with_limit(limit))?;
+        let mut out = vec![];
+        for (score, address) in top {
+            let document: TantivyDocument = searcher.doc(address)?;
+            let text = |field: Field| {
+                document
+                    .get_first(field)
+                    .and_then(|v| v.as_str())
+                    .map(|s| s.to_string())
+            };
+            let localized = |field: Field| {
+                text(field).and_then(|s| {
+                    serde_json::from_str::<LocalizedString>(&s).ok()
+                })
+            };
+            let name = text(f.name).unwrap_or_default();
+            let description = text(f.description);
+            out.push(SearchHit {
+                score,
+                source_id: text(f.source_id).unwrap_or_default(),
+                agency_id: text(f.agency_id).unwrap_or_default(),
+                resource_id: text(f.resource_id).unwrap_or_default(),
+                version: text(f.version).unwrap_or_default(),
+                name: languages
+                    .resolve(localized(f.names).as_ref(), &name)
+                    .to_string(),
+                description: languages
+                    .resolve_opt(
+                        localized(f.descriptions).as_ref(),
+                        description.as_deref(),
+                    )
+                    .map(|s| s.to_string()),
+            });
+        }
+        Ok(out)
+    }
+
+    fn lock_writer(&self) -> Result<MutexGuard<'_, IndexWriter>> {
+        self.writer
+            .as_ref()
+            .ok_or_else(|| anyhow!("Search index was opened read-only"))?
+            .lock()
+            .map_err(|_| anyhow!("Search index writer lock was poisoned"))
+    }
+}
+
+/// The text of a localized field like `names` in all languages
+fn localized(text: Option<&LocalizedString>) -> Vec<String> {
+    text.into_iter()
+        .flat_map(|t| t.values())
+        .map(|s| s.to_string())
+        .collect()
+}
+
+fn annotation_text(annotations: &Option<Vec<AnnotationType>>) -> Vec<String> {
+    let mut out = vec![];
+    for a in annotations.iter().flatten() {
+        out.extend(a.title.clone());
+        out.extend(a.text.clone());
+        out.extend(localized(a.texts.as_ref()));
+    }
+    out
+}
+
+/// The data structure of a dataflow, if it is included in the message
+fn find_structure<'a>(
+    data: &'a Data,
+    df: &DataflowTypeElement,
+) -> Option<&'a DataStructureTypeElement> {
+    let urn: Urn = df.structure.as_ref()?.parse().ok()?;
+    data.data_structures.iter().flatten().find(|dsd| {
+        dsd.agency_id == urn.agency_id
+            && dsd.id == urn.id
+            && dsd
+                .parsed_version()
+                .is_some_and(|v| urn.version.matches(&v))
+    })
+}
+
+/// Concept identities and codelist references of all components of a data structure
+fn component_references(
+    dsd: &DataStructureTypeElement,
+) -> (Vec<Urn>, Vec<Urn>) {
+    let mut concepts = vec![];
+    let mut codelists = vec![];
+    let components = match &dsd.data_structure_components {
+        Some(c) => c,
+        None => return (concepts, codelists),
+    };
+    let mut add = |concept: &String, codelist: Option<&String>| {
+        concepts.extend(concept.parse::<Urn>().ok());
+        codelists.extend(codelist.and_then(|c| c.parse::<Urn>().ok()));
+    };
+    let dims = &components.dimension_list;
+    for d in dims.dimensions.iter().flatten() {
+        add(
+            &d.concept_identity,
+            d.local_representation
+                .as_ref()
+                .and_then(|r| r.enumeration.as_ref()),
+        );
+    }
+    for d in dims.time_dimensions.iter().flatten() {
+        add(&d.concept_identity, None);
+    }
+    for d in dims.measure_dimensions.iter().flatten() {
+        add(&d.concept_identity, None);
+    }
+    let attributes = components
+        .attribute_list
+        .as_ref()
+        .and_then(|a| a.attributes.as_ref());
+    for a in attributes.into_iter().flatten() {
+        add(
+            &a.concept_identity,
+            a.local_representation
+                .as_ref()
+                .and_then(|r| r.enumeration.as_ref()),
+        );
+    }
+    add(
+        &components.measure_list.primary_measure.concept_identity,
+        None,
+    );
+    (concepts, codelists)
+}
+
+fn concept_labels(data: &Data, dsd: &DataStructureTypeElement) -> Vec<String> {
+    let (concepts, _) = component_references(dsd);
+    let mut out = vec![];
+    for urn in concepts {
+        let scheme = data
+            .concept_schemes
+            .iter()
+            .flatten()
+            .find(|cs| cs.agency_id == urn.agency_id && cs.id == urn.id);
+        let concept = scheme
+            .and_then(|cs| cs.concepts.as_ref())
+            .into_iter()
+            .flatten()
+            .find(|c| c.id.as_ref() == urn.item_ids.last());
+        if let Some(c) = concept {
+            out.push(c.name.clone());
+            out.extend(localized(c.names.as_ref()));
+            out.extend(c.description.clone());
+        }
+    }
+    out
+}
+
+fn code_labels(data: &Data, dsd: &DataStructureTypeElement) -> Vec<String> {
+    let (_, codelists) = component_references(dsd);
+    let mut out = vec![];
+    for urn in codelists {
+        let codelist = data
+            .codelists
+            .iter()
+            .flatten()
+            .find(|cl| cl.agency_id == urn.agency_id && cl.id == urn.id);
+        if let Some(cl) = codelist {
+            out.push(cl.name.clone());
+            for code in cl.codes.iter().flatten() {
+                out.push(code.name.clone());
+                out.extend(localized(code.names.as_ref()));
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::archive::tests::temp_dir;
+
+    #[test]
+    fn read_only_index_searches_alongside_a_writer() {
+        let dir = temp_dir();
+        let index = SearchIndex::open(&dir).unwrap();
+        let data: Data = serde_json::from_value(json!({
+            "dataflows": [{
+                "id": "UNEMP", "agencyID": "A", "version": "1.0",
+                "name": "Youth unemployment"
+            }]
+        }))
+        .unwrap();
+        index.add("src", &data).unwrap();
+        index.commit().unwrap();
+
+        // the writer above still holds the lock
+        assert!(SearchIndex::open(&dir).is_err());
+        let reader = SearchIndex::open_read_only(&dir).unwrap();
+        let languages = LanguagePreferences::new(vec!["en"]);
+        let hits = reader.search("unemployment", 10, &languages).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].resource_id, "UNEMP");
+        assert!(reader.add("src", &data).is_err());
+
+        assert!(SearchIndex::open_read_only(dir.join("missing")).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// A labour force survey whose name mentions neither unemployment nor
+    /// young people; both come from its data structure
+    fn survey(name: &str) -> Data {
+        let urn = "urn:sdmx:org.sdmx.infomodel";
+        let concept = |id: &str| {
+            format!("{}.conceptscheme.Concept=A:CS(1.0).{}", urn, id)
+        };
+        serde_json::from_value(json!({
+            "dataflows": [{
+                "id": "LFS", "agencyID": "A", "version": "1.0", "name": name,
+                "structure": format!("{}.datastructure.DataStructure=A:DSD(1.0)", urn)
+            }],
+            "dataStructures": [{
+                "id": "DSD", "agencyID": "A", "version": "1.0", "name": "DSD",
+                "dataStructureComponents": {
+                    "dimensionList": {
+                        "dimensions": [{
+                            "id": "AGE", "position": 1,
+                            "conceptIdentity": concept("AGE"),
+                            "localRepresentation": {
+                                "enumeration": format!("{}.codelist.Codelist=A:CL_AGE(1.0)", urn)
+                            }
+                        }],
+                        "timeDimensions": [{
+                            "id": "TIME_PERIOD", "position": 2,
+                            "conceptIdentity": concept("TIME_PERIOD"),
+                            "localRepresentation": {
+                                "textFormat": { "textType": "ObservationalTimePeriod" }
+                            }
+                        }]
+                    },
+                    "measureList": {
+                        "primaryMeasure": { "conceptIdentity": concept("UNEMP") }
+                    }
+                }
+            }],
+            "conceptSchemes": [{
+                "id": "CS", "agencyID": "A", "version": "1.0",
+                "name": "Concepts",
+                "concepts": [
+                    { "id": "AGE", "name": "Age" },
+                    { "id": "TIME_PERIOD", "name": "Time period" },
+                    { "id": "UNEMP", "name": "Unemployment rate" }
+                ]
+            }],
+            "codelists": [{
+                "id": "CL_AGE", "agencyID": "A", "version": "1.0",
+                "name": "Age groups",
+                "codes": [
+                    { "id": "Y15T24", "name": "Youth" },
+                    { "id": "Y25T64", "name": "Adults" }
+                ]
+            }]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn dataflows_are_found_through_their_concepts_and_codes() {
+        let dir = temp_dir();
+        let index = SearchIndex::open(&dir).unwrap();
+        index.add("src", &survey("Labour force survey")).unwrap();
+        index.commit().unwrap();
+
+        let languages = LanguagePreferences::new(vec!["en"]);
+        let hits = index.search("unemployment youth", 10, &languages).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].resource_id, "LFS");
+        assert_eq!(hits[0].name, "Labour force survey");
+        assert_eq!(index.search("adults", 10, &languages).unwrap().len(), 1);
+        assert!(index.search("pensions", 10, &languages).unwrap().is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn name_matches_rank_above_code_matches() {
+        let dir = temp_dir();
+        let index = SearchIndex::open(&dir).unwrap();
+        index.add("codes", &survey("Labour force survey")).unwrap();
+        let named: Data = serde_json::from_value(json!({
+            "dataflows": [{
+                "id": "YOUTH", "agencyID": "B", "version": "1.0",
+                "name": "Youth"
+            }]
+        }))
+        .unwrap();
+        index.add("names", &named).unwrap();
+        index.commit().unwrap();
+
+        let languages = LanguagePreferences::new(vec!["en"]);
+        let hits = index.search("youth", 10, &languages).unwrap();
+        let sources: Vec<_> = hits.iter().map(|h| &h.source_id[..]).collect();
+        assert_eq!(sources, vec!["names", "codes"]);
+        assert!(hits[0].score > hits[1].score);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn adding_a_dataflow_again_replaces_it() {
+        let dir = temp_dir();
+        let index = SearchIndex::open(&dir).unwrap();
+        index.add("src", &survey("Labour force survey")).unwrap();
+        index.commit().unwrap();
+        index.add("src", &survey("Employment survey")).unwrap();
+        index.commit().unwrap();
+
+        let languages = LanguagePreferences::new(vec!["en"]);
+        let hits = index.search("survey", 10, &languages).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].name, "Employment survey");
+        assert!(index.search("labour", 10, &languages).unwrap().is_empty());
+
+        // the same dataflow from another source is a separate document
+        index.add("other", &survey("Labour force survey")).unwrap();
+        index.commit().unwrap();
+        assert_eq!(index.search("survey", 10, &languages).unwrap().len(), 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
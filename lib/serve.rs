@@ -0,0 +1,722 @@
+//! serve runs the crawler as a long-running service with a local HTTP API to
+//! enqueue crawls of the configured sources and follow them:
+//!
+//! ```text
+//! POST /jobs               queue a crawl, body {"sources": ["ECB"]}, all sources if omitted
+//! GET  /jobs               all jobs, newest first
+//! GET  /jobs/{id}          status and progress of a job
+//! POST /jobs/{id}/cancel   cancel a queued or running job
+//! GET  /jobs/{id}/report   the crawl reports of the sources crawled so far
+//! GET  /schedules          the sources with a schedule, their next and last run
+//! GET  /schedules/{source} the run history of a scheduled source
+//! GET  /metrics            request metrics in the Prometheus text format
+//! ```
+//!
+//! Jobs are crawled one at a time in the order they were queued, and are
+//! persisted in a `JobStore`. Jobs that were running when the service stopped
+//! are crawled again from the start.
+//!
+//! Sources with a `schedule` are queued as a job of their own whenever they
+//! are due, unless a job with the source is still queued or running. Every
+//! time a source is due is recorded in the run history, along with the job
+//! or the reason it was skipped. A source that was due while the service was
+//! stopped is crawled once when it starts.
+//!
+//! Scheduled crawls are incremental: they only fetch the structures that
+//! changed since the last crawl of the source without failed requests (see
+//! `Crawler::crawl_since`). Jobs queued through the API crawl everything.
+
+use std::{
+    collections::BTreeMap,
+    convert::Infallible,
+    future,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use hyper::{
+    body,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use tracing::{error, info, info_span, warn, Instrument};
+
+use crate::{
+    crawler::Crawler,
+    jobs::{Job, JobStatus, JobStore, ScheduleRun},
+    metrics::Metrics,
+    schedule::Schedule,
+    sdmx_sources::Sources,
+};
+
+/// Number of runs returned by `GET /schedules/{source}`
+const HISTORY_LIMIT: usize = 100;
+
+/// How long the worker waits after an error of the job store before it
+/// carries on
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The state shared by the API and the worker that crawls the jobs
+pub struct Service {
+    jobs: JobStore,
+    sources: Sources,
+    metrics: Metrics,
+    /// Wakes the worker when a job is queued
+    queued: Notify,
+    /// The running job and the signal to cancel it
+    running: Mutex<Option<(String, Arc<Notify>)>>,
+    /// When each scheduled source is due next
+    due: Mutex<BTreeMap<String, DateTime<Utc>>>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct JobRequest {
+    #[serde(default)]
+    sources: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct ScheduleStatus<'a> {
+    source_id: &'a str,
+    schedule: &'a Schedule,
+    next_run: Option<String>,
+    last_run: Option<ScheduleRun>,
+}
+
+#[derive(Serialize, Debug)]
+struct ErrorBody {
+    error: String,
+}
+
+impl Service {
+    /// `metrics` should be the metrics of the crawler, so they can be served
+    /// at `/metrics`
+    pub fn new(jobs: JobStore, sources: Sources, metrics: Metrics) -> Self {
+        Service {
+            jobs,
+            sources,
+            metrics,
+            queued: Notify::new(),
+            running: Mutex::new(None),
+            due: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Queues a crawl of the given sources, or of all sources if empty
+    pub fn enqueue(&self, source_ids: Vec<String>) -> Result<Job> {
+        let unknown: Vec<_> = source_ids
+            .iter()
+            .filter(|id| !self.sources.iter().any(|s| &s.id == *id))
+            .cloned()
+            .collect();
+        if !unknown.is_empty() {
+            return Err(anyhow!("Unknown sources {}", unknown.join(", ")));
+        }
+        let source_ids = match source_ids.is_empty() {
+            true => self.sources.iter().map(|s| s.id.clone()).collect(),
+            false => source_ids,
+        };
+        let job = self.jobs.enqueue(source_ids)?;
+        info!(job = %job.id, sources = ?job.sources, "Queued job");
+        self.queued.notify_one();
+        Ok(job)
+    }
+
+    /// Cancels a queued or running job. Returns false if the job had
+    /// already finished.
+    pub fn cancel(&self, id: &str) -> Result<bool> {
+        if !self.jobs.cancel(id)? {
+            return Ok(false);
+        }
+        if let Some((running, cancel)) = &*self.running.lock().unwrap() {
+            if running == id {
+                cancel.notify_one();
+            }
+        }
+        info!(job = %id, "Cancelled job");
+        Ok(true)
+    }
+
+    async fn handle(&self, req: Request<Body>) -> Response<Body> {
+        match self.route(req).await {
+            Ok(res) => res,
+            Err(e) => error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &format!("{:#}", e),
+            ),
+        }
+    }
+
+    async fn route(&self, req: Request<Body>) -> Result<Response<Body>> {
+        let path = req.uri().path().to_string();
+        let segments: Vec<_> = path.trim_matches('/').split('/').collect();
+        let method = req.method().clone();
+
+        let not_found = || error_response(StatusCode::NOT_FOUND, "Not found");
+        Ok(match (&method, segments.as_slice()) {
+            (&Method::GET, ["metrics"]) => self.metrics.respond(&req),
+            (&Method::GET, ["jobs"]) => {
+                json(StatusCode::OK, &self.jobs.list()?)
+            }
+            (&Method::POST, ["jobs"]) => {
+                let body = body::to_bytes(req.into_body()).await?;
+                let request: JobRequest = match body.is_empty() {
+                    true => JobRequest::default(),
+                    false => match serde_json::from_slice(&body) {
+                        Ok(r) => r,
+                        Err(e) => {
+                            return Ok(error_response(
+                                StatusCode::BAD_REQUEST,
+                                &e.to_string(),
+                            ))
+                        }
+                    },
+                };
+                match self.enqueue(request.sources) {
+                    Ok(job) => json(StatusCode::CREATED, &job),
+                    Err(e) => {
+                        error_response(StatusCode::BAD_REQUEST, &e.to_string())
+                    }
+                }
+            }
+            (&Method::GET, ["jobs", id]) => match self.jobs.get(id)? {
+                Some(job) => json(StatusCode::OK, &job),
+                None => not_found(),
+            },
+            (&Method::POST, ["jobs", id, "cancel"]) => {
+                if self.jobs.get(id)?.is_none() {
+                    return Ok(not_found());
+                }
+                match self.cancel(id)? {
+                    true => json(StatusCode::OK, &self.jobs.get(id)?),
+                    false => error_response(
+                        StatusCode::CONFLICT,
+                        "The job has already finished",
+                    ),
+                }
+            }
+            (&Method::GET, ["jobs", id, "report"]) => {
+                match self.jobs.reports(id)? {
+                    Some(reports) => json(StatusCode::OK, &reports),
+                    None => not_found(),
+                }
+            }
+            (&Method::GET, ["schedules"]) => {
+                json(StatusCode::OK, &self.schedules()?)
+            }
+            (&Method::GET, ["schedules", source_id]) => {
+                match self.sources.iter().any(|s| s.id == *source_id) {
+                    true => json(
+                        StatusCode::OK,
+                        &self.jobs.schedule_runs(source_id, HISTORY_LIMIT)?,
+                    ),
+                    false => not_found(),
+                }
+            }
+            _ => not_found(),
+        })
+    }
+
+    /// Crawls the queued jobs one at a time, forever. Errors are logged
+    /// rather than stopping the service.
+    async fn work(&self, crawler: &Crawler) {
+        loop {
+            if let Err(e) = self.work_next(crawler).await {
+                error!(error = %format!("{:#}", e), "Job worker failed");
+                tokio::time::sleep(RETRY_INTERVAL).await;
+            }
+        }
+    }
+
+    /// Crawls the oldest queued job, or waits until a job is queued
+    async fn work_next(&self, crawler: &Crawler) -> Result<()> {
+        let job = match self.jobs.next_queued()? {
+            Some(job) => job,
+            None => {
+                self.queued.notified().await;
+                return Ok(());
+            }
+        };
+
+        // The job is running as far as `cancel` is concerned before it is
+        // started, so a cancel in between is not lost
+        let cancel = Arc::new(Notify::new());
+        *self.running.lock().unwrap() = Some((job.id.clone(), cancel.clone()));
+        let started = self.jobs.start(&job.id);
+        if !matches!(started, Ok(true)) {
+            *self.running.lock().unwrap() = None;
+            return started.map(|_| ());
+        }
+
+        let span = info_span!("job", job = %job.id);
+        let result = tokio::select! {
+            r = self.run(crawler, &job).instrument(span) => Some(r),
+            _ = cancel.notified() => None,
+        };
+        *self.running.lock().unwrap() = None;
+
+        match result {
+            Some(Ok(())) => {
+                info!(job = %job.id, "Completed job");
+                self.jobs.finish(&job.id, JobStatus::Completed, None)?;
+            }
+            Some(Err(e)) => {
+                let e = format!("{:#}", e);
+                error!(job = %job.id, error = %e, "Job failed");
+                self.jobs.finish(&job.id, JobStatus::Failed, Some(&e))?;
+            }
+            // The job was marked as cancelled by `cancel`
+            None => {}
+        }
+        Ok(())
+    }
+
+    fn schedules(&self) -> Result<Vec<ScheduleStatus<'_>>> {
+        let due = self.due.lock().unwrap().clone();
+        let mut out = vec![];
+        for source in &self.sources {
+            if let Some(schedule) = &source.schedule {
+                out.push(ScheduleStatus {
+                    source_id: &source.id,
+                    schedule,
+                    next_run: due.get(&source.id).map(|d| d.to_rfc3339()),
+                    last_run: self.jobs.schedule_runs(&source.id, 1)?.pop(),
+                });
+            }
+        }
+        Ok(out)
+    }
+
+    /// Queues a crawl of each source with a schedule whenever it is due,
+    /// forever. Errors are logged rather than stopping the service.
+    async fn schedule(&self) {
+        self.plan(Utc::now());
+        loop {
+            self.trigger_due(Utc::now());
+            let next = self.due.lock().unwrap().values().min().cloned();
+            match next {
+                Some(next) => {
+                    let wait = (next - Utc::now()).to_std().unwrap_or_default();
+                    tokio::time::sleep(wait).await;
+                }
+                None => future::pending().await,
+            }
+        }
+    }
+
+    /// Works out when each source with a schedule is due first, from its
+    /// last run if it has one. A run that was missed while the service was
+    /// stopped is due right away.
+    fn plan(&self, now: DateTime<Utc>) {
+        for source in &self.sources {
+            let schedule = match &source.schedule {
+                Some(schedule) => schedule,
+                None => continue,
+            };
+            let next = match self.last_run(&source.id, schedule) {
+                Ok(Some(previous)) => schedule.next_after(previous),
+                Ok(None) => schedule.first(now),
+                Err(e) => {
+                    error!(
+                        source = %source.id,
+                        error = %format!("{:#}", e),
+                        "Failed to read the schedule history"
+                    );
+                    schedule.first(now)
+                }
+            };
+            match next {
+                Some(next) => {
+                    info!(
+                        source = %source.id,
+                        schedule = %schedule,
+                        next = %next.to_rfc3339(),
+                        "Scheduled source"
+                    );
+                    self.due.lock().unwrap().insert(source.id.clone(), next);
+                }
+                None => warn!(
+                    source = %source.id,
+                    schedule = %schedule,
+                    "Schedule never matches"
+                ),
+            }
+        }
+    }
+
+    /// The time the schedule of a source continues from after its last
+    /// run: when the run was triggered for intervals, which count from the
+    /// previous crawl, and when it was due for cron expressions
+    fn last_run(
+        &self,
+        source_id: &str,
+        schedule: &Schedule,
+    ) -> Result<Option<DateTime<Utc>>> {
+        let run = match self.jobs.schedule_runs(source_id, 1)?.pop() {
+            Some(run) => run,
+            None => return Ok(None),
+        };
+        let time = match schedule {
+            Schedule::Every(_) => &run.triggered_at,
+            Schedule::Cron(_) => &run.due_at,
+        };
+        Ok(Some(DateTime::parse_from_rfc3339(time)?.into()))
+    }
+
+    /// Triggers the sources that are due at `now` and works out when they
+    /// are due next, counting intervals from `now`
+    fn trigger_due(&self, now: DateTime<Utc>) {
+        let due: Vec<_> = self
+            .due
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, at)| **at <= now)
+            .map(|(id, at)| (id.clone(), *at))
+            .collect();
+        for (source_id, at) in due {
+            if let Err(e) = self.trigger(&source_id, at, now) {
+                error!(
+                    source = %source_id,
+                    error = %format!("{:#}", e),
+                    "Failed to trigger a scheduled crawl"
+                );
+            }
+            let next = self
+                .sources
+                .iter()
+                .find(|s| s.id == source_id)
+                .and_then(|s| s.schedule.as_ref())
+                .and_then(|s| s.next_after(now));
+            let mut due = self.due.lock().unwrap();
+            match next {
+                Some(next) => due.insert(source_id, next),
+                None => due.remove(&source_id),
+            };
+        }
+    }
+
+    /// Queues a crawl of a source that is due, unless one is queued or
+    /// running already, and records the run
+    fn trigger(
+        &self,
+        source_id: &str,
+        due_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        let mut run = ScheduleRun {
+            source_id: source_id.to_string(),
+            due_at: due_at.to_rfc3339(),
+            triggered_at: now.to_rfc3339(),
+            job_id: None,
+            skipped: None,
+        };
+        if self.jobs.is_active(source_id)? {
+            info!(
+                source = %source_id,
+                "Skipping scheduled crawl, the previous crawl is still running"
+            );
+            run.skipped =
+                Some("A crawl of the source is still queued or running".into());
+        } else {
+            let job = self.jobs.enqueue(vec![source_id.to_string()])?;
+            info!(job = %job.id, source = %source_id, "Queued scheduled job");
+            run.job_id = Some(job.id);
+        }
+        // The run is recorded before the worker is woken, so it knows the
+        // job is a scheduled one
+        self.jobs.add_schedule_run(&run)?;
+        if run.job_id.is_some() {
+            self.queued.notify_one();
+        }
+        Ok(())
+    }
+
+    /// When the newest crawl of a source without failed requests started.
+    /// Structures that changed later are fetched by the next scheduled
+    /// crawl, the others were stored by that crawl or an earlier one.
+    fn previous_crawl(&self, source_id: &str) -> Result<Option<DateTime<Utc>>> {
+        match self.jobs.last_clean_crawl(source_id)? {
+            Some(started) => {
+                Ok(Some(DateTime::parse_from_rfc3339(&started)?.into()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn run(&self, crawler: &Crawler, job: &Job) -> Result<()> {
+        info!(sources = ?job.sources, "Starting job");
+        let scheduled = self.jobs.is_scheduled(&job.id)?;
+        for source_id in &job.sources {
+            let source = self
+                .sources
+                .iter()
+                .find(|s| &s.id == source_id)
+                .ok_or_else(|| anyhow!("Unknown source {}", source_id))?;
+            self.jobs.set_current(&job.id, source_id)?;
+            let since = match scheduled {
+                true => self.previous_crawl(source_id)?,
+                false => None,
+            };
+            let report = match since {
+                Some(since) => crawler.crawl_since(source, since).await?,
+                None => crawler.crawl(source).await?,
+            };
+            self.jobs.add_report(&job.id, &report)?;
+        }
+        Ok(())
+    }
+}
+
+/// Serves the API on `addr` and crawls the queued jobs until interrupted
+pub async fn serve(
+    addr: SocketAddr,
+    service: Service,
+    crawler: Crawler,
+) -> Result<()> {
+    let requeued = service.jobs.requeue_interrupted()?;
+    if requeued > 0 {
+        info!(jobs = requeued, "Queued interrupted jobs again");
+    }
+
+    let service = Arc::new(service);
+    let api = service.clone();
+    let make_service = make_service_fn(move |_| {
+        let api = api.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let api = api.clone();
+                async move { Ok::<_, Infallible>(api.handle(req).await) }
+            }))
+        }
+    });
+    let server = Server::try_bind(&addr)?.serve(make_service);
+    info!(addr = %server.local_addr(), "Serving crawl API");
+
+    tokio::select! {
+        r = server => r?,
+        _ = service.work(&crawler) => {}
+        _ = service.schedule() => {}
+        r = tokio::signal::ctrl_c() => {
+            r?;
+            info!("Shutting down");
+        }
+    }
+    Ok(())
+}
+
+fn json<T: Serialize>(status: StatusCode, value: &T) -> Response<Body> {
+    match serde_json::to_vec(value) {
+        Ok(body) => Response::builder()
+            .status(status)
+            .header(hyper::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .unwrap(),
+        Err(e) => {
+            error_response(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string())
+        }
+    }
+}
+
+fn error_response(status: StatusCode, error: &str) -> Response<Body> {
+    let body = serde_json::to_vec(&ErrorBody {
+        error: error.to_string(),
+    })
+    .unwrap_or_default();
+    Response::builder()
+        .status(status)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        queries::RestVersion,
+        report::{CrawlReport, RequestReport, StageReport},
+    };
+
+    fn service(sources: serde_json::Value) -> Service {
+        let sources = serde_json::from_value(sources).unwrap();
+        Service::new(
+            JobStore::open_in_memory().unwrap(),
+            sources,
+            Metrics::new(),
+        )
+    }
+
+    fn at(time: &str) -> DateTime<Utc> {
+        time.parse().unwrap()
+    }
+
+    fn source(
+        id: &str,
+        url: &str,
+        schedule: Option<&str>,
+    ) -> serde_json::Value {
+        json!({ "id": id, "name": id, "url": url, "schedule": schedule })
+    }
+
+    #[test]
+    fn scheduled_sources_are_queued_when_due() {
+        let service = service(json!([
+            source("DAILY", "http://localhost", Some("0 3 * * *")),
+            source("EVERY", "http://localhost", Some("every 6h")),
+            source("NEVER", "http://localhost", Some("0 0 31 2 *")),
+            source("MANUAL", "http://localhost", None),
+        ]));
+        let now = at("2021-01-01T12:00:00Z");
+        service.plan(now);
+        let due = service.due.lock().unwrap().clone();
+        assert_eq!(due.keys().collect::<Vec<_>>(), vec!["DAILY", "EVERY"]);
+        assert_eq!(due["DAILY"], at("2021-01-02T03:00:00Z"));
+        assert_eq!(due["EVERY"], now);
+
+        service.trigger_due(now);
+        let run = service.jobs.schedule_runs("EVERY", 1).unwrap().remove(0);
+        let job = service.jobs.get(run.job_id.as_ref().unwrap()).unwrap();
+        assert_eq!(job.unwrap().sources, vec!["EVERY"]);
+        assert!(service.jobs.schedule_runs("DAILY", 1).unwrap().is_empty());
+        assert_eq!(
+            service.due.lock().unwrap()["EVERY"],
+            at("2021-01-01T18:00:00Z")
+        );
+    }
+
+    #[test]
+    fn due_sources_with_an_active_job_are_skipped() {
+        let service =
+            service(json!([source("A", "http://localhost", Some("every 1h"))]));
+        let now = Utc::now();
+        service.enqueue(vec!["A".into()]).unwrap();
+        service.plan(now);
+        service.trigger_due(now);
+        let run = service.jobs.schedule_runs("A", 1).unwrap().remove(0);
+        assert_eq!(run.job_id, None);
+        assert!(run.skipped.is_some());
+        assert_eq!(service.jobs.list().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn schedules_resume_from_the_last_run() {
+        let service = service(json!([source(
+            "A",
+            "http://localhost",
+            Some("0 3 * * *")
+        )]));
+        service
+            .jobs
+            .add_schedule_run(&ScheduleRun {
+                source_id: "A".into(),
+                due_at: "2021-01-01T03:00:00+00:00".into(),
+                triggered_at: "2021-01-01T03:00:01+00:00".into(),
+                job_id: None,
+                skipped: None,
+            })
+            .unwrap();
+        // due while the service was stopped, so crawled once on start
+        service.plan(at("2021-01-05T00:00:00Z"));
+        assert_eq!(
+            service.due.lock().unwrap()["A"],
+            at("2021-01-02T03:00:00Z")
+        );
+    }
+
+    #[test]
+    fn intervals_resume_from_when_the_last_run_was_triggered() {
+        let service =
+            service(json!([source("A", "http://localhost", Some("every 6h"))]));
+        service
+            .jobs
+            .add_schedule_run(&ScheduleRun {
+                source_id: "A".into(),
+                due_at: "2021-01-01T00:00:00+00:00".into(),
+                triggered_at: "2021-01-01T02:00:00+00:00".into(),
+                job_id: None,
+                skipped: None,
+            })
+            .unwrap();
+        service.plan(at("2021-01-01T03:00:00Z"));
+        assert_eq!(
+            service.due.lock().unwrap()["A"],
+            at("2021-01-01T08:00:00Z")
+        );
+    }
+
+    #[test]
+    fn scheduled_crawls_continue_from_the_last_clean_crawl() {
+        let service =
+            service(json!([source("A", "http://localhost", Some("every 6h"))]));
+        assert_eq!(service.previous_crawl("A").unwrap(), None);
+
+        let crawled = |started_at: &str, error: Option<&str>| {
+            let mut report = CrawlReport::new("A", RestVersion::V2_1);
+            report.started_at = started_at.to_string();
+            let mut stage = StageReport::new("dataflow");
+            let mut request = RequestReport::new("http://localhost/dataflow");
+            request.error = error.map(str::to_string);
+            stage.requests.push(request);
+            report.stages.push(stage);
+            let job = service.enqueue(vec!["A".into()]).unwrap();
+            service.jobs.start(&job.id).unwrap();
+            service.jobs.add_report(&job.id, &report).unwrap();
+            service
+                .jobs
+                .finish(&job.id, JobStatus::Completed, None)
+                .unwrap();
+        };
+        crawled("2021-01-01T00:00:00+00:00", None);
+        crawled("2021-01-01T06:00:00+00:00", None);
+        // changes between the clean crawl and this one may have been missed
+        crawled("2021-01-01T12:00:00+00:00", Some("Response status 500"));
+        assert_eq!(
+            service.previous_crawl("A").unwrap(),
+            Some(at("2021-01-01T06:00:00Z"))
+        );
+
+        let manual = service.enqueue(vec!["A".into()]).unwrap();
+        assert!(!service.jobs.is_scheduled(&manual.id).unwrap());
+        service.jobs.cancel(&manual.id).unwrap();
+        let now = at("2021-01-01T18:00:00Z");
+        service.trigger("A", now, now).unwrap();
+        let run = service.jobs.schedule_runs("A", 1).unwrap().pop().unwrap();
+        assert!(service.jobs.is_scheduled(&run.job_id.unwrap()).unwrap());
+    }
+
+    #[tokio::test]
+    async fn running_jobs_can_be_cancelled() {
+        // accepts connections but never answers
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let service = service(json!([source("A", &url, None)]));
+        let job = service.enqueue(vec!["A".into()]).unwrap();
+        let crawler = Crawler::default();
+
+        let cancel = async {
+            while service.jobs.get(&job.id).unwrap().unwrap().status
+                != JobStatus::Running
+            {
+                tokio::task::yield_now().await;
+            }
+            assert!(service.cancel(&job.id).unwrap());
+        };
+        let work = tokio::time::timeout(
+            Duration::from_secs(10),
+            service.work_next(&crawler),
+        );
+        let (worked, _) = tokio::join!(work, cancel);
+        worked.expect("cancelled promptly").unwrap();
+        let job = service.jobs.get(&job.id).unwrap().unwrap();
+        assert_eq!(job.status, JobStatus::Cancelled);
+        assert!(service.running.lock().unwrap().is_none());
+    }
+}
@@ -0,0 +1,1207 @@
+//! store persists crawled structural metadata into a normalized SQLite database.
+//!
+//! Every maintainable artefact (dataflow, data structure, codelist, concept
+//! scheme) is stored once per source and version, along with the provenance
+//! of the response it was parsed from. Components, codes and concepts
+//! reference their parent artefact, and references to other artefacts are
+//! split into agency, ID and version columns so they can be joined on, e.g.
+//!
+//! ```sql
+//! SELECT df.source_id, df.agency_id, df.resource_id
+//! FROM dataflows df
+//! JOIN data_structures ds ON ds.source_id = df.source_id
+//!     AND ds.agency_id = df.structure_agency_id
+//!     AND ds.resource_id = df.structure_id
+//! JOIN dimensions d ON d.data_structure_id = ds.id
+//! WHERE d.codelist_id = 'CL_AREA';
+//! ```
+//!
+//! `name` and `description` hold the text in the main language of the message,
+//! while `names` and `descriptions` hold all languages as JSON objects keyed by
+//! language tag. `content_languages` holds the content languages of the
+//! message as a JSON array, which are the fallbacks when names are resolved
+//! to the preferred languages.
+
+use std::{path::Path, sync::Mutex};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, Transaction};
+
+use crate::{
+    artefact::{Maintainable, DEFAULT_VERSION},
+    components::component_id,
+    localized::{LanguagePreferences, LocalizedString},
+    minimal_structure::{CatalogEntry, Dataflow},
+    structure::{
+        CategorisationTypeElement, CategorySchemeType, CategoryTypeElement,
+        CodelistType, ConceptSchemeType, Data, DataStructureTypeElement,
+        DataflowTypeElement, SimpleDataStructureRepresentationType,
+        UsageStatusType,
+    },
+    urn::Urn,
+    version::{Version, VersionReq},
+};
+
+/// The tables of the first schema version
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS dataflows (
+    id INTEGER PRIMARY KEY,
+    source_id TEXT NOT NULL,
+    agency_id TEXT NOT NULL,
+    resource_id TEXT NOT NULL,
+    version TEXT NOT NULL,
+    name TEXT NOT NULL,
+    description TEXT,
+    names TEXT,
+    descriptions TEXT,
+    fetched_at TEXT NOT NULL,
+    warc_record_id TEXT,
+    content_languages TEXT,
+    structure_agency_id TEXT,
+    structure_id TEXT,
+    structure_version TEXT,
+    UNIQUE (source_id, agency_id, resource_id, version)
+);
+
+CREATE TABLE IF NOT EXISTS data_structures (
+    id INTEGER PRIMARY KEY,
+    source_id TEXT NOT NULL,
+    agency_id TEXT NOT NULL,
+    resource_id TEXT NOT NULL,
+    version TEXT NOT NULL,
+    name TEXT NOT NULL,
+    description TEXT,
+    names TEXT,
+    descriptions TEXT,
+    fetched_at TEXT NOT NULL,
+    warc_record_id TEXT,
+    content_languages TEXT,
+    UNIQUE (source_id, agency_id, resource_id, version)
+);
+
+CREATE TABLE IF NOT EXISTS dimensions (
+    data_structure_id INTEGER NOT NULL
+        REFERENCES data_structures (id) ON DELETE CASCADE,
+    id TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    concept_urn TEXT NOT NULL,
+    codelist_agency_id TEXT,
+    codelist_id TEXT,
+    codelist_version TEXT,
+    PRIMARY KEY (data_structure_id, id)
+);
+
+CREATE TABLE IF NOT EXISTS attributes (
+    data_structure_id INTEGER NOT NULL
+        REFERENCES data_structures (id) ON DELETE CASCADE,
+    id TEXT NOT NULL,
+    assignment_status TEXT NOT NULL,
+    concept_urn TEXT NOT NULL,
+    codelist_agency_id TEXT,
+    codelist_id TEXT,
+    codelist_version TEXT,
+    PRIMARY KEY (data_structure_id, id)
+);
+
+CREATE TABLE IF NOT EXISTS codelists (
+    id INTEGER PRIMARY KEY,
+    source_id TEXT NOT NULL,
+    agency_id TEXT NOT NULL,
+    resource_id TEXT NOT NULL,
+    version TEXT NOT NULL,
+    name TEXT NOT NULL,
+    description TEXT,
+    names TEXT,
+    descriptions TEXT,
+    fetched_at TEXT NOT NULL,
+    warc_record_id TEXT,
+    content_languages TEXT,
+    UNIQUE (source_id, agency_id, resource_id, version)
+);
+
+CREATE TABLE IF NOT EXISTS codes (
+    codelist_id INTEGER NOT NULL REFERENCES codelists (id) ON DELETE CASCADE,
+    id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    description TEXT,
+    names TEXT,
+    descriptions TEXT,
+    parent TEXT,
+    PRIMARY KEY (codelist_id, id)
+);
+
+CREATE TABLE IF NOT EXISTS concept_schemes (
+    id INTEGER PRIMARY KEY,
+    source_id TEXT NOT NULL,
+    agency_id TEXT NOT NULL,
+    resource_id TEXT NOT NULL,
+    version TEXT NOT NULL,
+    name TEXT NOT NULL,
+    description TEXT,
+    names TEXT,
+    descriptions TEXT,
+    fetched_at TEXT NOT NULL,
+    warc_record_id TEXT,
+    content_languages TEXT,
+    UNIQUE (source_id, agency_id, resource_id, version)
+);
+
+CREATE TABLE IF NOT EXISTS concepts (
+    concept_scheme_id INTEGER NOT NULL
+        REFERENCES concept_schemes (id) ON DELETE CASCADE,
+    id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    description TEXT,
+    names TEXT,
+    descriptions TEXT,
+    codelist_agency_id TEXT,
+    codelist_id TEXT,
+    codelist_version TEXT,
+    PRIMARY KEY (concept_scheme_id, id)
+);
+
+CREATE TABLE IF NOT EXISTS category_schemes (
+    id INTEGER PRIMARY KEY,
+    source_id TEXT NOT NULL,
+    agency_id TEXT NOT NULL,
+    resource_id TEXT NOT NULL,
+    version TEXT NOT NULL,
+    name TEXT NOT NULL,
+    description TEXT,
+    names TEXT,
+    descriptions TEXT,
+    fetched_at TEXT NOT NULL,
+    warc_record_id TEXT,
+    content_languages TEXT,
+    UNIQUE (source_id, agency_id, resource_id, version)
+);
+
+CREATE TABLE IF NOT EXISTS categories (
+    category_scheme_id INTEGER NOT NULL
+        REFERENCES category_schemes (id) ON DELETE CASCADE,
+    -- Dot separated path of the IDs of nested categories
+    id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    description TEXT,
+    names TEXT,
+    descriptions TEXT,
+    PRIMARY KEY (category_scheme_id, id)
+);
+
+CREATE TABLE IF NOT EXISTS categorisations (
+    id INTEGER PRIMARY KEY,
+    source_id TEXT NOT NULL,
+    agency_id TEXT NOT NULL,
+    resource_id TEXT NOT NULL,
+    version TEXT NOT NULL,
+    name TEXT NOT NULL,
+    description TEXT,
+    names TEXT,
+    descriptions TEXT,
+    fetched_at TEXT NOT NULL,
+    warc_record_id TEXT,
+    content_languages TEXT,
+    object_agency_id TEXT,
+    object_id TEXT,
+    object_version TEXT,
+    category_scheme_agency_id TEXT,
+    category_scheme_id TEXT,
+    category_scheme_version TEXT,
+    category_id TEXT,
+    UNIQUE (source_id, agency_id, resource_id, version)
+);
+";
+
+/// Indexes on the columns the artefacts are joined on
+const JOIN_INDEXES: &str = "
+CREATE INDEX dataflows_structure
+    ON dataflows (source_id, structure_agency_id, structure_id);
+CREATE INDEX dimensions_codelist
+    ON dimensions (codelist_agency_id, codelist_id);
+CREATE INDEX attributes_codelist
+    ON attributes (codelist_agency_id, codelist_id);
+CREATE INDEX concepts_codelist
+    ON concepts (codelist_agency_id, codelist_id);
+CREATE INDEX categorisations_object
+    ON categorisations (source_id, object_agency_id, object_id);
+CREATE INDEX categorisations_category
+    ON categorisations (source_id, category_scheme_agency_id,
+        category_scheme_id, category_id);
+";
+
+type Migration = fn(&Transaction) -> Result<()>;
+
+/// The schema migrations in order. The `user_version` of a database is the
+/// number of migrations applied to it. Released migrations must not change,
+/// changes to the schema are added as new migrations.
+const MIGRATIONS: &[Migration] = &[create_tables, index_join_keys];
+
+fn create_tables(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(SCHEMA)?;
+    Ok(())
+}
+
+fn index_join_keys(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(JOIN_INDEXES)?;
+    Ok(())
+}
+
+/// Applies the migrations the database has not seen yet, each in a
+/// transaction of its own
+fn migrate(conn: &mut Connection) -> Result<()> {
+    let applied: i64 =
+        conn.query_row("PRAGMA user_version", params![], |r| r.get(0))?;
+    let applied = applied as usize;
+    if applied > MIGRATIONS.len() {
+        return Err(anyhow!(
+            "The store has schema version {}, but this version of sdmx \
+             only knows up to {}",
+            applied,
+            MIGRATIONS.len()
+        ));
+    }
+    for (idx, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+        let tx = conn.transaction()?;
+        migration(&tx)?;
+        tx.execute_batch(&format!("PRAGMA user_version = {}", idx + 1))?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+/// Where and when a structure message was fetched
+#[derive(Debug, Clone)]
+pub struct Provenance {
+    pub source_id: String,
+    pub fetched_at: DateTime<Utc>,
+    /// Record ID of the response in the WARC archive
+    pub warc_record_id: Option<String>,
+    /// The content languages of the message, most important first
+    pub content_languages: Vec<String>,
+}
+
+/// A dimension of any kind, before it is inserted
+struct DimensionRow<'a> {
+    id: String,
+    position: Option<i64>,
+    kind: &'static str,
+    concept_identity: &'a String,
+    codelist: Option<&'a String>,
+}
+
+/// A SQLite database of structural metadata
+pub struct Store {
+    conn: Mutex<Connection>,
+}
+
+impl Store {
+    /// Opens (creating if needed) the database at the given path
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Store::init(Connection::open(path)?)
+    }
+
+    /// Opens a temporary database that only lives in memory
+    pub fn open_in_memory() -> Result<Self> {
+        Store::init(Connection::open_in_memory()?)
+    }
+
+    fn init(mut conn: Connection) -> Result<Self> {
+        conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+        migrate(&mut conn)?;
+        Ok(Store {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Saves all supported artefacts in a structure message. Artefacts that were
+    /// already stored for the same source and version are replaced.
+    pub fn save(&self, data: &Data, provenance: &Provenance) -> Result<()> {
+        let mut conn = self
+            .conn
+            .lock()
+            .map_err(|_| anyhow!("Store connection lock was poisoned"))?;
+        let tx = conn.transaction()?;
+
+        for df in data.dataflows.iter().flatten() {
+            save_dataflow(&tx, df, provenance)?;
+        }
+        for dsd in data.data_structures.iter().flatten() {
+            save_data_structure(&tx, dsd, provenance)?;
+        }
+        for cl in data.codelists.iter().flatten() {
+            save_codelist(&tx, cl, provenance)?;
+        }
+        for cs in data.concept_schemes.iter().flatten() {
+            save_concept_scheme(&tx, cs, provenance)?;
+        }
+        for cs in data.category_schemes.iter().flatten() {
+            save_category_scheme(&tx, cs, provenance)?;
+        }
+        for c in data.categorisations.iter().flatten() {
+            save_categorisation(&tx, c, provenance)?;
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Builds a catalog entry for every stored dataflow, combining its data
+    /// structure, codelists and categories. Provider information is not
+    /// stored, see `CatalogEntry::set_source`. Names are given in the most
+    /// preferred available language.
+    pub fn catalog(
+        &self,
+        languages: &LanguagePreferences,
+    ) -> Result<Vec<CatalogEntry>> {
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|_| anyhow!("Store connection lock was poisoned"))?;
+
+        let mut stmt = conn.prepare(
+            "SELECT source_id, agency_id, resource_id, version, name, \
+             description, names, descriptions, fetched_at, \
+             structure_agency_id, structure_id, structure_version, \
+             content_languages \
+             FROM dataflows \
+             ORDER BY source_id, agency_id, resource_id, version",
+        )?;
+        let rows = stmt.query_map(params![], |r| {
+            Ok((
+                r.get::<_, String>(0)?,
+                r.get::<_, String>(1)?,
+                r.get::<_, String>(2)?,
+                r.get::<_, String>(3)?,
+                r.get::<_, String>(4)?,
+                r.get::<_, Option<String>>(5)?,
+                r.get::<_, Option<String>>(6)?,
+                r.get::<_, Option<String>>(7)?,
+                r.get::<_, String>(8)?,
+                r.get::<_, Option<String>>(9)?,
+                r.get::<_, Option<String>>(10)?,
+                r.get::<_, Option<String>>(11)?,
+                r.get::<_, Option<String>>(12)?,
+            ))
+        })?;
+
+        let mut out = vec![];
+        for row in rows {
+            let (
+                source_id,
+                agency_id,
+                resource_id,
+                version,
+                name,
+                description,
+                names,
+                descriptions,
+                fetched_at,
+                structure_agency_id,
+                structure_id,
+                structure_version,
+                content_languages,
+            ) = row?;
+            let df_languages = artefact_languages(languages, content_languages);
+
+            let mut entry = CatalogEntry {
+                id: format!(
+                    "{}:{}:{}({})",
+                    source_id, agency_id, resource_id, version
+                ),
+                source_id: source_id.clone(),
+                source_name: None,
+                source_url: None,
+                dataflow: Dataflow {
+                    resource_id: resource_id.clone(),
+                    agency_id: agency_id.clone(),
+                    name: resolve_label(&df_languages, Some(name), names)
+                        .unwrap_or_default(),
+                    version: version.parse().ok(),
+                    description: resolve_label(
+                        &df_languages,
+                        description,
+                        descriptions,
+                    ),
+                    structure: None,
+                },
+                structure_name: None,
+                dimensions: vec![],
+                attributes: vec![],
+                codelists: vec![],
+                codes: vec![],
+                categories: categories(
+                    &conn,
+                    languages,
+                    &source_id,
+                    &agency_id,
+                    &resource_id,
+                )?,
+                fetched_at,
+            };
+
+            if let (Some(s_agency), Some(s_id)) =
+                (structure_agency_id, structure_id)
+            {
+                let s_version = structure_version.unwrap_or_default();
+                entry.dataflow.structure = Some(format!(
+                    "urn:sdmx:org.sdmx.infomodel.datastructure.DataStructure={}:{}({})",
+                    s_agency, s_id, s_version
+                ));
+                let dsd = find_maintainable(
+                    &conn,
+                    languages,
+                    "data_structures",
+                    &source_id,
+                    &s_agency,
+                    &s_id,
+                    &s_version,
+                )?;
+                if let Some((dsd_row, dsd_name, _)) = dsd {
+                    entry.structure_name = Some(dsd_name);
+                    add_components(
+                        &conn, languages, &source_id, dsd_row, &mut entry,
+                    )?;
+                }
+            }
+            out.push(entry);
+        }
+        Ok(out)
+    }
+
+    /// The stored dataflows of a source in every version, so a crawl can
+    /// carry on from them when the list of dataflows hasn't changed
+    pub fn dataflows(&self, source_id: &str) -> Result<Vec<Dataflow>> {
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|_| anyhow!("Store connection lock was poisoned"))?;
+        let mut stmt = conn.prepare(
+            "SELECT agency_id, resource_id, version, name, description, \
+             structure_agency_id, structure_id, structure_version \
+             FROM dataflows WHERE source_id = ?1 \
+             ORDER BY agency_id, resource_id, version",
+        )?;
+        let rows = stmt.query_map(params![source_id], |r| {
+            Ok((
+                r.get::<_, String>(0)?,
+                r.get::<_, String>(1)?,
+                r.get::<_, String>(2)?,
+                r.get::<_, String>(3)?,
+                r.get::<_, Option<String>>(4)?,
+                r.get::<_, Option<String>>(5)?,
+                r.get::<_, Option<String>>(6)?,
+                r.get::<_, Option<String>>(7)?,
+            ))
+        })?;
+        let mut out = vec![];
+        for row in rows {
+            let (
+                agency_id,
+                resource_id,
+                version,
+                name,
+                description,
+                s_agency,
+                s_id,
+                s_version,
+            ) = row?;
+            let structure = match (s_agency, s_id) {
+                (Some(s_agency), Some(s_id)) => Some(format!(
+                    "urn:sdmx:org.sdmx.infomodel.datastructure.DataStructure={}:{}({})",
+                    s_agency,
+                    s_id,
+                    s_version.unwrap_or_default()
+                )),
+                _ => None,
+            };
+            out.push(Dataflow {
+                resource_id,
+                agency_id,
+                name,
+                version: version.parse().ok(),
+                description,
+                structure,
+            });
+        }
+        Ok(out)
+    }
+}
+
+/// Finds the row ID and name of a maintainable artefact in the version that
+/// matches the requested one, see `pick_version`. Also returns the
+/// language preferences for the labels of its items.
+fn find_maintainable(
+    conn: &Connection,
+    languages: &LanguagePreferences,
+    table: &str,
+    source_id: &str,
+    agency_id: &str,
+    id: &str,
+    version: &str,
+) -> Result<Option<(i64, String, LanguagePreferences)>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id, version, name, names, content_languages FROM {} \
+         WHERE source_id = ?1 AND agency_id = ?2 AND resource_id = ?3",
+        table
+    ))?;
+    let mut rows = stmt
+        .query_map(params![source_id, agency_id, id], |r| {
+            Ok((
+                r.get::<_, i64>(0)?,
+                r.get::<_, String>(1)?,
+                r.get::<_, String>(2)?,
+                r.get::<_, Option<String>>(3)?,
+                r.get::<_, Option<String>>(4)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let versions: Vec<_> = rows.iter().map(|r| r.1.as_str()).collect();
+    let idx = match pick_version(&versions, version) {
+        Some(idx) => idx,
+        None => return Ok(None),
+    };
+    let (row, _, name, names, content_languages) = rows.swap_remove(idx);
+    let languages = artefact_languages(languages, content_languages);
+    let name = resolve_label(&languages, Some(name), names).unwrap_or_default();
+    Ok(Some((row, name, languages)))
+}
+
+/// The index of the stored version that matches a requested version or
+/// wildcard: the same version, else the highest version matching the
+/// request. Versions are compared by `Version`, so `1.10` is above `1.9`
+/// and `1.0` is the same as `1.0.0`. An exact version that isn't stored
+/// gives `None` rather than another version of the artefact.
+fn pick_version(stored: &[&str], requested: &str) -> Option<usize> {
+    if let Some(idx) = stored.iter().position(|v| *v == requested) {
+        return Some(idx);
+    }
+    let requested = requested.parse::<VersionReq>().ok()?;
+    stored
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, v)| v.parse::<Version>().ok().map(|v| (idx, v)))
+        .filter(|(_, v)| requested.matches(v))
+        .max_by(|a, b| a.1.cmp(&b.1))
+        .map(|(idx, _)| idx)
+}
+
+/// Adds the dimensions, attributes and their codelists of a data structure to a catalog entry
+fn add_components(
+    conn: &Connection,
+    languages: &LanguagePreferences,
+    source_id: &str,
+    data_structure_id: i64,
+    entry: &mut CatalogEntry,
+) -> Result<()> {
+    let mut codelists = vec![];
+    for table in &["dimensions", "attributes"] {
+        let order = match *table {
+            "dimensions" => "position",
+            _ => "id",
+        };
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, codelist_agency_id, codelist_id, codelist_version \
+             FROM {} WHERE data_structure_id = ?1 ORDER BY {}",
+            table, order
+        ))?;
+        let rows = stmt.query_map(params![data_structure_id], |r| {
+            Ok((
+                r.get::<_, String>(0)?,
+                r.get::<_, Option<String>>(1)?,
+                r.get::<_, Option<String>>(2)?,
+                r.get::<_, Option<String>>(3)?,
+            ))
+        })?;
+        for row in rows {
+            let (id, cl_agency, cl_id, cl_version) = row?;
+            match *table {
+                "dimensions" => entry.dimensions.push(id),
+                _ => entry.attributes.push(id),
+            }
+            if let (Some(cl_agency), Some(cl_id)) = (cl_agency, cl_id) {
+                codelists.push((
+                    cl_agency,
+                    cl_id,
+                    cl_version.unwrap_or_default(),
+                ));
+            }
+        }
+    }
+
+    codelists.sort();
+    codelists.dedup();
+    for (cl_agency, cl_id, cl_version) in codelists {
+        let codelist = find_maintainable(
+            conn,
+            languages,
+            "codelists",
+            source_id,
+            &cl_agency,
+            &cl_id,
+            &cl_version,
+        )?;
+        if let Some((cl_row, cl_name, cl_languages)) = codelist {
+            entry.codelists.push(cl_name);
+            let mut stmt = conn.prepare(
+                "SELECT name, names FROM codes WHERE codelist_id = ?1",
+            )?;
+            let names = stmt
+                .query_map(params![cl_row], |r| Ok((r.get(0)?, r.get(1)?)))?;
+            for row in names {
+                let (name, names) = row?;
+                entry.codes.extend(resolve_label(
+                    &cl_languages,
+                    Some(name),
+                    names,
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Names of the categories an artefact is categorised under
+fn categories(
+    conn: &Connection,
+    languages: &LanguagePreferences,
+    source_id: &str,
+    agency_id: &str,
+    id: &str,
+) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT DISTINCT cat.name, cat.names, cs.content_languages \
+         FROM categorisations cz \
+         JOIN category_schemes cs ON cs.source_id = cz.source_id \
+             AND cs.agency_id = cz.category_scheme_agency_id \
+             AND cs.resource_id = cz.category_scheme_id \
+         JOIN categories cat ON cat.category_scheme_id = cs.id \
+             AND cat.id = cz.category_id \
+         WHERE cz.source_id = ?1 AND cz.object_agency_id = ?2 \
+             AND cz.object_id = ?3",
+    )?;
+    let rows = stmt.query_map(params![source_id, agency_id, id], |r| {
+        Ok((r.get(0)?, r.get(1)?, r.get(2)?))
+    })?;
+    let mut out = vec![];
+    for row in rows {
+        let (name, names, content_languages) = row?;
+        let languages = artefact_languages(languages, content_languages);
+        out.extend(resolve_label(&languages, Some(name), names));
+    }
+    Ok(out)
+}
+
+/// Replaces a maintainable artefact in the given table, returning its row ID
+fn upsert_maintainable(
+    tx: &Transaction,
+    table: &str,
+    m: &dyn Maintainable,
+    provenance: &Provenance,
+) -> Result<i64> {
+    let version = m.version().unwrap_or(DEFAULT_VERSION);
+    // Deleting first also removes the children of the previous row
+    tx.execute(
+        &format!(
+            "DELETE FROM {} WHERE source_id = ?1 AND agency_id = ?2 \
+             AND resource_id = ?3 AND version = ?4",
+            table
+        ),
+        params![
+            provenance.source_id,
+            m.agency_id(),
+            m.resource_id(),
+            version
+        ],
+    )?;
+    tx.execute(
+        &format!(
+            "INSERT INTO {} (source_id, agency_id, resource_id, version, name, \
+             description, names, descriptions, fetched_at, warc_record_id, \
+             content_languages) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            table
+        ),
+        params![
+            provenance.source_id,
+            m.agency_id(),
+            m.resource_id(),
+            version,
+            m.name(),
+            m.description(),
+            localized_json(m.names())?,
+            localized_json(m.descriptions())?,
+            provenance.fetched_at.to_rfc3339(),
+            provenance.warc_record_id,
+            content_languages_json(&provenance.content_languages)?,
+        ],
+    )?;
+    Ok(tx.last_insert_rowid())
+}
+
+/// Serializes a localized text for the `names` and `descriptions` columns
+fn localized_json(text: Option<&LocalizedString>) -> Result<Option<String>> {
+    Ok(match text {
+        Some(t) if !t.is_empty() => Some(serde_json::to_string(t)?),
+        _ => None,
+    })
+}
+
+/// Serializes the content languages of a message for the `content_languages`
+/// column
+fn content_languages_json(languages: &[String]) -> Result<Option<String>> {
+    Ok(match languages.is_empty() {
+        true => None,
+        false => Some(serde_json::to_string(languages)?),
+    })
+}
+
+/// The preferred languages followed by the content languages of the message
+/// an artefact was stored from
+fn artefact_languages(
+    languages: &LanguagePreferences,
+    content_languages: Option<String>,
+) -> LanguagePreferences {
+    let content: Vec<String> = content_languages
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or_default();
+    languages.clone().with_content_languages(&content)
+}
+
+/// Resolves a text column and its localized JSON column to the preferred language
+fn resolve_label(
+    languages: &LanguagePreferences,
+    text: Option<String>,
+    localized: Option<String>,
+) -> Option<String> {
+    let localized = localized
+        .and_then(|l| serde_json::from_str::<LocalizedString>(&l).ok());
+    languages
+        .resolve_opt(localized.as_ref(), text.as_deref())
+        .map(|s| s.to_string())
+}
+
+/// Splits an optional artefact URN into agency, ID and version columns
+fn reference_columns(
+    urn: Option<&String>,
+) -> (Option<String>, Option<String>, Option<String>) {
+    match urn.and_then(|u| u.parse::<Urn>().ok()) {
+        Some(u) => (Some(u.agency_id), Some(u.id), Some(u.version.to_string())),
+        None => (None, None, None),
+    }
+}
+
+fn enumeration(
+    repr: Option<&SimpleDataStructureRepresentationType>,
+) -> Option<&String> {
+    repr.and_then(|r| r.enumeration.as_ref())
+}
+
+fn save_dataflow(
+    tx: &Transaction,
+    df: &DataflowTypeElement,
+    provenance: &Provenance,
+) -> Result<()> {
+    let row = upsert_maintainable(tx, "dataflows", df, provenance)?;
+    let (agency_id, id, version) = reference_columns(df.structure.as_ref());
+    tx.execute(
+        "UPDATE dataflows SET structure_agency_id = ?1, structure_id = ?2, \
+         structure_version = ?3 WHERE id = ?4",
+        params![agency_id, id, version, row],
+    )?;
+    Ok(())
+}
+
+fn save_data_structure(
+    tx: &Transaction,
+    dsd: &DataStructureTypeElement,
+    provenance: &Provenance,
+) -> Result<()> {
+    let row = upsert_maintainable(tx, "data_structures", dsd, provenance)?;
+    let components = match &dsd.data_structure_components {
+        Some(c) => c,
+        None => return Ok(()),
+    };
+
+    let dims = &components.dimension_list;
+    let mut rows = vec![];
+    for d in dims.dimensions.iter().flatten() {
+        rows.push(DimensionRow {
+            id: component_id(d.id.as_ref(), &d.concept_identity),
+            position: d.position,
+            kind: "Dimension",
+            concept_identity: &d.concept_identity,
+            codelist: enumeration(d.local_representation.as_ref()),
+        });
+    }
+    for d in dims.measure_dimensions.iter().flatten() {
+        rows.push(DimensionRow {
+            id: component_id(d.id.as_ref(), &d.concept_identity),
+            position: d.position,
+            kind: "MeasureDimension",
+            concept_identity: &d.concept_identity,
+            codelist: None,
+        });
+    }
+    for d in dims.time_dimensions.iter().flatten() {
+        rows.push(DimensionRow {
+            id: component_id(d.id.as_ref(), &d.concept_identity),
+            position: d.position,
+            kind: "TimeDimension",
+            concept_identity: &d.concept_identity,
+            codelist: None,
+        });
+    }
+    for (idx, d) in rows.into_iter().enumerate() {
+        let (cl_agency, cl_id, cl_version) = reference_columns(d.codelist);
+        tx.execute(
+            "INSERT OR REPLACE INTO dimensions (data_structure_id, id, \
+             position, kind, concept_urn, codelist_agency_id, codelist_id, \
+             codelist_version) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                row,
+                d.id,
+                // Positions start at 1
+                d.position.unwrap_or(idx as i64 + 1),
+                d.kind,
+                d.concept_identity,
+                cl_agency,
+                cl_id,
+                cl_version
+            ],
+        )?;
+    }
+
+    let attributes = components
+        .attribute_list
+        .as_ref()
+        .and_then(|a| a.attributes.as_ref());
+    for a in attributes.into_iter().flatten() {
+        let (cl_agency, cl_id, cl_version) =
+            reference_columns(enumeration(a.local_representation.as_ref()));
+        let status = match a.assignment_status {
+            UsageStatusType::Conditional => "Conditional",
+            UsageStatusType::Mandatory => "Mandatory",
+        };
+        tx.execute(
+            "INSERT OR REPLACE INTO attributes (data_structure_id, id, \
+             assignment_status, concept_urn, codelist_agency_id, codelist_id, \
+             codelist_version) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                row,
+                component_id(a.id.as_ref(), &a.concept_identity),
+                status,
+                a.concept_identity,
+                cl_agency,
+                cl_id,
+                cl_version
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+fn save_codelist(
+    tx: &Transaction,
+    cl: &CodelistType,
+    provenance: &Provenance,
+) -> Result<()> {
+    let row = upsert_maintainable(tx, "codelists", cl, provenance)?;
+    for code in cl.codes.iter().flatten() {
+        let id = match &code.id {
+            Some(id) => id,
+            None => continue,
+        };
+        tx.execute(
+            "INSERT OR REPLACE INTO codes (codelist_id, id, name, description, \
+             names, descriptions, parent) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                row,
+                id,
+                code.name,
+                code.description,
+                localized_json(code.names.as_ref())?,
+                localized_json(code.descriptions.as_ref())?,
+                code.parent
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+fn save_concept_scheme(
+    tx: &Transaction,
+    cs: &ConceptSchemeType,
+    provenance: &Provenance,
+) -> Result<()> {
+    let row = upsert_maintainable(tx, "concept_schemes", cs, provenance)?;
+    for concept in cs.concepts.iter().flatten() {
+        let id = match &concept.id {
+            Some(id) => id,
+            None => continue,
+        };
+        let codelist = concept
+            .core_representation
+            .as_ref()
+            .and_then(|r| r.enumeration.as_ref());
+        let (cl_agency, cl_id, cl_version) = reference_columns(codelist);
+        tx.execute(
+            "INSERT OR REPLACE INTO concepts (concept_scheme_id, id, name, \
+             description, names, descriptions, codelist_agency_id, \
+             codelist_id, codelist_version) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                row,
+                id,
+                concept.name,
+                concept.description,
+                localized_json(concept.names.as_ref())?,
+                localized_json(concept.descriptions.as_ref())?,
+                cl_agency,
+                cl_id,
+                cl_version
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+fn save_category_scheme(
+    tx: &Transaction,
+    cs: &CategorySchemeType,
+    provenance: &Provenance,
+) -> Result<()> {
+    let row = upsert_maintainable(tx, "category_schemes", cs, provenance)?;
+    save_categories(tx, row, None, cs.categories.as_ref())
+}
+
+/// Saves nested categories, identified by the dot separated path of their IDs
+fn save_categories(
+    tx: &Transaction,
+    row: i64,
+    parent: Option<&str>,
+    categories: Option<&Vec<CategoryTypeElement>>,
+) -> Result<()> {
+    for category in categories.into_iter().flatten() {
+        let id = match (&category.id, parent) {
+            (Some(id), Some(parent)) => format!("{}.{}", parent, id),
+            (Some(id), None) => id.clone(),
+            (None, _) => continue,
+        };
+        tx.execute(
+            "INSERT OR REPLACE INTO categories (category_scheme_id, id, name, \
+             description, names, descriptions) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                row,
+                id,
+                category.name,
+                category.description,
+                localized_json(category.names.as_ref())?,
+                localized_json(category.descriptions.as_ref())?
+            ],
+        )?;
+        save_categories(tx, row, Some(&id), category.categories.as_ref())?;
+    }
+    Ok(())
+}
+
+fn save_categorisation(
+    tx: &Transaction,
+    c: &CategorisationTypeElement,
+    provenance: &Provenance,
+) -> Result<()> {
+    let row = upsert_maintainable(tx, "categorisations", c, provenance)?;
+    let (o_agency, o_id, o_version) = reference_columns(c.source.as_ref());
+    let target = c.target.as_ref().and_then(|t| t.parse::<Urn>().ok());
+    let (cs_agency, cs_id, cs_version) = reference_columns(c.target.as_ref());
+    tx.execute(
+        "UPDATE categorisations SET object_agency_id = ?1, object_id = ?2, \
+         object_version = ?3, category_scheme_agency_id = ?4, \
+         category_scheme_id = ?5, category_scheme_version = ?6, \
+         category_id = ?7 WHERE id = ?8",
+        params![
+            o_agency,
+            o_id,
+            o_version,
+            cs_agency,
+            cs_id,
+            cs_version,
+            target.and_then(|t| t.item_id()),
+            row
+        ],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn provenance(content_languages: &[&str]) -> Provenance {
+        Provenance {
+            source_id: "SRC".into(),
+            fetched_at: Utc::now(),
+            warc_record_id: None,
+            content_languages: content_languages
+                .iter()
+                .map(|l| l.to_string())
+                .collect(),
+        }
+    }
+
+    fn user_version(conn: &Connection) -> usize {
+        conn.query_row("PRAGMA user_version", params![], |r| r.get::<_, i64>(0))
+            .unwrap() as usize
+    }
+
+    #[test]
+    fn migrations_run_once() {
+        let dir = crate::archive::tests::temp_dir();
+        let path = dir.join("store.db");
+        Store::open(&path).unwrap();
+        // opening again must not apply the migrations again
+        let store = Store::open(&path).unwrap();
+        let conn = store.conn.lock().unwrap();
+        assert_eq!(user_version(&conn), MIGRATIONS.len());
+        let indexes: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE type = 'index' \
+                 AND name = 'dimensions_codelist'",
+                params![],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(indexes, 1);
+        drop(conn);
+
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch("PRAGMA user_version = 99").unwrap();
+        drop(conn);
+        assert!(Store::open(&path).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn dimensions_without_a_position_are_numbered_from_one() {
+        let store = Store::open_in_memory().unwrap();
+        let concept = |id: &str| {
+            let cs = "urn:sdmx:org.sdmx.infomodel.conceptscheme.Concept=A:CS";
+            format!("{}(1.0).{}", cs, id)
+        };
+        let data: Data = serde_json::from_value(json!({
+            "dataStructures": [{
+                "id": "DSD", "agencyID": "A", "name": "DSD",
+                "dataStructureComponents": {
+                    "dimensionList": {
+                        "dimensions": [
+                            { "id": "FREQ", "conceptIdentity": concept("F") },
+                            { "id": "AREA", "conceptIdentity": concept("A") }
+                        ]
+                    },
+                    "measureList": {
+                        "primaryMeasure": { "conceptIdentity": concept("OBS") }
+                    }
+                }
+            }]
+        }))
+        .unwrap();
+        store.save(&data, &provenance(&[])).unwrap();
+        let conn = store.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare("SELECT id, position FROM dimensions ORDER BY position")
+            .unwrap();
+        let rows: Vec<(String, i64)> = stmt
+            .query_map(params![], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(rows, vec![("FREQ".into(), 1), ("AREA".into(), 2)]);
+    }
+
+    #[test]
+    fn versions_are_picked_numerically() {
+        let stored = ["1.9", "1.10", "2.0.0-draft", "x"];
+        assert_eq!(pick_version(&stored, "1.9"), Some(0));
+        // the same version in another form
+        assert_eq!(pick_version(&stored, "1.10.0"), Some(1));
+        assert_eq!(pick_version(&stored, "+"), Some(1));
+        assert_eq!(pick_version(&stored, "latest"), Some(2));
+        assert_eq!(pick_version(&stored, "1.0+.0"), Some(1));
+        assert_eq!(pick_version(&stored, "x"), Some(3));
+        // missing versions are not replaced by another one
+        assert_eq!(pick_version(&stored, "1.5"), None);
+        assert_eq!(pick_version(&stored, "2.0.0"), None);
+        assert_eq!(pick_version(&stored, "2.0+.0"), None);
+        assert_eq!(pick_version(&["b", "a"], "1.0"), None);
+        assert_eq!(pick_version(&[], "1.0"), None);
+    }
+
+    #[test]
+    fn catalog_leaves_out_structures_in_missing_versions() {
+        let store = Store::open_in_memory().unwrap();
+        let dsd = "urn:sdmx:org.sdmx.infomodel.datastructure.DataStructure";
+        let concept =
+            "urn:sdmx:org.sdmx.infomodel.conceptscheme.Concept=A:CS(1.0).F";
+        let data: Data = serde_json::from_value(json!({
+            "dataflows": [{
+                "id": "DF", "agencyID": "A", "version": "1.0", "name": "DF",
+                "structure": format!("{}=A:DSD(1.5)", dsd)
+            }],
+            "dataStructures": [{
+                "id": "DSD", "agencyID": "A", "version": "2.0.0-draft",
+                "name": "Draft",
+                "dataStructureComponents": {
+                    "dimensionList": {
+                        "dimensions": [
+                            { "id": "FREQ", "conceptIdentity": concept }
+                        ]
+                    },
+                    "measureList": {
+                        "primaryMeasure": { "conceptIdentity": concept }
+                    }
+                }
+            }]
+        }))
+        .unwrap();
+        store.save(&data, &provenance(&[])).unwrap();
+
+        let entry = store
+            .catalog(&LanguagePreferences::default())
+            .unwrap()
+            .remove(0);
+        assert_eq!(
+            entry.dataflow.structure.as_deref(),
+            Some(format!("{}=A:DSD(1.5)", dsd).as_str())
+        );
+        assert_eq!(entry.structure_name, None);
+        assert!(entry.dimensions.is_empty());
+    }
+
+    #[test]
+    fn catalog_names_fall_back_to_the_content_languages() {
+        let store = Store::open_in_memory().unwrap();
+        let data: Data = serde_json::from_value(json!({
+            "dataflows": [{
+                "id": "DF", "agencyID": "A", "version": "1.0",
+                "name": "Prices",
+                "names": { "en": "Prices", "fr": "Prix", "it": "Prezzi" }
+            }]
+        }))
+        .unwrap();
+        store.save(&data, &provenance(&["it", "en"])).unwrap();
+
+        let name = |languages: &str| {
+            let languages = languages.parse().unwrap();
+            store.catalog(&languages).unwrap().remove(0).dataflow.name
+        };
+        assert_eq!(name("fr"), "Prix");
+        assert_eq!(name("de"), "Prezzi");
+
+        store.save(&data, &provenance(&[])).unwrap();
+        assert_eq!(name("de"), "Prices");
+    }
+}
@@ -13,6 +13,8 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use crate::localized::LocalizedString;
+
 /// SDMX-JSON Schema for structure messages
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Structure {
@@ -139,8 +141,8 @@ pub struct AgencySchemeTypeElement {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     #[serde(rename = "isExternalReference")]
     pub is_external_reference: Option<bool>,
     #[serde(rename = "isFinal")]
@@ -176,8 +178,8 @@ pub struct AgencyType {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     pub contacts: Option<Vec<ContactType>>,
 }
 
@@ -201,7 +203,7 @@ pub struct AnnotationType {
     /// AnnotationText holds a language-specific string containing the text of the annotation.
     pub text: Option<String>,
     /// AnnotationText holds a language-specific string containing the text of the annotation.
-    pub texts: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub texts: Option<LocalizedString>,
     /// AnnotationTitle provides a title for the annotation.
     pub title: Option<String>,
     /// AnnotationType is used to distinguish between annotations designed to support various
@@ -230,7 +232,7 @@ pub struct Link {
     /// indicate the urn to the parent object.
     pub rel: String,
     pub title: Option<String>,
-    pub titles: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub titles: Option<LocalizedString>,
     /// A hint about the type of representation returned by the link.
     #[serde(rename = "type")]
     pub link_type: Option<String>,
@@ -249,19 +251,19 @@ pub struct ContactType {
     pub department: Option<String>,
     /// Department is a humain-readable designation of the organisational structure by a
     /// linguistic expression, within which the contact person works.
-    pub departments: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub departments: Option<LocalizedString>,
     pub emails: Option<Vec<String>>,
     pub faxes: Option<Vec<String>>,
     /// Name contains a humain-readable name for the contact.
     pub name: Option<String>,
     /// Name contains a humain-readable name for the contact.
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub names: Option<LocalizedString>,
     /// Role is the humain-readable responsibility of the contact person with respect to the
     /// object for which this person is the contact.
     pub role: Option<String>,
     /// Role is the humain-readable responsibility of the contact person with respect to the
     /// object for which this person is the contact.
-    pub roles: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub roles: Option<LocalizedString>,
     pub telephones: Option<Vec<String>>,
     pub uris: Option<Vec<String>>,
     #[serde(rename = "x400s")]
@@ -296,8 +298,8 @@ pub struct AttachmentConstraintTypeElement {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     #[serde(rename = "isExternalReference")]
     pub is_external_reference: Option<bool>,
     #[serde(rename = "isFinal")]
@@ -467,8 +469,8 @@ pub struct CategorisationTypeElement {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     #[serde(rename = "isExternalReference")]
     pub is_external_reference: Option<bool>,
     #[serde(rename = "isFinal")]
@@ -513,8 +515,8 @@ pub struct CategorySchemeType {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     #[serde(rename = "isExternalReference")]
     pub is_external_reference: Option<bool>,
     #[serde(rename = "isFinal")]
@@ -550,8 +552,8 @@ pub struct CategoryTypeElement {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     pub categories: Option<Vec<CategoryTypeElement>>,
 }
 
@@ -581,8 +583,8 @@ pub struct CodelistType {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     #[serde(rename = "isExternalReference")]
     pub is_external_reference: Option<bool>,
     #[serde(rename = "isFinal")]
@@ -620,8 +622,8 @@ pub struct CodeTypeElement {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     /// Parent provides the ability to describe simple hierarchies within a single codelist, by
     /// referencing the id value of another code in the same codelist.
     pub parent: Option<String>,
@@ -657,8 +659,8 @@ pub struct ConceptSchemeType {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     #[serde(rename = "isExternalReference")]
     pub is_external_reference: Option<bool>,
     #[serde(rename = "isFinal")]
@@ -695,8 +697,8 @@ pub struct ConceptType {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     #[serde(rename = "coreRepresentation")]
     pub core_representation: Option<ConceptRepresentation>,
     /// Provides a urn reference (containing conceptSchemeID, conceptAgency, conceptID) to an ISO
@@ -818,8 +820,8 @@ pub struct ContentConstraintTypeElement {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     #[serde(rename = "isExternalReference")]
     pub is_external_reference: Option<bool>,
     #[serde(rename = "isFinal")]
@@ -1106,8 +1108,8 @@ pub struct DataConsumerSchemeTypeElement {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     #[serde(rename = "isExternalReference")]
     pub is_external_reference: Option<bool>,
     #[serde(rename = "isFinal")]
@@ -1143,8 +1145,8 @@ pub struct DataConsumerTypeElement {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     pub contacts: Option<Vec<ContactType>>,
 }
 
@@ -1178,8 +1180,8 @@ pub struct DataProviderSchemeTypeElement {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     #[serde(rename = "isExternalReference")]
     pub is_external_reference: Option<bool>,
     #[serde(rename = "isFinal")]
@@ -1215,8 +1217,8 @@ pub struct DataProviderTypeElement {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     pub contacts: Option<Vec<ContactType>>,
 }
 
@@ -1248,8 +1250,8 @@ pub struct DataStructureTypeElement {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     #[serde(rename = "isExternalReference")]
     pub is_external_reference: Option<bool>,
     #[serde(rename = "isFinal")]
@@ -1762,6 +1764,9 @@ pub struct MeasureListTypeClass {
     /// Links field is an array of link objects. Also used to specify the URI or the URN to
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
+    /// All measures of an SDMX 3.0 data structure, which may define more than one. Not part of
+    /// SDMX-JSON 1.0; set when converting SDMX-JSON 2.0 messages, see `structure_v2`.
+    pub measures: Option<Vec<PrimaryMeasureType>>,
 }
 
 /// PrimaryMeasure defines the structure of the primary measure, which is the concept that is
@@ -1826,8 +1831,8 @@ pub struct DataflowTypeElement {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     #[serde(rename = "isExternalReference")]
     pub is_external_reference: Option<bool>,
     #[serde(rename = "isFinal")]
@@ -1871,8 +1876,8 @@ pub struct HierarchicalCodelistTypeElement {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     #[serde(rename = "isExternalReference")]
     pub is_external_reference: Option<bool>,
     #[serde(rename = "isFinal")]
@@ -1912,8 +1917,8 @@ pub struct HierarchyTypeElement {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     #[serde(rename = "hierarchicalCodes")]
     pub hierarchical_codes: Vec<HierarchicalCodeTypeElement>,
     /// In a formally leveled hierarchy, Level describes a group of codes which are characterised
@@ -2003,8 +2008,8 @@ pub struct LevelTypeClass {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     /// CodingFormat specifies the text formatting of the codes in this level. This includes
     /// facets such as the expected characters and the length of the codes.
     #[serde(rename = "codingFormat")]
@@ -2071,8 +2076,8 @@ pub struct MetadataStructureTypeElement {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     #[serde(rename = "isExternalReference")]
     pub is_external_reference: Option<bool>,
     #[serde(rename = "isFinal")]
@@ -2446,8 +2451,8 @@ pub struct MetadataflowTypeElement {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     #[serde(rename = "isExternalReference")]
     pub is_external_reference: Option<bool>,
     #[serde(rename = "isFinal")]
@@ -2489,8 +2494,8 @@ pub struct OrganisationUnitSchemeTypeElement {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     #[serde(rename = "isExternalReference")]
     pub is_external_reference: Option<bool>,
     #[serde(rename = "isFinal")]
@@ -2528,8 +2533,8 @@ pub struct OrganisationUnitTypeElement {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     pub contacts: Option<Vec<ContactType>>,
     /// Urn reference to an organisation unit, where the reference to the organisation unit
     /// scheme which defines it is provided in another context.
@@ -2570,8 +2575,8 @@ pub struct ProcessTypeElement {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     #[serde(rename = "isExternalReference")]
     pub is_external_reference: Option<bool>,
     #[serde(rename = "isFinal")]
@@ -2606,8 +2611,8 @@ pub struct ProcessStepTypeElement {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     /// Computation describes the computations involved in the process, in any form desired by
     /// the user (these are informational rather than machine-actionable), and so may be supplied
     /// in multiple, parallel-language versions.
@@ -2628,7 +2633,7 @@ pub struct ProcessStepTypeElement {
 pub struct ComputationType {
     pub annotations: Option<Vec<AnnotationType>>,
     pub description: String,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
     #[serde(rename = "localID")]
     pub local_id: Option<String>,
     #[serde(rename = "softwareLanguage")]
@@ -2686,7 +2691,7 @@ pub struct TransitionTypeElement {
     /// Condition is a textual description of the conditions to be met in order for the target
     /// step to be proceeded to. It is informational only (not machine-actionable), and may be
     /// supplied in multiple, parallel-language form.
-    pub conditions: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub conditions: Option<LocalizedString>,
     #[serde(rename = "localID")]
     pub local_id: Option<String>,
 }
@@ -2719,8 +2724,8 @@ pub struct ProvisionAgreementTypeElement {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     #[serde(rename = "isExternalReference")]
     pub is_external_reference: Option<bool>,
     #[serde(rename = "isFinal")]
@@ -2767,8 +2772,8 @@ pub struct ReportingTaxonomyTypeElement {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     #[serde(rename = "isExternalReference")]
     pub is_external_reference: Option<bool>,
     #[serde(rename = "isFinal")]
@@ -2805,8 +2810,8 @@ pub struct ReportingCategoryTypeElement {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     #[serde(rename = "provisioningMetadata")]
     pub provisioning_metadata: Option<Vec<String>>,
     #[serde(rename = "reportingCategories")]
@@ -2843,8 +2848,8 @@ pub struct StructureSetTypeElement {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     #[serde(rename = "isExternalReference")]
     pub is_external_reference: Option<bool>,
     #[serde(rename = "isFinal")]
@@ -2892,8 +2897,8 @@ pub struct CategorySchemeMapTypeElement {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     #[serde(rename = "categoryMaps")]
     pub category_maps: Vec<CategoryMapType>,
     /// Urn reference to a category scheme object.
@@ -2939,8 +2944,8 @@ pub struct CodelistMapTypeElement {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     #[serde(rename = "codeMaps")]
     pub code_maps: Vec<CodeMapType>,
     /// Source provides a urn reference to a codelist.
@@ -2986,8 +2991,8 @@ pub struct ConceptSchemeMapTypeElement {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     #[serde(rename = "conceptMaps")]
     pub concept_maps: Vec<ConceptMapType>,
     /// Urn reference to a concept scheme object.
@@ -3031,8 +3036,8 @@ pub struct HybridCodelistMapTypeElement {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     #[serde(rename = "hybridCodeMaps")]
     pub hybrid_code_maps: Vec<HybridCodeMapType>,
     /// Source provides a urn reference to either a codelist or a hierarchical codelist, from
@@ -3083,8 +3088,8 @@ pub struct OrganisationSchemeMapTypeElement {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     #[serde(rename = "organisationMaps")]
     pub organisation_maps: Vec<OrganisationMapType>,
     /// Urn reference to an organisation scheme regardless of the specific type.
@@ -3129,8 +3134,8 @@ pub struct ReportingTaxonomyMapTypeElement {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     #[serde(rename = "reportingCategoryMaps")]
     pub reporting_category_maps: Vec<ReportingCategoryMapType>,
     /// Urn reference to a reporting taxonomy object.
@@ -3180,8 +3185,8 @@ pub struct StructureMapTypeElement {
     /// itself. If appropriate, a collection of links to additional external resources.
     pub links: Option<Vec<Link>>,
     pub description: Option<String>,
-    pub descriptions: Option<HashMap<String, Option<serde_json::Value>>>,
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub descriptions: Option<LocalizedString>,
+    pub names: Option<LocalizedString>,
     #[serde(rename = "componentMaps")]
     pub component_maps: Vec<ComponentMapType>,
     #[serde(rename = "isExtension")]
@@ -3324,7 +3329,7 @@ pub struct Error {
     /// human-readable localised explanations specific to this occurrence of the problem. Like
     /// title, this field’s value can be localized. It is fully customizable by the service
     /// providers and should provide enough detail to ease understanding the reasons of the error.
-    pub details: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub details: Option<LocalizedString>,
     /// Links field is an array of link objects. If appropriate, a collection of links to
     /// additional external resources for the error.
     pub links: Option<Vec<Link>>,
@@ -3335,7 +3340,7 @@ pub struct Error {
     /// Title contains the title of the message, in parallel language values. A list of short,
     /// human-readable localised summary of the problem that SHOULD NOT change from occurrence to
     /// occurrence of the problem, except for purposes of localization.
-    pub titles: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub titles: Option<LocalizedString>,
 }
 
 /// A meta object that contains non-standard meta-information and basic technical information
@@ -3358,7 +3363,7 @@ pub struct Meta {
     pub name: Option<String>,
     /// Name provides a name for the transmission. Multiple instances allow for parallel language
     /// values.
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub names: Option<LocalizedString>,
     /// A timestamp indicating when the message was prepared. Values must follow the ISO 8601
     /// syntax for combined dates and times, including time zone.
     pub prepared: String,
@@ -3388,7 +3393,7 @@ pub struct Party {
     /// Name is a human-readable name of the party.
     pub name: Option<String>,
     /// Name is a human-readable name of the party.
-    pub names: Option<HashMap<String, Option<serde_json::Value>>>,
+    pub names: Option<LocalizedString>,
 }
 
 /// OccurenceType is used to express the maximum occurrence of an object. It combines an
@@ -3622,7 +3627,7 @@ pub enum TargetObjectDataType {
 /// ObjectTypeCodelistType provides an enumeration of all objects outside of the base
 /// infomration model class. This includes some abstract object types such as Organsiation
 /// and Constraint.
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ObjectTypeCodelistType {
     Agency,
     AgencyScheme,
@@ -0,0 +1,697 @@
+//! structure_v2 converts SDMX-JSON 2.0 structure messages, as used by
+//! SDMX 3.0, into the SDMX-JSON 1.0 model of `structure`, so that the rest
+//! of the crate handles messages from SDMX 2.1 and SDMX 3.0 endpoints alike.
+//!
+//! The conversion works on the JSON value before it is deserialized:
+//!
+//! - `isFinal` is derived from the version, as SDMX 3.0 marks unstable
+//!   versions with an extension like `1.0.0-draft`
+//! - components get their 2.1 form: the time dimension goes into
+//!   `timeDimensions`, `format` becomes `textFormat`, `usage` becomes
+//!   `assignmentStatus`, and attribute relationships to the dataflow or the
+//!   observation become `none` and `primaryMeasure` relationships
+//! - of several measures, `OBS_VALUE` or else the first becomes the primary
+//!   measure, while all of them are kept in `measures`
+//! - `valueLists` become codelists, `hierarchies` hierarchical codelists
+//!   with a single hierarchy, `dataConstraints` content constraints and
+//!   `metadataProvisionAgreements` provision agreements, with URNs rewritten
+//!   to the 2.1 classes
+//!
+//! Metadata structures and metadata constraints have no 2.1 counterpart the
+//! crate uses, so they are dropped.
+
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+use crate::components::OBS_VALUE;
+
+/// The version of the SDMX-JSON structure message schema
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaVersion {
+    /// SDMX-JSON 1.0, used by SDMX 2.1
+    V1,
+    /// SDMX-JSON 2.0, used by SDMX 3.0
+    V2,
+}
+
+impl SchemaVersion {
+    /// Reads the version parameter of a content type like
+    /// `application/vnd.sdmx.structure+json; version=2.0.0`
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let version = content_type.split(';').find_map(|p| {
+            p.trim()
+                .strip_prefix("version=")
+                .map(|v| v.trim_matches('"'))
+        })?;
+        match version.split('.').next() {
+            Some("1") => Some(SchemaVersion::V1),
+            Some("2") => Some(SchemaVersion::V2),
+            _ => None,
+        }
+    }
+
+    /// Detects the version of a message from the schema given in its `meta`,
+    /// like `https://json.sdmx.org/2.0.0/sdmx-json-structure-schema.json`,
+    /// or else from content which only exists in SDMX-JSON 2.0
+    pub fn detect(message: &Value) -> Self {
+        let schema = message.pointer("/meta/schema").and_then(|s| s.as_str());
+        if let Some(schema) = schema {
+            if schema.contains("/2.0") {
+                return SchemaVersion::V2;
+            }
+            if schema.contains("/1.0") {
+                return SchemaVersion::V1;
+            }
+        }
+        let data = match message.get("data") {
+            Some(d) => d,
+            None => return SchemaVersion::V1,
+        };
+        let v2_only = [
+            "dataConstraints",
+            "valueLists",
+            "hierarchies",
+            "metadataProvisionAgreements",
+        ];
+        let time_dimension = |dsd: &Value| {
+            dsd.pointer("/dataStructureComponents/dimensionList/timeDimension")
+                .is_some()
+        };
+        let is_v2 = v2_only.iter().any(|k| data.get(k).is_some())
+            || items(data, "dataStructures").any(time_dimension);
+        match is_v2 {
+            true => SchemaVersion::V2,
+            false => SchemaVersion::V1,
+        }
+    }
+}
+
+fn items<'a>(data: &'a Value, key: &str) -> impl Iterator<Item = &'a Value> {
+    data.get(key)
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+}
+
+fn items_mut<'a>(
+    data: &'a mut Value,
+    key: &str,
+) -> impl Iterator<Item = &'a mut Value> {
+    data.get_mut(key)
+        .and_then(|v| v.as_array_mut())
+        .into_iter()
+        .flatten()
+}
+
+/// Removes a list of artefacts from the message
+fn take_items(data: &mut Map<String, Value>, key: &str) -> Vec<Value> {
+    match data.remove(key) {
+        Some(Value::Array(items)) => items,
+        _ => vec![],
+    }
+}
+
+/// Appends artefacts to a list of the message, creating it if needed
+fn append_items(data: &mut Map<String, Value>, key: &str, new: Vec<Value>) {
+    if new.is_empty() {
+        return;
+    }
+    let list = data.entry(key).or_insert_with(|| Value::Array(vec![]));
+    if let Value::Array(list) = list {
+        list.extend(new);
+    }
+}
+
+/// Converts the `data` of an SDMX-JSON 2.0 message in place
+pub fn convert_data(data: &mut Value) {
+    let object = match data.as_object_mut() {
+        Some(o) => o,
+        None => return,
+    };
+    for key in [
+        "metadataStructures",
+        "metadataConstraints",
+        "hierarchyAssociations",
+    ] {
+        object.remove(key);
+    }
+
+    let value_lists = take_items(object, "valueLists")
+        .into_iter()
+        .map(|mut vl| {
+            rename(&mut vl, "valueItems", "codes");
+            vl
+        })
+        .collect();
+    append_items(object, "codelists", value_lists);
+
+    let hierarchies = take_items(object, "hierarchies")
+        .into_iter()
+        .map(hierarchical_codelist)
+        .collect();
+    append_items(object, "hierarchicalCodelists", hierarchies);
+
+    let constraints = take_items(object, "dataConstraints")
+        .into_iter()
+        .map(content_constraint)
+        .collect();
+    append_items(object, "contentConstraints", constraints);
+
+    let agreements = take_items(object, "metadataProvisionAgreements")
+        .into_iter()
+        .map(|mut pa| {
+            rename(&mut pa, "metadataflow", "structureUsage");
+            rename(&mut pa, "metadataProvider", "dataProvider");
+            pa
+        })
+        .collect();
+    append_items(object, "provisionAgreements", agreements);
+
+    for dsd in items_mut(data, "dataStructures") {
+        convert_data_structure(dsd);
+    }
+    for scheme in items_mut(data, "conceptSchemes") {
+        for concept in items_mut(scheme, "concepts") {
+            if let Some(r) = concept.get_mut("coreRepresentation") {
+                convert_representation(r);
+            }
+        }
+    }
+    for codelist in items_mut(data, "codelists") {
+        if let Some(o) = codelist.as_object_mut() {
+            o.remove("codelistExtensions");
+        }
+    }
+    if let Some(object) = data.as_object_mut() {
+        for list in object.values_mut() {
+            for artefact in list.as_array_mut().into_iter().flatten() {
+                set_final(artefact);
+            }
+        }
+    }
+    rewrite_urns(data);
+}
+
+fn rename(value: &mut Value, from: &str, to: &str) {
+    if let Some(o) = value.as_object_mut() {
+        if let Some(v) = o.remove(from) {
+            o.insert(to.to_string(), v);
+        }
+    }
+}
+
+/// Stable versions, without an extension like `-draft`, are final
+fn set_final(artefact: &mut Value) {
+    let object = match artefact.as_object_mut() {
+        Some(o) if !o.contains_key("isFinal") => o,
+        _ => return,
+    };
+    if let Some(version) = object.get("version").and_then(|v| v.as_str()) {
+        let stable = !version.contains('-');
+        object.insert("isFinal".to_string(), Value::Bool(stable));
+    }
+}
+
+/// An SDMX 3.0 hierarchy is a maintainable artefact of its own, which
+/// becomes a hierarchical codelist with one hierarchy of the same ID
+fn hierarchical_codelist(mut hierarchy: Value) -> Value {
+    let object = match hierarchy.as_object_mut() {
+        Some(o) => o,
+        None => return hierarchy,
+    };
+    let mut inner = Map::new();
+    for key in ["id", "name", "names", "description", "descriptions"] {
+        if let Some(v) = object.get(key) {
+            inner.insert(key.to_string(), v.clone());
+        }
+    }
+    for key in ["hierarchicalCodes", "level"] {
+        if let Some(v) = object.remove(key) {
+            inner.insert(key.to_string(), v);
+        }
+    }
+    if let Some(leveled) = object.remove("hasFormalLevels") {
+        inner.insert("leveled".to_string(), leveled);
+    }
+    inner
+        .entry("hierarchicalCodes")
+        .or_insert_with(|| Value::Array(vec![]));
+    object.insert("hierarchies".to_string(), json!([inner]));
+    hierarchy
+}
+
+fn content_constraint(mut constraint: Value) -> Value {
+    if let Some(role) =
+        constraint.as_object_mut().and_then(|o| o.remove("role"))
+    {
+        // Roles are lowercase in some messages, while the 2.1 type is not
+        let role = match role.as_str().map(|r| r.to_ascii_lowercase()) {
+            Some(r) if r == "allowed" => "Allowed",
+            _ => "Actual",
+        };
+        constraint["type"] = Value::String(role.to_string());
+    }
+    let regions = match constraint.get_mut("cubeRegions") {
+        Some(Value::Array(regions)) => std::mem::take(regions),
+        _ => return constraint,
+    };
+    let mut converted = vec![];
+    for region in regions {
+        converted.extend(cube_regions(region));
+    }
+    constraint["cubeRegions"] = Value::Array(converted);
+    constraint
+}
+
+/// Converts a cube region. Values excluded from an included region, which
+/// 2.1 cannot express, go into an extra excluded region.
+fn cube_regions(mut region: Value) -> Vec<Value> {
+    rename(&mut region, "include", "isIncluded");
+    rename(&mut region, "components", "attributes");
+    let included = region
+        .get("isIncluded")
+        .and_then(|i| i.as_bool())
+        .unwrap_or(true);
+    let mut excluded = json!({
+        "isIncluded": false,
+        "keyValues": [],
+        "attributes": [],
+    });
+    for key in ["keyValues", "attributes"] {
+        let selections = match region.get_mut(key) {
+            Some(Value::Array(s)) => std::mem::take(s),
+            _ => continue,
+        };
+        let mut kept = vec![];
+        for mut selection in selections {
+            let object = match selection.as_object_mut() {
+                Some(o) => o,
+                None => continue,
+            };
+            let include = object.remove("include").and_then(|i| i.as_bool());
+            for k in ["removePrefix", "validFrom", "validTo"] {
+                object.remove(k);
+            }
+            // Values are either plain strings or objects with a value
+            if let Some(Value::Array(values)) = object.get_mut("values") {
+                for v in values.iter_mut() {
+                    if let Some(value) = v.get("value").cloned() {
+                        *v = value;
+                    }
+                }
+            }
+            match (included, include) {
+                (true, Some(false)) => {
+                    excluded[key].as_array_mut().unwrap().push(selection)
+                }
+                _ => kept.push(selection),
+            }
+        }
+        region[key] = Value::Array(kept);
+    }
+    let split = ["keyValues", "attributes"]
+        .iter()
+        .any(|k| excluded[*k].as_array().is_some_and(|a| !a.is_empty()));
+    match split {
+        true => vec![region, excluded],
+        false => vec![region],
+    }
+}
+
+fn convert_data_structure(dsd: &mut Value) {
+    if let Some(o) = dsd.as_object_mut() {
+        o.remove("metadata");
+        o.remove("evolvingStructure");
+    }
+    let components = match dsd.get_mut("dataStructureComponents") {
+        Some(c) => c,
+        None => return,
+    };
+
+    if let Some(list) = components.get_mut("dimensionList") {
+        if let Some(time) =
+            list.as_object_mut().and_then(|l| l.remove("timeDimension"))
+        {
+            list["timeDimensions"] = json!([time]);
+        }
+        for d in items_mut(list, "dimensions") {
+            convert_component(d);
+        }
+        for d in items_mut(list, "timeDimensions") {
+            convert_component(d);
+            // Required in SDMX-JSON 1.0
+            if let Some(o) = d.as_object_mut() {
+                let repr =
+                    o.entry("localRepresentation").or_insert_with(|| json!({}));
+                if let Some(repr) = repr.as_object_mut() {
+                    repr.entry("textFormat").or_insert_with(|| json!({}));
+                }
+            }
+        }
+    }
+
+    let primary = convert_measures(components);
+
+    if let Some(list) = components.get_mut("attributeList") {
+        if let Some(o) = list.as_object_mut() {
+            o.remove("metadataAttributeUsages");
+        }
+        for a in items_mut(list, "attributes") {
+            convert_component(a);
+            convert_attribute(a, &primary);
+        }
+    }
+}
+
+/// Moves the measures into the 2.1 primary measure, returning its ID
+fn convert_measures(components: &mut Value) -> String {
+    let list = match components.get_mut("measureList") {
+        Some(l) => l,
+        None => return OBS_VALUE.to_string(),
+    };
+    let mut measures = match list.get("measures") {
+        Some(Value::Array(m)) => m.clone(),
+        _ => return OBS_VALUE.to_string(),
+    };
+    for m in &mut measures {
+        convert_component(m);
+        if let Some(o) = m.as_object_mut() {
+            o.remove("usage");
+        }
+    }
+    let id =
+        |m: &Value| m.get("id").and_then(|id| id.as_str()).map(String::from);
+    let primary = measures
+        .iter()
+        .find(|m| id(m).as_deref() == Some(OBS_VALUE))
+        .or_else(|| measures.first())
+        .cloned();
+    if let Some(primary) = &primary {
+        list["primaryMeasure"] = primary.clone();
+    }
+    list["measures"] = Value::Array(measures);
+    primary
+        .as_ref()
+        .and_then(id)
+        .unwrap_or_else(|| OBS_VALUE.to_string())
+}
+
+fn convert_component(component: &mut Value) {
+    if let Some(repr) = component.get_mut("localRepresentation") {
+        convert_representation(repr);
+    }
+}
+
+fn convert_representation(repr: &mut Value) {
+    if let Some(o) = repr.as_object_mut() {
+        for key in ["minOccurs", "maxOccurs", "enumerationFormat"] {
+            o.remove(key);
+        }
+    }
+    rename(repr, "format", "textFormat");
+    if let Some(format) = repr.get_mut("textFormat") {
+        rename(format, "dataType", "textType");
+        if let Some(o) = format.as_object_mut() {
+            o.remove("isMultiLingual");
+        }
+    }
+}
+
+fn convert_attribute(attribute: &mut Value, primary_measure: &str) {
+    let object = match attribute.as_object_mut() {
+        Some(o) => o,
+        None => return,
+    };
+    let usage = object.remove("usage");
+    let mandatory = usage
+        .as_ref()
+        .and_then(|u| u.as_str())
+        .is_some_and(|u| u.eq_ignore_ascii_case("mandatory"));
+    let status = match mandatory {
+        true => "Mandatory",
+        false => "Conditional",
+    };
+    object
+        .entry("assignmentStatus")
+        .or_insert_with(|| Value::String(status.to_string()));
+
+    let measures = object.remove("measureRelationship");
+    let relationship = object
+        .entry("attributeRelationship")
+        .or_insert_with(|| json!({}));
+    let relationship = match relationship.as_object_mut() {
+        Some(r) => r,
+        None => return,
+    };
+    if relationship.remove("dataflow").is_some() {
+        relationship.insert("none".to_string(), json!({}));
+    }
+    if relationship.remove("observation").is_some() || measures.is_some() {
+        relationship.insert(
+            "primaryMeasure".to_string(),
+            Value::String(primary_measure.to_string()),
+        );
+    }
+    if relationship.is_empty() {
+        relationship.insert("none".to_string(), json!({}));
+    }
+}
+
+/// Rewrites URNs of SDMX 3.0 classes to the classes they are converted to
+fn rewrite_urns(value: &mut Value) {
+    const CLASSES: [(&str, &str); 3] = [
+        ("codelist.ValueList=", "codelist.Codelist="),
+        ("codelist.Hierarchy=", "codelist.HierarchicalCodelist="),
+        ("registry.DataConstraint=", "registry.ContentConstraint="),
+    ];
+    match value {
+        Value::String(s) if s.starts_with("urn:sdmx:") => {
+            for (from, to) in &CLASSES {
+                if s.contains(from) {
+                    *s = s.replacen(from, to, 1);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(rewrite_urns),
+        Value::Object(o) => o.values_mut().for_each(rewrite_urns),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        artefact::Maintainable,
+        parse::{parse_structure_value, ParseMode},
+        structure::{ContentConstraintTypeCodeType, Data, UsageStatusType},
+    };
+
+    fn concept(id: &str) -> String {
+        format!(
+            "urn:sdmx:org.sdmx.infomodel.conceptscheme.Concept=A:CS(1.0.0).{}",
+            id
+        )
+    }
+
+    /// An SDMX-JSON 2.0 message as returned by an SDMX 3.0 endpoint
+    fn message() -> Value {
+        json!({
+            "meta": {
+                "id": "IREF1", "prepared": "2024-01-01T00:00:00Z",
+                "sender": { "id": "A" },
+                "schema": "https://json.sdmx.org/2.0.0/sdmx-json-structure-schema.json"
+            },
+            "data": {
+                "dataStructures": [{
+                    "id": "DSD", "agencyID": "A", "version": "1.0.0-draft",
+                    "name": "DSD",
+                    "dataStructureComponents": {
+                        "dimensionList": {
+                            "dimensions": [{
+                                "id": "FREQ", "position": 0,
+                                "conceptIdentity": concept("FREQ"),
+                                "localRepresentation": {
+                                    "enumeration": "urn:sdmx:org.sdmx.infomodel.codelist.ValueList=A:VL_FREQ(1.0.0)"
+                                }
+                            }],
+                            "timeDimension": {
+                                "id": "TIME_PERIOD",
+                                "conceptIdentity": concept("TIME_PERIOD"),
+                                "localRepresentation": {
+                                    "format": { "dataType": "ObservationalTimePeriod" }
+                                }
+                            }
+                        },
+                        "attributeList": {
+                            "attributes": [{
+                                "id": "OBS_STATUS", "usage": "mandatory",
+                                "conceptIdentity": concept("OBS_STATUS"),
+                                "attributeRelationship": { "observation": {} }
+                            }, {
+                                "id": "UNIT", "usage": "optional",
+                                "conceptIdentity": concept("UNIT"),
+                                "attributeRelationship": { "dataflow": {} }
+                            }]
+                        },
+                        "measureList": {
+                            "id": "MeasureDescriptor",
+                            "measures": [{
+                                "id": "PRICE", "usage": "optional",
+                                "conceptIdentity": concept("PRICE"),
+                                "localRepresentation": {
+                                    "format": { "dataType": "Double" }
+                                }
+                            }, {
+                                "id": "OBS_VALUE",
+                                "conceptIdentity": concept("OBS_VALUE")
+                            }]
+                        }
+                    }
+                }],
+                "valueLists": [{
+                    "id": "VL_FREQ", "agencyID": "A", "version": "1.0.0",
+                    "name": "Frequency",
+                    "valueItems": [
+                        { "id": "A", "name": "Annual" },
+                        { "id": "M", "name": "Monthly" }
+                    ]
+                }],
+                "dataConstraints": [{
+                    "id": "DC", "agencyID": "A", "version": "2.1.0",
+                    "name": "Allowed", "role": "allowed",
+                    "constraintAttachment": {
+                        "dataStructures": [
+                            "urn:sdmx:org.sdmx.infomodel.datastructure.DataStructure=A:DSD(1.0.0-draft)"
+                        ]
+                    },
+                    "cubeRegions": [{
+                        "include": true,
+                        "keyValues": [
+                            { "id": "FREQ", "values": [{ "value": "A" }] },
+                            { "id": "REF_AREA", "include": false, "values": ["XX"] }
+                        ]
+                    }]
+                }]
+            }
+        })
+    }
+
+    #[test]
+    fn versions_are_detected_from_the_schema_or_the_content() {
+        assert_eq!(SchemaVersion::detect(&message()), SchemaVersion::V2);
+
+        let mut v1 = message();
+        v1["meta"]["schema"] =
+            json!("https://json.sdmx.org/1.0/sdmx-json-structure-schema.json");
+        assert_eq!(SchemaVersion::detect(&v1), SchemaVersion::V1);
+
+        // Without a schema, content only SDMX-JSON 2.0 has gives it away
+        let mut content = message();
+        content.as_object_mut().unwrap().remove("meta");
+        assert_eq!(SchemaVersion::detect(&content), SchemaVersion::V2);
+        let dataflows = json!({ "data": { "dataflows": [] } });
+        assert_eq!(SchemaVersion::detect(&dataflows), SchemaVersion::V1);
+        assert_eq!(SchemaVersion::detect(&json!({})), SchemaVersion::V1);
+
+        let content_type = |ct: &str| SchemaVersion::from_content_type(ct);
+        assert_eq!(
+            content_type("application/vnd.sdmx.structure+json; version=2.0.0"),
+            Some(SchemaVersion::V2)
+        );
+        assert_eq!(
+            content_type("application/vnd.sdmx.structure+json;version=\"1.0\""),
+            Some(SchemaVersion::V1)
+        );
+        assert_eq!(content_type("application/json"), None);
+    }
+
+    #[test]
+    fn malformed_components_do_not_stop_the_conversion() {
+        let list =
+            "/data/dataStructures/0/dataStructureComponents/dimensionList";
+        let malformed = [
+            json!("TIME_PERIOD"),
+            json!({
+                "id": "TIME_PERIOD",
+                "conceptIdentity": concept("TIME_PERIOD"),
+                "localRepresentation": "ObservationalTimePeriod"
+            }),
+        ];
+        for time in malformed {
+            let mut message = message();
+            message.pointer_mut(list).unwrap()["timeDimension"] = time.clone();
+            let parsed =
+                parse_structure_value(message, None, ParseMode::Lenient)
+                    .unwrap();
+            let data = parsed.value.data.unwrap();
+            assert_eq!(data.codelists.unwrap().len(), 1, "{}", time);
+        }
+    }
+
+    #[test]
+    fn messages_are_converted_to_the_internal_model() {
+        let parsed =
+            parse_structure_value(message(), None, ParseMode::Strict).unwrap();
+        assert_eq!(parsed.version, SchemaVersion::V2);
+        let data: Data = parsed.value.data.unwrap();
+
+        // value lists become codelists, with URNs rewritten to match
+        let codelists = data.codelists.as_ref().unwrap();
+        assert_eq!(codelists[0].id, "VL_FREQ");
+        assert_eq!(codelists[0].codes.as_ref().unwrap().len(), 2);
+        assert!(codelists[0].is_final());
+
+        // semantic versions with an extension are not final
+        let dsd = &data.data_structures.as_ref().unwrap()[0];
+        assert!(!dsd.is_final());
+        assert_eq!(
+            dsd.parsed_version().unwrap().extension.as_deref(),
+            Some("draft")
+        );
+        let dimensions: Vec<_> =
+            dsd.dimensions().into_iter().map(|d| d.id).collect();
+        assert_eq!(dimensions, vec!["FREQ", "TIME_PERIOD"]);
+        assert_eq!(
+            dsd.dimensions()[0].enumeration,
+            Some("urn:sdmx:org.sdmx.infomodel.codelist.Codelist=A:VL_FREQ(1.0.0)")
+        );
+
+        // OBS_VALUE is the primary measure, and both measures are kept
+        let measures: Vec<_> =
+            dsd.measures().into_iter().map(|m| m.id).collect();
+        assert_eq!(measures, vec!["OBS_VALUE", "PRICE"]);
+
+        let attributes = dsd.attributes();
+        assert!(matches!(
+            attributes[0].assignment_status,
+            Some(UsageStatusType::Mandatory)
+        ));
+        let relationship = attributes[0].attribute_relationship.unwrap();
+        assert_eq!(relationship.primary_measure.as_deref(), Some("OBS_VALUE"));
+        assert!(attributes[1].attribute_relationship.unwrap().none.is_some());
+
+        // data constraints become content constraints, and values excluded
+        // from an included region get a region of their own
+        let constraint = &data.content_constraints.as_ref().unwrap()[0];
+        assert!(matches!(
+            constraint.type_type,
+            Some(ContentConstraintTypeCodeType::Allowed)
+        ));
+        let regions = constraint.cube_regions.as_ref().unwrap();
+        assert_eq!(regions.len(), 2);
+        let values = |region: usize| {
+            let key_values = regions[region].key_values.as_ref().unwrap();
+            (
+                regions[region].is_included,
+                key_values[0].id.clone(),
+                key_values[0].values.clone().unwrap(),
+            )
+        };
+        assert_eq!(values(0), (Some(true), "FREQ".into(), vec!["A".into()]));
+        assert_eq!(
+            values(1),
+            (Some(false), "REF_AREA".into(), vec!["XX".into()])
+        );
+    }
+}
@@ -0,0 +1,500 @@
+//! urn parses and formats SDMX Registry URNs, e.g.
+//! `urn:sdmx:org.sdmx.infomodel.datastructure.DataStructure=ECB:EXR(1.0)`
+//!
+//! The general form is `urn:sdmx:org.sdmx.infomodel.{package}.{class}={agencyID}:{id}({version})`
+//! followed by an optional, dot separated path of item IDs for objects that live
+//! inside a maintainable artefact, such as codes in a codelist.
+
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    queries::{RestVersion, StructureQuery},
+    structure::ObjectTypeCodelistType,
+    version::VersionReq,
+};
+
+const URN_PREFIX: &str = "urn:sdmx:org.sdmx.infomodel.";
+
+/// A parsed SDMX URN
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Urn {
+    pub class: ObjectTypeCodelistType,
+    pub agency_id: String,
+    /// ID of the maintainable artefact
+    pub id: String,
+    /// Version of the maintainable artefact. This may contain wildcards
+    pub version: VersionReq,
+    /// Nested IDs of the item within the maintainable artefact, if any. Most
+    /// items only have one ID, but categories, reporting categories and
+    /// process steps may be nested.
+    pub item_ids: Vec<String>,
+}
+
+impl Urn {
+    pub fn new<S: ToString>(
+        class: ObjectTypeCodelistType,
+        agency_id: S,
+        id: S,
+        version: VersionReq,
+    ) -> Self {
+        Urn {
+            class,
+            agency_id: agency_id.to_string(),
+            id: id.to_string(),
+            version,
+            item_ids: vec![],
+        }
+    }
+
+    /// Creates the URN of an item within this artefact, e.g. a code within a codelist
+    pub fn item<S: ToString>(
+        &self,
+        class: ObjectTypeCodelistType,
+        item_id: S,
+    ) -> Self {
+        let mut out = self.clone();
+        out.class = class;
+        out.item_ids.push(item_id.to_string());
+        out
+    }
+
+    /// Whether this URN references an item within a maintainable artefact
+    pub fn is_item(&self) -> bool {
+        !self.item_ids.is_empty()
+    }
+
+    /// The dot separated item ID, e.g. `A.B` for a nested category
+    pub fn item_id(&self) -> Option<String> {
+        match self.is_item() {
+            true => Some(self.item_ids.join(".")),
+            false => None,
+        }
+    }
+
+    /// The information model package of the referenced class
+    pub fn package(&self) -> Option<&'static str> {
+        package(self.class)
+    }
+
+    /// The URN of the maintainable artefact which contains the referenced object.
+    /// For maintainable artefacts this is the URN itself.
+    pub fn maintainable(&self) -> Urn {
+        Urn {
+            class: maintainable_class(self.class).unwrap_or(self.class),
+            agency_id: self.agency_id.clone(),
+            id: self.id.clone(),
+            version: self.version.clone(),
+            item_ids: vec![],
+        }
+    }
+
+    /// Generates the SDMX 2.1 REST query (relative to the endpoint base URL)
+    /// that returns the referenced artefact. Items are requested with the
+    /// itemID path segment where the REST API supports it, otherwise the
+    /// whole maintainable artefact is requested.
+    pub fn rest_query(&self) -> Result<String> {
+        self.rest_query_for(RestVersion::V2_1)
+    }
+
+    /// Like [`Urn::rest_query`], in the grammar of the given REST API version
+    pub fn rest_query_for(&self, rest: RestVersion) -> Result<String> {
+        let maintainable = maintainable_class(self.class)
+            .ok_or_else(|| anyhow!("No maintainable artefact for {}", self))?;
+        let resource = rest_resource(maintainable).ok_or_else(|| {
+            anyhow!("No REST resource for {}", class_name(maintainable))
+        })?;
+
+        let mut query = StructureQuery::new(resource)
+            .with_agency(&self.agency_id)
+            .with_id(&self.id)
+            .with_version(self.version.clone());
+        if let Some(item_id) = self.item_id() {
+            if supports_item_query(maintainable) {
+                query = query.with_item(item_id);
+            }
+        }
+        query.path(rest)
+    }
+}
+
+impl fmt::Display for Urn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}.{}={}:{}({})",
+            URN_PREFIX,
+            self.package().unwrap_or("base"),
+            class_name(self.class),
+            self.agency_id,
+            self.id,
+            self.version
+        )?;
+        for item_id in &self.item_ids {
+            write!(f, ".{}", item_id)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Urn {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let rest = s
+            .trim()
+            .strip_prefix(URN_PREFIX)
+            .ok_or_else(|| anyhow!("Not an SDMX URN: {}", s))?;
+
+        let (qualified_class, reference) = rest
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Missing '=' in URN {}", s))?;
+        let (package_name, name) = qualified_class
+            .rsplit_once('.')
+            .ok_or_else(|| anyhow!("Missing package in URN {}", s))?;
+        let class = parse_class(name)?;
+        if package(class) != Some(package_name) {
+            return Err(anyhow!(
+                "Class {} does not belong to package {} in URN {}",
+                name,
+                package_name,
+                s
+            ));
+        }
+
+        let (agency_id, reference) = reference
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Missing agency ID in URN {}", s))?;
+        let (id, reference) = reference
+            .split_once('(')
+            .ok_or_else(|| anyhow!("Missing version in URN {}", s))?;
+        let (version, items) = reference
+            .split_once(')')
+            .ok_or_else(|| anyhow!("Unterminated version in URN {}", s))?;
+
+        let item_ids = match items {
+            "" => vec![],
+            _ => items
+                .strip_prefix('.')
+                .ok_or_else(|| anyhow!("Invalid item path in URN {}", s))?
+                .split('.')
+                .map(|i| i.to_string())
+                .collect(),
+        };
+
+        if agency_id.is_empty()
+            || id.is_empty()
+            || item_ids.iter().any(|i: &String| i.is_empty())
+        {
+            return Err(anyhow!("Empty identifier in URN {}", s));
+        }
+
+        Ok(Urn {
+            class,
+            agency_id: agency_id.to_string(),
+            id: id.to_string(),
+            version: version.parse()?,
+            item_ids,
+        })
+    }
+}
+
+/// The name of the class as used in URNs, e.g. `DataStructure`
+pub fn class_name(class: ObjectTypeCodelistType) -> String {
+    // The serde representation of ObjectTypeCodelistType is the SDMX class name
+    match serde_json::to_value(class) {
+        Ok(serde_json::Value::String(s)) => s,
+        _ => format!("{:?}", class),
+    }
+}
+
+/// Parses an SDMX class name, e.g. `Codelist`
+pub fn parse_class(name: &str) -> Result<ObjectTypeCodelistType> {
+    serde_json::from_value(serde_json::Value::String(name.to_string()))
+        .map_err(|_| anyhow!("Unknown SDMX class {}", name))
+}
+
+/// The information model package a class belongs to, e.g. `codelist` for `Code`.
+/// Returns `None` for `Any`, which is only used as a wildcard.
+pub fn package(class: ObjectTypeCodelistType) -> Option<&'static str> {
+    use ObjectTypeCodelistType::*;
+    Some(match class {
+        Any => return None,
+        Agency
+        | AgencyScheme
+        | DataConsumer
+        | DataConsumerScheme
+        | DataProvider
+        | DataProviderScheme
+        | Organisation
+        | OrganisationScheme
+        | OrganisationUnit
+        | OrganisationUnitScheme => "base",
+        Attribute
+        | AttributeDescriptor
+        | DataStructure
+        | Dataflow
+        | Dimension
+        | DimensionDescriptor
+        | GroupDimensionDescriptor
+        | MeasureDescriptor
+        | MeasureDimension
+        | PrimaryMeasure
+        | ReportingYearStartDay
+        | TimeDimension => "datastructure",
+        ConstraintTarget
+        | DataSetTarget
+        | DimensionDescriptorValuesTarget
+        | IdentifiableObjectTarget
+        | MetadataAttribute
+        | MetadataSet
+        | MetadataStructure
+        | MetadataTarget
+        | Metadataflow
+        | ReportPeriodTarget
+        | ReportStructure => "metadatastructure",
+        Code | Codelist | HierarchicalCode | HierarchicalCodelist
+        | Hierarchy | Level => "codelist",
+        Concept | ConceptScheme => "conceptscheme",
+        Categorisation | Category | CategoryScheme | ReportingCategory
+        | ReportingTaxonomy => "categoryscheme",
+        AttachmentConstraint | Constraint | ContentConstraint
+        | ProvisionAgreement => "registry",
+        CategorySchemeMap
+        | CodeMap
+        | CodelistMap
+        | ComponentMap
+        | ConceptMap
+        | ConceptSchemeMap
+        | HybridCodeMap
+        | HybridCodelistMap
+        | OrganisationMap
+        | OrganisationSchemeMap
+        | ReportingCategoryMap
+        | ReportingTaxonomyMap
+        | StructureMap
+        | StructureSet => "mapping",
+        Process | ProcessStep | Transition => "process",
+    })
+}
+
+/// The class of the maintainable artefact which contains objects of the given class.
+/// Maintainable classes return themselves.
+pub fn maintainable_class(
+    class: ObjectTypeCodelistType,
+) -> Option<ObjectTypeCodelistType> {
+    use ObjectTypeCodelistType::*;
+    Some(match class {
+        Any => return None,
+        Agency => AgencyScheme,
+        DataConsumer => DataConsumerScheme,
+        DataProvider => DataProviderScheme,
+        Organisation => OrganisationScheme,
+        OrganisationUnit => OrganisationUnitScheme,
+        Attribute
+        | AttributeDescriptor
+        | Dimension
+        | DimensionDescriptor
+        | GroupDimensionDescriptor
+        | MeasureDescriptor
+        | MeasureDimension
+        | PrimaryMeasure
+        | ReportingYearStartDay
+        | TimeDimension => DataStructure,
+        ConstraintTarget
+        | DataSetTarget
+        | DimensionDescriptorValuesTarget
+        | IdentifiableObjectTarget
+        | MetadataAttribute
+        | MetadataTarget
+        | ReportPeriodTarget
+        | ReportStructure => MetadataStructure,
+        Code => Codelist,
+        HierarchicalCode | Hierarchy | Level => HierarchicalCodelist,
+        Concept => ConceptScheme,
+        Category => CategoryScheme,
+        ReportingCategory => ReportingTaxonomy,
+        CategorySchemeMap
+        | CodeMap
+        | CodelistMap
+        | ComponentMap
+        | ConceptMap
+        | ConceptSchemeMap
+        | HybridCodeMap
+        | HybridCodelistMap
+        | OrganisationMap
+        | OrganisationSchemeMap
+        | ReportingCategoryMap
+        | ReportingTaxonomyMap
+        | StructureMap => StructureSet,
+        ProcessStep | Transition => Process,
+        maintainable => maintainable,
+    })
+}
+
+/// The SDMX 2.1 REST resource used to query a maintainable class, e.g. `codelist`
+pub fn rest_resource(class: ObjectTypeCodelistType) -> Option<&'static str> {
+    use ObjectTypeCodelistType::*;
+    Some(match class {
+        AgencyScheme => "agencyscheme",
+        AttachmentConstraint => "attachmentconstraint",
+        Categorisation => "categorisation",
+        CategoryScheme => "categoryscheme",
+        Codelist => "codelist",
+        ConceptScheme => "conceptscheme",
+        ContentConstraint => "contentconstraint",
+        DataConsumerScheme => "dataconsumerscheme",
+        DataProviderScheme => "dataproviderscheme",
+        DataStructure => "datastructure",
+        Dataflow => "dataflow",
+        HierarchicalCodelist => "hierarchicalcodelist",
+        MetadataStructure => "metadatastructure",
+        Metadataflow => "metadataflow",
+        OrganisationScheme => "organisationscheme",
+        OrganisationUnitScheme => "organisationunitscheme",
+        Process => "process",
+        ProvisionAgreement => "provisionagreement",
+        ReportingTaxonomy => "reportingtaxonomy",
+        StructureSet => "structureset",
+        _ => return None,
+    })
+}
+
+/// Whether the REST resource for a maintainable class accepts the itemID path segment
+fn supports_item_query(class: ObjectTypeCodelistType) -> bool {
+    use ObjectTypeCodelistType::*;
+    matches!(
+        class,
+        AgencyScheme
+            | CategoryScheme
+            | Codelist
+            | ConceptScheme
+            | DataConsumerScheme
+            | DataProviderScheme
+            | OrganisationUnitScheme
+            | ReportingTaxonomy
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PREFIX: &str = "urn:sdmx:org.sdmx.infomodel";
+
+    /// A maintainable artefact and an item of each package
+    const ROUND_TRIPS: &[&str] = &[
+        "base.AgencyScheme=SDMX:AGENCIES(1.0)",
+        "base.Agency=SDMX:AGENCIES(1.0).ECB",
+        "base.DataProvider=ECB:DATA_PROVIDERS(1.0).BE2",
+        "datastructure.DataStructure=ECB:ECB_EXR1(1.0)",
+        "datastructure.Dataflow=ECB:EXR(1.0)",
+        "datastructure.Dimension=ECB:ECB_EXR1(1.0).FREQ",
+        "datastructure.TimeDimension=ECB:ECB_EXR1(1.0).TIME_PERIOD",
+        "metadatastructure.MetadataStructure=ECB:MSD(1.2.0)",
+        "metadatastructure.MetadataAttribute=ECB:MSD(1.2.0).REPORT.CONTACT",
+        "codelist.Codelist=ECB:CL_FREQ(1.0)",
+        "codelist.Code=ECB:CL_FREQ(1.0).A",
+        "codelist.HierarchicalCode=ECB:HCL(1.0).H.C1",
+        "conceptscheme.ConceptScheme=ECB:ECB_CONCEPTS(1.0)",
+        "conceptscheme.Concept=ECB:ECB_CONCEPTS(1.0).FREQ",
+        "categoryscheme.CategoryScheme=ECB:ECB_CATS(latest)",
+        "categoryscheme.Category=ECB:ECB_CATS(1.0).EXR.EXR_A",
+        "categoryscheme.Categorisation=ECB:CAT_EXR(1.0)",
+        "registry.ContentConstraint=ECB:EXR_CONSTRAINTS(1.0)",
+        "registry.ProvisionAgreement=ECB:PA_EXR(1.0)",
+        "mapping.StructureSet=ECB:SS(1.0)",
+        "mapping.CodelistMap=ECB:SS(1.0).CLM",
+        "process.Process=ECB:PROC(2.0.0-draft)",
+        "process.ProcessStep=ECB:PROC(1.0).COLLECT.VALIDATE",
+    ];
+
+    fn urn(s: &str) -> Urn {
+        format!("{}.{}", PREFIX, s).parse().unwrap()
+    }
+
+    #[test]
+    fn urns_round_trip() {
+        for s in ROUND_TRIPS {
+            let full = format!("{}.{}", PREFIX, s);
+            let parsed: Urn = full.parse().unwrap();
+            assert_eq!(parsed.to_string(), full);
+            assert_eq!(parsed.to_string().parse::<Urn>().unwrap(), parsed);
+        }
+    }
+
+    #[test]
+    fn items_are_split_into_their_parts() {
+        let code = urn("codelist.Code=ECB:CL_FREQ(1.0).A");
+        assert_eq!(code.class, ObjectTypeCodelistType::Code);
+        assert_eq!(
+            (code.agency_id.as_str(), code.id.as_str()),
+            ("ECB", "CL_FREQ")
+        );
+        assert_eq!(code.version, "1.0".parse().unwrap());
+        assert_eq!(code.item_ids, vec!["A"]);
+        assert_eq!(code.package(), Some("codelist"));
+        assert_eq!(
+            code.maintainable(),
+            urn("codelist.Codelist=ECB:CL_FREQ(1.0)")
+        );
+
+        let category =
+            urn("categoryscheme.Category=ECB:ECB_CATS(1.0).EXR.EXR_A");
+        assert_eq!(category.item_id().as_deref(), Some("EXR.EXR_A"));
+        let built = category
+            .maintainable()
+            .item(ObjectTypeCodelistType::Category, "EXR")
+            .item(ObjectTypeCodelistType::Category, "EXR_A");
+        assert_eq!(built, category);
+        assert!(!category.maintainable().is_item());
+    }
+
+    #[test]
+    fn malformed_urns_are_rejected() {
+        let malformed = [
+            "urn:sdmx:org.other.codelist.Codelist=ECB:CL_FREQ(1.0)",
+            "codelist.Codelist",
+            "Codelist=ECB:CL_FREQ(1.0)",
+            "codelist.NoSuchClass=ECB:CL_FREQ(1.0)",
+            "conceptscheme.Codelist=ECB:CL_FREQ(1.0)",
+            "codelist.Codelist=CL_FREQ(1.0)",
+            "codelist.Codelist=ECB:CL_FREQ",
+            "codelist.Codelist=ECB:CL_FREQ(1.0",
+            "codelist.Codelist=ECB:CL_FREQ(1.x)",
+            "codelist.Codelist=:CL_FREQ(1.0)",
+            "codelist.Codelist=ECB:(1.0)",
+            "codelist.Code=ECB:CL_FREQ(1.0)A",
+            "codelist.Code=ECB:CL_FREQ(1.0).",
+            "codelist.Code=ECB:CL_FREQ(1.0).A..B",
+        ];
+        for s in &malformed {
+            let full = match s.starts_with("urn:") {
+                true => s.to_string(),
+                false => format!("{}.{}", PREFIX, s),
+            };
+            assert!(full.parse::<Urn>().is_err(), "{}", full);
+        }
+    }
+
+    #[test]
+    fn items_are_queried_through_their_maintainable() {
+        let path = |s: &str, rest| {
+            let query = urn(s).rest_query_for(rest).unwrap();
+            query.split('?').next().unwrap().to_string()
+        };
+        let code = "codelist.Code=ECB:CL_FREQ(1.0).A";
+        assert_eq!(path(code, RestVersion::V2_1), "codelist/ECB/CL_FREQ/1.0/A");
+        assert_eq!(
+            path(code, RestVersion::V3_0),
+            "structure/codelist/ECB/CL_FREQ/1.0/A"
+        );
+        // data structures have no item queries
+        let dimension = "datastructure.Dimension=ECB:ECB_EXR1(1.0).FREQ";
+        assert_eq!(
+            path(dimension, RestVersion::V2_1),
+            "datastructure/ECB/ECB_EXR1/1.0"
+        );
+    }
+}
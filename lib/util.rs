@@ -1,16 +1,24 @@
 use std::{fs, ops::Deref, path::Path};
 
-use serde_json::from_str;
+use tracing::trace;
 
-use crate::sdmx_sources::{Source, Sources};
+use crate::{
+    parse::{parse_structure, Diagnostic, ParseMode},
+    reqwest_warc::read_warc_file,
+    sdmx_sources::{parse_sources, Source, Sources},
+    structure::Structure,
+    structure_v2::SchemaVersion,
+};
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 
-/// Reads a JSON file of sources into memory
+/// Reads a JSON file of sources into memory, rejecting unknown fields
 pub fn read_sources<P: AsRef<Path>>(location: P) -> Result<Sources> {
+    let location = location.as_ref();
     let sources = fs::read_to_string(location)?;
-    let res: Sources = from_str(&sources)?;
-    Ok(res)
+    parse_sources(&sources).with_context(|| {
+        format!("Failed to read sources from {}", location.display())
+    })
 }
 
 /// Filters the provided sources by sourceIDs
@@ -34,6 +42,109 @@ pub fn get_source(location: String, sourceID: String) -> Result<Source> {
     }
 }
 
+/// A structure message read from disk, labelled with the file it came from
+/// and, for WARC files, the record ID
+pub struct StoredMessage {
+    pub label: String,
+    /// The origin of the URL requested for a WARC record, e.g.
+    /// `https://sdw-wsrest.ecb.europa.eu`, which tells the source apart
+    pub origin: Option<String>,
+    pub message: Result<Structure>,
+    /// The deviations from the schema repaired in lenient mode
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl StoredMessage {
+    fn parse(
+        label: String,
+        origin: Option<String>,
+        body: Result<String>,
+        version: Option<SchemaVersion>,
+        mode: ParseMode,
+    ) -> Self {
+        match body.and_then(|b| parse_structure(&b, version, mode)) {
+            Ok(parsed) => StoredMessage {
+                label,
+                origin,
+                message: Ok(parsed.value),
+                diagnostics: parsed.diagnostics,
+            },
+            Err(e) => StoredMessage {
+                label,
+                origin,
+                message: Err(e),
+                diagnostics: vec![],
+            },
+        }
+    }
+}
+
+/// Reads the SDMX-JSON structure messages in a JSON file, a WARC file written
+/// by the crawler, or a directory of such files. Only WARC responses with a
+/// JSON content type are read.
+pub fn read_structure_messages<P: AsRef<Path>>(
+    path: P,
+    mode: ParseMode,
+) -> Result<Vec<StoredMessage>> {
+    let path = path.as_ref();
+    let mut out = vec![];
+    if path.is_dir() {
+        let mut entries = fs::read_dir(path)?
+            .map(|e| Ok(e?.path()))
+            .collect::<Result<Vec<_>>>()?;
+        entries.sort();
+        for entry in entries {
+            if entry.is_dir() || is_message_file(&entry) {
+                out.extend(read_structure_messages(entry, mode)?);
+            }
+        }
+        return Ok(out);
+    }
+
+    let label = path.display().to_string();
+    if path.extension().is_some_and(|e| e == "warc") {
+        for record in read_warc_file(path)? {
+            if record.warc_type != "response" {
+                continue;
+            }
+            let (headers, body) = record.http_parts();
+            let content_type = headers.lines().find_map(|h| {
+                let (name, value) = h.split_once(':')?;
+                match name.trim().eq_ignore_ascii_case("content-type") {
+                    true => Some(value.trim().to_ascii_lowercase()),
+                    false => None,
+                }
+            });
+            if let Some(ct) = content_type.filter(|ct| ct.contains("json")) {
+                let body = String::from_utf8(body.to_vec());
+                let origin = record
+                    .target_uri
+                    .as_deref()
+                    .and_then(|u| url::Url::parse(u).ok())
+                    .map(|u| u.origin().ascii_serialization());
+                out.push(StoredMessage::parse(
+                    format!("{}#{}", label, record.id),
+                    origin,
+                    body.map_err(|e| e.into()),
+                    SchemaVersion::from_content_type(&ct),
+                    mode,
+                ));
+            }
+        }
+    } else {
+        let body = fs::read_to_string(path).map_err(|e| e.into());
+        out.push(StoredMessage::parse(label, None, body, None, mode));
+    }
+    Ok(out)
+}
+
+fn is_message_file(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|e| e.to_str()),
+        Some("json") | Some("warc")
+    )
+}
+
 pub fn cdx_url_canonical(u: url::Url) -> Result<String> {
     let mut domains: Vec<_> = u
         .host_str()
@@ -41,12 +152,62 @@ pub fn cdx_url_canonical(u: url::Url) -> Result<String> {
         .split(".")
         .collect();
     domains.reverse();
-    println!("{:#?}", domains);
     // TODO: how to deal with query params that are important for the page?
     // Not specified in any CDX specs, probably need to look at example Wayback CDX repsponses
     let out = domains.join(",") + ")"; // + u.path();
                                        // Path was causing huge issues, can't really have files with / in them
 
-    println!("{:#?}", out);
+    trace!(url = %u, key = %out, "Canonicalized URL");
     Ok(out)
 }
+
+/// The SURT form of a URL used as the key of CDX indexes, e.g.
+/// `org,ecb,sdw-wsrest)/service/dataflow?detail=allstubs` for
+/// `https://sdw-wsrest.ecb.org/service/dataflow?detail=allstubs`: the host
+/// name reversed without `www`, then the path and the sorted query,
+/// lowercased
+pub fn cdx_surt(u: &url::Url) -> String {
+    let mut out = match u.host() {
+        Some(url::Host::Domain(domain)) => {
+            let domain = domain.to_ascii_lowercase();
+            let mut labels: Vec<_> =
+                domain.trim_start_matches("www.").split('.').collect();
+            labels.reverse();
+            labels.join(",")
+        }
+        // IP addresses are kept as they are
+        Some(host) => host.to_string(),
+        None => String::new(),
+    };
+    if let Some(port) = u.port() {
+        out += &format!(":{}", port);
+    }
+    out += ")";
+    out += &u.path().to_ascii_lowercase();
+    if let Some(query) = u.query().filter(|q| !q.is_empty()) {
+        let mut params: Vec<_> = query.split('&').collect();
+        params.sort_unstable();
+        out += "?";
+        out += &params.join("&").to_ascii_lowercase();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn surt_keys() {
+        let surt = |u: &str| cdx_surt(&url::Url::parse(u).unwrap());
+        assert_eq!(
+            surt("https://www.SDW-wsrest.ecb.org/service/Dataflow?b=2&a=1"),
+            "org,ecb,sdw-wsrest)/service/dataflow?a=1&b=2"
+        );
+        assert_eq!(
+            surt("http://127.0.0.1:9000/rest/dataflow"),
+            "127.0.0.1:9000)/rest/dataflow"
+        );
+        assert_eq!(surt("https://ecb.org"), "org,ecb)/");
+    }
+}
@@ -0,0 +1,1635 @@
+//! validate checks structure messages against the integrity rules of SDMX
+//! which are not expressed by the JSON schema, such as reserved component
+//! IDs, unique component IDs and references between artefacts.
+//!
+//! It also checks data sets against their data structure definition and
+//! content constraints, see [`DataValidator`].
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use regex::Regex;
+use serde::Serialize;
+
+use crate::{
+    artefact::{ItemScheme, Maintainable, DEFAULT_VERSION},
+    components::{
+        AttachmentLevel, Component, ComponentKind, OBS_VALUE,
+        REPORTING_YEAR_START_DAY, TIME_PERIOD,
+    },
+    dataset::{DataSet, Group, Series},
+    hierarchy::CodeTree,
+    structure::{
+        CodelistType, ContentConstraintAttachmentType,
+        ContentConstraintTypeCodeType, ContentConstraintTypeElement, Data,
+        DataStructureTypeElement, DataflowTypeElement,
+        SimpleComponentTextFormatType, SimpleDataType,
+    },
+    urn::{class_name, Urn},
+};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A violation of an integrity rule
+#[derive(Serialize, Debug, Clone)]
+pub struct Issue {
+    pub severity: Severity,
+    /// The artefact the issue was found in, e.g. `DataStructure ECB:ECB_EXR1(1.0)`
+    pub artefact: String,
+    /// The component, code or field within the artefact, if any
+    pub location: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.severity, self.artefact)?;
+        if let Some(location) = &self.location {
+            write!(f, " {}", location)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Collects the issues of one artefact
+struct Issues<'a> {
+    artefact: String,
+    out: &'a mut Vec<Issue>,
+}
+
+impl Issues<'_> {
+    fn error<L: ToString, M: ToString>(
+        &mut self,
+        location: Option<L>,
+        message: M,
+    ) {
+        self.out.push(Issue {
+            severity: Severity::Error,
+            artefact: self.artefact.clone(),
+            location: location.map(|l| l.to_string()),
+            message: message.to_string(),
+        });
+    }
+}
+
+/// Checks all artefacts in a structure message
+pub fn validate_structure(data: &Data) -> Vec<Issue> {
+    let mut out = vec![];
+    for m in data.maintainables() {
+        check_validity(m, &mut issues(m, &mut out));
+    }
+    for dsd in data.data_structures.iter().flatten() {
+        check_data_structure(data, dsd, &mut issues(dsd, &mut out));
+    }
+    for cl in data.codelists.iter().flatten() {
+        check_codelist(cl, &mut issues(cl, &mut out));
+    }
+    out
+}
+
+fn issues<'a>(m: &dyn Maintainable, out: &'a mut Vec<Issue>) -> Issues<'a> {
+    Issues {
+        artefact: artefact_label(m),
+        out,
+    }
+}
+
+/// Identifies an artefact in messages, e.g. `DataStructure ECB:ECB_EXR1(1.0)`
+fn artefact_label(m: &dyn Maintainable) -> String {
+    format!(
+        "{} {}:{}({})",
+        class_name(m.class()),
+        m.agency_id(),
+        m.resource_id(),
+        m.version().unwrap_or(DEFAULT_VERSION)
+    )
+}
+
+/// Parses the date-time formats used by `validFrom` and `validTo`
+fn parse_date_time(s: &str) -> Option<DateTime<Utc>> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+        return Some(dt.with_timezone(&Utc));
+    }
+    if let Ok(dt) = NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f") {
+        return Some(dt.and_utc());
+    }
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|dt| dt.and_utc())
+}
+
+fn check_validity(m: &dyn Maintainable, issues: &mut Issues) {
+    let mut parse = |field: &str, value: Option<&str>| {
+        let value = value?;
+        let parsed = parse_date_time(value);
+        if parsed.is_none() {
+            issues.error(Some(field), format!("{} is not a valid date", value));
+        }
+        parsed
+    };
+    let from = parse("validFrom", m.valid_from());
+    let to = parse("validTo", m.valid_to());
+    if let (Some(from), Some(to)) = (from, to) {
+        if from > to {
+            issues.error(
+                Some("validTo"),
+                format!(
+                    "{} is before validFrom {}",
+                    m.valid_to().unwrap_or_default(),
+                    m.valid_from().unwrap_or_default()
+                ),
+            );
+        }
+    }
+}
+
+fn check_data_structure(
+    data: &Data,
+    dsd: &DataStructureTypeElement,
+    issues: &mut Issues,
+) {
+    let components = match &dsd.data_structure_components {
+        Some(c) => c,
+        None => {
+            issues.error(
+                None::<&str>,
+                "has no components, so no dimension or primary measure",
+            );
+            return;
+        }
+    };
+    let dimensions = dsd.dimensions();
+    if dimensions.is_empty() {
+        issues.error(None::<&str>, "has no dimensions");
+    }
+
+    // SDMX 3.0 data structures may have several measures with any ID
+    let sdmx3 = components.measure_list.measures.is_some();
+    let all = dsd.components();
+    for c in &all {
+        check_reserved_id(c, sdmx3, issues);
+        if let Some(enumeration) = c.enumeration {
+            check_enumeration(data, c, enumeration, issues);
+        }
+    }
+
+    let mut seen = HashSet::new();
+    let groups = components.groups.iter().flatten().map(|g| &g.id);
+    for id in all.iter().map(|c| &c.id).chain(groups) {
+        if !seen.insert(id) {
+            issues.error(
+                Some(id),
+                "the ID is used by more than one component or group",
+            );
+        }
+    }
+}
+
+fn location(c: &Component) -> String {
+    format!("{:?} {}", c.kind, c.id)
+}
+
+fn check_reserved_id(c: &Component, sdmx3: bool, issues: &mut Issues) {
+    let reserved = [
+        (OBS_VALUE, ComponentKind::PrimaryMeasure),
+        (TIME_PERIOD, ComponentKind::TimeDimension),
+        (
+            REPORTING_YEAR_START_DAY,
+            ComponentKind::ReportingYearStartDay,
+        ),
+    ];
+    for (id, kind) in &reserved {
+        if c.id == *id && c.kind != *kind {
+            let inherited = match c.explicit_id {
+                true => "",
+                false => " inherited from its concept identity",
+            };
+            issues.error(
+                Some(location(c)),
+                format!(
+                    "uses the ID {}{}, which is reserved for the {:?}",
+                    id, inherited, kind
+                ),
+            );
+        }
+        let any_id = sdmx3 && *kind == ComponentKind::PrimaryMeasure;
+        if c.kind == *kind && c.id != *id && !any_id {
+            issues.error(Some(location(c)), format!("must have the ID {}", id));
+        }
+    }
+}
+
+fn check_enumeration(
+    data: &Data,
+    c: &Component,
+    enumeration: &str,
+    issues: &mut Issues,
+) {
+    let urn: Urn = match enumeration.parse() {
+        Ok(urn) => urn,
+        Err(e) => {
+            issues.error(Some(location(c)), e);
+            return;
+        }
+    };
+    let found = match c.kind {
+        ComponentKind::MeasureDimension => data
+            .concept_schemes
+            .iter()
+            .flatten()
+            .any(|cs| matches(cs, &urn)),
+        _ => data.codelists.iter().flatten().any(|cl| matches(cl, &urn)),
+    };
+    if !found {
+        issues.error(
+            Some(location(c)),
+            format!("references {} which is not in the message", urn),
+        );
+    }
+}
+
+/// Whether an artefact is the one referenced by a URN
+fn matches<M: Maintainable>(m: &M, urn: &Urn) -> bool {
+    m.agency_id() == urn.agency_id
+        && m.resource_id() == urn.id
+        && m.parsed_version().is_some_and(|v| urn.version.matches(&v))
+}
+
+fn check_codelist(cl: &CodelistType, issues: &mut Issues) {
+    let codes: HashSet<_> = cl
+        .codes
+        .iter()
+        .flatten()
+        .filter_map(|c| c.id.as_deref())
+        .collect();
+    // Partial codelists may leave out the parents of their codes
+    let partial = cl.is_partial.unwrap_or(false);
+    for code in cl.codes.iter().flatten().filter(|_| !partial) {
+        if let Some(parent) = &code.parent {
+            if !codes.contains(parent.as_str()) {
+                issues.error(
+                    code.id.as_ref().map(|id| format!("code {}", id)),
+                    format!(
+                        "has the parent {} which is not in the codelist",
+                        parent
+                    ),
+                );
+            }
+        }
+    }
+    // Duplicate codes and cycles of parents
+    if let Err(e) = CodeTree::from_codelist(cl) {
+        issues.error(None::<&str>, e);
+    }
+}
+
+/// The rule broken by a violation in a data set
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Rule {
+    /// The series or group key does not match the dimensions
+    Key,
+    /// The component is not defined by the data structure
+    UnknownComponent,
+    /// The value is not a code of the codelist of the component
+    Code,
+    /// The attribute is reported at a level its relationship does not allow
+    Attachment,
+    MandatoryAttribute,
+    /// The value does not conform to the text format of the component
+    TextFormat,
+    /// The value is outside the cube regions of a content constraint
+    Constraint,
+}
+
+/// A violation of the data structure or its constraints in a data set
+#[derive(Serialize, Debug, Clone)]
+pub struct Violation {
+    pub rule: Rule,
+    /// ID of the dimension, attribute or primary measure, if any
+    pub component: Option<String>,
+    /// Time period of the observation, for observation level violations
+    pub observation: Option<String>,
+    pub message: String,
+}
+
+impl Violation {
+    fn new<M: ToString>(
+        rule: Rule,
+        component: Option<&str>,
+        message: M,
+    ) -> Self {
+        Violation {
+            rule,
+            component: component.map(|c| c.to_string()),
+            observation: None,
+            message: message.to_string(),
+        }
+    }
+
+    fn at(mut self, observation: Option<&str>) -> Self {
+        self.observation = observation.map(|o| o.to_string());
+        self
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(component) = &self.component {
+            write!(f, "{} ", component)?;
+        }
+        if let Some(observation) = &self.observation {
+            write!(f, "at {} ", observation)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// The violations found in one series, including its observations
+#[derive(Serialize, Debug, Clone)]
+pub struct SeriesReport {
+    /// The dot separated series key
+    pub key: String,
+    pub violations: Vec<Violation>,
+}
+
+/// The result of validating a data set
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct DataReport {
+    /// Violations in data set level attributes and in groups
+    pub data_set: Vec<Violation>,
+    /// The series with violations, in the order of the data set
+    pub series: Vec<SeriesReport>,
+}
+
+impl DataReport {
+    pub fn is_valid(&self) -> bool {
+        self.data_set.is_empty() && self.series.is_empty()
+    }
+
+    /// The number of violations over all levels
+    pub fn len(&self) -> usize {
+        self.data_set.len()
+            + self
+                .series
+                .iter()
+                .map(|s| s.violations.len())
+                .sum::<usize>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.is_valid()
+    }
+}
+
+/// A cube region of an allowed content constraint. Components without
+/// values are left out, as they do not restrict the region.
+struct Region<'a> {
+    constraint: String,
+    included: bool,
+    key_values: HashMap<&'a str, HashSet<&'a str>>,
+    attributes: HashMap<&'a str, HashSet<&'a str>>,
+}
+
+impl<'a> Region<'a> {
+    fn new(constraint: &'a ContentConstraintTypeElement) -> Vec<Self> {
+        let values = |values: &'a Option<Vec<String>>| -> HashSet<&'a str> {
+            values.iter().flatten().map(|v| v.as_str()).collect()
+        };
+        let mut out = vec![];
+        for region in constraint.cube_regions.iter().flatten() {
+            out.push(Region {
+                constraint: artefact_label(constraint),
+                included: region.is_included.unwrap_or(true),
+                key_values: region
+                    .key_values
+                    .iter()
+                    .flatten()
+                    .filter(|k| k.values.is_some())
+                    .map(|k| (k.id.as_str(), values(&k.values)))
+                    .collect(),
+                attributes: region
+                    .attributes
+                    .iter()
+                    .flatten()
+                    .filter(|a| a.values.is_some())
+                    .map(|a| (a.id.as_str(), values(&a.values)))
+                    .collect(),
+            });
+        }
+        out
+    }
+
+    /// The dimensions listed by the region whose values in the series key
+    /// are outside the region, ignoring the time dimension
+    fn outside<'s>(
+        &self,
+        series: &'s Series,
+        time: Option<&str>,
+    ) -> Vec<&'s str> {
+        series
+            .key
+            .iter()
+            .filter(|k| Some(k.id.as_str()) != time)
+            .filter(|k| {
+                self.key_values
+                    .get(k.id.as_str())
+                    .is_some_and(|values| !values.contains(k.value.as_str()))
+            })
+            .map(|k| k.id.as_str())
+            .collect()
+    }
+
+    /// Whether the series key lies within the region
+    fn contains(&self, series: &Series, time: Option<&str>) -> bool {
+        self.key_values
+            .keys()
+            .filter(|id| Some(**id) != time)
+            .all(|id| series.key_value(id).is_some())
+            && self.outside(series, time).is_empty()
+    }
+}
+
+/// Checks data sets against a data structure definition and the allowed
+/// content constraints attached to it.
+///
+/// Codes are checked against the codelists in the structure message the
+/// validator is created from. Components whose codelist is not in the
+/// message are only checked against their text format. Time ranges of cube
+/// regions are not checked.
+pub struct DataValidator<'a> {
+    structures: &'a Data,
+    /// The dimensions of the series key, in order
+    dimensions: Vec<Component<'a>>,
+    time_dimension: Option<Component<'a>>,
+    attributes: Vec<Component<'a>>,
+    primary_measure: Option<Component<'a>>,
+    /// The dimensions of each group
+    groups: HashMap<&'a str, Vec<&'a str>>,
+    /// The codes of each coded component
+    codes: HashMap<String, HashSet<&'a str>>,
+    /// The compiled text format patterns of each component
+    patterns: HashMap<String, Regex>,
+    regions: Vec<Region<'a>>,
+}
+
+impl<'a> DataValidator<'a> {
+    /// Creates a validator for data of a data structure definition, which
+    /// is looked up in `structures` together with its codelists and the
+    /// constraints attached to it
+    pub fn new(
+        structures: &'a Data,
+        dsd: &'a DataStructureTypeElement,
+    ) -> Self {
+        let (time, dimensions) = dsd
+            .dimensions()
+            .into_iter()
+            .partition::<Vec<_>, _>(|d| d.kind == ComponentKind::TimeDimension);
+        let groups = dsd
+            .data_structure_components
+            .iter()
+            .flat_map(|c| c.groups.iter().flatten())
+            .map(|g| {
+                let dimensions = g.group_dimensions.iter().flatten();
+                (g.id.as_str(), dimensions.map(|d| d.as_str()).collect())
+            })
+            .collect();
+        let mut validator = DataValidator {
+            structures,
+            dimensions,
+            time_dimension: time.into_iter().next(),
+            attributes: dsd.attributes(),
+            primary_measure: dsd.primary_measure(),
+            groups,
+            codes: HashMap::new(),
+            patterns: HashMap::new(),
+            regions: vec![],
+        };
+        for c in dsd.components() {
+            if let Some(codes) =
+                c.enumeration.and_then(|e| codes(structures, &c, e))
+            {
+                validator.codes.insert(c.id.clone(), codes);
+            }
+            let pattern = c.text_format.and_then(|f| f.pattern.as_ref());
+            // Invalid patterns are left out, as the structure is at fault
+            if let Some(Ok(re)) =
+                pattern.map(|p| Regex::new(&format!("^(?:{})$", p)))
+            {
+                validator.patterns.insert(c.id.clone(), re);
+            }
+        }
+        validator.add_constraints(|c| {
+            c.data_structures
+                .iter()
+                .flatten()
+                .any(|urn| urn.parse().is_ok_and(|urn| matches(dsd, &urn)))
+        });
+        validator
+    }
+
+    /// Also applies the allowed content constraints attached to a dataflow
+    /// of the data structure
+    pub fn with_dataflow(mut self, dataflow: &DataflowTypeElement) -> Self {
+        self.add_constraints(|c| {
+            c.dataflows
+                .iter()
+                .flatten()
+                .any(|urn| urn.parse().is_ok_and(|urn| matches(dataflow, &urn)))
+        });
+        self
+    }
+
+    /// Creates a validator for the data of the dataflow or data structure
+    /// with ID `id` in a structure message, or of the only data structure
+    /// in the message if no ID is given
+    pub fn find(structures: &'a Data, id: Option<&str>) -> Result<Self> {
+        let dsds: Vec<_> =
+            structures.data_structures.iter().flatten().collect();
+        let id = match id {
+            Some(id) => id,
+            None => {
+                return match dsds.as_slice() {
+                    [dsd] => Ok(DataValidator::new(structures, dsd)),
+                    _ => Err(anyhow!(
+                        "The structure message has {} data structures, select one by ID",
+                        dsds.len()
+                    )),
+                }
+            }
+        };
+        let dataflow = structures
+            .dataflows
+            .iter()
+            .flatten()
+            .find(|df| df.resource_id() == id);
+        if let Some(dataflow) = dataflow {
+            let urn: Urn = dataflow
+                .structure
+                .as_deref()
+                .ok_or_else(|| anyhow!("Dataflow {} has no structure", id))?
+                .parse()?;
+            let dsd = dsds
+                .into_iter()
+                .find(|dsd| matches(*dsd, &urn))
+                .ok_or_else(|| {
+                    anyhow!("{} is not in the structure message", urn)
+                })?;
+            return Ok(
+                DataValidator::new(structures, dsd).with_dataflow(dataflow)
+            );
+        }
+        dsds.into_iter()
+            .find(|dsd| dsd.resource_id() == id)
+            .map(|dsd| DataValidator::new(structures, dsd))
+            .ok_or_else(|| {
+                anyhow!(
+                    "No dataflow or data structure {} in the structure message",
+                    id
+                )
+            })
+    }
+
+    fn add_constraints<F>(&mut self, attached: F)
+    where
+        F: Fn(&ContentConstraintAttachmentType) -> bool,
+    {
+        let structures = self.structures;
+        for c in structures.content_constraints.iter().flatten() {
+            // Actual constraints describe the data that exists, so only
+            // allowed constraints restrict valid data
+            let allowed = matches!(
+                c.type_type,
+                Some(ContentConstraintTypeCodeType::Allowed)
+            );
+            if allowed
+                && c.constraint_attachment.as_ref().is_some_and(&attached)
+            {
+                self.regions.extend(Region::new(c));
+            }
+        }
+    }
+
+    pub fn validate(&self, data: &DataSet) -> DataReport {
+        let mut report = DataReport::default();
+        self.check_attributes(
+            &data.attributes,
+            AttachmentLevel::DataSet,
+            None,
+            &mut report.data_set,
+        );
+        for a in self.mandatory(AttachmentLevel::DataSet) {
+            if !data.attributes.contains_key(&a.id) {
+                report.data_set.push(missing(a, None));
+            }
+        }
+        for group in &data.groups {
+            self.check_group(group, &mut report.data_set);
+        }
+        for series in &data.series {
+            let mut violations = vec![];
+            self.check_series(data, series, &mut violations);
+            if !violations.is_empty() {
+                report.series.push(SeriesReport {
+                    key: series.key_string(),
+                    violations,
+                });
+            }
+        }
+        report
+    }
+
+    fn time_id(&self) -> Option<&str> {
+        self.time_dimension.as_ref().map(|t| t.id.as_str())
+    }
+
+    fn dimension(&self, id: &str) -> Option<&Component<'a>> {
+        self.dimensions.iter().find(|d| d.id == id)
+    }
+
+    fn attribute(&self, id: &str) -> Option<&Component<'a>> {
+        self.attributes.iter().find(|a| a.id == id)
+    }
+
+    fn mandatory(
+        &self,
+        level: AttachmentLevel,
+    ) -> impl Iterator<Item = &Component<'a>> {
+        let time = self.time_id();
+        self.attributes.iter().filter(move |a| {
+            a.is_mandatory() && a.attachment_level(time) == Some(level)
+        })
+    }
+
+    /// Whether an attribute may be reported at a level. Group attributes
+    /// may also be reported for each series, and series attributes with
+    /// attachment groups for each of these groups.
+    fn allowed_at(&self, a: &Component, level: AttachmentLevel) -> bool {
+        let expected = a.attachment_level(self.time_id());
+        let attachment_groups = a
+            .attribute_relationship
+            .and_then(|r| r.attachment_groups.as_ref())
+            .is_some_and(|g| !g.is_empty());
+        expected == Some(level)
+            || (expected == Some(AttachmentLevel::Group)
+                && level == AttachmentLevel::Series)
+            || (attachment_groups && level == AttachmentLevel::Group)
+    }
+
+    fn check_group(&self, group: &Group, out: &mut Vec<Violation>) {
+        let dimensions = match self.groups.get(group.id.as_str()) {
+            Some(d) => d,
+            None => {
+                out.push(Violation::new(
+                    Rule::UnknownComponent,
+                    Some(&group.id),
+                    "is not a group of the data structure",
+                ));
+                return;
+            }
+        };
+        let key: Vec<_> = group.key.iter().map(|k| k.id.as_str()).collect();
+        if key != *dimensions {
+            out.push(Violation::new(
+                Rule::Key,
+                Some(&group.id),
+                format!(
+                    "has the key {} instead of {}",
+                    key.join(","),
+                    dimensions.join(",")
+                ),
+            ));
+        }
+        let mut violations = vec![];
+        for k in &group.key {
+            match self.dimension(&k.id) {
+                Some(d) => self.check_value(d, &k.value, None, &mut violations),
+                None => violations.push(unknown(&k.id, "dimension")),
+            }
+        }
+        self.check_attributes(
+            &group.attributes,
+            AttachmentLevel::Group,
+            None,
+            &mut violations,
+        );
+        let values: Vec<_> =
+            group.key.iter().map(|k| k.value.as_str()).collect();
+        for mut v in violations {
+            v.message = format!(
+                "{} in group {} {}",
+                v.message,
+                group.id,
+                values.join(".")
+            );
+            out.push(v);
+        }
+    }
+
+    fn check_series(
+        &self,
+        data: &DataSet,
+        series: &Series,
+        out: &mut Vec<Violation>,
+    ) {
+        self.check_key(series, out);
+        self.check_key_regions(series, out);
+        self.check_attributes(
+            &series.attributes,
+            AttachmentLevel::Series,
+            None,
+            out,
+        );
+
+        let groups: Vec<_> = data
+            .groups
+            .iter()
+            .filter(|g| {
+                g.key
+                    .iter()
+                    .all(|k| series.key_value(&k.id) == Some(&k.value))
+            })
+            .collect();
+        for a in self.mandatory(AttachmentLevel::Series) {
+            if !series.attributes.contains_key(&a.id) {
+                out.push(missing(a, None));
+            }
+        }
+        for a in self.mandatory(AttachmentLevel::Group) {
+            let in_group =
+                groups.iter().any(|g| g.attributes.contains_key(&a.id));
+            if !in_group && !series.attributes.contains_key(&a.id) {
+                out.push(missing(a, None));
+            }
+        }
+
+        let time = self.time_dimension.as_ref();
+        for obs in &series.observations {
+            let at = Some(obs.time_period.as_str()).filter(|t| !t.is_empty());
+            if obs.time_period.is_empty() {
+                out.push(Violation::new(
+                    Rule::Key,
+                    time.map(|t| t.id.as_str()),
+                    "the observation has no time period",
+                ));
+            } else if let Some(t) = time {
+                self.check_value(t, &obs.time_period, at, out);
+            }
+            if let (Some(m), Some(value)) = (&self.primary_measure, &obs.value)
+            {
+                self.check_value(m, value, at, out);
+            }
+            self.check_attributes(
+                &obs.attributes,
+                AttachmentLevel::Observation,
+                at,
+                out,
+            );
+            for a in self.mandatory(AttachmentLevel::Observation) {
+                if !obs.attributes.contains_key(&a.id) {
+                    out.push(missing(a, at));
+                }
+            }
+        }
+    }
+
+    /// Checks the dimensions and their order in the series key
+    fn check_key(&self, series: &Series, out: &mut Vec<Violation>) {
+        for k in &series.key {
+            match self.dimension(&k.id) {
+                Some(d) => self.check_value(d, &k.value, None, out),
+                None => out.push(unknown(&k.id, "dimension")),
+            }
+        }
+        let expected: Vec<_> =
+            self.dimensions.iter().map(|d| d.id.as_str()).collect();
+        let actual: Vec<_> = series.key.iter().map(|k| k.id.as_str()).collect();
+        if actual == expected {
+            return;
+        }
+        let mut complete = true;
+        for d in &expected {
+            match actual.iter().filter(|a| *a == d).count() {
+                0 => {
+                    out.push(Violation::new(
+                        Rule::Key,
+                        Some(d),
+                        "is missing from the series key",
+                    ));
+                    complete = false;
+                }
+                1 => {}
+                _ => {
+                    out.push(Violation::new(
+                        Rule::Key,
+                        Some(d),
+                        "appears more than once in the series key",
+                    ));
+                    complete = false;
+                }
+            }
+        }
+        if complete && actual.len() == expected.len() {
+            out.push(Violation::new(
+                Rule::Key,
+                None,
+                format!(
+                    "the series key is ordered {} instead of {}",
+                    actual.join(","),
+                    expected.join(",")
+                ),
+            ));
+        }
+    }
+
+    /// Checks the series key against the cube regions. A series must lie
+    /// within one of the included regions, if there are any, and in none
+    /// of the excluded regions.
+    fn check_key_regions(&self, series: &Series, out: &mut Vec<Violation>) {
+        let time = self.time_id();
+        let keyed = self
+            .regions
+            .iter()
+            .filter(|r| r.key_values.keys().any(|id| Some(*id) != time));
+        let (included, excluded): (Vec<_>, Vec<_>) =
+            keyed.partition(|r| r.included);
+        for r in excluded {
+            if r.contains(series, time) {
+                out.push(Violation::new(
+                    Rule::Constraint,
+                    None,
+                    format!("the series key is excluded by {}", r.constraint),
+                ));
+            }
+        }
+        if included.is_empty()
+            || included.iter().any(|r| r.contains(series, time))
+        {
+            return;
+        }
+        match included.as_slice() {
+            [r] => {
+                for id in r.outside(series, time) {
+                    let value = series.key_value(id).unwrap_or_default();
+                    out.push(Violation::new(
+                        Rule::Constraint,
+                        Some(id),
+                        format!("{} is not allowed by {}", value, r.constraint),
+                    ));
+                }
+            }
+            _ => out.push(Violation::new(
+                Rule::Constraint,
+                None,
+                "the series key is not in any cube region allowed by the constraints",
+            )),
+        }
+    }
+
+    fn check_attributes(
+        &self,
+        attributes: &BTreeMap<String, String>,
+        level: AttachmentLevel,
+        observation: Option<&str>,
+        out: &mut Vec<Violation>,
+    ) {
+        for (id, value) in attributes {
+            let a = match self.attribute(id) {
+                Some(a) => a,
+                None => {
+                    out.push(unknown(id, "attribute").at(observation));
+                    continue;
+                }
+            };
+            if !self.allowed_at(a, level) {
+                let expected = a.attachment_level(self.time_id());
+                out.push(
+                    Violation::new(
+                        Rule::Attachment,
+                        Some(id),
+                        format!(
+                            "is reported at the {:?} level but attached at the {:?} level",
+                            level,
+                            expected.unwrap_or(AttachmentLevel::DataSet)
+                        ),
+                    )
+                    .at(observation),
+                );
+            }
+            self.check_value(a, value, observation, out);
+        }
+    }
+
+    /// Checks a value against the codelist or text format of a component
+    /// and against the values of the cube regions
+    fn check_value(
+        &self,
+        c: &Component,
+        value: &str,
+        observation: Option<&str>,
+        out: &mut Vec<Violation>,
+    ) {
+        if let Some(codes) = self.codes.get(&c.id) {
+            if !codes.contains(value) {
+                out.push(
+                    Violation::new(
+                        Rule::Code,
+                        Some(&c.id),
+                        format!(
+                            "{} is not a code of {}",
+                            value,
+                            c.enumeration.unwrap_or_default()
+                        ),
+                    )
+                    .at(observation),
+                );
+            }
+        } else if let Some(format) = c.text_format {
+            if let Err(e) = self.check_format(c, format, value) {
+                out.push(
+                    Violation::new(Rule::TextFormat, Some(&c.id), e)
+                        .at(observation),
+                );
+            }
+        }
+        // Key values of series are checked by region in check_key_regions
+        let key =
+            c.kind.is_dimension() && c.kind != ComponentKind::TimeDimension;
+        if !key {
+            self.check_value_regions(c, value, observation, out);
+        }
+    }
+
+    /// Checks attribute values and time periods against the value sets of
+    /// the cube regions that list the component
+    fn check_value_regions(
+        &self,
+        c: &Component,
+        value: &str,
+        observation: Option<&str>,
+        out: &mut Vec<Violation>,
+    ) {
+        let listing = self.regions.iter().filter_map(|r| {
+            let values = match c.kind {
+                ComponentKind::TimeDimension => r.key_values.get(c.id.as_str()),
+                _ => r.attributes.get(c.id.as_str()),
+            };
+            values.map(|v| (r, v))
+        });
+        let (included, excluded): (Vec<_>, Vec<_>) =
+            listing.partition(|(r, _)| r.included);
+        for (r, values) in excluded {
+            if values.contains(value) {
+                out.push(
+                    Violation::new(
+                        Rule::Constraint,
+                        Some(&c.id),
+                        format!("{} is excluded by {}", value, r.constraint),
+                    )
+                    .at(observation),
+                );
+            }
+        }
+        if let Some((r, _)) = included.first() {
+            if !included.iter().any(|(_, values)| values.contains(value)) {
+                out.push(
+                    Violation::new(
+                        Rule::Constraint,
+                        Some(&c.id),
+                        format!("{} is not allowed by {}", value, r.constraint),
+                    )
+                    .at(observation),
+                );
+            }
+        }
+    }
+
+    fn check_format(
+        &self,
+        c: &Component,
+        format: &SimpleComponentTextFormatType,
+        value: &str,
+    ) -> Result<(), String> {
+        let length = value.chars().count() as i64;
+        if let Some(min) = format.min_length.filter(|min| length < *min) {
+            return Err(format!(
+                "{} is shorter than {} characters",
+                value, min
+            ));
+        }
+        if let Some(max) = format.max_length.filter(|max| length > *max) {
+            return Err(format!("{} is longer than {} characters", value, max));
+        }
+        if let Some(re) = self.patterns.get(&c.id) {
+            if !re.is_match(value) {
+                return Err(format!(
+                    "{} does not match the pattern {}",
+                    value,
+                    re.as_str()
+                ));
+            }
+        }
+        let numeric = match &format.text_type {
+            Some(t) => match numeric_type(t) {
+                Some(integer) => Some(integer),
+                None if matches!(t, SimpleDataType::Boolean) => {
+                    return match value {
+                        "true" | "false" => Ok(()),
+                        _ => Err(format!("{} is not a boolean", value)),
+                    };
+                }
+                None => None,
+            },
+            // Numeric facets imply a number
+            None if format.decimals.is_some()
+                || format.min_value.is_some()
+                || format.max_value.is_some() =>
+            {
+                Some(false)
+            }
+            None => None,
+        };
+        let integer = match numeric {
+            Some(integer) => integer,
+            None => return Ok(()),
+        };
+        let number = match value.parse::<f64>() {
+            Ok(n) if !integer || is_integer(value) => n,
+            _ if integer => return Err(format!("{} is not an integer", value)),
+            _ => return Err(format!("{} is not a number", value)),
+        };
+        // Missing observations are commonly given as NaN
+        if number.is_nan() {
+            return Ok(());
+        }
+        if let Some(decimals) = format.decimals {
+            let mantissa = value.split(['e', 'E']).next().unwrap_or_default();
+            let places = mantissa.split_once('.').map_or(0, |(_, f)| f.len());
+            if places as i64 > decimals {
+                return Err(format!(
+                    "{} has more than {} decimals",
+                    value, decimals
+                ));
+            }
+        }
+        if let Some(min) = format.min_value.filter(|min| number < *min) {
+            return Err(format!("{} is less than the minimum {}", value, min));
+        }
+        if let Some(max) = format.max_value.filter(|max| number > *max) {
+            return Err(format!(
+                "{} is greater than the maximum {}",
+                value, max
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The codes of the codelist, or the concepts of the concept scheme of a
+/// measure dimension, referenced by a component
+fn codes<'a>(
+    structures: &'a Data,
+    c: &Component,
+    enumeration: &str,
+) -> Option<HashSet<&'a str>> {
+    let urn: Urn = enumeration.parse().ok()?;
+    let ids: Vec<_> = match c.kind {
+        ComponentKind::MeasureDimension => structures
+            .concept_schemes
+            .iter()
+            .flatten()
+            .find(|cs| matches(*cs, &urn))?
+            .items()
+            .iter()
+            .filter_map(|i| i.id.as_deref())
+            .collect(),
+        _ => structures
+            .codelists
+            .iter()
+            .flatten()
+            .find(|cl| matches(*cl, &urn))?
+            .items()
+            .iter()
+            .filter_map(|i| i.id.as_deref())
+            .collect(),
+    };
+    Some(ids.into_iter().collect())
+}
+
+/// Whether a text type is numeric, and if so whether it is integral
+fn numeric_type(t: &SimpleDataType) -> Option<bool> {
+    match t {
+        SimpleDataType::BigInteger
+        | SimpleDataType::Count
+        | SimpleDataType::Integer
+        | SimpleDataType::Long
+        | SimpleDataType::Short => Some(true),
+        SimpleDataType::Decimal
+        | SimpleDataType::Double
+        | SimpleDataType::Float
+        | SimpleDataType::Incremental
+        | SimpleDataType::Numeric => Some(false),
+        _ => None,
+    }
+}
+
+fn is_integer(value: &str) -> bool {
+    let digits = value.strip_prefix(['-', '+']).unwrap_or(value);
+    !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+}
+
+fn unknown(id: &str, kind: &str) -> Violation {
+    Violation::new(
+        Rule::UnknownComponent,
+        Some(id),
+        format!("is not a {} of the data structure", kind),
+    )
+}
+
+fn missing(a: &Component, observation: Option<&str>) -> Violation {
+    Violation::new(
+        Rule::MandatoryAttribute,
+        Some(&a.id),
+        "is mandatory but missing",
+    )
+    .at(observation)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::dataset::{KeyValue, Observation};
+
+    fn structures() -> Data {
+        serde_json::from_value(structures_json()).unwrap()
+    }
+
+    fn structures_json() -> serde_json::Value {
+        let urn = |class: &str, id: &str| {
+            format!("urn:sdmx:org.sdmx.infomodel.{}=A:{}(1.0)", class, id)
+        };
+        let concept = |id: &str| {
+            format!("{}.{}", urn("conceptscheme.ConceptScheme", "CS"), id)
+        };
+        let codelist = |id: &str, codes: &[&str]| {
+            let codes: Vec<_> = codes
+                .iter()
+                .map(|c| json!({ "id": c, "name": c }))
+                .collect();
+            json!({
+                "id": id, "agencyID": "A", "version": "1.0", "name": id,
+                "codes": codes
+            })
+        };
+        let enumeration =
+            |id: &str| json!({ "enumeration": urn("codelist.Codelist", id) });
+        json!({
+            "codelists": [
+                codelist("CL_FREQ", &["A", "M"]),
+                codelist("CL_AREA", &["DE", "FR", "IT"]),
+                codelist("CL_STATUS", &["A", "E"])
+            ],
+            "dataStructures": [{
+                "id": "DSD", "agencyID": "A", "version": "1.0", "name": "DSD",
+                "dataStructureComponents": {
+                    "dimensionList": {
+                        "dimensions": [{
+                            "id": "FREQ", "position": 1,
+                            "conceptIdentity": concept("FREQ"),
+                            "localRepresentation": enumeration("CL_FREQ")
+                        }, {
+                            "id": "AREA", "position": 2,
+                            "conceptIdentity": concept("AREA"),
+                            "localRepresentation": enumeration("CL_AREA")
+                        }],
+                        "timeDimensions": [{
+                            "id": "TIME_PERIOD",
+                            "conceptIdentity": concept("TIME_PERIOD"),
+                            "localRepresentation": {
+                                "textFormat": {
+                                    "textType": "ObservationalTimePeriod"
+                                }
+                            }
+                        }]
+                    },
+                    "attributeList": {
+                        "attributes": [{
+                            "id": "UNIT_MULT",
+                            "conceptIdentity": concept("UNIT_MULT"),
+                            "assignmentStatus": "Conditional",
+                            "attributeRelationship": { "none": {} }
+                        }, {
+                            "id": "TITLE",
+                            "conceptIdentity": concept("TITLE"),
+                            "assignmentStatus": "Conditional",
+                            "attributeRelationship": {
+                                "dimensions": ["FREQ", "AREA"]
+                            }
+                        }, {
+                            "id": "OBS_STATUS",
+                            "conceptIdentity": concept("OBS_STATUS"),
+                            "assignmentStatus": "Mandatory",
+                            "attributeRelationship": {
+                                "primaryMeasure": "OBS_VALUE"
+                            },
+                            "localRepresentation": enumeration("CL_STATUS")
+                        }]
+                    },
+                    "measureList": {
+                        "primaryMeasure": {
+                            "id": "OBS_VALUE",
+                            "conceptIdentity": concept("OBS_VALUE")
+                        }
+                    }
+                }
+            }],
+            "dataflows": [{
+                "id": "DF", "agencyID": "A", "version": "1.0", "name": "DF",
+                "structure": urn("datastructure.DataStructure", "DSD")
+            }],
+            "contentConstraints": [{
+                "id": "CC", "agencyID": "A", "version": "1.0", "name": "CC",
+                "type": "Allowed",
+                "constraintAttachment": {
+                    "dataflows": [urn("datastructure.Dataflow", "DF")]
+                },
+                "cubeRegions": [{
+                    "isIncluded": true,
+                    "keyValues": [{ "id": "AREA", "values": ["DE", "FR"] }],
+                    "attributes": [{ "id": "OBS_STATUS", "values": ["A"] }]
+                }]
+            }]
+        })
+    }
+
+    /// The structure issues after changing the fixture, as location and message
+    fn structure_issues<F>(change: F) -> Vec<(Option<String>, String)>
+    where
+        F: FnOnce(&mut serde_json::Value),
+    {
+        let mut json = structures_json();
+        change(&mut json);
+        let data: Data = serde_json::from_value(json).unwrap();
+        validate_structure(&data)
+            .into_iter()
+            .map(|i| (i.location, i.message))
+            .collect()
+    }
+
+    /// The components of the data structure in the fixture
+    fn dsd_components(json: &mut serde_json::Value) -> &mut serde_json::Value {
+        &mut json["dataStructures"][0]["dataStructureComponents"]
+    }
+
+    fn values(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    /// A series with one observation of status A in 2020
+    fn series(key: &[(&str, &str)]) -> Series {
+        Series {
+            key: key
+                .iter()
+                .map(|(id, value)| KeyValue {
+                    id: id.to_string(),
+                    value: value.to_string(),
+                })
+                .collect(),
+            attributes: BTreeMap::new(),
+            observations: vec![Observation {
+                time_period: "2020".to_string(),
+                value: Some("1.5".to_string()),
+                attributes: values(&[("OBS_STATUS", "A")]),
+            }],
+        }
+    }
+
+    fn data_set(series: Vec<Series>) -> DataSet {
+        DataSet {
+            series,
+            ..Default::default()
+        }
+    }
+
+    /// The rule and component of each violation
+    fn violations(report: &DataReport) -> Vec<(Rule, Option<&str>)> {
+        report
+            .data_set
+            .iter()
+            .chain(report.series.iter().flat_map(|s| &s.violations))
+            .map(|v| (v.rule, v.component.as_deref()))
+            .collect()
+    }
+
+    #[test]
+    fn valid_data_has_no_violations() {
+        let structures = structures();
+        let validator = DataValidator::find(&structures, Some("DF")).unwrap();
+        let mut ds = data_set(vec![
+            series(&[("FREQ", "A"), ("AREA", "DE")]),
+            series(&[("FREQ", "M"), ("AREA", "FR")]),
+        ]);
+        ds.attributes = values(&[("UNIT_MULT", "0")]);
+        ds.series[0].attributes = values(&[("TITLE", "Prices")]);
+        let report = validator.validate(&ds);
+        assert!(report.is_valid(), "{:?}", report);
+    }
+
+    #[test]
+    fn series_keys_must_match_the_dimensions() {
+        let structures = structures();
+        let validator = DataValidator::find(&structures, Some("DSD")).unwrap();
+        let ds = data_set(vec![
+            series(&[("FREQ", "A")]),
+            series(&[("AREA", "DE"), ("FREQ", "A")]),
+            series(&[("FREQ", "A"), ("AREA", "DE"), ("REF", "X")]),
+        ]);
+        let report = validator.validate(&ds);
+        assert_eq!(report.series.len(), 3);
+        assert_eq!(
+            violations(&report),
+            vec![
+                (Rule::Key, Some("AREA")),
+                (Rule::Key, None),
+                (Rule::UnknownComponent, Some("REF")),
+            ]
+        );
+        assert_eq!(report.series[1].key, "DE.A");
+    }
+
+    #[test]
+    fn values_must_be_codes_of_their_codelist() {
+        let structures = structures();
+        let validator = DataValidator::find(&structures, Some("DSD")).unwrap();
+        let mut ds = data_set(vec![series(&[("FREQ", "Q"), ("AREA", "DE")])]);
+        ds.series[0].observations[0].attributes =
+            values(&[("OBS_STATUS", "X")]);
+        let report = validator.validate(&ds);
+        assert_eq!(
+            violations(&report),
+            vec![(Rule::Code, Some("FREQ")), (Rule::Code, Some("OBS_STATUS"))]
+        );
+        let obs = &report.series[0].violations[1];
+        assert_eq!(obs.observation.as_deref(), Some("2020"));
+    }
+
+    #[test]
+    fn attributes_must_be_reported_at_their_attachment_level() {
+        let structures = structures();
+        let validator = DataValidator::find(&structures, Some("DSD")).unwrap();
+        let mut ds = data_set(vec![series(&[("FREQ", "A"), ("AREA", "DE")])]);
+        ds.attributes = values(&[("TITLE", "Prices")]);
+        let s = &mut ds.series[0];
+        s.attributes = values(&[("UNIT_MULT", "0")]);
+        s.observations[0]
+            .attributes
+            .insert("TITLE".into(), "Prices".into());
+        s.observations.push(Observation {
+            time_period: "2021".to_string(),
+            value: None,
+            attributes: BTreeMap::new(),
+        });
+        let report = validator.validate(&ds);
+        assert_eq!(
+            violations(&report),
+            vec![
+                (Rule::Attachment, Some("TITLE")),
+                (Rule::Attachment, Some("UNIT_MULT")),
+                (Rule::Attachment, Some("TITLE")),
+                (Rule::MandatoryAttribute, Some("OBS_STATUS")),
+            ]
+        );
+    }
+
+    #[test]
+    fn dataflow_constraints_restrict_keys_and_attributes() {
+        let structures = structures();
+        let mut ds = data_set(vec![
+            series(&[("FREQ", "A"), ("AREA", "IT")]),
+            series(&[("FREQ", "A"), ("AREA", "DE")]),
+        ]);
+        ds.series[1].observations[0].attributes =
+            values(&[("OBS_STATUS", "E")]);
+
+        // The constraint is attached to the dataflow only
+        let validator = DataValidator::find(&structures, Some("DSD")).unwrap();
+        assert!(validator.validate(&ds).is_valid());
+
+        let validator = DataValidator::find(&structures, Some("DF")).unwrap();
+        let report = validator.validate(&ds);
+        assert_eq!(
+            violations(&report),
+            vec![
+                (Rule::Constraint, Some("AREA")),
+                (Rule::Constraint, Some("OBS_STATUS")),
+            ]
+        );
+        assert_eq!(
+            report.series[0].violations[0].message,
+            "IT is not allowed by ContentConstraint A:CC(1.0)"
+        );
+    }
+
+    #[test]
+    fn validators_are_found_by_dataflow_or_data_structure() {
+        let structures = structures();
+        assert!(DataValidator::find(&structures, None).is_ok());
+        assert!(DataValidator::find(&structures, Some("DSD")).is_ok());
+        assert!(DataValidator::find(&structures, Some("DF")).is_ok());
+        assert!(DataValidator::find(&structures, Some("X")).is_err());
+    }
+
+    #[test]
+    fn valid_structures_have_no_issues() {
+        assert!(validate_structure(&structures()).is_empty());
+    }
+
+    #[test]
+    fn data_structures_need_a_primary_measure() {
+        let issues = structure_issues(|json| {
+            let dsd = json["dataStructures"][0].as_object_mut().unwrap();
+            dsd.remove("dataStructureComponents");
+        });
+        assert_eq!(
+            issues,
+            vec![(
+                None,
+                "has no components, so no dimension or primary measure"
+                    .to_string()
+            )]
+        );
+
+        let issues = structure_issues(|json| {
+            dsd_components(json)["measureList"]["primaryMeasure"]["id"] =
+                json!("VALUE");
+        });
+        assert_eq!(
+            issues,
+            vec![(
+                Some("PrimaryMeasure VALUE".to_string()),
+                "must have the ID OBS_VALUE".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn reserved_ids_are_only_used_by_their_component() {
+        let issues = structure_issues(|json| {
+            let components = dsd_components(json);
+            components["attributeList"]["attributes"][0]["id"] =
+                json!("OBS_VALUE");
+            // The ID is inherited from the concept identity
+            let dimension = &mut components["dimensionList"]["dimensions"][1];
+            dimension.as_object_mut().unwrap().remove("id");
+            dimension["conceptIdentity"] = json!(
+                "urn:sdmx:org.sdmx.infomodel.conceptscheme.Concept=\
+                 A:CS(1.0).TIME_PERIOD"
+            );
+        });
+        assert_eq!(
+            issues,
+            vec![
+                (
+                    Some("Dimension TIME_PERIOD".to_string()),
+                    "uses the ID TIME_PERIOD inherited from its concept \
+                     identity, which is reserved for the TimeDimension"
+                        .to_string()
+                ),
+                (
+                    Some("Attribute OBS_VALUE".to_string()),
+                    "uses the ID OBS_VALUE, which is reserved for the \
+                     PrimaryMeasure"
+                        .to_string()
+                ),
+                (
+                    Some("TIME_PERIOD".to_string()),
+                    "the ID is used by more than one component or group"
+                        .to_string()
+                ),
+                (
+                    Some("OBS_VALUE".to_string()),
+                    "the ID is used by more than one component or group"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn enumerations_must_be_in_the_message() {
+        let issues = structure_issues(|json| {
+            let area = &mut dsd_components(json)["dimensionList"]["dimensions"]
+                [1]["localRepresentation"];
+            area["enumeration"] = json!(
+                "urn:sdmx:org.sdmx.infomodel.codelist.Codelist=A:CL_AREA(2.0)"
+            );
+        });
+        assert_eq!(
+            issues,
+            vec![(
+                Some("Dimension AREA".to_string()),
+                "references urn:sdmx:org.sdmx.infomodel.codelist.Codelist=\
+                 A:CL_AREA(2.0) which is not in the message"
+                    .to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn component_ids_are_unique() {
+        let issues = structure_issues(|json| {
+            let components = dsd_components(json);
+            components["attributeList"]["attributes"][1]["id"] = json!("FREQ");
+            components["groups"] =
+                json!([{ "id": "AREA", "groupDimensions": ["AREA"] }]);
+        });
+        assert_eq!(
+            issues,
+            vec![
+                (
+                    Some("FREQ".to_string()),
+                    "the ID is used by more than one component or group"
+                        .to_string()
+                ),
+                (
+                    Some("AREA".to_string()),
+                    "the ID is used by more than one component or group"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn code_parents_must_be_in_the_codelist() {
+        let issues = structure_issues(|json| {
+            json["codelists"][1]["codes"][0]["parent"] = json!("EU");
+        });
+        assert_eq!(
+            issues,
+            vec![(
+                Some("code DE".to_string()),
+                "has the parent EU which is not in the codelist".to_string()
+            )]
+        );
+
+        // Partial codelists may leave out parents
+        let issues = structure_issues(|json| {
+            json["codelists"][1]["codes"][0]["parent"] = json!("EU");
+            json["codelists"][1]["isPartial"] = json!(true);
+        });
+        assert!(issues.is_empty(), "{:?}", issues);
+    }
+
+    #[test]
+    fn validity_must_not_end_before_it_starts() {
+        let issues = structure_issues(|json| {
+            let df = &mut json["dataflows"][0];
+            df["validFrom"] = json!("2021-01-01");
+            df["validTo"] = json!("2020-12-31T00:00:00Z");
+        });
+        assert_eq!(
+            issues,
+            vec![(
+                Some("validTo".to_string()),
+                "2020-12-31T00:00:00Z is before validFrom 2021-01-01"
+                    .to_string()
+            )]
+        );
+
+        let issues = structure_issues(|json| {
+            json["dataflows"][0]["validFrom"] = json!("soon");
+        });
+        assert_eq!(
+            issues,
+            vec![(
+                Some("validFrom".to_string()),
+                "soon is not a valid date".to_string()
+            )]
+        );
+    }
+}
@@ -0,0 +1,454 @@
+//! version handles the versions of maintainable artefacts.
+//!
+//! SDMX 2.1 uses dotted numeric versions like `1.0`, while SDMX 3.0 uses
+//! semantic versions like `1.2.3` with an optional extension for unstable
+//! versions, e.g. `1.2.3-draft`. Both are represented by [`Version`].
+//!
+//! References to versions may also contain wildcards, which are represented
+//! by [`VersionReq`]: the 2.1 keywords `latest` and `all`, and the 3.0 forms
+//! `~` (latest), `+` (latest stable), `*` (all) and `1.2+.0` (the given
+//! version or any later backwards compatible one).
+
+use std::{
+    cmp::Ordering,
+    fmt,
+    hash::{Hash, Hasher},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, Result};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// The version of a maintainable artefact
+#[derive(Debug, Clone)]
+pub struct Version {
+    /// Numeric parts, e.g. `[1, 0]` for `1.0`
+    pub parts: Vec<u64>,
+    /// Extension of an unstable SDMX 3.0 version, e.g. `draft` for `1.0.0-draft`
+    pub extension: Option<String>,
+}
+
+impl Version {
+    pub fn new(parts: Vec<u64>) -> Self {
+        Version {
+            parts,
+            extension: None,
+        }
+    }
+
+    /// Versions without an extension are stable. All SDMX 2.1 versions are stable.
+    pub fn is_stable(&self) -> bool {
+        self.extension.is_none()
+    }
+
+    pub fn major(&self) -> u64 {
+        self.part(0)
+    }
+
+    pub fn minor(&self) -> u64 {
+        self.part(1)
+    }
+
+    pub fn patch(&self) -> u64 {
+        self.part(2)
+    }
+
+    /// Gets a numeric part, where missing parts are treated as 0
+    fn part(&self, idx: usize) -> u64 {
+        self.parts.get(idx).copied().unwrap_or(0)
+    }
+
+    /// Numeric parts without trailing zeros, so that `1.0` and `1.0.0` compare equal
+    fn significant_parts(&self) -> &[u64] {
+        let len = self
+            .parts
+            .iter()
+            .rposition(|p| *p != 0)
+            .map_or(0, |i| i + 1);
+        &self.parts[..len]
+    }
+
+    /// The latest version in `known`, including unstable versions
+    pub fn latest<'a, I>(known: I) -> Option<&'a Version>
+    where
+        I: IntoIterator<Item = &'a Version>,
+    {
+        known.into_iter().max()
+    }
+
+    /// The latest stable version in `known`
+    pub fn latest_stable<'a, I>(known: I) -> Option<&'a Version>
+    where
+        I: IntoIterator<Item = &'a Version>,
+    {
+        known.into_iter().filter(|v| v.is_stable()).max()
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let len = self.parts.len().max(other.parts.len());
+        for idx in 0..len {
+            match self.part(idx).cmp(&other.part(idx)) {
+                Ordering::Equal => continue,
+                ord => return ord,
+            }
+        }
+        // As in semver, an unstable version precedes the stable one
+        match (&self.extension, &other.extension) {
+            (None, None) => Ordering::Equal,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(_), None) => Ordering::Less,
+            (Some(a), Some(b)) => cmp_extensions(a, b),
+        }
+    }
+}
+
+/// Compares the extensions of unstable versions like semver pre-releases,
+/// identifier by identifier: numeric identifiers numerically and before the
+/// others, which are compared in ASCII order. So `rc.9` precedes `rc.10`,
+/// `alpha.1` precedes `alpha.beta`, and `alpha` precedes `alpha.1`.
+fn cmp_extensions(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.split('.'), b.split('.'));
+    loop {
+        let ord = match (a.next(), b.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a), Some(b)) => match (numeric(a), numeric(b)) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => a.cmp(b),
+            },
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+}
+
+/// The value of a numeric identifier of an extension. As in semver, numeric
+/// identifiers have no leading zeros, so equal extensions are identical.
+fn numeric(id: &str) -> Option<u64> {
+    let digits = !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit());
+    match digits && (id == "0" || !id.starts_with('0')) {
+        true => id.parse().ok(),
+        false => None,
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
+impl Hash for Version {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.significant_parts().hash(state);
+        self.extension.hash(state);
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<_> = self.parts.iter().map(|p| p.to_string()).collect();
+        write!(f, "{}", parts.join("."))?;
+        if let Some(ext) = &self.extension {
+            write!(f, "-{}", ext)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Version {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let (numbers, extension) = match s.split_once('-') {
+            Some((numbers, ext)) if !ext.is_empty() => {
+                (numbers, Some(ext.to_string()))
+            }
+            Some(_) => return Err(anyhow!("Empty version extension in {}", s)),
+            None => (s, None),
+        };
+        let parts = numbers
+            .split('.')
+            .map(|p| {
+                p.parse::<u64>()
+                    .map_err(|_| anyhow!("Invalid version {}", s))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Version { parts, extension })
+    }
+}
+
+impl Serialize for Version {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Version {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+/// A reference to one or more versions of an artefact, as used in URNs and REST queries
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum VersionReq {
+    /// A single version
+    Exact(Version),
+    /// The latest version, stable or not (`latest` in SDMX 2.1, `~` in SDMX 3.0)
+    Latest,
+    /// The latest stable version (`+` in SDMX 3.0)
+    LatestStable,
+    /// All versions (`all` in SDMX 2.1, `*` in SDMX 3.0)
+    All,
+    /// The given version or any later stable version that only increments the
+    /// part at `position` or later, e.g. `1.2+.0` has position 1 and matches
+    /// `1.2.0` and `1.4.1` but not `2.0.0`
+    Wildcard { version: Version, position: usize },
+}
+
+impl VersionReq {
+    /// Whether a version satisfies this requirement
+    pub fn matches(&self, v: &Version) -> bool {
+        match self {
+            VersionReq::Exact(exact) => exact == v,
+            VersionReq::Latest | VersionReq::All => true,
+            VersionReq::LatestStable => v.is_stable(),
+            VersionReq::Wildcard { version, position } => {
+                v.is_stable()
+                    && (0..*position)
+                        .all(|idx| v.part(idx) == version.part(idx))
+                    && v >= version
+            }
+        }
+    }
+
+    /// Resolves the requirement against the versions known for an artefact.
+    /// `All` resolves to every known version, the other requirements resolve
+    /// to at most one.
+    pub fn resolve<'a, I>(&self, known: I) -> Vec<&'a Version>
+    where
+        I: IntoIterator<Item = &'a Version>,
+    {
+        let mut matching: Vec<_> =
+            known.into_iter().filter(|v| self.matches(v)).collect();
+        matching.sort();
+        matching.dedup();
+        match self {
+            VersionReq::All => matching,
+            _ => matching.pop().into_iter().collect(),
+        }
+    }
+}
+
+impl From<Version> for VersionReq {
+    fn from(v: Version) -> Self {
+        VersionReq::Exact(v)
+    }
+}
+
+/// The text form of a requirement, as used in URNs and when serialized. It
+/// uses the SDMX 2.1 keyword where there is one (`latest`, `all`), so 2.1
+/// references read as they do in 2.1 messages, and the SDMX 3.0 form where
+/// 2.1 has none (`+`, `1.2+.0`). Both parse back with `FromStr`, which
+/// accepts either grammar. REST queries must be rendered in the grammar of
+/// the API instead, see `RestVersion` in `queries`.
+impl fmt::Display for VersionReq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VersionReq::Exact(v) => write!(f, "{}", v),
+            VersionReq::Latest => write!(f, "latest"),
+            VersionReq::LatestStable => write!(f, "+"),
+            VersionReq::All => write!(f, "all"),
+            VersionReq::Wildcard { version, position } => {
+                let parts: Vec<_> = version
+                    .parts
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, p)| match idx == *position {
+                        true => format!("{}+", p),
+                        false => p.to_string(),
+                    })
+                    .collect();
+                write!(f, "{}", parts.join("."))
+            }
+        }
+    }
+}
+
+impl FromStr for VersionReq {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        match s {
+            "latest" | "~" => return Ok(VersionReq::Latest),
+            "+" => return Ok(VersionReq::LatestStable),
+            "all" | "*" => return Ok(VersionReq::All),
+            _ => {}
+        }
+
+        let positions: Vec<_> = s
+            .split('.')
+            .enumerate()
+            .filter(|(_, p)| p.ends_with('+'))
+            .map(|(idx, _)| idx)
+            .collect();
+        match positions.as_slice() {
+            [] => Ok(VersionReq::Exact(s.parse()?)),
+            [position] => Ok(VersionReq::Wildcard {
+                version: s.replace('+', "").parse()?,
+                position: *position,
+            }),
+            _ => Err(anyhow!("Only one wildcard is allowed in version {}", s)),
+        }
+    }
+}
+
+impl Serialize for VersionReq {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for VersionReq {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(s: &str) -> Version {
+        s.parse().unwrap()
+    }
+
+    fn req(s: &str) -> VersionReq {
+        s.parse().unwrap()
+    }
+
+    fn resolve(r: &str, known: &[&str]) -> Vec<String> {
+        let known: Vec<_> = known.iter().map(|s| v(s)).collect();
+        req(r)
+            .resolve(&known)
+            .iter()
+            .map(|v| v.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn versions_are_ordered_numerically() {
+        assert!(v("1.10") > v("1.9"));
+        assert!(v("2.0") > v("1.99.99"));
+        assert_eq!(v("1.0"), v("1.0.0"));
+        assert_eq!(v("1"), v("1.0"));
+        // an unstable version precedes the stable one
+        assert!(v("1.0.0-draft") < v("1.0.0"));
+        assert!(v("1.0.0-draft") > v("0.9.0"));
+        assert!(v("1.0.0-alpha") < v("1.0.0-beta"));
+        // extensions are compared identifier by identifier, like in semver
+        assert!(v("1.0.0-rc.9") < v("1.0.0-rc.10"));
+        assert!(v("1.0.0-alpha") < v("1.0.0-alpha.1"));
+        assert!(v("1.0.0-alpha.1") < v("1.0.0-alpha.beta"));
+        assert!(v("1.0.0-beta.11") < v("1.0.0-rc.1"));
+        assert!(v("1.0.0-rc.1") != v("1.0.0-rc.01"));
+        assert_eq!(v(" 1.2 ").to_string(), "1.2");
+        assert_eq!(v("1.2.3-draft").to_string(), "1.2.3-draft");
+        for invalid in &["", "1.x", "1..0", "1.0-", "latest"] {
+            assert!(invalid.parse::<Version>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn equal_versions_hash_equally() {
+        use std::collections::HashSet;
+        let set: HashSet<_> =
+            vec![v("1.0"), v("1.0.0"), v("1")].into_iter().collect();
+        assert_eq!(set.len(), 1);
+    }
+
+    #[test]
+    fn wildcards_are_parsed_and_displayed() {
+        for s in &["latest", "+", "all", "1.2+.0", "1.0"] {
+            assert_eq!(req(s).to_string(), *s);
+        }
+        // the 3.0 keywords are displayed as their 2.1 equivalents
+        assert_eq!(req("~"), VersionReq::Latest);
+        assert_eq!(req("~").to_string(), "latest");
+        assert_eq!(req("*"), VersionReq::All);
+        assert_eq!(req("*").to_string(), "all");
+        assert_eq!(
+            req("1.2+.0"),
+            VersionReq::Wildcard {
+                version: v("1.2.0"),
+                position: 1
+            }
+        );
+        assert!("1+.2+.0".parse::<VersionReq>().is_err());
+        assert!("1.x+".parse::<VersionReq>().is_err());
+    }
+
+    #[test]
+    fn wildcards_match_compatible_stable_versions() {
+        let minor = req("1.2+.0");
+        assert!(minor.matches(&v("1.2.0")));
+        assert!(minor.matches(&v("1.4.1")));
+        assert!(!minor.matches(&v("1.1.9")));
+        assert!(!minor.matches(&v("2.0.0")));
+        assert!(!minor.matches(&v("1.3.0-draft")));
+        let patch = req("1.2.3+");
+        assert!(patch.matches(&v("1.2.7")));
+        assert!(!patch.matches(&v("1.3.0")));
+        assert!(req("+").matches(&v("1.0")));
+        assert!(!req("+").matches(&v("1.0-draft")));
+        assert!(req("1.0").matches(&v("1.0.0")));
+    }
+
+    #[test]
+    fn requirements_resolve_against_known_versions() {
+        let known = ["1.0", "1.10", "1.9", "2.0.0-draft", "1.10.0"];
+        assert_eq!(resolve("latest", &known), vec!["2.0.0-draft"]);
+        assert_eq!(resolve("+", &known), vec!["1.10"]);
+        assert_eq!(
+            resolve("all", &known),
+            vec!["1.0", "1.9", "1.10", "2.0.0-draft"]
+        );
+        assert_eq!(resolve("1.0+.0", &known), vec!["1.10"]);
+        assert_eq!(resolve("1.9", &known), vec!["1.9"]);
+        assert!(resolve("3.0", &known).is_empty());
+        assert!(resolve("latest", &[]).is_empty());
+        assert_eq!(
+            Version::latest_stable(&[v("1.0"), v("2.0-draft")]),
+            Some(&v("1.0"))
+        );
+    }
+}